@@ -0,0 +1,142 @@
+//! Alt text for generated images, derived from their prompts.
+//!
+//! [`from_prompts`] joins the positive prompts of a request into a short
+//! description, with the weights and the `(emphasis:1.2)` or `[de-emphasis]`
+//! syntax of A1111-style tools removed. [`write_sidecar`] saves it as a
+//! `.txt` file next to the image, the caption sidecar the `dataset` module
+//! reads too, so content management systems get accessibility text without
+//! a captioning step. Batch runs write one for every saved image with
+//! `BatchRunner::alt_text`.
+//!
+//! ```
+//! use stability_rs::alt_text;
+//! use stability_rs::api::rest::generation::{PromptGroups, WeightedPrompt};
+//!
+//! let prompts = PromptGroups {
+//!     positive: vec![WeightedPrompt {
+//!         text: "a lighthouse at dusk, (crashing waves:1.3), [fog]".to_string(),
+//!         weight: 1.0,
+//!     }],
+//!     negative: Vec::new(),
+//! };
+//! assert_eq!(
+//!     alt_text::from_prompts(&prompts),
+//!     "A lighthouse at dusk, crashing waves, fog"
+//! );
+//! ```
+
+use crate::api::rest::generation::PromptGroups;
+use crate::atomic_write::OverwritePolicy;
+use crate::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// The extension of alt text sidecars
+pub const SIDECAR_EXTENSION: &str = "txt";
+
+/// The positive prompts of `prompts`, cleaned and joined into one sentence
+/// starting with a capital letter
+///
+/// Prompts weighted 0 contribute nothing to the image and are left out, as
+/// are the negative ones.
+pub fn from_prompts(prompts: &PromptGroups) -> String {
+    let text = prompts
+        .positive
+        .iter()
+        .filter(|prompt| prompt.weight > 0.0)
+        .map(|prompt| clean(&prompt.text))
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => text,
+    }
+}
+
+/// `text` without emphasis brackets and `:weight` suffixes, escaped
+/// brackets kept as literal ones, and runs of whitespace collapsed
+pub fn clean(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some((_, escaped)) = chars.next() {
+                    out.push(escaped);
+                }
+            }
+            '(' | ')' | '[' | ']' => out.push(' '),
+            // `:1.3` before a closing bracket is a weight, not text
+            ':' if is_weight(&text[i + 1..]) => {
+                while chars.peek().is_some_and(|(_, c)| *c != ')') {
+                    chars.next();
+                }
+            }
+            c => out.push(c),
+        }
+    }
+
+    let collapsed = out.split_whitespace().collect::<Vec<_>>().join(" ");
+    // brackets may leave a comma with a space before it, or one at either end
+    collapsed
+        .replace(" ,", ",")
+        .trim_matches(|c: char| c == ',' || c.is_whitespace())
+        .to_string()
+}
+
+/// Whether `rest` starts with a number closed by `)`
+fn is_weight(rest: &str) -> bool {
+    rest.split_once(')')
+        .is_some_and(|(weight, _)| weight.trim().parse::<f32>().is_ok())
+}
+
+/// Write `text` to the sidecar of the image at `image_path`, the same path
+/// with a `.txt` extension, returning the sidecar's path
+///
+/// The sidecar is encrypted when an
+/// [encryption key](crate::encryption::install) is installed, as it repeats
+/// the prompt.
+pub async fn write_sidecar(image_path: impl AsRef<Path>, text: &str) -> Result<PathBuf> {
+    let path = image_path.as_ref().with_extension(SIDECAR_EXTENSION);
+    #[cfg(feature = "encryption")]
+    if let Some(key) = crate::encryption::installed() {
+        let sealed = key.encrypt(text.as_bytes());
+        crate::atomic_write::write_async(&path, &sealed, OverwritePolicy::Overwrite).await?;
+        return Ok(path);
+    }
+    crate::atomic_write::write_async(&path, text.as_bytes(), OverwritePolicy::Overwrite).await?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::rest::generation::WeightedPrompt;
+
+    fn prompt(text: &str, weight: f32) -> WeightedPrompt {
+        WeightedPrompt {
+            text: text.to_string(),
+            weight,
+        }
+    }
+
+    #[test]
+    fn clean_is_removing_weights_and_brackets() {
+        assert_eq!(
+            clean("((masterpiece)), a fox:1.2 \\(red\\), [[blurry]] , (snow: 0.8)"),
+            "masterpiece, a fox:1.2 (red), blurry, snow"
+        );
+    }
+
+    #[test]
+    fn from_prompts_is_joining_the_positive_prompts() {
+        let prompts = PromptGroups {
+            positive: vec![prompt("a fox", 1.0), prompt("ignored", 0.0), prompt("(snow)", 0.5)],
+            negative: vec![prompt("blurry", -1.0)],
+        };
+        assert_eq!(from_prompts(&prompts), "A fox, snow");
+        assert_eq!(from_prompts(&PromptGroups::default()), "");
+    }
+}
@@ -0,0 +1,235 @@
+//! Packaging frame sequences as animations.
+//!
+//! An [`Animation`] holds decoded frames, such as the images of a seed walk
+//! or a strength sweep, and writes them out as a looping GIF or APNG. Frames
+//! can also be read from an animated GIF or WebP file with
+//! [`Animation::open`].
+//!
+//! ```no_run
+//! use stability_rs::{animation::Animation, Result};
+//!
+//! fn save(artifacts: &[stability_rs::api::rest::artifact::Artifact]) -> Result<()> {
+//!     let animation = Animation::from_artifacts(artifacts)?;
+//!     animation.save_gif("walk.gif", 8)?;
+//!     animation.save_apng("walk.png", 8)?;
+//!     Ok(())
+//! }
+//! ```
+
+use crate::api::rest::artifact::Artifact;
+use crate::atomic_write::TempPath;
+use crate::error::Error;
+use crate::prelude::*;
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::codecs::webp::WebPDecoder;
+use image::{imageops::FilterType, AnimationDecoder, Delay, ImageFormat, RgbaImage};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct Animation {
+    frames: Vec<RgbaImage>,
+    fps: Option<u32>,
+}
+
+impl Animation {
+    /// Decode image artifacts into frames, resizing every frame to the size
+    /// of the first
+    pub fn from_artifacts<'a>(artifacts: impl IntoIterator<Item = &'a Artifact>) -> Result<Self> {
+        let frames = artifacts
+            .into_iter()
+            .map(|artifact| Ok(artifact.decode()?.to_rgba8()))
+            .collect::<Result<Vec<_>>>()?;
+        Self::from_frames(frames)
+    }
+
+    /// Frames already decoded, resized to the size of the first
+    pub fn from_frames(mut frames: Vec<RgbaImage>) -> Result<Self> {
+        let Some((width, height)) = frames.first().map(|f| f.dimensions()) else {
+            return Err(Box::new(Error::AnimationEmpty));
+        };
+        for frame in frames
+            .iter_mut()
+            .filter(|f| f.dimensions() != (width, height))
+        {
+            *frame = image::imageops::resize(frame, width, height, FilterType::Lanczos3);
+        }
+        Ok(Self { frames, fps: None })
+    }
+
+    /// Read every frame of an animated GIF or WebP file
+    ///
+    /// A still WebP file is read as a single frame.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let format = ImageFormat::from_path(path).ok();
+        if !matches!(format, Some(ImageFormat::Gif | ImageFormat::WebP)) {
+            return Err(Box::new(Error::AnimationFormatUnsupported(
+                path.display().to_string(),
+            )));
+        }
+
+        let reader = BufReader::new(File::open(path)?);
+        let frames = match format {
+            Some(ImageFormat::Gif) => GifDecoder::new(reader)?.into_frames().collect_frames()?,
+            _ => WebPDecoder::new(reader)?.into_frames().collect_frames()?,
+        };
+
+        let fps = frames.first().map(|frame| {
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            match numer {
+                0 => 10,
+                _ => (1000 * denom / numer).max(1),
+            }
+        });
+        let mut animation =
+            Self::from_frames(frames.into_iter().map(|f| f.into_buffer()).collect())?;
+        animation.fps = fps;
+        Ok(animation)
+    }
+
+    /// Record the frame rate the frames are meant to be shown at
+    pub fn with_fps(mut self, fps: u32) -> Self {
+        self.fps = Some(fps);
+        self
+    }
+
+    pub fn frames(&self) -> &[RgbaImage] {
+        &self.frames
+    }
+
+    /// The frame rate of the file the frames were read from, going by the
+    /// delay of the first frame
+    pub fn fps(&self) -> Option<u32> {
+        self.fps
+    }
+
+    /// Write a looping GIF showing `fps` frames a second
+    ///
+    /// GIF is limited to 256 colours a frame, so gradients will band; use
+    /// [`Animation::save_apng`] where that matters.
+    pub fn save_gif(&self, path: impl AsRef<Path>, fps: u32) -> Result<()> {
+        crate::preflight::create_parent_dirs(path.as_ref())?;
+        let temp = TempPath::new(path.as_ref());
+        let mut writer = BufWriter::new(File::create(temp.path())?);
+        {
+            let mut encoder = GifEncoder::new(&mut writer);
+            encoder.set_repeat(Repeat::Infinite)?;
+            let delay = Delay::from_saturating_duration(frame_duration(fps));
+            for frame in &self.frames {
+                encoder.encode_frame(image::Frame::from_parts(frame.clone(), 0, 0, delay))?;
+            }
+        }
+        writer.flush()?;
+        drop(writer);
+        temp.commit()?;
+        Ok(())
+    }
+
+    /// Write a looping animated PNG showing `fps` frames a second
+    pub fn save_apng(&self, path: impl AsRef<Path>, fps: u32) -> Result<()> {
+        let (width, height) = self.frames[0].dimensions();
+        crate::preflight::create_parent_dirs(path.as_ref())?;
+        let temp = TempPath::new(path.as_ref());
+        let mut file = BufWriter::new(File::create(temp.path())?);
+        let mut encoder = png::Encoder::new(&mut file, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(self.frames.len() as u32, 0)?;
+        // a delay of one over fps seconds
+        encoder.set_frame_delay(1, fps.clamp(1, u16::MAX as u32) as u16)?;
+
+        let mut writer = encoder.write_header()?;
+        for frame in &self.frames {
+            writer.write_image_data(frame.as_raw())?;
+        }
+        writer.finish()?;
+        file.flush()?;
+        drop(file);
+        temp.commit()?;
+        Ok(())
+    }
+}
+
+fn frame_duration(fps: u32) -> Duration {
+    Duration::from_secs(1) / fps.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{AnimationDecoder, Rgba};
+
+    fn animation() -> Animation {
+        Animation::from_frames(vec![
+            RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255])),
+            RgbaImage::from_pixel(8, 8, Rgba([0, 0, 255, 255])),
+        ])
+        .unwrap()
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("stability_rs_anim_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn frames_are_resized_to_the_first() {
+        assert!(animation()
+            .frames()
+            .iter()
+            .all(|f| f.dimensions() == (4, 4)));
+    }
+
+    #[test]
+    fn from_frames_is_erring_when_empty() {
+        let err = Animation::from_frames(Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "an animation needs at least one frame");
+    }
+
+    #[test]
+    fn gif_is_holding_every_frame() {
+        let path = temp_path("frames.gif");
+        animation().save_gif(&path, 10).unwrap();
+
+        let file = std::io::BufReader::new(File::open(&path).unwrap());
+        let frames = image::codecs::gif::GifDecoder::new(file)
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].delay().numer_denom_ms(), (100, 1));
+    }
+
+    #[test]
+    fn open_is_reading_gif_frames_and_frame_rate() {
+        let path = temp_path("open.gif");
+        animation().save_gif(&path, 10).unwrap();
+
+        let opened = Animation::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(opened.frames().len(), 2);
+        assert_eq!(opened.fps(), Some(10));
+    }
+
+    #[test]
+    fn open_is_erring_on_still_formats() {
+        let err = Animation::open("frame.png").unwrap_err();
+        assert_eq!(err.to_string(), "frame.png is not a GIF or WebP file");
+    }
+
+    #[test]
+    fn apng_is_holding_every_frame() {
+        let path = temp_path("frames.png");
+        animation().save_apng(&path, 10).unwrap();
+
+        let decoder = png::Decoder::new(std::io::BufReader::new(File::open(&path).unwrap()));
+        let reader = decoder.read_info().unwrap();
+        let control = reader.info().animation_control().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(control.num_frames, 2);
+    }
+}
@@ -0,0 +1,519 @@
+//! A client for Stability's gRPC gateway, which streams the intermediate
+//! images of a generation as they are denoised.
+//!
+//! The REST API only answers once every sample is done; the gateway sends
+//! each sample again after some of its steps, so an interface can show the
+//! generation take shape. Requests are the same [`TextToImage`] and
+//! [`ImageToImage`] values the REST endpoints take, minus their `extras`,
+//! which the gateway has no field for.
+//!
+//! ```no_run
+//! # use stability_rs::model::text_to_img::TextToImage;
+//! # async fn run(request: TextToImage) -> stability_rs::Result<()> {
+//! use stability_rs::api::grpc::GrpcClient;
+//!
+//! let client = GrpcClient::connect().await?;
+//! let mut stream = client
+//!     .text_to_image("stable-diffusion-xl-1024-v1-0", &request)
+//!     .await?;
+//! while let Some(streamed) = stream.next().await {
+//!     let streamed = streamed?;
+//!     println!("sample {}, final: {}", streamed.index, streamed.is_final());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+mod proto;
+
+#[cfg(feature = "image-to-image")]
+use crate::api::rest::generation::upload;
+#[cfg(feature = "image-to-image")]
+use crate::error::Error;
+#[cfg(feature = "image-to-image")]
+use crate::model::img_to_img::{ImageMode, ImageToImage};
+use crate::model::text_to_img::TextToImage;
+#[cfg(feature = "image-to-image")]
+use crate::model::UploadOptions;
+use crate::model::{
+    ClipGuidancePreset, GenerationMetadata, Image, ImageResponse, Sampler, Seed, TextPrompt,
+};
+use crate::prelude::*;
+use base64::{engine::general_purpose, Engine as _};
+use proto::{prompt, ArtifactType, DiffusionSampler, FinishReason, GuidancePreset};
+use std::collections::VecDeque;
+use std::env;
+use std::fmt;
+#[cfg(feature = "image-to-image")]
+use std::path::Path;
+use tonic::client::Grpc;
+use tonic::codec::{ProstCodec, Streaming};
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+
+/// The gateway's address
+pub const GRPC_URL: &str = "https://grpc.stability.ai:443";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::text_to_img::TextToImageBuilder;
+    use crate::model::StylePreset;
+    use prost::Message;
+
+    fn artifact(index: u32, finish_reason: FinishReason) -> proto::Artifact {
+        proto::Artifact {
+            r#type: ArtifactType::Image as i32,
+            mime: "image/png".to_string(),
+            binary: bytes::Bytes::from_static(b"png"),
+            index,
+            finish_reason: finish_reason as i32,
+            seed: 42,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn text_to_image_request_is_carrying_prompts_and_parameters() {
+        let request = TextToImageBuilder::new()
+            .style_preset(StylePreset::Enhance)
+            .unwrap()
+            .text_prompt("a lighthouse", 1.0)
+            .unwrap()
+            .text_prompt("fog", -0.5)
+            .unwrap()
+            .sampler(Sampler::KEuler)
+            .unwrap()
+            .seed(7)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let request = text_to_image_request("engine", &request);
+
+        assert_eq!(request.engine_id, "engine");
+        assert_eq!(request.prompt.len(), 2);
+        assert_eq!(
+            request.prompt[1].prompt,
+            Some(prompt::Prompt::Text("fog".to_string()))
+        );
+        let image = request.image.unwrap();
+        assert_eq!(image.seed, [7]);
+        assert_eq!(
+            image.transform.unwrap().diffusion,
+            Some(DiffusionSampler::KEuler as i32)
+        );
+        assert_eq!(request.extras, Some(proto::style_preset_extras("enhance")));
+    }
+
+    #[cfg(feature = "image-to-image")]
+    #[test]
+    fn image_to_image_request_is_starting_part_way_by_image_strength() {
+        use crate::model::img_to_img::ImageToImageBuilder;
+
+        let request = ImageToImageBuilder::new()
+            .init_image_path("init.png")
+            .unwrap()
+            .style_preset(StylePreset::Enhance)
+            .unwrap()
+            .image_strength(0.25)
+            .unwrap()
+            .text_prompt("a lighthouse", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let request = image_to_image_request("engine", &request, proto::Artifact::default()).unwrap();
+
+        let schedule = request.image.unwrap().parameters[0].schedule.clone().unwrap();
+        assert_eq!(schedule.start, Some(0.75));
+        assert_eq!(request.prompt.len(), 2);
+    }
+
+    #[cfg(feature = "image-to-image")]
+    #[test]
+    fn image_to_image_request_is_refusing_the_step_schedule_mode() {
+        use crate::model::img_to_img::ImageToImageBuilder;
+
+        let request = ImageToImageBuilder::new()
+            .init_image_path("init.png")
+            .unwrap()
+            .style_preset(StylePreset::Enhance)
+            .unwrap()
+            .init_image_mode(ImageMode::StepSchedule)
+            .unwrap()
+            .text_prompt("a lighthouse", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let err = image_to_image_request("engine", &request, proto::Artifact::default()).unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::UnsupportedOverGrpc(_))
+        ));
+    }
+
+    #[test]
+    fn answers_are_streamed_as_intermediate_then_final_images() {
+        let answer = proto::Answer {
+            artifacts: vec![
+                artifact(0, FinishReason::Null),
+                artifact(1, FinishReason::Stop),
+                artifact(2, FinishReason::Filter),
+            ],
+            ..Default::default()
+        };
+        // decode what went over the wire, as the stream would
+        let answer = proto::Answer::decode(answer.encode_to_vec().as_slice()).unwrap();
+
+        let streamed: Vec<StreamedImage> = streamed_images(answer).collect();
+
+        let finals: Vec<bool> = streamed.iter().map(StreamedImage::is_final).collect();
+        assert_eq!(finals, [false, true, true]);
+        assert_eq!(streamed[1].image.finish_reason, "SUCCESS");
+        assert_eq!(streamed[2].image.finish_reason, "CONTENT_FILTERED");
+        assert_eq!(streamed[1].image.base64, general_purpose::STANDARD.encode(b"png"));
+    }
+}
+
+/// A connection to the gRPC gateway
+#[derive(Clone)]
+pub struct GrpcClient {
+    grpc: Grpc<Channel>,
+    api_key: String,
+}
+
+impl fmt::Debug for GrpcClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GrpcClient").finish_non_exhaustive()
+    }
+}
+
+impl GrpcClient {
+    /// Connect to [`GRPC_URL`], reading the API key from `STABILITY_API_KEY`
+    pub async fn connect() -> Result<Self> {
+        let api_key = env::var("STABILITY_API_KEY")?;
+        Self::connect_to(GRPC_URL, &api_key).await
+    }
+
+    /// Connect to the gateway at `url`, verifying `https` URLs against the
+    /// webpki roots
+    pub async fn connect_to(url: &str, api_key: &str) -> Result<Self> {
+        let mut endpoint = Endpoint::from_shared(url.to_string())?;
+        if url.starts_with("https://") {
+            endpoint = endpoint.tls_config(ClientTlsConfig::new().with_webpki_roots())?;
+        }
+        Ok(Self {
+            grpc: Grpc::new(endpoint.connect().await?),
+            api_key: api_key.to_string(),
+        })
+    }
+
+    /// Generate `request` with `engine`, streaming its samples as they are
+    /// denoised
+    pub async fn text_to_image(&self, engine: &str, request: &TextToImage) -> Result<ImageStream> {
+        let message = text_to_image_request(engine, request);
+        self.generate(engine, message, request.organization()).await
+    }
+
+    /// Generate `request` with `engine`, streaming its samples as they are
+    /// denoised
+    ///
+    /// Fails with [`Error::UnsupportedOverGrpc`] for
+    /// [`ImageMode::StepSchedule`], as the request carries no schedule for
+    /// the gateway to follow.
+    #[cfg(feature = "image-to-image")]
+    pub async fn image_to_image(
+        &self,
+        engine: &str,
+        request: &ImageToImage,
+    ) -> Result<ImageStream> {
+        let init = init_image(&request.init_image, &request.upload).await?;
+        let message = image_to_image_request(engine, request, init)?;
+        self.generate(engine, message, request.organization.as_deref())
+            .await
+    }
+
+    async fn generate(
+        &self,
+        engine: &str,
+        message: proto::Request,
+        organization: Option<&str>,
+    ) -> Result<ImageStream> {
+        let mut request = tonic::Request::new(message);
+        let metadata = request.metadata_mut();
+        metadata.insert("authorization", format!("Bearer {}", self.api_key).parse()?);
+        if let Some(organization) = organization {
+            metadata.insert("organization", organization.parse()?);
+        }
+
+        let mut grpc = self.grpc.clone();
+        grpc.ready().await?;
+        let path = PathAndQuery::from_static(proto::GENERATE_PATH);
+        let codec = ProstCodec::<proto::Request, proto::Answer>::default();
+        let answers = grpc.server_streaming(request, path, codec).await?;
+        Ok(ImageStream {
+            engine: engine.to_string(),
+            answers: answers.into_inner(),
+            pending: VecDeque::new(),
+        })
+    }
+}
+
+/// A sample of a generation, either partly denoised or final
+#[derive(Debug, Clone)]
+pub struct StreamedImage {
+    /// Which of the requested samples this is, from 0
+    pub index: u32,
+    /// The image so far; its `finish_reason` is empty until it is final
+    pub image: Image,
+}
+
+impl StreamedImage {
+    /// Whether the sample is done, so no later image of it will follow
+    pub fn is_final(&self) -> bool {
+        !self.image.finish_reason.is_empty()
+    }
+}
+
+/// The images of a generation, in the order the gateway sends them
+#[derive(Debug)]
+pub struct ImageStream {
+    engine: String,
+    answers: Streaming<proto::Answer>,
+    pending: VecDeque<StreamedImage>,
+}
+
+impl ImageStream {
+    /// The next image, or `None` once the generation is done
+    pub async fn next(&mut self) -> Option<Result<StreamedImage>> {
+        loop {
+            if let Some(image) = self.pending.pop_front() {
+                return Some(Ok(image));
+            }
+            match self.answers.message().await {
+                Ok(Some(answer)) => self.pending.extend(streamed_images(answer)),
+                Ok(None) => return None,
+                Err(status) => return Some(Err(Box::new(status))),
+            }
+        }
+    }
+
+    /// Wait for the generation to finish, keeping the final images only, as
+    /// the REST endpoints would have answered
+    pub async fn finish(mut self) -> Result<ImageResponse> {
+        let mut artifacts = Vec::new();
+        while let Some(streamed) = self.next().await {
+            let streamed = streamed?;
+            if streamed.is_final() {
+                artifacts.push(streamed.image);
+            }
+        }
+        Ok(ImageResponse {
+            artifacts,
+            metadata: GenerationMetadata {
+                engine: Some(self.engine),
+                ..Default::default()
+            },
+        })
+    }
+}
+
+/// The images among the artifacts of `answer`
+fn streamed_images(answer: proto::Answer) -> impl Iterator<Item = StreamedImage> {
+    answer
+        .artifacts
+        .into_iter()
+        .filter(|artifact| artifact.r#type == ArtifactType::Image as i32)
+        .map(|artifact| StreamedImage {
+            index: artifact.index,
+            image: Image {
+                base64: general_purpose::STANDARD.encode(&artifact.binary),
+                finish_reason: finish_reason(artifact.finish_reason).to_string(),
+                seed: artifact.seed,
+            },
+        })
+}
+
+/// The REST API's name for a finish reason, empty while still generating
+fn finish_reason(reason: i32) -> &'static str {
+    match FinishReason::try_from(reason) {
+        Ok(FinishReason::Null) => "",
+        Ok(FinishReason::Stop | FinishReason::Length) => "SUCCESS",
+        Ok(FinishReason::Filter) => "CONTENT_FILTERED",
+        Ok(FinishReason::Error) | Err(_) => "ERROR",
+    }
+}
+
+fn text_to_image_request(engine: &str, request: &TextToImage) -> proto::Request {
+    let image = proto::ImageParameters {
+        height: Some(request.height.into()),
+        width: Some(request.width.into()),
+        seed: seeds(request.seed),
+        samples: Some(request.samples.into()),
+        steps: Some(request.steps.into()),
+        transform: transform(&request.sampler),
+        parameters: vec![step_parameter(
+            request.cfg_scale,
+            &request.clip_guidance_preset,
+            None,
+        )],
+    };
+    proto::Request {
+        engine_id: engine.to_string(),
+        requested_type: ArtifactType::Image as i32,
+        prompt: prompts(&request.text_prompts),
+        image: Some(image),
+        extras: Some(proto::style_preset_extras(&request.style_preset.to_string())),
+        ..Default::default()
+    }
+}
+
+/// The request for `request`, the size of the output taken from `init`
+#[cfg(feature = "image-to-image")]
+fn image_to_image_request(
+    engine: &str,
+    request: &ImageToImage,
+    init: proto::Artifact,
+) -> Result<proto::Request> {
+    // the diffusion starts part way, so more strength keeps more of the image
+    let start = match request.init_image_mode {
+        ImageMode::ImageStrength => 1.0 - request.image_strength,
+        ImageMode::StepSchedule => {
+            return Err(Box::new(Error::UnsupportedOverGrpc("the step schedule init image mode")))
+        }
+    };
+    let image = proto::ImageParameters {
+        seed: seeds(request.seed),
+        samples: Some(request.samples.into()),
+        steps: Some(request.steps.into()),
+        transform: transform(&request.sampler),
+        parameters: vec![step_parameter(
+            request.cfg_scale,
+            &request.clip_guidance_preset,
+            Some(start),
+        )],
+        ..Default::default()
+    };
+    let mut prompt = prompts(&request.text_prompts);
+    prompt.push(proto::Prompt {
+        parameters: Some(proto::PromptParameters {
+            init: Some(true),
+            weight: None,
+        }),
+        prompt: Some(prompt::Prompt::Artifact(init)),
+    });
+    Ok(proto::Request {
+        engine_id: engine.to_string(),
+        requested_type: ArtifactType::Image as i32,
+        prompt,
+        image: Some(image),
+        extras: Some(proto::style_preset_extras(&request.style_preset.to_string())),
+        ..Default::default()
+    })
+}
+
+/// The init image at `path`, prepared as the REST upload would be
+#[cfg(feature = "image-to-image")]
+async fn init_image(path: &Path, options: &UploadOptions) -> Result<proto::Artifact> {
+    let bytes = tokio::fs::read(path).await?;
+
+    #[cfg(feature = "image")]
+    if options.exif_orientation {
+        if let Some(png) = upload::oriented_png(&bytes)? {
+            return Ok(image_artifact("image/png".to_string(), png));
+        }
+    }
+
+    let mime = match path.extension() {
+        Some(extension) => format!("image/{}", extension.to_string_lossy().to_lowercase()),
+        None => "image/png".to_string(),
+    };
+    if options.strip_metadata {
+        if let Some(stripped) = upload::stripped(&bytes) {
+            return Ok(image_artifact(mime, stripped));
+        }
+    }
+    Ok(image_artifact(mime, bytes))
+}
+
+#[cfg(feature = "image-to-image")]
+fn image_artifact(mime: String, bytes: Vec<u8>) -> proto::Artifact {
+    proto::Artifact {
+        r#type: ArtifactType::Image as i32,
+        mime,
+        binary: bytes.into(),
+        ..Default::default()
+    }
+}
+
+fn prompts(text_prompts: &[TextPrompt]) -> Vec<proto::Prompt> {
+    text_prompts
+        .iter()
+        .map(|text_prompt| proto::Prompt {
+            parameters: Some(proto::PromptParameters {
+                init: None,
+                weight: Some(text_prompt.weight),
+            }),
+            prompt: Some(prompt::Prompt::Text(text_prompt.text.clone())),
+        })
+        .collect()
+}
+
+/// No seed asks the gateway to pick one
+fn seeds(seed: Seed) -> Vec<u32> {
+    match seed {
+        Seed::Random => Vec::new(),
+        Seed::Fixed(seed) => vec![seed],
+    }
+}
+
+fn transform(sampler: &Sampler) -> Option<proto::TransformParameters> {
+    let diffusion = match sampler {
+        Sampler::Ddim => DiffusionSampler::Ddim,
+        Sampler::Ddpm => DiffusionSampler::Ddpm,
+        Sampler::KDpmpp2m => DiffusionSampler::KDpmpp2m,
+        Sampler::KDpmpp2sAncestral => DiffusionSampler::KDpmpp2sAncestral,
+        Sampler::KDpm2 => DiffusionSampler::KDpm2,
+        Sampler::KDpm2Ancestral => DiffusionSampler::KDpm2Ancestral,
+        Sampler::KEuler => DiffusionSampler::KEuler,
+        Sampler::KEAncestral => DiffusionSampler::KEulerAncestral,
+        Sampler::KHeun => DiffusionSampler::KHeun,
+        Sampler::KLms => DiffusionSampler::KLms,
+        Sampler::None => return None,
+    };
+    Some(proto::TransformParameters {
+        diffusion: Some(diffusion as i32),
+    })
+}
+
+fn step_parameter(
+    cfg_scale: u32,
+    clip_guidance_preset: &ClipGuidancePreset,
+    start: Option<f32>,
+) -> proto::StepParameter {
+    let guidance_preset = match clip_guidance_preset {
+        ClipGuidancePreset::FastBlue => GuidancePreset::FastBlue,
+        ClipGuidancePreset::FastGreen => GuidancePreset::FastGreen,
+        ClipGuidancePreset::Simple => GuidancePreset::Simple,
+        ClipGuidancePreset::Slow => GuidancePreset::Slow,
+        ClipGuidancePreset::Slower => GuidancePreset::Slower,
+        ClipGuidancePreset::Slowest => GuidancePreset::Slowest,
+        ClipGuidancePreset::None => GuidancePreset::None,
+    };
+    proto::StepParameter {
+        scaled_step: 0.0,
+        sampler: Some(proto::SamplerParameters {
+            cfg_scale: Some(cfg_scale as f32),
+        }),
+        schedule: start.map(|start| proto::ScheduleParameters {
+            start: Some(start),
+            end: Some(0.01),
+        }),
+        guidance: Some(proto::GuidanceParameters {
+            guidance_preset: guidance_preset as i32,
+        }),
+    }
+}
@@ -0,0 +1,220 @@
+//! The messages of the `gooseai.GenerationService` the gateway serves.
+//!
+//! Written out by hand from the `generation.proto` of the `stability-sdk`
+//! repository, keeping only the fields this crate sends and reads; prost
+//! skips the others when decoding.
+
+use std::collections::HashMap;
+
+/// The path of the server-streaming `Generate` method
+pub(crate) const GENERATE_PATH: &str = "/gooseai.GenerationService/Generate";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+#[repr(i32)]
+pub(crate) enum FinishReason {
+    /// Still being generated
+    Null = 0,
+    Length = 1,
+    Stop = 2,
+    Error = 3,
+    Filter = 4,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+#[repr(i32)]
+pub(crate) enum ArtifactType {
+    None = 0,
+    Image = 1,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+#[repr(i32)]
+pub(crate) enum DiffusionSampler {
+    Ddim = 0,
+    Ddpm = 1,
+    KEuler = 2,
+    KEulerAncestral = 3,
+    KHeun = 4,
+    KDpm2 = 5,
+    KDpm2Ancestral = 6,
+    KLms = 7,
+    KDpmpp2sAncestral = 8,
+    KDpmpp2m = 9,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+#[repr(i32)]
+pub(crate) enum GuidancePreset {
+    None = 0,
+    Simple = 1,
+    FastBlue = 2,
+    FastGreen = 3,
+    Slow = 4,
+    Slower = 5,
+    Slowest = 6,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct Artifact {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(enumeration = "ArtifactType", tag = "2")]
+    pub r#type: i32,
+    #[prost(string, tag = "3")]
+    pub mime: String,
+    #[prost(bytes = "bytes", tag = "5")]
+    pub binary: bytes::Bytes,
+    #[prost(uint32, tag = "8")]
+    pub index: u32,
+    #[prost(enumeration = "FinishReason", tag = "9")]
+    pub finish_reason: i32,
+    #[prost(uint32, tag = "10")]
+    pub seed: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct PromptParameters {
+    #[prost(bool, optional, tag = "1")]
+    pub init: Option<bool>,
+    #[prost(float, optional, tag = "2")]
+    pub weight: Option<f32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct Prompt {
+    #[prost(message, optional, tag = "1")]
+    pub parameters: Option<PromptParameters>,
+    #[prost(oneof = "prompt::Prompt", tags = "2, 4")]
+    pub prompt: Option<prompt::Prompt>,
+}
+
+pub(crate) mod prompt {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub(crate) enum Prompt {
+        #[prost(string, tag = "2")]
+        Text(String),
+        #[prost(message, tag = "4")]
+        Artifact(super::Artifact),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct SamplerParameters {
+    #[prost(float, optional, tag = "5")]
+    pub cfg_scale: Option<f32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct ScheduleParameters {
+    #[prost(float, optional, tag = "1")]
+    pub start: Option<f32>,
+    #[prost(float, optional, tag = "2")]
+    pub end: Option<f32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct GuidanceParameters {
+    #[prost(enumeration = "GuidancePreset", tag = "1")]
+    pub guidance_preset: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct StepParameter {
+    #[prost(float, tag = "1")]
+    pub scaled_step: f32,
+    #[prost(message, optional, tag = "2")]
+    pub sampler: Option<SamplerParameters>,
+    #[prost(message, optional, tag = "3")]
+    pub schedule: Option<ScheduleParameters>,
+    #[prost(message, optional, tag = "4")]
+    pub guidance: Option<GuidanceParameters>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct TransformParameters {
+    #[prost(enumeration = "DiffusionSampler", optional, tag = "1")]
+    pub diffusion: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct ImageParameters {
+    #[prost(uint64, optional, tag = "1")]
+    pub height: Option<u64>,
+    #[prost(uint64, optional, tag = "2")]
+    pub width: Option<u64>,
+    #[prost(uint32, repeated, tag = "3")]
+    pub seed: Vec<u32>,
+    #[prost(uint64, optional, tag = "4")]
+    pub samples: Option<u64>,
+    #[prost(uint64, optional, tag = "5")]
+    pub steps: Option<u64>,
+    #[prost(message, optional, tag = "6")]
+    pub transform: Option<TransformParameters>,
+    #[prost(message, repeated, tag = "7")]
+    pub parameters: Vec<StepParameter>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct Request {
+    #[prost(string, tag = "1")]
+    pub engine_id: String,
+    #[prost(string, tag = "2")]
+    pub request_id: String,
+    #[prost(enumeration = "ArtifactType", tag = "3")]
+    pub requested_type: i32,
+    #[prost(message, repeated, tag = "4")]
+    pub prompt: Vec<Prompt>,
+    #[prost(message, optional, tag = "5")]
+    pub image: Option<ImageParameters>,
+    /// Carries the style preset
+    #[prost(message, optional, tag = "2047")]
+    pub extras: Option<Struct>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct Answer {
+    #[prost(string, tag = "1")]
+    pub answer_id: String,
+    #[prost(string, tag = "2")]
+    pub request_id: String,
+    #[prost(message, repeated, tag = "7")]
+    pub artifacts: Vec<Artifact>,
+}
+
+/// A `google.protobuf.Struct`, with the only kinds of value sent here
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct Struct {
+    #[prost(map = "string, message", tag = "1")]
+    pub fields: HashMap<String, Value>,
+}
+
+/// A `google.protobuf.Value`
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct Value {
+    #[prost(oneof = "value::Kind", tags = "3, 5")]
+    pub kind: Option<value::Kind>,
+}
+
+pub(crate) mod value {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub(crate) enum Kind {
+        #[prost(string, tag = "3")]
+        StringValue(String),
+        #[prost(message, tag = "5")]
+        StructValue(super::Struct),
+    }
+}
+
+/// `extras` asking for `preset`, as the gateway reads style presets
+pub(crate) fn style_preset_extras(preset: &str) -> Struct {
+    let value = |kind| Value { kind: Some(kind) };
+    let ipc = Struct {
+        fields: HashMap::from([(
+            "preset".to_string(),
+            value(value::Kind::StringValue(preset.to_string())),
+        )]),
+    };
+    Struct {
+        fields: HashMap::from([("$IPC".to_string(), value(value::Kind::StructValue(ipc)))]),
+    }
+}
@@ -1,2 +1,3 @@
+#[cfg(feature = "grpc")]
 pub mod grpc;
 pub mod rest;
@@ -0,0 +1,395 @@
+use crate::api::rest::generation::Image;
+use crate::atomic_write::{self, OverwritePolicy, TempPath};
+use crate::error::Error;
+pub use crate::model::ArtifactKind;
+use crate::prelude::*;
+use base64::{engine::general_purpose, Engine as _};
+use hyper::body::Bytes;
+use tokio::io::AsyncWriteExt;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kind_is_derived_from_content_type() {
+        assert_eq!(ArtifactKind::from_content_type("image/png"), Some(ArtifactKind::Image));
+        assert_eq!(ArtifactKind::from_content_type("video/mp4"), Some(ArtifactKind::Video));
+        assert_eq!(
+            ArtifactKind::from_content_type("model/gltf-binary"),
+            Some(ArtifactKind::Model3D)
+        );
+        assert_eq!(
+            ArtifactKind::from_content_type("audio/mpeg; charset=binary"),
+            Some(ArtifactKind::Audio)
+        );
+        assert_eq!(ArtifactKind::from_content_type("application/json"), None);
+    }
+
+    #[test]
+    fn expect_is_erring_on_a_different_kind() {
+        let err = Artifact::expect(ArtifactKind::Video, "image/png", Bytes::new()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected a Video artifact, but content type was image/png"
+        );
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn decode_is_reading_png_dimensions() {
+        let artifact = Artifact::new(IMAGE_PNG, crate::testing::png_1x1().into()).unwrap();
+        let image = artifact.decode().unwrap();
+        assert_eq!((image.width(), image.height()), (1, 1));
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn to_rgba8_is_holding_four_bytes_a_pixel() {
+        let rgba = crate::testing::image(1, "SUCCESS").to_rgba8().unwrap();
+
+        assert_eq!((rgba.width, rgba.height), (1, 1));
+        assert_eq!(rgba.pixels.len(), 4);
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn to_array_is_shaped_height_width_channels() {
+        let image = crate::testing::image(1, "SUCCESS");
+        let bytes = image.to_array().unwrap();
+        let scaled = image.to_array_f32().unwrap();
+
+        assert_eq!(bytes.dim(), (1, 1, 3));
+        assert_eq!(scaled[[0, 0, 0]], bytes[[0, 0, 0]] as f32 / 255.0);
+    }
+
+    #[tokio::test]
+    async fn save_is_creating_missing_parent_directories() {
+        let dir = std::env::temp_dir().join(format!("stability_rs_save_{}", std::process::id()));
+        let path = dir.join("a/b/image.png");
+        let artifact = Artifact::new(IMAGE_PNG, crate::testing::png_1x1().into()).unwrap();
+
+        artifact.save(&path).await.unwrap();
+
+        assert!(path.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn write_base64_is_matching_a_whole_decode() {
+        let path = std::env::temp_dir()
+            .join(format!("stability_rs_base64_{}.png", std::process::id()));
+        let encoded = general_purpose::STANDARD.encode(crate::testing::png_1x1());
+
+        let file = tokio::fs::File::create(&path).await.unwrap();
+        write_base64(file, encoded.as_bytes(), 8).await.unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), crate::testing::png_1x1());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn save_base64_is_removing_the_file_when_decoding_fails() {
+        let path = std::env::temp_dir()
+            .join(format!("stability_rs_bad_base64_{}.png", std::process::id()));
+
+        assert!(save_base64("AAAA!!!!", &path, OverwritePolicy::Overwrite).await.is_err());
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn save_as_is_correcting_the_extension() {
+        let dir = std::env::temp_dir().join(format!("stability_rs_save_as_{}", std::process::id()));
+        let artifact = Artifact::new("image/jpeg", Bytes::from_static(b"jpeg")).unwrap();
+
+        let path = artifact.save_as(dir.join("edit.png")).await.unwrap();
+
+        assert_eq!(path, dir.join("edit.jpg"));
+        assert!(path.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn data_uri_is_matching_for_images_and_artifacts() {
+        let image = Image {
+            base64: general_purpose::STANDARD.encode(crate::testing::png_1x1()),
+            finish_reason: "SUCCESS".to_string(),
+            seed: 1,
+        };
+        let uri = image.to_data_uri();
+
+        assert!(uri.starts_with("data:image/png;base64,iVBOR"));
+        assert_eq!(uri, image.to_artifact().unwrap().to_data_uri());
+    }
+
+    #[test]
+    fn extension_is_following_the_content_type() {
+        let artifact = Artifact::new("model/gltf-binary", Bytes::new()).unwrap();
+        assert_eq!(artifact.extension(), "glb");
+    }
+}
+
+/// A generated asset of any modality, held as raw bytes with its content type
+#[derive(Debug, Clone, PartialEq)]
+pub struct Artifact {
+    kind: ArtifactKind,
+    content_type: String,
+    bytes: Bytes,
+}
+
+impl Artifact {
+    pub fn new(content_type: &str, bytes: Bytes) -> Result<Self> {
+        let Some(kind) = ArtifactKind::from_content_type(content_type) else {
+            return Err(Box::new(Error::UnsupportedContentType(
+                content_type.to_string(),
+            )));
+        };
+
+        Ok(Self {
+            kind,
+            content_type: essence(content_type).to_string(),
+            bytes,
+        })
+    }
+
+    /// Like [`Artifact::new`], but errs unless the content type is of `kind`
+    pub fn expect(kind: ArtifactKind, content_type: &str, bytes: Bytes) -> Result<Self> {
+        let artifact = Self::new(content_type, bytes)?;
+        if artifact.kind != kind {
+            return Err(Box::new(Error::UnexpectedContentType {
+                expected: kind,
+                found: artifact.content_type,
+            }));
+        }
+        Ok(artifact)
+    }
+
+    pub fn kind(&self) -> ArtifactKind {
+        self.kind
+    }
+
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    pub fn bytes(&self) -> &Bytes {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        self.bytes
+    }
+
+    /// The file extension matching the content type, without a leading dot
+    pub fn extension(&self) -> &str {
+        match self.content_type.as_str() {
+            "image/jpeg" => "jpg",
+            "image/svg+xml" => "svg",
+            "model/gltf-binary" => "glb",
+            "model/gltf+json" => "gltf",
+            "audio/mpeg" => "mp3",
+            "video/quicktime" => "mov",
+            other => other.split_once('/').map(|(_, sub)| sub).unwrap_or("bin"),
+        }
+    }
+
+    /// Write the artifact to `path`, creating missing parent directories
+    ///
+    /// The file is written [atomically](crate::atomic_write), so `path` never
+    /// holds part of an artifact.
+    pub async fn save(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        self.save_with_policy(path, OverwritePolicy::Overwrite).await?;
+        Ok(())
+    }
+
+    /// Like [`Artifact::save`], doing as `policy` says when a file exists at
+    /// `path`, and return the path written
+    ///
+    /// The file is encrypted when an
+    /// [encryption key](crate::encryption::install) is installed.
+    pub async fn save_with_policy(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        policy: OverwritePolicy,
+    ) -> Result<std::path::PathBuf> {
+        #[cfg(feature = "encryption")]
+        if let Some(key) = crate::encryption::installed() {
+            let path = path.as_ref();
+            crate::preflight::create_parent_dirs(path)?;
+            return atomic_write::write_async(path, &key.encrypt(&self.bytes), policy).await;
+        }
+        self.save_unencrypted(path, policy).await
+    }
+
+    /// Like [`Artifact::save_with_policy`], never encrypting, for files
+    /// which are sent back to the API
+    pub(crate) async fn save_unencrypted(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        policy: OverwritePolicy,
+    ) -> Result<std::path::PathBuf> {
+        let path = path.as_ref();
+        crate::preflight::create_parent_dirs(path)?;
+        atomic_write::write_async(path, &self.bytes, policy).await
+    }
+
+    /// Write the artifact to `path` with the extension of its content type,
+    /// replacing any extension `path` has, and return the path written
+    ///
+    /// Endpoints asked for `image/*` pick the format themselves, so saving
+    /// under an assumed `.png` can mislabel the file.
+    pub async fn save_as(&self, path: impl AsRef<std::path::Path>) -> Result<std::path::PathBuf> {
+        let path = path.as_ref().with_extension(self.extension());
+        self.save(&path).await?;
+        Ok(path)
+    }
+
+    /// The artifact as a `data:` URI, for embedding into HTML or JSON
+    pub fn to_data_uri(&self) -> String {
+        format!(
+            "data:{};base64,{}",
+            self.content_type,
+            general_purpose::STANDARD.encode(&self.bytes)
+        )
+    }
+
+    /// Decode an image artifact into pixels
+    #[cfg(feature = "image")]
+    pub fn decode(&self) -> Result<image::DynamicImage> {
+        if self.kind != ArtifactKind::Image {
+            return Err(Box::new(Error::UnexpectedContentType {
+                expected: ArtifactKind::Image,
+                found: self.content_type.clone(),
+            }));
+        }
+        let format = image::ImageFormat::from_mime_type(&self.content_type)
+            .ok_or_else(|| Error::UnsupportedContentType(self.content_type.clone()))?;
+        Ok(image::load_from_memory_with_format(&self.bytes, format)?)
+    }
+
+    /// Decode an image artifact into 8-bit RGBA pixels, ready to upload
+    /// into a GUI texture
+    #[cfg(feature = "image")]
+    pub fn to_rgba8(&self) -> Result<Rgba8> {
+        let rgba = self.decode()?.into_rgba8();
+        let (width, height) = rgba.dimensions();
+        Ok(Rgba8 {
+            width,
+            height,
+            pixels: rgba.into_raw(),
+        })
+    }
+
+    /// Decode an image artifact into a height x width x 3 array of its RGB
+    /// values, dropping any alpha channel
+    ///
+    /// The array comes straight from the decoded pixels, so classifiers and
+    /// scorers can read outputs without writing them to disk first.
+    #[cfg(feature = "ndarray")]
+    pub fn to_array(&self) -> Result<ndarray::Array3<u8>> {
+        let rgb = self.decode()?.into_rgb8();
+        let (width, height) = rgb.dimensions();
+        let shape = (height as usize, width as usize, 3);
+        Ok(ndarray::Array3::from_shape_vec(shape, rgb.into_raw())?)
+    }
+
+    /// Like [`Artifact::to_array`], with values scaled from 0.0 to 1.0 as
+    /// models usually take them
+    #[cfg(feature = "ndarray")]
+    pub fn to_array_f32(&self) -> Result<ndarray::Array3<f32>> {
+        Ok(self.to_array()?.mapv(|value| value as f32 / 255.0))
+    }
+}
+
+/// Decoded pixels, four bytes per pixel in red, green, blue, alpha order,
+/// row by row from the top left, with straight (not premultiplied) alpha
+///
+/// The layout GUI toolkits take for textures, such as egui's
+/// `ColorImage::from_rgba_unmultiplied([width, height], &pixels)`.
+#[cfg(feature = "image")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rgba8 {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl TryFrom<&Image> for Artifact {
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn try_from(image: &Image) -> Result<Self> {
+        let bytes = general_purpose::STANDARD.decode(&image.base64)?;
+        Artifact::new(IMAGE_PNG, Bytes::from(bytes))
+    }
+}
+
+impl Image {
+    /// Decode the image into an [`Artifact`]
+    pub fn to_artifact(&self) -> Result<Artifact> {
+        Artifact::try_from(self)
+    }
+
+    /// The image as a `data:image/png;base64,...` URI, reusing the payload
+    /// the API already encoded
+    pub fn to_data_uri(&self) -> String {
+        format!("data:{IMAGE_PNG};base64,{}", self.base64)
+    }
+
+    /// The image as 8-bit RGBA pixels, see [`Artifact::to_rgba8`]
+    #[cfg(feature = "image")]
+    pub fn to_rgba8(&self) -> Result<Rgba8> {
+        self.to_artifact()?.to_rgba8()
+    }
+
+    /// The image as a height x width x 3 array of RGB values, see
+    /// [`Artifact::to_array`]
+    #[cfg(feature = "ndarray")]
+    pub fn to_array(&self) -> Result<ndarray::Array3<u8>> {
+        self.to_artifact()?.to_array()
+    }
+
+    /// The image as a height x width x 3 array of RGB values from 0.0 to 1.0
+    #[cfg(feature = "ndarray")]
+    pub fn to_array_f32(&self) -> Result<ndarray::Array3<f32>> {
+        self.to_artifact()?.to_array_f32()
+    }
+}
+
+/// Base64 payloads longer than this are decoded in chunks as they are saved,
+/// rather than into one buffer first
+pub const STREAMING_DECODE_THRESHOLD: usize = 8 * 1024 * 1024;
+
+/// Base64 characters decoded at a time, a multiple of 4 so only the last
+/// chunk can carry padding
+const DECODE_CHUNK: usize = 64 * 1024;
+
+/// Decode `encoded` into `path` chunk by chunk, creating missing parent
+/// directories and leaving `path` untouched if decoding fails
+pub(crate) async fn save_base64(
+    encoded: &str,
+    path: &std::path::Path,
+    policy: OverwritePolicy,
+) -> Result<std::path::PathBuf> {
+    crate::preflight::create_parent_dirs(path)?;
+    let temp = TempPath::new(path);
+    let file = tokio::fs::File::create(temp.path()).await?;
+    write_base64(file, encoded.as_bytes(), DECODE_CHUNK).await?;
+    temp.commit_with(policy)
+}
+
+async fn write_base64(file: tokio::fs::File, encoded: &[u8], chunk: usize) -> Result<()> {
+    let mut writer = tokio::io::BufWriter::new(file);
+    let mut decoded = Vec::with_capacity(chunk / 4 * 3);
+    for part in encoded.chunks(chunk) {
+        decoded.clear();
+        general_purpose::STANDARD.decode_vec(part, &mut decoded)?;
+        writer.write_all(&decoded).await?;
+    }
+    writer.flush().await?;
+    Ok(())
+}
+
+/// The content type without parameters such as `; charset=binary`
+fn essence(content_type: &str) -> &str {
+    content_type.split(';').next().unwrap_or_default().trim()
+}
@@ -1,4 +1,20 @@
+use crate::api::rest::artifact::Artifact;
+use crate::api::rest::connect;
+use crate::api::rest::exchange::{connect_tls, exchange};
+use crate::audit::{self, AuditSink};
+use crate::cache::{self, MetadataCache};
+use crate::circuit::{self, CircuitBreaker};
+use crate::download_progress::{self, DownloadProgress};
 use crate::error::{ApiResponseError, Error};
+use crate::lifecycle::InFlight;
+use crate::limiter;
+use crate::model::ResponseTiming;
+use crate::resolver::{self, Resolver};
+use crate::signing;
+use crate::tenancy;
+use crate::upload_progress::{self, ProgressBody, UploadProgress};
+pub use crate::api::rest::version::ApiVersion;
+use sha2::{Digest, Sha256};
 use crate::prelude::*;
 use crate::support::*;
 pub use http_body_util::{BodyExt, Empty, Full};
@@ -6,27 +22,161 @@ pub use hyper::{
     body::{Body, Bytes},
     client::conn::http1::handshake,
     header::{HeaderMap, HeaderName, HeaderValue},
-    Method, Request, Uri,
+    Method, Request, Response, Uri,
 };
 pub use serde::{Deserialize, Serialize};
+pub use futures_util::future::BoxFuture;
 use std::env;
+use std::future::Future;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 pub use tokio::{
     io::{AsyncWriteExt, BufWriter},
     net::TcpStream,
 };
 
 const BASE_URL: &str = "https://api.stability.ai";
-const V1_PATH: &str = "/v1";
 const AUTHORIZATION_HEADER: &str = "authorization";
+const ORGANIZATION_HEADER: &str = "organization";
+
+/// How many times a download failing verification is requested
+const DOWNLOAD_ATTEMPTS: usize = 3;
+
+/// Generous for ten 1024x1024 PNGs, base64 encoded
+const DEFAULT_MAX_RESPONSE_SIZE: usize = 128 * 1024 * 1024;
+
+static MAX_RESPONSE_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_RESPONSE_SIZE);
+
+/// Sent as the User-Agent, ahead of any application product token
+const DEFAULT_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+static USER_AGENT_PRODUCT: RwLock<Option<String>> = RwLock::new(None);
+
+static BASE_URL_OVERRIDE: RwLock<Option<BaseUrl>> = RwLock::new(None);
 
 static HOST: &str = "host";
 static AUTHORITY: &str = "api.stability.ai";
 
-#[derive(Debug)]
+/// Cap the size of response bodies, in bytes, for clients which do not set
+/// their own with [`ClientBuilder::max_response_size`]
+///
+/// A larger response is abandoned with [`Error::ResponseTooLarge`] rather
+/// than buffered. Defaults to 128 MiB.
+pub fn set_max_response_size(bytes: usize) {
+    MAX_RESPONSE_SIZE.store(bytes, Ordering::Relaxed);
+}
+
+pub fn max_response_size() -> usize {
+    MAX_RESPONSE_SIZE.load(Ordering::Relaxed)
+}
+
+/// Append a product token such as `my-app/1.2` to the User-Agent of clients
+/// which do not set their own with [`ClientBuilder::user_agent_product`]
+pub fn set_user_agent_product(product: &str) -> Result<()> {
+    product.parse::<HeaderValue>()?;
+    *USER_AGENT_PRODUCT.write().unwrap() = Some(product.to_string());
+    Ok(())
+}
+
+pub fn reset_user_agent_product() {
+    *USER_AGENT_PRODUCT.write().unwrap() = None;
+}
+
+/// Where requests are sent, see [`set_base_url`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum BaseUrl {
+    /// The API host over TLS
+    #[default]
+    Api,
+    /// Plain HTTP to `host:port`, such as a local mock or proxy
+    Http { host: String, port: u16 },
+    /// Plain HTTP over the Unix socket at the path
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl FromStr for BaseUrl {
+    type Err = Box<dyn std::error::Error + Send + Sync>;
+
+    /// Parse `https://api.stability.ai`, `http://host:port` or
+    /// `unix:/path/to.sock`
+    fn from_str(url: &str) -> Result<Self> {
+        let unsupported = || Box::new(Error::UnsupportedBaseUrl(url.to_string()));
+        #[cfg(unix)]
+        if let Some(path) = url.strip_prefix("unix:") {
+            // unix:///tmp/x.sock is read the same as unix:/tmp/x.sock
+            let path = path.strip_prefix("//").unwrap_or(path);
+            return Ok(BaseUrl::Unix(path.into()));
+        }
+        if url.trim_end_matches('/') == BASE_URL {
+            return Ok(BaseUrl::Api);
+        }
+
+        let uri = url.parse::<Uri>().map_err(|_| unsupported())?;
+        match (uri.scheme_str(), uri.host()) {
+            (Some("http"), Some(host)) if uri.path() == "/" || uri.path().is_empty() => {
+                Ok(BaseUrl::Http {
+                    host: host.trim_matches(|c| c == '[' || c == ']').to_string(),
+                    port: uri.port_u16().unwrap_or(80),
+                })
+            }
+            _ => Err(unsupported()),
+        }
+    }
+}
+
+/// Send the requests of clients which do not set their own with
+/// [`ClientBuilder::base_url`] to `url` rather than the API
+///
+/// Meant for tests against a local mock without setting up TLS: `url` is
+/// `https://api.stability.ai`, an `http://host:port` address or, on Unix,
+/// a `unix:/path/to.sock` socket. Requests keep their path and `host`
+/// header, and while a base URL is set clients are built without an API key
+/// when `STABILITY_API_KEY` is unset.
+pub fn set_base_url(url: &str) -> Result<()> {
+    *BASE_URL_OVERRIDE.write().unwrap() = Some(url.parse()?);
+    Ok(())
+}
+
+pub fn reset_base_url() {
+    *BASE_URL_OVERRIDE.write().unwrap() = None;
+}
+
+fn base_url_overridden() -> bool {
+    BASE_URL_OVERRIDE.read().unwrap().is_some()
+}
+
+/// The User-Agent sent with `product`, or with the one set by
+/// [`set_user_agent_product`]
+fn user_agent(product: Option<&str>) -> String {
+    let installed = USER_AGENT_PRODUCT.read().unwrap();
+    match product.or(installed.as_deref()) {
+        Some(product) => format!("{} {}", DEFAULT_USER_AGENT, product),
+        None => DEFAULT_USER_AGENT.to_string(),
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Client {
     pub url: Uri,
     pub method: Method,
     pub headers: HeaderMap,
+    /// Overrides [`max_response_size`] for this client
+    pub max_response_size: Option<usize>,
+    /// Overrides the installed [`audit`] sink for this client
+    pub audit: Option<Arc<dyn AuditSink>>,
+    /// Overrides the installed [`circuit`] breaker for this client
+    pub circuit_breaker: Option<CircuitBreaker>,
+    /// Overrides the installed metadata [`cache`] for this client
+    pub cache: Option<MetadataCache>,
+    /// Overrides the installed [`resolver`] for this client
+    pub resolver: Option<Resolver>,
+    /// Overrides the base URL set by [`set_base_url`] for this client
+    pub base_url: Option<BaseUrl>,
 }
 
 impl Client {
@@ -55,68 +205,460 @@ impl Client {
         T::Data: Send,
         T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
     {
-        let req = self.build_request(body)?;
-        let stream = TcpStream::connect(self.format_address()).await?;
-        let tls_stream = async_native_tls::connect(self.url.host().unwrap(), stream).await?;
-        let io = TokioIo::new(tls_stream);
-        let (mut sender, conn) = handshake(io).await?;
-        tokio::task::spawn(async move {
-            if let Err(e) = conn.await {
-                eprintln!("connection error: {}", e);
-            }
-        });
+        let (_, bytes) = self.send_request_with_headers(body).await?;
+        Ok(bytes)
+    }
 
-        let mut res = sender.send_request(req).await?;
+    /// Send a request for a binary asset, checking the response content type
+    ///
+    /// The body must match the `content-length` header, and the `etag` header
+    /// when it is a SHA-256; downloads which don't, or are cut off, are
+    /// requested again up to three times in all.
+    pub async fn download_artifact<T: Body + Clone + Send + 'static>(&self, body: T) -> Result<Artifact>
+    where
+        T::Data: Send,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        self.download_verified_artifact(body, None).await
+    }
 
-        if res.status() != 200 {
-            let w = Vec::new();
-            let mut writer = BufWriter::new(w);
-            while let Some(resulting_frame) = res.frame().await {
-                let frame = resulting_frame?;
-                if let Some(chunk) = frame.data_ref() {
-                    writer.write_all(chunk).await?;
+    /// Like [`Client::download_artifact`], also checking the body against a
+    /// known hex SHA-256
+    pub async fn download_verified_artifact<T: Body + Clone + Send + 'static>(
+        &self,
+        body: T,
+        sha256: Option<&str>,
+    ) -> Result<Artifact>
+    where
+        T::Data: Send,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let mut attempt = 1;
+        loop {
+            let result = self
+                .send_request_with_headers(body.clone())
+                .await
+                .and_then(|(headers, bytes)| {
+                    verify_download(&headers, &bytes, sha256)?;
+                    Ok((headers, bytes))
+                });
+            let (headers, bytes) = match result {
+                Ok(download) => download,
+                Err(e) if attempt < DOWNLOAD_ATTEMPTS && is_corrupt_download(&*e) => {
+                    attempt += 1;
+                    continue;
                 }
-                writer.flush().await?;
+                Err(e) => return Err(e),
+            };
+
+            let content_type = headers
+                .get(CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default();
+            return Artifact::new(content_type, bytes);
+        }
+    }
+
+    /// Send a request, returning the response headers alongside the body
+    pub async fn send_request_with_headers<T: Body + Send + 'static>(
+        &self,
+        body: T,
+    ) -> Result<(HeaderMap, Bytes)>
+    where
+        T::Data: Send,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let (headers, bytes, _) = self.send_request_timed(body).await?;
+        Ok((headers, bytes))
+    }
+
+    /// Send a request, returning the response headers and body alongside
+    /// how long each phase of the exchange took
+    pub async fn send_request_timed<T: Body + Send + 'static>(
+        &self,
+        body: T,
+    ) -> Result<(HeaderMap, Bytes, ResponseTiming)>
+    where
+        T::Data: Send,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let _in_flight = InFlight::enter()?;
+        let breaker = self.circuit_breaker.clone().or_else(circuit::installed);
+        if let Some(breaker) = &breaker {
+            breaker.check()?;
+        }
+        let tenant = tenancy::current_tenant().zip(tenancy::installed());
+        if let Some((tenant, tracker)) = &tenant {
+            tracker.check(tenant)?;
+        }
+        let req = self.build_request(body)?;
+        // a slot is taken before pacing, so waiting for it doesn't use up
+        // the limiter's start interval
+        let _slot = match limiter::ConcurrencyLimit::installed() {
+            Some(concurrency) => Some(concurrency.acquire().await),
+            None => None,
+        };
+        let permit = match limiter::global() {
+            Some(limiter) => Some(limiter.acquire(limiter::current_priority()).await),
+            None => None,
+        };
+
+        // request bodies are small and fully buffered anyway, and signing
+        // needs every byte
+        let (mut parts, body) = req.into_parts();
+        let body = body.collect().await.map_err(Into::into)?.to_bytes();
+        let cache = self
+            .cache
+            .clone()
+            .or_else(cache::installed)
+            .and_then(|cache| Some((cache::key(&parts)?, cache)));
+        let cached = cache
+            .as_ref()
+            .and_then(|(key, cache)| cache.revalidate(key, &mut parts.headers));
+        signing::sign(&mut parts, &body)?;
+        let req = Request::from_parts(parts, body);
+
+        let sink = self.audit.clone().or_else(audit::installed);
+        let pending = (sink.is_some() || tenant.is_some()).then(|| audit::Pending::new(&req));
+        let res = self.send_buffered(req).await;
+        if let (Some(permit), Ok(res)) = (&permit, &res) {
+            permit.record_status(res.status().as_u16());
+        }
+        if let Some(breaker) = &breaker {
+            breaker.record(res.as_ref().ok().map(|res| res.status().as_u16()));
+        }
+        if let Some(pending) = pending {
+            let record = pending.into_record(res.as_ref().map_err(|e| &**e));
+            if let Some(sink) = sink {
+                let _ = sink.record(&record);
+            }
+            if let Some((tenant, tracker)) = &tenant {
+                let _ = tracker.record(tenant, &record);
             }
+        }
+        let res = res?;
+        let timing = response_timing(&res);
 
-            let err_value = serde_json::from_slice::<ApiResponseError>(&writer.into_inner())?;
+        if let (hyper::StatusCode::NOT_MODIFIED, Some(cached)) = (res.status(), cached) {
+            return Ok((cached.headers, cached.body, timing));
+        }
+        // 202 Accepted is how asynchronous endpoints answer while a result
+        // is still being generated
+        if res.status() != 200 && res.status() != 202 {
+            let err_value = serde_json::from_slice::<ApiResponseError>(res.body())?;
 
             return Err(Box::new(Error::ClientSendRequestError(err_value)));
         }
-        let w = Vec::new();
-        let mut writer = BufWriter::new(w);
-        while let Some(resulting_frame) = res.frame().await {
-            let frame = resulting_frame?;
-            if let Some(chunk) = frame.data_ref() {
-                writer.write_all(chunk).await?;
+
+        let (parts, body) = res.into_parts();
+        if let (hyper::StatusCode::OK, Some((key, cache))) = (parts.status, cache) {
+            cache.store(key, &parts.headers, &body);
+        }
+        Ok((parts.headers, body, timing))
+    }
+
+    async fn send_buffered(&self, req: Request<Bytes>) -> Result<Response<Bytes>> {
+        let limit = self.max_response_size.unwrap_or_else(max_response_size);
+        match TRANSPORT.try_with(|transport| transport.clone()) {
+            Ok(transport) => {
+                // a transport takes the body whole, so report it sent at once
+                if let Some(callback) = upload_progress::current() {
+                    let total = req.body().len() as u64;
+                    callback(UploadProgress { sent: total, total });
+                }
+                let started = Instant::now();
+                let mut res = transport.send(req).await?;
+                // transports wrapping `exchange` have timed it already
+                if res.extensions().get::<Phases>().is_none() {
+                    res.extensions_mut().insert(Phases {
+                        ttfb: started.elapsed(),
+                        ..Phases::default()
+                    });
+                }
+                if res.body().len() > limit {
+                    return Err(Box::new(Error::ResponseTooLarge { limit }));
+                }
+                // and the response received at once
+                if let Some(callback) = download_progress::current() {
+                    let received = res.body().len() as u64;
+                    callback(DownloadProgress {
+                        received,
+                        total: Some(received),
+                    });
+                }
+                Ok(res)
+            }
+            Err(_) => {
+                let callback = upload_progress::current();
+                let req = req.map(|body| ProgressBody::new(body, callback));
+                let base_url = self
+                    .base_url
+                    .clone()
+                    .or_else(|| BASE_URL_OVERRIDE.read().unwrap().clone())
+                    .unwrap_or_default();
+                match base_url {
+                    BaseUrl::Api => {
+                        let host = self.url.host().unwrap();
+                        let connect = async {
+                            let stream = self.dial(host, 443).await?;
+                            connect_tls(host, stream).await
+                        };
+                        self.send_over(&format!("https://{}", host), connect, req, limit)
+                            .await
+                    }
+                    BaseUrl::Http { host, port } => {
+                        let origin = format!("http://{}:{}", host, port);
+                        let connect = self.dial(&host, port);
+                        self.send_over(&origin, connect, origin_form(req)?, limit)
+                            .await
+                    }
+                    #[cfg(unix)]
+                    BaseUrl::Unix(path) => {
+                        let origin = format!("unix:{}", path.display());
+                        let connect = async { Ok(tokio::net::UnixStream::connect(&path).await?) };
+                        self.send_over(&origin, connect, origin_form(req)?, limit)
+                            .await
+                    }
+                }
+            }
+        }
+    }
+
+    /// Send `req` over a connection to `origin` from `connect`, or an idle
+    /// one once the [`global`](crate::global) client is initialized
+    ///
+    /// A client with a [resolver](ClientBuilder::resolver) of its own always
+    /// dials, as an idle connection to the same origin may lead to an
+    /// address its resolver would not have picked.
+    async fn send_over<I, F>(
+        &self,
+        origin: &str,
+        connect: F,
+        req: Request<ProgressBody>,
+        limit: usize,
+    ) -> Result<Response<Bytes>>
+    where
+        I: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+        F: Future<Output = Result<I>>,
+    {
+        if let Some(pool) = crate::global::pool().filter(|_| self.resolver.is_none()) {
+            return pool.send(origin, connect, req, limit).await;
+        }
+        let started = Instant::now();
+        let io = connect.await?;
+        let connected = started.elapsed();
+        let mut res = exchange(TokioIo::new(io), req, limit).await?;
+        if let Some(phases) = res.extensions_mut().get_mut::<Phases>() {
+            phases.connect = Some(connected);
+        }
+        Ok(res)
+    }
+
+    async fn dial(&self, host: &str, port: u16) -> Result<TcpStream> {
+        let resolver = self
+            .resolver
+            .clone()
+            .or_else(resolver::installed)
+            .unwrap_or_default();
+        let addrs = resolver.resolve(host, port).await?;
+        Ok(connect::happy_eyeballs(addrs, connect::CONNECTION_ATTEMPT_DELAY).await?)
+    }
+}
+
+/// How long the phases of an exchange took, carried in the extensions of
+/// its response
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Phases {
+    pub(crate) connect: Option<Duration>,
+    pub(crate) ttfb: Duration,
+    pub(crate) download: Duration,
+}
+
+/// The timing of `res`, from its phases and the durations its server
+/// reports
+fn response_timing(res: &Response<Bytes>) -> ResponseTiming {
+    let phases = res.extensions().get::<Phases>().copied().unwrap_or_default();
+    let millis = |d: Duration| d.as_millis() as u64;
+    let (server_ms, queue_ms) = server_timing(res.headers());
+    ResponseTiming {
+        connect_ms: phases.connect.map(millis),
+        ttfb_ms: millis(phases.ttfb),
+        download_ms: millis(phases.download),
+        server_ms,
+        queue_ms,
+    }
+}
+
+/// The total and queueing durations reported in `headers`, in milliseconds
+///
+/// `Server-Timing` metrics such as `inference;dur=5210.4, queue;dur=830`
+/// are summed into the total, with the `queue` metric also reported on its
+/// own; without them, the `x-envoy-upstream-service-time` of proxies in
+/// front of the API is taken.
+fn server_timing(headers: &HeaderMap) -> (Option<u64>, Option<u64>) {
+    let mut total = None;
+    let mut queue = None;
+    for value in headers.get_all("server-timing") {
+        let Ok(value) = value.to_str() else { continue };
+        for metric in value.split(',') {
+            let mut params = metric.split(';').map(str::trim);
+            let name = params.next().unwrap_or_default();
+            let dur = params
+                .filter_map(|param| param.strip_prefix("dur="))
+                .find_map(|dur| dur.parse::<f64>().ok())
+                .filter(|dur| dur.is_finite() && *dur >= 0.0);
+            if let Some(dur) = dur {
+                *total.get_or_insert(0.0) += dur;
+                if name.eq_ignore_ascii_case("queue") {
+                    *queue.get_or_insert(0.0) += dur;
+                }
             }
-            writer.flush().await?;
         }
-        Ok(Bytes::from(writer.into_inner()))
     }
+
+    let envoy = || {
+        headers
+            .get("x-envoy-upstream-service-time")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+    };
+    let total = total.map(|ms: f64| ms.round() as u64).or_else(envoy);
+    (total, queue.map(|ms: f64| ms.round() as u64))
+}
+
+/// `req` addressed by its path alone, as a server sent plain HTTP expects
+fn origin_form<B>(req: Request<B>) -> Result<Request<B>> {
+    let (mut parts, body) = req.into_parts();
+    let path = parts.uri.path_and_query().map_or("/", |p| p.as_str());
+    parts.uri = path.parse()?;
+    Ok(Request::from_parts(parts, body))
+}
+
+/// Check a downloaded body against its declared length and checksums
+fn verify_download(headers: &HeaderMap, bytes: &[u8], sha256: Option<&str>) -> Result<()> {
+    let declared = headers
+        .get(hyper::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if let Some(expected) = declared.filter(|len| *len != bytes.len()) {
+        return Err(Box::new(Error::DownloadTruncated {
+            expected,
+            received: bytes.len(),
+        }));
+    }
+
+    // an etag is opaque unless it looks like a SHA-256, as some storage
+    // backends serve them
+    let etag = headers
+        .get(hyper::header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim_start_matches("W/").trim_matches('"'))
+        .filter(|v| v.len() == 64 && v.chars().all(|c| c.is_ascii_hexdigit()));
+
+    for expected in sha256.into_iter().chain(etag) {
+        let found: String = Sha256::digest(bytes)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        if !found.eq_ignore_ascii_case(expected) {
+            return Err(Box::new(Error::ChecksumMismatch {
+                expected: expected.to_string(),
+                found,
+            }));
+        }
+    }
+    Ok(())
 }
 
-#[derive(Debug)]
+/// Whether a download failed in a way that requesting it again may fix
+fn is_corrupt_download(e: &(dyn std::error::Error + 'static)) -> bool {
+    match e.downcast_ref::<Error>() {
+        Some(Error::DownloadTruncated { .. } | Error::ChecksumMismatch { .. }) => true,
+        _ => e
+            .downcast_ref::<hyper::Error>()
+            .is_some_and(|e| e.is_incomplete_message()),
+    }
+}
+
+/// Answers fully buffered requests in place of the network, see
+/// [`with_transport`]
+pub trait Transport: Send + Sync {
+    fn send(&self, req: Request<Bytes>) -> BoxFuture<'_, Result<Response<Bytes>>>;
+}
+
+tokio::task_local! {
+    static TRANSPORT: Arc<dyn Transport>;
+}
+
+/// Run `f` with every request it sends answered by `transport`
+pub async fn with_transport<F: Future>(transport: Arc<dyn Transport>, f: F) -> F::Output {
+    TRANSPORT.scope(transport, f).await
+}
+
+fn transport_overridden() -> bool {
+    TRANSPORT.try_with(|_| ()).is_ok()
+}
+
+#[derive(Debug, Clone)]
 pub struct ClientBuilder {
     pub url: Option<Uri>,
     method: Option<Method>,
     headers: Option<HeaderMap>,
+    max_response_size: Option<usize>,
+    audit: Option<Arc<dyn AuditSink>>,
+    circuit_breaker: Option<CircuitBreaker>,
+    cache: Option<MetadataCache>,
+    resolver: Option<Resolver>,
+    base_url: Option<BaseUrl>,
+    user_agent_product: Option<String>,
+    api_version: ApiVersion,
+    path: Option<String>,
 }
 
 impl ClientBuilder {
+    /// A builder starting from the [`global`](crate::global) config if one
+    /// was set, reading the API key from `STABILITY_API_KEY` otherwise
     pub fn new() -> Result<Self> {
-        let mut cb = ClientBuilder::default();
-        let apikey = env::var("STABILITY_API_KEY")?;
-        cb = cb.header(AUTHORIZATION_HEADER, &apikey)?;
+        let config = crate::global::config();
+        let mut cb = match &config {
+            Some(config) => config.apply(ClientBuilder::default())?,
+            None => ClientBuilder::default(),
+        };
+        if config.is_some_and(|config| config.has_api_key()) {
+            return Ok(cb);
+        }
+        match env::var("STABILITY_API_KEY") {
+            Ok(apikey) => cb = cb.api_key(&apikey)?,
+            // an overridden transport or base URL never reaches the API, so
+            // needs no key
+            Err(_) if transport_overridden() || base_url_overridden() || cb.base_url.is_some() => {}
+            Err(e) => return Err(Box::new(e)),
+        }
         Ok(cb)
     }
 
+    /// Request `path` below the prefix of the builder's [`ApiVersion`]
     pub fn path(mut self, path: impl Into<String>) -> Result<Self> {
-        let url = format!("{}{}{}", BASE_URL, V1_PATH, path.into()).parse::<Uri>()?;
-        self.url = Some(url);
+        self.path = Some(path.into());
+        self.set_url()?;
+        Ok(self)
+    }
+
+    /// Request the path from `version` of the API, `v1` unless set
+    pub fn api_version(mut self, version: ApiVersion) -> Result<Self> {
+        self.api_version = version;
+        self.set_url()?;
         Ok(self)
     }
 
+    fn set_url(&mut self) -> Result<()> {
+        if let Some(path) = &self.path {
+            let prefix = self.api_version.resolve().prefix();
+            self.url = Some(format!("{}{}{}", BASE_URL, prefix, path).parse::<Uri>()?);
+        }
+        Ok(())
+    }
+
     pub fn method(mut self, method: impl Into<String>) -> Result<Self> {
         let method = method.into().parse::<Method>()?;
         self.method = Some(method);
@@ -134,6 +676,68 @@ impl ClientBuilder {
         Ok(self)
     }
 
+    pub(crate) fn api_key(self, api_key: &str) -> Result<Self> {
+        self.header(AUTHORIZATION_HEADER, api_key)
+    }
+
+    /// Set the organization requests are billed to, replacing one set before
+    pub fn organization(mut self, organization: &str) -> Result<Self> {
+        let value = organization.parse::<HeaderValue>()?;
+        // unwrap() is warranted because self.headers has default headers set with one initial entry
+        self.headers.as_mut().unwrap().insert(ORGANIZATION_HEADER, value);
+        Ok(self)
+    }
+
+    /// Cap the size of response bodies, in bytes, instead of using
+    /// [`max_response_size`]
+    pub fn max_response_size(mut self, bytes: usize) -> Result<Self> {
+        self.max_response_size = Some(bytes);
+        Ok(self)
+    }
+
+    /// Record this client's requests to `sink` instead of the installed
+    /// [`audit`] sink
+    pub fn audit(mut self, sink: Arc<dyn AuditSink>) -> Result<Self> {
+        self.audit = Some(sink);
+        Ok(self)
+    }
+
+    /// Guard this client's requests with `breaker` instead of the installed
+    /// [`circuit`] breaker
+    pub fn circuit_breaker(mut self, breaker: CircuitBreaker) -> Result<Self> {
+        self.circuit_breaker = Some(breaker);
+        Ok(self)
+    }
+
+    /// Revalidate this client's metadata requests against `cache` instead of
+    /// the installed [`cache`]
+    pub fn cache(mut self, cache: MetadataCache) -> Result<Self> {
+        self.cache = Some(cache);
+        Ok(self)
+    }
+
+    /// Resolve the API host with `resolver` instead of the installed
+    /// [`resolver`]
+    pub fn resolver(mut self, resolver: Resolver) -> Result<Self> {
+        self.resolver = Some(resolver);
+        Ok(self)
+    }
+
+    /// Send this client's requests to `url` instead of the base URL set by
+    /// [`set_base_url`]
+    pub fn base_url(mut self, url: &str) -> Result<Self> {
+        self.base_url = Some(url.parse()?);
+        Ok(self)
+    }
+
+    /// Append `product`, such as `my-app/1.2`, to the User-Agent instead of
+    /// the one set by [`set_user_agent_product`]
+    pub fn user_agent_product(mut self, product: &str) -> Result<Self> {
+        product.parse::<HeaderValue>()?;
+        self.user_agent_product = Some(product.to_string());
+        Ok(self)
+    }
+
     pub fn build(self) -> Result<Client> {
         let Some(url) = self.url else {
             return Err(Box::new(Error::ClientBuildError(
@@ -143,11 +747,24 @@ impl ClientBuilder {
 
         let method = self.method.unwrap_or(Method::GET);
 
+        // unwrap() is warranted because self.headers has default headers set with one intial entry
+        let mut headers = self.headers.unwrap();
+        // a User-Agent set with header() is kept as is
+        if !headers.contains_key(hyper::header::USER_AGENT) {
+            let user_agent = user_agent(self.user_agent_product.as_deref());
+            headers.insert(hyper::header::USER_AGENT, user_agent.parse()?);
+        }
+
         Ok(Client {
             url,
             method,
-            // unwrap() is warranted because self.headers has default headers set with one intial entry
-            headers: self.headers.unwrap(),
+            headers,
+            max_response_size: self.max_response_size,
+            audit: self.audit,
+            circuit_breaker: self.circuit_breaker,
+            cache: self.cache,
+            resolver: self.resolver,
+            base_url: self.base_url,
         })
     }
 }
@@ -162,6 +779,300 @@ impl Default for ClientBuilder {
             url: None,
             method: None,
             headers: Some(headers),
+            max_response_size: None,
+            audit: None,
+            circuit_breaker: None,
+            cache: None,
+            resolver: None,
+            base_url: None,
+            user_agent_product: None,
+            api_version: ApiVersion::default(),
+            path: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+    use crate::testing::FakeTransport;
+    use std::sync::atomic::AtomicUsize;
+
+    /// A transport answering with `png`, cut short on the first `cut` requests
+    fn flaky_download(png: &'static [u8], cut: usize) -> Arc<FakeTransport> {
+        let count = AtomicUsize::new(0);
+        FakeTransport::new(move |_| {
+            let n = count.fetch_add(1, Ordering::SeqCst);
+            let body = if n < cut { &png[..2] } else { png };
+            Response::builder()
+                .header(CONTENT_TYPE, IMAGE_PNG)
+                .header(hyper::header::CONTENT_LENGTH, png.len())
+                .body(Bytes::from_static(body))
+                .unwrap()
+        })
+    }
+
+    async fn download(sha256: Option<&str>) -> Result<Artifact> {
+        ClientBuilder::new()?
+            .path("/assets/1")?
+            .build()?
+            .download_verified_artifact(Empty::<Bytes>::new(), sha256)
+            .await
+    }
+
+    #[tokio::test]
+    async fn truncated_downloads_are_requested_again() {
+        let transport = flaky_download(b"\x89PNG", 2);
+        let artifact = transport.scope(download(None)).await.unwrap();
+
+        assert_eq!(artifact.bytes().as_ref(), b"\x89PNG");
+        assert_eq!(transport.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn checksum_mismatches_are_erring_after_the_last_attempt() {
+        let transport = flaky_download(b"\x89PNG", 0);
+        let err = transport
+            .scope(download(Some(&"0".repeat(64))))
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().starts_with("the download's SHA-256 was"));
+        assert_eq!(transport.requests().len(), DOWNLOAD_ATTEMPTS);
+    }
+
+    #[derive(Default)]
+    struct Records(std::sync::Mutex<Vec<crate::audit::AuditRecord>>);
+
+    impl AuditSink for Records {
+        fn record(&self, record: &crate::audit::AuditRecord) -> Result<()> {
+            self.0.lock().unwrap().push(record.clone());
+            Ok(())
         }
     }
+
+    #[tokio::test]
+    async fn client_audit_sink_is_recording_each_request() {
+        let transport = FakeTransport::with_response(&crate::testing::image_response(&[5, 6]));
+        let records = Arc::new(Records::default());
+        let sink: Arc<dyn AuditSink> = records.clone();
+
+        transport
+            .scope(async move {
+                ClientBuilder::new()?
+                    .path("/generation/stable-diffusion-v1-6/text-to-image")?
+                    .method("POST")?
+                    .audit(sink)?
+                    .build()?
+                    .send_request(Full::new(Bytes::from(r#"{"steps":30}"#)))
+                    .await
+            })
+            .await
+            .unwrap();
+
+        let records = records.0.lock().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].engine.as_deref(), Some("stable-diffusion-v1-6"));
+        assert_eq!(records[0].seeds, vec![5, 6]);
+    }
+
+    #[tokio::test]
+    async fn user_agent_is_naming_the_crate_and_the_application() {
+        let transport = FakeTransport::with_response(&crate::testing::image_response(&[5]));
+
+        transport
+            .scope(async {
+                ClientBuilder::new()?
+                    .path("/user/balance")?
+                    .user_agent_product("my-app/1.2")?
+                    .build()?
+                    .send_request(Empty::<Bytes>::new())
+                    .await
+            })
+            .await
+            .unwrap();
+
+        let requests = transport.requests();
+        assert_eq!(
+            requests[0].headers["user-agent"],
+            format!("stability_rs/{} my-app/1.2", env!("CARGO_PKG_VERSION"))
+        );
+    }
+
+    #[test]
+    fn path_is_prefixed_with_the_api_version() {
+        let url = |cb: ClientBuilder| cb.url.unwrap().to_string();
+
+        let v1 = ClientBuilder::default().path("/engines/list").unwrap();
+        let v2beta = ClientBuilder::default()
+            .path("/stable-image/generate/core")
+            .unwrap()
+            .api_version(ApiVersion::V2Beta)
+            .unwrap();
+
+        assert_eq!(url(v1), "https://api.stability.ai/v1/engines/list");
+        assert_eq!(
+            url(v2beta),
+            "https://api.stability.ai/v2beta/stable-image/generate/core"
+        );
+    }
+
+    #[tokio::test]
+    async fn open_circuit_is_short_circuiting_requests() {
+        let transport = FakeTransport::with_error(503, "service_unavailable", "down");
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(60));
+        let send = |breaker: CircuitBreaker| async move {
+            ClientBuilder::new()?
+                .path("/user/balance")?
+                .circuit_breaker(breaker)?
+                .build()?
+                .send_request(Empty::<Bytes>::new())
+                .await
+        };
+
+        let first = transport.scope(send(breaker.clone())).await.unwrap_err();
+        let second = transport.scope(send(breaker.clone())).await.unwrap_err();
+
+        assert!(first.to_string().contains("down"));
+        assert!(matches!(
+            second.downcast_ref::<Error>(),
+            Some(Error::CircuitOpen { .. })
+        ));
+        assert_eq!(transport.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn not_modified_metadata_is_served_from_the_cache() {
+        let transport = FakeTransport::new(|req| match req.headers.get("if-none-match") {
+            Some(_) => Response::builder()
+                .status(304)
+                .body(Bytes::new())
+                .unwrap(),
+            None => Response::builder()
+                .header("etag", "\"v1\"")
+                .body(Bytes::from(r#"{"credits":5.0}"#))
+                .unwrap(),
+        });
+        let cache = MetadataCache::new();
+        let send = |cache: MetadataCache| async move {
+            ClientBuilder::new()?
+                .path("/user/balance")?
+                .cache(cache)?
+                .build()?
+                .send_request(Empty::<Bytes>::new())
+                .await
+        };
+
+        let first = transport.scope(send(cache.clone())).await.unwrap();
+        let second = transport.scope(send(cache.clone())).await.unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(cache.len(), 1);
+        let requests = transport.requests();
+        assert!(requests[0].headers.get("if-none-match").is_none());
+        assert_eq!(requests[1].headers["if-none-match"], "\"v1\"");
+    }
+
+    #[test]
+    fn base_url_is_parsed_from_each_form() {
+        assert_eq!("https://api.stability.ai/".parse::<BaseUrl>().unwrap(), BaseUrl::Api);
+        assert_eq!(
+            "http://127.0.0.1:8080".parse::<BaseUrl>().unwrap(),
+            BaseUrl::Http {
+                host: "127.0.0.1".to_string(),
+                port: 8080
+            }
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            "unix:///tmp/mock.sock".parse::<BaseUrl>().unwrap(),
+            BaseUrl::Unix("/tmp/mock.sock".into())
+        );
+        assert!("https://example.com".parse::<BaseUrl>().is_err());
+        assert!("http://127.0.0.1:8080/v1".parse::<BaseUrl>().is_err());
+    }
+
+    #[tokio::test]
+    async fn send_request_is_using_plain_http_when_a_base_url_is_set() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let read = stream.read(&mut buf).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")
+                .await
+                .unwrap();
+            let request = String::from_utf8_lossy(&buf[..read]).into_owned();
+            // hold the connection open until the client is done with it
+            let _ = stream.read(&mut buf).await;
+            request
+        });
+
+        let body = ClientBuilder::default()
+            .path("/user/balance")
+            .unwrap()
+            .base_url(&format!("http://{}", addr))
+            .unwrap()
+            .build()
+            .unwrap()
+            .send_request(Empty::<Bytes>::new())
+            .await
+            .unwrap();
+
+        assert_eq!(body.as_ref(), b"ok");
+        let request = server.await.unwrap();
+        assert!(request.starts_with("GET /v1/user/balance HTTP/1.1\r\n"));
+        assert!(request.contains("host: api.stability.ai\r\n"));
+    }
+
+    #[tokio::test]
+    async fn send_request_timed_is_reporting_connect_and_server_times() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).await.unwrap();
+            stream
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\
+                      server-timing: queue;dur=40, inference;dur=1210.6\r\n\r\nok",
+                )
+                .await
+                .unwrap();
+            let _ = stream.read(&mut buf).await;
+        });
+
+        let (_, body, timing) = ClientBuilder::default()
+            .path("/user/balance")
+            .unwrap()
+            .base_url(&format!("http://{}", addr))
+            .unwrap()
+            .build()
+            .unwrap()
+            .send_request_timed(Empty::<Bytes>::new())
+            .await
+            .unwrap();
+
+        assert_eq!(body.as_ref(), b"ok");
+        assert!(timing.connect_ms.is_some());
+        assert_eq!(timing.server_ms, Some(1251));
+        assert_eq!(timing.queue_ms, Some(40));
+    }
+
+    #[test]
+    fn server_timing_is_falling_back_to_the_proxy_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-envoy-upstream-service-time", "930".parse().unwrap());
+        assert_eq!(server_timing(&headers), (Some(930), None));
+
+        headers.insert("server-timing", "cache;desc=miss, total;dur=12.4".parse().unwrap());
+        assert_eq!(server_timing(&headers), (Some(12), None));
+        assert_eq!(server_timing(&HeaderMap::new()), (None, None));
+    }
 }
@@ -0,0 +1,115 @@
+//! Dual-stack connection attempts ("happy eyeballs", RFC 8305).
+//!
+//! A host often resolves to both IPv6 and IPv4 addresses, one family of
+//! which may be broken on the local network. Rather than dialing the first
+//! address and waiting out its timeout, [`happy_eyeballs`] alternates the
+//! families and starts the next attempt once the previous one has failed or
+//! [`CONNECTION_ATTEMPT_DELAY`] has passed without an answer, keeping
+//! whichever connection is established first.
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use tokio::net::TcpStream;
+
+/// How long an attempt may go unanswered before the next one is started
+/// alongside it, as recommended by RFC 8305
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Connect to the first of `addrs` to answer, trying them in interleaved
+/// order with attempts staggered by `delay`
+pub async fn happy_eyeballs(addrs: Vec<SocketAddr>, delay: Duration) -> io::Result<TcpStream> {
+    let mut pending = interleave(addrs).into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error = None;
+
+    loop {
+        if attempts.is_empty() {
+            match pending.next() {
+                Some(addr) => attempts.push(TcpStream::connect(addr)),
+                None => {
+                    return Err(last_error.unwrap_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, "no address to connect to")
+                    }))
+                }
+            }
+        }
+
+        tokio::select! {
+            Some(result) = attempts.next() => match result {
+                Ok(stream) => return Ok(stream),
+                Err(e) => {
+                    last_error = Some(e);
+                    // a refused attempt doesn't wait out the delay
+                    if let Some(addr) = pending.next() {
+                        attempts.push(TcpStream::connect(addr));
+                    }
+                }
+            },
+            _ = tokio::time::sleep(delay), if !pending.as_slice().is_empty() => {
+                if let Some(addr) = pending.next() {
+                    attempts.push(TcpStream::connect(addr));
+                }
+            }
+        }
+    }
+}
+
+/// `addrs` with IPv6 and IPv4 addresses alternating, starting with the
+/// family of the first one and otherwise keeping the resolver's order
+pub(crate) fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(ipv6_first) = addrs.first().map(SocketAddr::is_ipv6) else {
+        return addrs;
+    };
+    let mut interleaved = Vec::with_capacity(addrs.len());
+    let (preferred, other): (Vec<SocketAddr>, Vec<SocketAddr>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == ipv6_first);
+
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => return interleaved,
+            (a, b) => interleaved.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    fn addrs(addrs: &[&str]) -> Vec<SocketAddr> {
+        addrs.iter().map(|a| a.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn interleave_is_alternating_the_families() {
+        assert_eq!(
+            interleave(addrs(&["[::1]:443", "[::2]:443", "[::3]:443", "10.0.0.1:443"])),
+            addrs(&["[::1]:443", "10.0.0.1:443", "[::2]:443", "[::3]:443"])
+        );
+    }
+
+    #[tokio::test]
+    async fn happy_eyeballs_is_falling_back_past_a_refused_address() {
+        let refused = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap();
+
+        let stream = happy_eyeballs(vec![refused, open], Duration::from_secs(60))
+            .await
+            .unwrap();
+
+        assert_eq!(stream.peer_addr().unwrap(), open);
+    }
+
+    #[tokio::test]
+    async fn happy_eyeballs_is_erring_without_addresses() {
+        let err = happy_eyeballs(Vec::new(), CONNECTION_ATTEMPT_DELAY).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -16,12 +16,12 @@ pub async fn get_engines() -> Result<Vec<Engine>> {
 
     let resp = c.send_request(Empty::<Bytes>::new()).await?;
 
-    let engines = serde_json::from_slice::<Vec<Engine>>(&resp.as_ref())?;
+    let engines = crate::schema::from_slice::<Vec<Engine>>(resp.as_ref())?;
 
     Ok(engines)
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Engine {
     description: String,
     id: String,
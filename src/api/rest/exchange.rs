@@ -0,0 +1,261 @@
+//! A single HTTP/1 exchange over an established connection, and TLS to set
+//! one up.
+//!
+//! These are the pieces [`support`](crate::support) exposes behind the
+//! `unstable-transport` feature.
+
+use crate::api::rest::client::Phases;
+use crate::download_progress::{self, DownloadProgress};
+use crate::error::Error;
+use crate::prelude::*;
+use http_body_util::BodyExt;
+use hyper::body::{Body, Bytes, Incoming};
+use hyper::client::conn::http1::handshake;
+use hyper::{Request, Response};
+#[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::net::TcpStream;
+
+/// Send `req` over a fresh HTTP/1 connection on `io` and read the response,
+/// erring once its body grows past `limit` bytes
+///
+/// The connection is driven by this future alongside the request rather than
+/// by a spawned task, so it is closed as soon as the response is read, the
+/// exchange fails or the future is dropped.
+pub async fn exchange<I, B>(
+    io: I,
+    req: Request<B>,
+    limit: usize,
+) -> Result<Response<Bytes>>
+where
+    I: hyper::rt::Read + hyper::rt::Write + Unpin + Send,
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let (mut sender, conn) = handshake(io).await?;
+    let response = async move {
+        let started = Instant::now();
+        let res = sender.send_request(req).await?;
+        read_response(res, started, limit).await
+    };
+
+    tokio::pin!(response);
+    tokio::select! {
+        biased;
+        res = &mut response => res,
+        res = conn => {
+            res?;
+            // a server closing the connection right after its response leaves
+            // the response to be read still
+            response.await.map_err(|e| match e.downcast::<hyper::Error>() {
+                Ok(_) => Box::new(Error::ConnectionClosed),
+                Err(e) => e,
+            })
+        }
+    }
+}
+
+/// Read the body of `res` to a request sent at `started`, erring once it
+/// grows past `limit` bytes
+pub(crate) async fn read_response(
+    mut res: Response<Incoming>,
+    started: Instant,
+    limit: usize,
+) -> Result<Response<Bytes>> {
+    let ttfb = started.elapsed();
+    let declared = res
+        .headers()
+        .get(hyper::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if declared.is_some_and(|len| len > limit) {
+        return Err(Box::new(Error::ResponseTooLarge { limit }));
+    }
+
+    let callback = download_progress::current();
+    let mut body = Vec::new();
+    while let Some(frame) = res.frame().await {
+        if let Some(chunk) = frame?.data_ref() {
+            if body.len() + chunk.len() > limit {
+                return Err(Box::new(Error::ResponseTooLarge { limit }));
+            }
+            body.extend_from_slice(chunk);
+            if let Some(callback) = &callback {
+                callback(DownloadProgress {
+                    received: body.len() as u64,
+                    total: declared.map(|len| len as u64),
+                });
+            }
+        }
+    }
+    let (mut parts, _) = res.into_parts();
+    parts.extensions.insert(Phases {
+        connect: None,
+        ttfb,
+        download: started.elapsed() - ttfb,
+    });
+    Ok(Response::from_parts(parts, body.into()))
+}
+
+/// A TLS connection, as established by [`connect_tls`]
+#[cfg(feature = "native-tls")]
+pub type TlsStream = async_native_tls::TlsStream<TcpStream>;
+/// A TLS connection, as established by [`connect_tls`]
+#[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+pub type TlsStream = tokio_rustls::client::TlsStream<TcpStream>;
+
+/// Establish TLS on `stream`, verifying the certificate against `host` with
+/// the enabled TLS backend
+#[cfg(feature = "native-tls")]
+pub async fn connect_tls(host: &str, stream: TcpStream) -> Result<TlsStream> {
+    Ok(async_native_tls::connect(host, stream).await?)
+}
+
+/// Establish TLS on `stream`, verifying the certificate against `host` with
+/// the enabled TLS backend
+#[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+pub async fn connect_tls(host: &str, stream: TcpStream) -> Result<TlsStream> {
+    use tokio_rustls::rustls::{pki_types::ServerName, ClientConfig, RootCertStore};
+
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+    let server_name = ServerName::try_from(host.to_string())?;
+    Ok(connector.connect(server_name, stream).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::support::TokioIo;
+    use crate::upload_progress::{ProgressBody, UploadProgress};
+    use http_body_util::Full;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    /// Answer one request with `ok`, then report what a further read returned
+    async fn serve_once() -> (SocketAddr, JoinHandle<usize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")
+                .await
+                .unwrap();
+            // keep-alive would leave the connection open if anything still drove it
+            stream.read(&mut buf).await.unwrap_or(0)
+        });
+        (addr, server)
+    }
+
+    async fn get(addr: SocketAddr, limit: usize) -> Result<Response<Bytes>> {
+        let req = Request::get(format!("http://{}/", addr))
+            .header("host", addr.to_string())
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        let stream = TcpStream::connect(addr).await.unwrap();
+        exchange(TokioIo::new(stream), req, limit).await
+    }
+
+    #[tokio::test]
+    async fn exchange_is_closing_the_connection_once_the_response_is_read() {
+        let (addr, server) = serve_once().await;
+        let res = get(addr, 1024).await.unwrap();
+        assert_eq!(res.body().as_ref(), b"ok");
+
+        let read = tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(read, 0);
+    }
+
+    #[tokio::test]
+    async fn exchange_is_returning_the_response_when_the_server_closes_after_it() {
+        for _ in 0..20 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).await.unwrap();
+                let response = "HTTP/1.1 200 OK\r\nconnection: close\r\n\
+                                content-length: 2\r\n\r\nok";
+                stream.write_all(response.as_bytes()).await.unwrap();
+            });
+
+            let res = get(addr, 1024).await.unwrap();
+            assert_eq!(res.body().as_ref(), b"ok");
+        }
+    }
+
+    #[tokio::test]
+    async fn exchange_is_reporting_upload_progress() {
+        let (addr, _server) = serve_once().await;
+        let sent = Arc::new(AtomicUsize::new(0));
+        let seen = sent.clone();
+        let body = ProgressBody::new(
+            Bytes::from_static(b"0123456789"),
+            Some(Arc::new(move |progress: UploadProgress| {
+                seen.store(progress.sent as usize, Ordering::SeqCst)
+            })),
+        );
+        let req = Request::post(format!("http://{}/", addr))
+            .header("host", addr.to_string())
+            .body(body)
+            .unwrap();
+        let stream = TcpStream::connect(addr).await.unwrap();
+
+        let res = exchange(TokioIo::new(stream), req, 1024).await.unwrap();
+
+        assert_eq!(res.body().as_ref(), b"ok");
+        assert_eq!(sent.load(Ordering::SeqCst), 10);
+    }
+
+    #[tokio::test]
+    async fn exchange_is_reporting_download_progress() {
+        let (addr, _server) = serve_once().await;
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = reports.clone();
+
+        let res = download_progress::with_download_progress(
+            move |progress| seen.lock().unwrap().push(progress),
+            get(addr, 1024),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(res.body().as_ref(), b"ok");
+        assert_eq!(
+            reports.lock().unwrap().last(),
+            Some(&DownloadProgress {
+                received: 2,
+                total: Some(2)
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn exchange_is_erring_when_the_body_exceeds_the_limit() {
+        let (addr, _server) = serve_once().await;
+        let err = get(addr, 1).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the response body exceeded the limit of 1 bytes"
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! The v2beta `stable-image/edit` endpoints, sent through one entry point.
+//!
+//! ```no_run
+//! use stability_rs::edit::{self, Erase, RemoveBackground};
+//! use stability_rs::{OutputFormat, Result};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     let erased = edit::edit(&Erase::new("beach.png").mask("tourists_mask.png")?).await?;
+//!     erased.save("beach_empty.png").await?;
+//!
+//!     let cut_out = RemoveBackground::new("sneaker.jpg").output_format(OutputFormat::Webp)?;
+//!     edit::edit(&cut_out).await?.save("sneaker.webp").await?;
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! [`Inpaint`](crate::inpaint::Inpaint) and [`Relight`](crate::relight::Relight)
+//! requests are sent the same way.
+
+use super::results::{ResultHandle, DEFAULT_POLL_INTERVAL};
+use super::*;
+
+const EDIT_PATH: &str = "/stable-image/edit";
+
+pub use crate::model::edit::*;
+
+/// An edited image, as answered by the v2beta edit endpoints
+#[derive(Debug, Clone)]
+pub struct EditResponse {
+    pub artifact: crate::api::rest::artifact::Artifact,
+    pub seed: Option<u32>,
+    /// `SUCCESS`, or `CONTENT_FILTERED` when the image was blurred
+    pub finish_reason: Option<String>,
+}
+
+impl EditResponse {
+    /// Read the image from the body and the seed and finish reason from the
+    /// headers of a response
+    pub(crate) fn from_response(headers: &HeaderMap, bytes: Bytes) -> Result<Self> {
+        let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+        let artifact = crate::api::rest::artifact::Artifact::expect(
+            crate::api::rest::artifact::ArtifactKind::Image,
+            header(CONTENT_TYPE).unwrap_or_default(),
+            bytes,
+        )?;
+        Ok(Self {
+            artifact,
+            seed: header("seed").and_then(|v| v.parse().ok()),
+            finish_reason: header("finish-reason").map(str::to_string),
+        })
+    }
+
+    /// The format the API chose, going by the response's content type
+    pub fn output_format(&self) -> Option<OutputFormat> {
+        OutputFormat::from_content_type(self.artifact.content_type())
+    }
+
+    pub async fn save(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        self.artifact.save(path).await
+    }
+
+    /// Save with the extension of the format the API chose, see
+    /// [`Artifact::save_as`](crate::api::rest::artifact::Artifact::save_as)
+    pub async fn save_as(&self, path: impl AsRef<std::path::Path>) -> Result<std::path::PathBuf> {
+        self.artifact.save_as(path).await
+    }
+}
+
+/// Send an edit request, waiting for the result of asynchronous ones
+pub async fn edit(request: &impl EditRequest) -> Result<EditResponse> {
+    if request.is_async() {
+        return start(request).await?.wait(DEFAULT_POLL_INTERVAL).await;
+    }
+
+    let (headers, bytes) = send(request, IMAGE_ANY).await?;
+    EditResponse::from_response(&headers, bytes)
+}
+
+/// Start an asynchronous edit request, returning how to retrieve the result
+///
+/// Synchronous endpoints answer with the image rather than an id, so
+/// sending them this way fails.
+pub async fn start(request: &impl EditRequest) -> Result<ResultHandle> {
+    let (_, bytes) = send(request, APPLICATION_JSON).await?;
+    ResultHandle::from_response(
+        path(request),
+        &bytes,
+        request.organization().map(str::to_string),
+    )
+}
+
+fn path(request: &impl EditRequest) -> String {
+    format!("{}/{}", EDIT_PATH, request.operation())
+}
+
+async fn send(request: &impl EditRequest, accept: &str) -> Result<(HeaderMap, Bytes)> {
+    crate::prompt_screen::check(&prompt_groups(request))?;
+    let data = to_multipart_form_data(request)?;
+
+    let mut cb = ClientBuilder::new()?;
+    if let Some(organization) = request.organization() {
+        cb = cb.organization(organization)?;
+    }
+
+    let c = cb
+        .method(POST)?
+        .api_version(ApiVersion::V2Beta)?
+        .path(path(request))?
+        .header(ACCEPT, accept)?
+        .header(
+            CONTENT_TYPE,
+            &format!("{}{}", MULTIPART_FORM_DATA_BOUNDARY, data.boundary),
+        )?
+        .build()?;
+
+    c.send_request_with_headers(Full::<Bytes>::new(data.body.into()))
+        .await
+}
+
+/// The prompts of the form, for screening
+fn prompt_groups(request: &impl EditRequest) -> PromptGroups {
+    let mut groups = PromptGroups::default();
+    for (name, field) in request.fields() {
+        let EditField::Text(text) = field else { continue };
+        let (group, weight) = match name {
+            "prompt" | "search_prompt" => (&mut groups.positive, 1.0),
+            "negative_prompt" => (&mut groups.negative, -1.0),
+            _ => continue,
+        };
+        group.push(WeightedPrompt { text, weight });
+    }
+    groups
+}
+
+fn to_multipart_form_data(request: &impl EditRequest) -> Result<MultipartFormData> {
+    let mut multipart_form_data = MultipartFormData::new();
+    let upload = request.upload_options();
+
+    for (name, field) in request.fields() {
+        match field {
+            EditField::Text(text) => multipart_form_data.add_text(name, &text)?,
+            EditField::Image(path) => multipart_form_data.add_image(name, path, &upload)?,
+        }
+    }
+
+    multipart_form_data.end_body()?;
+
+    Ok(multipart_form_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeTransport;
+
+    #[tokio::test]
+    async fn edit_is_posting_to_the_operation_path() {
+        let path =
+            std::env::temp_dir().join(format!("stability_rs_edit_test_{}.png", std::process::id()));
+        std::fs::write(&path, crate::testing::png_1x1()).unwrap();
+        let request = SearchAndReplace::new(&path, "a red car", "the bicycle").unwrap();
+        let transport = FakeTransport::new(|_| {
+            Response::builder()
+                .header(CONTENT_TYPE, IMAGE_PNG)
+                .header("seed", "42")
+                .header("finish-reason", "SUCCESS")
+                .body(Bytes::from(crate::testing::png_1x1()))
+                .unwrap()
+        });
+
+        let resp = transport.scope(edit(&request)).await.unwrap();
+
+        assert_eq!(resp.seed, Some(42));
+        assert_eq!(resp.artifact.content_type(), IMAGE_PNG);
+        let requests = transport.requests();
+        assert_eq!(
+            requests[0].uri.path(),
+            "/v2beta/stable-image/edit/search-and-replace"
+        );
+        let body = String::from_utf8_lossy(&requests[0].body);
+        assert!(body.contains("name=\"search_prompt\"\r\n\r\nthe bicycle"));
+        assert!(body.contains("name=\"image\"; filename="));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
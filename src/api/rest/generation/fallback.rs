@@ -0,0 +1,144 @@
+use super::*;
+use std::future::Future;
+
+/// API error names which mean the engine itself can't serve requests right now
+const ENGINE_UNAVAILABLE: [&str; 4] = [
+    "not_found",
+    "engine_not_found",
+    "service_unavailable",
+    "maintenance",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_error(name: &str) -> Box<dyn std::error::Error + Send + Sync> {
+        Box::new(Error::ClientSendRequestError(ApiResponseError {
+            id: "id".to_string(),
+            name: name.to_string(),
+            message: "message".to_string(),
+        }))
+    }
+
+    fn response() -> ImageResponse {
+        ImageResponse {
+            artifacts: Vec::new(),
+            metadata: GenerationMetadata::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn fallback_is_moving_to_the_next_engine_when_unavailable() {
+        let fallback = EngineFallback::new(["sd3.5-large", "stable-diffusion-xl-1024-v1-0"]);
+        let resp = fallback
+            .generate(|engine| async move {
+                match engine.as_str() {
+                    "sd3.5-large" => Err(api_error("not_found")),
+                    _ => Ok(response()),
+                }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            resp.metadata.engine.as_deref(),
+            Some("stable-diffusion-xl-1024-v1-0")
+        );
+    }
+
+    #[tokio::test]
+    async fn fallback_is_returning_other_errors_immediately() {
+        let fallback = EngineFallback::new(["sd3.5-large", "stable-diffusion-xl-1024-v1-0"]);
+        let mut tried = Vec::new();
+        let err = fallback
+            .generate(|engine| {
+                tried.push(engine);
+                async { Err::<ImageResponse, _>(api_error("invalid_prompts")) }
+            })
+            .await
+            .unwrap_err();
+
+        assert_eq!(tried, vec!["sd3.5-large"]);
+        assert!(err.to_string().contains("invalid_prompts"));
+    }
+}
+
+/// An ordered list of engines to try, moving on to the next one when an engine
+/// is unknown or down for maintenance
+///
+/// # Example
+///
+#[cfg_attr(feature = "text-to-image", doc = "```no_run")]
+#[cfg_attr(not(feature = "text-to-image"), doc = "```ignore")]
+/// use stability_rs::{text_to_img::*, EngineFallback, Result, StylePreset};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<()> {
+///     let image = TextToImageBuilder::new()
+///         .style_preset(StylePreset::Photographic)?
+///         .text_prompt("A lighthouse at dusk", 1.0)?
+///         .build()?;
+///
+///     let fallback = EngineFallback::new(["sd3.5-large", "stable-diffusion-xl-1024-v1-0"]);
+///     let resp = image.generate_with_fallback(&fallback).await?;
+///
+///     println!("served by {:?}", resp.metadata.engine);
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineFallback {
+    engines: Vec<String>,
+}
+
+impl EngineFallback {
+    pub fn new<I, S>(engines: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            engines: engines.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    pub fn engines(&self) -> &[String] {
+        &self.engines
+    }
+
+    /// Call `generate` with each engine in turn until one serves the request,
+    /// recording the serving engine in the response metadata
+    pub async fn generate<F, Fut>(&self, mut generate: F) -> Result<ImageResponse>
+    where
+        F: FnMut(String) -> Fut,
+        Fut: Future<Output = Result<ImageResponse>>,
+    {
+        let mut last_err = None;
+
+        for engine in &self.engines {
+            match generate(engine.clone()).await {
+                Ok(mut resp) => {
+                    resp.metadata.engine = Some(engine.clone());
+                    return Ok(resp);
+                }
+                Err(e) if is_engine_unavailable(e.as_ref()) => last_err = Some(e),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| Box::new(Error::EngineFallbackEmpty)))
+    }
+}
+
+/// Whether an error means the engine is unknown or unavailable, rather than
+/// the request being at fault
+pub fn is_engine_unavailable(err: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    match err.downcast_ref::<Error>() {
+        Some(Error::ClientSendRequestError(api_err)) => {
+            ENGINE_UNAVAILABLE.contains(&api_err.name.as_str())
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,231 @@
+use super::*;
+use std::future::Future;
+
+/// The finish reason of an artifact the safety filter blurred
+const FINISH_CONTENT_FILTERED: &str = "CONTENT_FILTERED";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::image;
+
+    fn prompts(prompts: &[(&str, f32)]) -> Vec<TextPrompt> {
+        prompts
+            .iter()
+            .map(|(text, weight)| TextPrompt::new(text, *weight).unwrap())
+            .collect()
+    }
+
+    fn response(finish_reason: &str) -> ImageResponse {
+        ImageResponse {
+            artifacts: vec![image(1, finish_reason)],
+            metadata: GenerationMetadata::default(),
+        }
+    }
+
+    #[test]
+    fn drop_lowest_weight_is_keeping_the_last_positive_prompt() {
+        let mut sent = prompts(&[("a fox", 1.0), ("snow", 0.5), ("ice", 0.5), ("blurry", -1.0)]);
+
+        assert!(PromptMutation::DropLowestWeight.apply(&mut sent));
+        assert!(PromptMutation::DropLowestWeight.apply(&mut sent));
+        assert!(!PromptMutation::DropLowestWeight.apply(&mut sent));
+
+        let texts: Vec<&str> = sent.iter().map(|p| p.text()).collect();
+        assert_eq!(texts, ["a fox", "blurry"]);
+    }
+
+    #[tokio::test]
+    async fn generate_is_mutating_the_prompts_once_a_new_seed_was_filtered_too() {
+        let retry = FilterRetry::new(3)
+            .mutation(PromptMutation::DropLowestWeight)
+            .unwrap()
+            .mutation(PromptMutation::Append {
+                text: "safe for work".to_string(),
+                weight: 1.0,
+            })
+            .unwrap();
+        let mut attempts = Vec::new();
+        let resp = retry
+            .generate(&prompts(&[("a fox", 1.0), ("gore", 0.3)]), Seed::Fixed(7), |sent, seed| {
+                let texts: Vec<String> = sent.iter().map(|p| p.text().to_string()).collect();
+                attempts.push((texts.join(", "), seed));
+                let finish_reason = match attempts.len() {
+                    3 => "SUCCESS",
+                    _ => FINISH_CONTENT_FILTERED,
+                };
+                async move { Ok(response(finish_reason)) }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            attempts,
+            [
+                ("a fox, gore".to_string(), Seed::Fixed(7)),
+                ("a fox, gore".to_string(), Seed::Random),
+                ("a fox".to_string(), Seed::Random),
+            ]
+        );
+        assert_eq!(
+            resp.metadata.prompt_mutations,
+            [PromptMutation::DropLowestWeight]
+        );
+    }
+
+    #[tokio::test]
+    async fn generate_is_returning_the_last_filtered_response_when_out_of_attempts() {
+        let mut attempts = 0;
+        let resp = FilterRetry::new(1)
+            .generate(&prompts(&[("a fox", 1.0)]), Seed::Random, |_, _| {
+                attempts += 1;
+                async { Ok(response(FINISH_CONTENT_FILTERED)) }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(attempts, 2);
+        assert_eq!(resp.artifacts[0].finish_reason, FINISH_CONTENT_FILTERED);
+    }
+}
+
+/// Retries a generation the safety filter tripped on, at new seeds and,
+/// optionally, with its prompts changed
+///
+/// A generation counts as filtered when none of its artifacts succeeded and
+/// some were blurred as `CONTENT_FILTERED`, or when the API rejected its
+/// prompts outright. The first retry only re-rolls the seed, as filtering
+/// often comes down to the seed; each further retry also applies the next
+/// [`PromptMutation`], on top of the earlier ones. The mutations which led to
+/// the returned response are recorded in its
+/// [`GenerationMetadata::prompt_mutations`].
+///
+/// # Example
+///
+#[cfg_attr(feature = "text-to-image", doc = "```no_run")]
+#[cfg_attr(not(feature = "text-to-image"), doc = "```ignore")]
+/// use stability_rs::{text_to_img::*, FilterRetry, PromptMutation, Result, StylePreset};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<()> {
+///     let image = TextToImageBuilder::new()
+///         .style_preset(StylePreset::Photographic)?
+///         .text_prompt("A knight after the battle", 1.0)?
+///         .text_prompt("dramatic lighting", 0.4)?
+///         .build()?;
+///
+///     let retry = FilterRetry::new(3)
+///         .mutation(PromptMutation::DropLowestWeight)?
+///         .mutation(PromptMutation::Append {
+///             text: "safe for work".to_string(),
+///             weight: 1.0,
+///         })?;
+///     let resp = image
+///         .generate_with_filter_retry("stable-diffusion-xl-1024-v1-0", &retry)
+///         .await?;
+///
+///     println!("mutations applied: {:?}", resp.metadata.prompt_mutations);
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterRetry {
+    retries: u32,
+    mutations: Vec<PromptMutation>,
+}
+
+impl FilterRetry {
+    /// Retry a filtered generation up to `retries` times
+    pub fn new(retries: u32) -> Self {
+        Self {
+            retries,
+            mutations: Vec::new(),
+        }
+    }
+
+    /// Apply `mutation` from the next retry on, after the mutations added
+    /// before it
+    pub fn mutation(mut self, mutation: PromptMutation) -> Result<Self> {
+        if let PromptMutation::Append { text, weight } = &mutation {
+            TextPrompt::new(text, *weight)?;
+        }
+        self.mutations.push(mutation);
+        Ok(self)
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    pub fn mutations(&self) -> &[PromptMutation] {
+        &self.mutations
+    }
+
+    /// Call `generate` with `prompts` and `seed`, then with new seeds and
+    /// mutated prompts for as long as the results are filtered and retries
+    /// remain
+    ///
+    /// Once out of retries, the last filtered response or error is returned.
+    pub async fn generate<F, Fut>(
+        &self,
+        prompts: &[TextPrompt],
+        seed: Seed,
+        mut generate: F,
+    ) -> Result<ImageResponse>
+    where
+        F: FnMut(Vec<TextPrompt>, Seed) -> Fut,
+        Fut: Future<Output = Result<ImageResponse>>,
+    {
+        let mut prompts = prompts.to_vec();
+        let mut applied = Vec::new();
+        let mut seed = seed;
+        let mut attempt = 0;
+        loop {
+            let result = generate(prompts.clone(), seed).await;
+            let filtered = match &result {
+                Ok(resp) => is_filtered(resp),
+                Err(e) => is_prompt_flagged(e.as_ref()),
+            };
+            if !filtered || attempt == self.retries {
+                return result.map(|mut resp| {
+                    resp.metadata.prompt_mutations = applied;
+                    resp
+                });
+            }
+
+            attempt += 1;
+            seed = Seed::Random;
+            // the first retry keeps the prompts
+            let mutation = match attempt {
+                1 => None,
+                n => self.mutations.get(n as usize - 2),
+            };
+            if let Some(mutation) = mutation {
+                if mutation.apply(&mut prompts) {
+                    applied.push(mutation.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Whether none of the artifacts of `resp` succeeded because the safety
+/// filter blurred them
+pub fn is_filtered(resp: &ImageResponse) -> bool {
+    resp.successful().next().is_none()
+        && resp
+            .filter_by_finish_reason(FINISH_CONTENT_FILTERED)
+            .next()
+            .is_some()
+}
+
+/// Whether an error means the API rejected the prompts as unsafe
+pub fn is_prompt_flagged(err: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    match err.downcast_ref::<Error>() {
+        Some(Error::ClientSendRequestError(api_err)) => {
+            crate::error::FLAGGED.contains(&api_err.name.as_str())
+        }
+        _ => false,
+    }
+}
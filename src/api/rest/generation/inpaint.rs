@@ -0,0 +1,74 @@
+use super::*;
+use super::edit::edit;
+
+pub use crate::model::inpaint::*;
+
+impl Inpaint {
+    /// Fill the masked part of the image according to the prompt
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use stability_rs::{inpaint::*, OutputFormat, Result};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///    let request = InpaintBuilder::new()
+    ///      .image("living_room.png")?
+    ///      .mask("sofa_mask.png")?
+    ///      .prompt("a green velvet sofa")?
+    ///      .output_format(OutputFormat::Webp)?
+    ///      .build()?;
+    ///
+    ///    let resp = request.generate().await?;
+    ///
+    ///    resp.save("living_room_green.webp").await?;
+    ///
+    ///    Ok(())
+    /// }
+    /// ```
+    pub async fn generate(&self) -> Result<EditResponse> {
+        edit(self).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeTransport;
+
+    #[tokio::test]
+    async fn generate_is_posting_to_the_v2beta_route() {
+        let path = std::env::temp_dir().join(format!(
+            "stability_rs_inpaint_test_{}.png",
+            std::process::id()
+        ));
+        std::fs::write(&path, crate::testing::png_1x1()).unwrap();
+        let request = InpaintBuilder::new()
+            .image(&path)
+            .unwrap()
+            .prompt("a green velvet sofa")
+            .unwrap()
+            .build()
+            .unwrap();
+        let transport = FakeTransport::new(|_| {
+            Response::builder()
+                .header(CONTENT_TYPE, IMAGE_PNG)
+                .header("seed", "42")
+                .header("finish-reason", "SUCCESS")
+                .body(Bytes::from(crate::testing::png_1x1()))
+                .unwrap()
+        });
+
+        let resp = transport.scope(request.generate()).await.unwrap();
+
+        assert_eq!(resp.seed, Some(42));
+        assert_eq!(resp.artifact.content_type(), IMAGE_PNG);
+        let requests = transport.requests();
+        assert_eq!(requests[0].uri.path(), "/v2beta/stable-image/edit/inpaint");
+        let body = String::from_utf8_lossy(&requests[0].body);
+        assert!(body.contains("name=\"prompt\"\r\n\r\na green velvet sofa"));
+        assert!(!body.contains("name=\"mask\""));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,259 @@
+use super::UploadOptions;
+use rand::Rng;
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+tokio::task_local! {
+    static FIXED_BOUNDARY: String;
+}
+
+/// Run `f` with every multipart body it builds delimited by `boundary`
+/// rather than a random one, so tests and dry runs can compare bodies byte
+/// for byte
+pub async fn with_fixed_boundary<F: Future>(boundary: impl Into<String>, f: F) -> F::Output {
+    FIXED_BOUNDARY.scope(boundary.into(), f).await
+}
+
+pub struct MultipartFormData {
+    pub boundary: String,
+    pub body: Vec<u8>,
+}
+
+impl Default for MultipartFormData {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MultipartFormData {
+    /// A form delimited by a random boundary, or by the one set with
+    /// [`with_fixed_boundary`]
+    pub fn new() -> Self {
+        match FIXED_BOUNDARY.try_with(Clone::clone) {
+            Ok(boundary) => Self::with_boundary(boundary),
+            Err(_) => Self::with_boundary(format!(
+                "-----------------------------{}", rand::thread_rng().gen::<u64>())),
+        }
+    }
+
+    /// A form delimited by `boundary`, which must not occur in any part
+    pub fn with_boundary(boundary: impl Into<String>) -> Self {
+        Self {
+            boundary: boundary.into(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn add_text(&mut self, name: &str, value: &str) -> io::Result<()> {
+        write!(self.body, "--{}\r\n", self.boundary)?;
+        write!(self.body, "Content-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n", name, value)?;
+        Ok(())
+    }
+
+    pub fn add_file(&mut self, name: &str, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let content_type = image_content_type(path)?;
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        self.add_file_bytes(name, &path.to_string_lossy(), &content_type, &bytes)
+    }
+
+    /// Add `bytes` as a file part
+    ///
+    /// Only the basename of `filename` is sent, with characters outside
+    /// `[A-Za-z0-9._-]` replaced by `_`.
+    pub fn add_file_bytes(
+        &mut self,
+        name: &str,
+        filename: &str,
+        content_type: &str,
+        bytes: &[u8],
+    ) -> io::Result<()> {
+        let filename = sanitize_filename(filename);
+        write!(self.body, "--{}\r\n", self.boundary)?;
+        write!(self.body, "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n", name, filename)?;
+        write!(self.body, "Content-Type: {}\r\n\r\n", content_type)?;
+        self.body.extend_from_slice(bytes);
+        write!(self.body, "\r\n")?;
+        Ok(())
+    }
+
+    /// Add the image at `path` as a file part, prepared according to `options`
+    pub fn add_image(
+        &mut self,
+        name: &str,
+        path: impl AsRef<Path>,
+        options: &UploadOptions,
+    ) -> io::Result<()> {
+        let path = path.as_ref();
+        let content_type = image_content_type(path)?;
+
+        #[cfg(feature = "mmap")]
+        if options.memory_map {
+            let file = File::open(path)?;
+            // SAFETY: the map is only read while the part is copied into the
+            // body, and callers opting in keep the file unchanged meanwhile
+            let map = unsafe { memmap2::Mmap::map(&file)? };
+            return self.add_image_bytes(name, path, &content_type, &map, options);
+        }
+
+        let bytes = std::fs::read(path)?;
+        self.add_image_bytes(name, path, &content_type, &bytes, options)
+    }
+
+    fn add_image_bytes(
+        &mut self,
+        name: &str,
+        path: &Path,
+        content_type: &str,
+        bytes: &[u8],
+        options: &UploadOptions,
+    ) -> io::Result<()> {
+        #[cfg(feature = "image")]
+        if options.exif_orientation {
+            // re-encoding drops the metadata along with the orientation tag
+            if let Some(png) = super::upload::oriented_png(bytes)? {
+                let filename = path.with_extension("png");
+                return self.add_file_bytes(name, &filename.to_string_lossy(), "image/png", &png);
+            }
+        }
+
+        if options.strip_metadata {
+            if let Some(stripped) = super::upload::stripped(bytes) {
+                return self.add_file_bytes(name, &path.to_string_lossy(), content_type, &stripped);
+            }
+        }
+
+        self.add_file_bytes(name, &path.to_string_lossy(), content_type, bytes)
+    }
+
+    pub fn end_body(&mut self) -> io::Result<()> {
+        write!(self.body, "--{}--\r\n", self.boundary)?;
+        Ok(())
+    }
+
+}
+
+/// The content type of an image file, going by its extension
+fn image_content_type(path: &Path) -> io::Result<String> {
+    match path.extension() {
+        Some(extension) => Ok(format!(
+            "image/{}",
+            extension.to_string_lossy().to_ascii_lowercase()
+        )),
+        None => Err(io::Error::other("Invalid file path")),
+    }
+}
+
+/// The basename of `filename`, split on both `/` and `\\` whatever the
+/// platform, with every character but ASCII letters, digits, `.`, `-` and `_`
+/// replaced by `_`
+///
+/// Local directory names are never sent to the API, and quotes or line breaks
+/// can't end the `Content-Disposition` header early. A name left without any
+/// letter or digit becomes `image` plus the original extension.
+pub(crate) fn sanitize_filename(filename: &str) -> String {
+    let basename = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let sanitized: String = basename
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect();
+
+    let (stem, extension) = match sanitized.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+        _ => (sanitized.as_str(), None),
+    };
+    if stem.chars().any(|c| c.is_ascii_alphanumeric()) {
+        return sanitized;
+    }
+    match extension {
+        Some(extension) if !extension.is_empty() => format!("image.{}", extension),
+        _ => "image".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn add_image_is_sending_the_same_part_when_memory_mapped() {
+        let path = std::env::temp_dir().join(format!("stability_rs_mmap_{}.png", std::process::id()));
+        std::fs::write(&path, crate::testing::png_1x1()).unwrap();
+        let mut options = UploadOptions::default();
+        let mut read = MultipartFormData::new();
+        read.add_image("init_image", &path, &options).unwrap();
+
+        options.memory_map = true;
+        let mut mapped = MultipartFormData {
+            boundary: read.boundary.clone(),
+            body: Vec::new(),
+        };
+        mapped.add_image("init_image", &path, &options).unwrap();
+
+        assert_eq!(mapped.body, read.body);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn sanitize_filename_is_dropping_unix_directories() {
+        assert_eq!(sanitize_filename("/home/alice/shots/init.png"), "init.png");
+    }
+
+    #[test]
+    fn sanitize_filename_is_dropping_windows_directories() {
+        assert_eq!(
+            sanitize_filename(r"C:\Users\alice\My Pictures\init.jpeg"),
+            "init.jpeg"
+        );
+        assert_eq!(sanitize_filename(r"\\?\C:\shots\mask.png"), "mask.png");
+    }
+
+    #[test]
+    fn sanitize_filename_is_replacing_spaces_and_quotes() {
+        assert_eq!(
+            sanitize_filename("my \"best\" shot.png"),
+            "my__best__shot.png"
+        );
+        assert_eq!(sanitize_filename("a\r\nb.png"), "a__b.png");
+    }
+
+    #[test]
+    fn sanitize_filename_is_replacing_unicode() {
+        assert_eq!(sanitize_filename("café.png"), "caf_.png");
+        assert_eq!(sanitize_filename("猫.webp"), "image.webp");
+        assert_eq!(sanitize_filename(""), "image");
+    }
+
+    #[test]
+    fn add_file_is_sending_only_the_basename() {
+        let dir = std::env::temp_dir().join(format!(
+            "stability_rs_multipart_{}/private dir",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("init image.png");
+        std::fs::write(&path, crate::testing::png_1x1()).unwrap();
+
+        let mut form = MultipartFormData::new();
+        form.add_file("init_image", &path).unwrap();
+
+        std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+        let body = String::from_utf8_lossy(&form.body);
+        assert!(body.contains("filename=\"init_image.png\""));
+        assert!(!body.contains("private"));
+    }
+
+    #[tokio::test]
+    async fn new_is_using_the_fixed_boundary_in_scope() {
+        let form = with_fixed_boundary("fixed", async { MultipartFormData::new() }).await;
+        assert_eq!(form.boundary, "fixed");
+        assert_ne!(MultipartFormData::new().boundary, "fixed");
+    }
+}
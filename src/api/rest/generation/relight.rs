@@ -0,0 +1,102 @@
+use super::*;
+use super::edit;
+use super::results::ResultHandle;
+
+pub use crate::model::relight::*;
+
+impl Relight {
+    /// Replace the background and relight the subject, waiting for the
+    /// result
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use stability_rs::{relight::*, Result};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///    let request = RelightBuilder::new()
+    ///      .subject_image("sneaker.png")?
+    ///      .background_prompt("a marble plinth in a sunlit gallery")?
+    ///      .light_source_direction(LightDirection::Left)?
+    ///      .build()?;
+    ///
+    ///    let resp = request.generate().await?;
+    ///
+    ///    resp.save("sneaker_gallery.png").await?;
+    ///
+    ///    Ok(())
+    /// }
+    /// ```
+    pub async fn generate(&self) -> Result<EditResponse> {
+        edit::edit(self).await
+    }
+
+    /// Start the generation, returning how to retrieve the result
+    pub async fn start(&self) -> Result<ResultHandle> {
+        edit::start(self).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeTransport;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn result_is_polled_until_the_image_is_ready() {
+        let path = std::env::temp_dir().join(format!(
+            "stability_rs_relight_test_{}.png",
+            std::process::id()
+        ));
+        std::fs::write(&path, crate::testing::png_1x1()).unwrap();
+        let request = RelightBuilder::new()
+            .subject_image(&path)
+            .unwrap()
+            .background_prompt("a sunlit gallery")
+            .unwrap()
+            .build()
+            .unwrap();
+        let polls = AtomicUsize::new(0);
+        let transport = FakeTransport::new(move |req| {
+            let json = |status, body: &'static str| {
+                Response::builder()
+                    .status(status)
+                    .header(CONTENT_TYPE, APPLICATION_JSON)
+                    .body(Bytes::from(body))
+                    .unwrap()
+            };
+            match (req.method.as_str(), polls.fetch_add(1, Ordering::SeqCst)) {
+                ("POST", _) => json(200, r#"{"id":"abc"}"#),
+                (_, 1) => json(202, r#"{"id":"abc","status":"in-progress"}"#),
+                _ => Response::builder()
+                    .header(CONTENT_TYPE, IMAGE_PNG)
+                    .header("seed", "7")
+                    .body(Bytes::from(crate::testing::png_1x1()))
+                    .unwrap(),
+            }
+        });
+
+        let resp = transport
+            .scope(async {
+                let pending = request.start().await?;
+                pending.wait(Duration::from_millis(1)).await
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(resp.seed, Some(7));
+        let paths: Vec<_> = transport.requests().iter().map(|r| r.uri.path().to_string()).collect();
+        assert_eq!(
+            paths,
+            [
+                "/v2beta/stable-image/edit/replace-background-and-relight",
+                "/v2beta/results/abc",
+                "/v2beta/results/abc",
+            ]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
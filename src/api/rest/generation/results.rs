@@ -0,0 +1,165 @@
+//! Results of the asynchronous v2beta endpoints.
+//!
+//! Slow edits answer straight away with the id of a generation rather than
+//! the image, wrapped here in a [`ResultHandle`]. [`ResultHandle::fetch`]
+//! asks for the result once, and [`ResultHandle::wait`] keeps asking until it
+//! is ready.
+//!
+//! The API keeps results for 24 hours. A handle serializes with the endpoint
+//! it came from and when it was created, so a service can hand it to another
+//! process, which then fails fast with [`Error::ResultExpired`] rather than
+//! polling for a result that is gone.
+//!
+#![cfg_attr(feature = "relight", doc = "```no_run")]
+#![cfg_attr(not(feature = "relight"), doc = "```ignore")]
+//! use stability_rs::relight::RelightBuilder;
+//! use stability_rs::results::ResultHandle;
+//! use stability_rs::Result;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     let request = RelightBuilder::new()
+//!         .subject_image("sneaker.png")?
+//!         .background_prompt("a marble plinth in a sunlit gallery")?
+//!         .build()?;
+//!     let handle = request.start().await?;
+//!     let json = serde_json::to_string(&handle)?;
+//!
+//!     // later, maybe in a worker process
+//!     let handle: ResultHandle = serde_json::from_str(&json)?;
+//!     if let Some(resp) = handle.fetch().await? {
+//!         resp.save("sneaker_gallery.png").await?;
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use super::*;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const RESULTS_PATH: &str = "/results";
+
+/// The interval the `generate` methods of asynchronous endpoints poll at,
+/// as the API documentation recommends
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long the API keeps a result
+pub const RESULT_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Deserialize)]
+struct Started {
+    id: String,
+}
+
+/// A generation running, or finished, on the API
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ResultHandle {
+    pub id: String,
+    /// The path of the request which started the generation, below the API
+    /// version, e.g. `/stable-image/edit/replace-background-and-relight`
+    pub endpoint: String,
+    /// Seconds since the Unix epoch
+    pub created_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    organization: Option<String>,
+}
+
+impl ResultHandle {
+    /// Read the id from the answer of an asynchronous endpoint
+    pub(crate) fn from_response(
+        endpoint: String,
+        bytes: &[u8],
+        organization: Option<String>,
+    ) -> Result<Self> {
+        let started = crate::schema::from_slice::<Started>(bytes)?;
+        Ok(Self {
+            id: started.id,
+            endpoint,
+            created_at: unix_secs(SystemTime::now()),
+            organization,
+        })
+    }
+
+    /// How long until the API forgets the result, or `None` once it has
+    pub fn expires_in(&self) -> Option<Duration> {
+        let age = unix_secs(SystemTime::now()).saturating_sub(self.created_at);
+        RESULT_LIFETIME
+            .checked_sub(Duration::from_secs(age))
+            .filter(|left| !left.is_zero())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_in().is_none()
+    }
+
+    /// The result, or `None` while it is still being generated
+    pub async fn fetch(&self) -> Result<Option<EditResponse>> {
+        if self.is_expired() {
+            return Err(Box::new(Error::ResultExpired {
+                id: self.id.clone(),
+            }));
+        }
+
+        let mut cb = ClientBuilder::new()?;
+        if let Some(organization) = &self.organization {
+            cb = cb.organization(organization)?;
+        }
+
+        let c = cb
+            .method(GET)?
+            .api_version(ApiVersion::V2Beta)?
+            .path(format!("{}/{}", RESULTS_PATH, self.id))?
+            .header(ACCEPT, IMAGE_ANY)?
+            .build()?;
+
+        let (headers, bytes) = c.send_request_with_headers(Empty::<Bytes>::new()).await?;
+
+        // the image is only sent once it is ready; until then the answer is a
+        // JSON status
+        let in_progress = headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with(APPLICATION_JSON));
+        if in_progress {
+            return Ok(None);
+        }
+        EditResponse::from_response(&headers, bytes).map(Some)
+    }
+
+    /// Fetch every `interval` until the result is ready
+    pub async fn wait(&self, interval: Duration) -> Result<EditResponse> {
+        loop {
+            if let Some(resp) = self.fetch().await? {
+                return Ok(resp);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn expired_handles_are_not_fetched() {
+        let json = r#"{"id":"abc","endpoint":"/stable-image/edit/replace-background-and-relight","created_at":1700000000}"#;
+        let handle: ResultHandle = serde_json::from_str(json).unwrap();
+        assert!(handle.is_expired());
+
+        let err = handle.fetch().await.unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ResultExpired { .. })
+        ));
+        assert_eq!(serde_json::to_string(&handle).unwrap(), json);
+    }
+}
@@ -0,0 +1,101 @@
+//! Image-to-image on inputs of any size.
+//!
+//! [`ImageToImage::generate_round_trip`] downscales the init image to a size
+//! the engine accepts, runs image-to-image on it and upscales the result back
+//! to the original dimensions, the usual restoration or stylization workflow
+//! for photos that are neither the right size nor the right aspect ratio.
+
+use super::img_to_img::ImageToImage;
+use super::upscale::{UpscaleEngine, UpscalerBuilder};
+use crate::error::Error;
+use crate::prelude::*;
+use crate::staging::TempStore;
+use crate::validation::fit_dimensions;
+use image::{imageops::FilterType, DynamicImage, GenericImageView, ImageFormat};
+
+impl ImageToImage {
+    /// Run image-to-image at an engine-legal size, then bring the result back
+    /// to the init image's dimensions with `upscale_engine`
+    pub async fn generate_round_trip(
+        &self,
+        engine: &str,
+        upscale_engine: UpscaleEngine,
+    ) -> Result<DynamicImage> {
+        let source = image::open(&self.init_image)?;
+        let (width, height) = source.dimensions();
+        let (fit_width, fit_height) = fit_dimensions(engine, width, height);
+
+        let staging = TempStore::new()?;
+        let init = staging.file("init.png");
+        source
+            .resize_exact(fit_width, fit_height, FilterType::Lanczos3)
+            .save_with_format(&init, ImageFormat::Png)?;
+        let mut request = self.clone();
+        request.init_image = init;
+        let resp = request.generate(engine).await?;
+
+        let Some(image) = resp.artifacts.into_iter().next() else {
+            return Err(Box::new(Error::NoArtifacts));
+        };
+        let generated = image.to_artifact()?.decode()?;
+        if generated.width() >= width {
+            return Ok(generated.resize_exact(width, height, FilterType::Lanczos3));
+        }
+
+        let generated_path = staging.file("generated.png");
+        generated.save_with_format(&generated_path, ImageFormat::Png)?;
+        let mut upscaler = UpscalerBuilder::new().image(&generated_path)?;
+        if let Some(organization) = &self.organization {
+            upscaler = upscaler.organization(organization)?;
+        }
+        let upscaled = upscaler
+            .build()?
+            .generate_tiled(upscale_engine, width)
+            .await?;
+
+        // the fitted size only approximates the aspect ratio, so pin it back
+        Ok(upscaled.resize_exact(width, height, FilterType::Lanczos3))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_to_img::ImageToImageBuilder;
+    use crate::StylePreset;
+
+    #[tokio::test]
+    async fn round_trip_is_restoring_the_original_size() {
+        let path = std::env::temp_dir().join(format!(
+            "stability_rs_round_trip_test_{}.png",
+            std::process::id()
+        ));
+        image::RgbaImage::new(300, 200).save(&path).unwrap();
+        let request = ImageToImageBuilder::new()
+            .init_image_path(&path)
+            .unwrap()
+            .style_preset(StylePreset::Photographic)
+            .unwrap()
+            .text_prompt("a watercolour harbour", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let transport = crate::mock::transport();
+        let image = transport
+            .scope(request.generate_round_trip(
+                "stable-diffusion-xl-1024-v1-0",
+                UpscaleEngine::EsrganV1X2Plus,
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(image.dimensions(), (300, 200));
+        let requests = transport.requests();
+        assert!(requests[0].uri.path().ends_with("/image-to-image"));
+        assert!(requests[1..]
+            .iter()
+            .all(|r| r.uri.path().ends_with("/image-to-image/upscale")));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,245 @@
+//! Client-side tiling for upscale targets a single call cannot reach.
+//!
+//! The upscale engines cap the size of the images they accept and return, so
+//! [`Upscaler::generate_tiled`] splits the image into overlapping tiles,
+//! upscales each one separately and feathers the overlaps together, repeating
+//! the pass until the target width is reached.
+
+use super::upscale::{UpscaleEngine, Upscaler};
+use crate::error::Error;
+use crate::prelude::*;
+use crate::staging::TempStore;
+use image::{imageops::FilterType, DynamicImage, GenericImageView, ImageFormat, RgbaImage};
+use std::path::PathBuf;
+
+/// Side of the source tiles sent to the API
+pub const TILE_SIZE: u32 = 512;
+/// Overlap between neighbouring source tiles, blended to hide the seams
+pub const TILE_OVERLAP: u32 = 64;
+
+/// An upscaled tile and its position in the output image
+struct Tile {
+    x: u32,
+    y: u32,
+    image: RgbaImage,
+}
+
+impl Upscaler {
+    /// Upscale the image to `target_width`, preserving its aspect ratio, by
+    /// upscaling overlapping tiles and blending their seams
+    ///
+    /// Each pass multiplies the size by the engine's [`UpscaleEngine::factor`]
+    /// and costs one call per tile; the last pass is resized down to the
+    /// exact target locally.
+    pub async fn generate_tiled(
+        &self,
+        engine: UpscaleEngine,
+        target_width: u32,
+    ) -> Result<DynamicImage> {
+        let mut current = image::open(&self.image)?;
+        let staging = TempStore::new()?;
+        while current.width() < target_width {
+            current = self.upscale_pass(&current, &engine, &staging).await?;
+        }
+
+        let target_height =
+            (current.height() as u64 * target_width as u64 / current.width() as u64) as u32;
+        Ok(current.resize_exact(target_width, target_height.max(1), FilterType::Lanczos3))
+    }
+
+    async fn upscale_pass(
+        &self,
+        source: &DynamicImage,
+        engine: &UpscaleEngine,
+        staging: &TempStore,
+    ) -> Result<DynamicImage> {
+        let factor = engine.factor();
+        let (width, height) = source.dimensions();
+        let mut tiles = Vec::new();
+
+        for y in tile_starts(height, TILE_SIZE, TILE_OVERLAP) {
+            for x in tile_starts(width, TILE_SIZE, TILE_OVERLAP) {
+                let tile = source.crop_imm(x, y, TILE_SIZE.min(width), TILE_SIZE.min(height));
+                let path = staging.file(&format!("tile_{}_{}_{}.png", width, x, y));
+                let upscaled = self.upscale_tile(&tile, engine, path).await?;
+                // pin the tile to its exact slot in case the engine rounded its size
+                let image = upscaled
+                    .resize_exact(
+                        tile.width() * factor,
+                        tile.height() * factor,
+                        FilterType::Lanczos3,
+                    )
+                    .to_rgba8();
+                tiles.push(Tile {
+                    x: x * factor,
+                    y: y * factor,
+                    image,
+                });
+            }
+        }
+
+        Ok(DynamicImage::ImageRgba8(blend(
+            width * factor,
+            height * factor,
+            &tiles,
+            TILE_OVERLAP * factor,
+        )))
+    }
+
+    async fn upscale_tile(
+        &self,
+        tile: &DynamicImage,
+        engine: &UpscaleEngine,
+        path: PathBuf,
+    ) -> Result<DynamicImage> {
+        tile.save_with_format(&path, ImageFormat::Png)?;
+
+        let request = Upscaler {
+            image: path.clone(),
+            height: 0,
+            width: 0,
+            text_prompts: self.text_prompts.clone(),
+            cfg_scale: self.cfg_scale,
+            seed: self.seed,
+            steps: self.steps,
+            organization: self.organization.clone(),
+            upload: self.upload,
+        };
+        let resp = request.generate(engine.clone()).await;
+        // don't hold every tile of a huge upscale on disk until the store drops
+        let _ = std::fs::remove_file(&path);
+
+        let Some(image) = resp?.artifacts.into_iter().next() else {
+            return Err(Box::new(Error::NoArtifacts));
+        };
+        image.to_artifact()?.decode()
+    }
+}
+
+/// Offsets of tiles of side `tile` covering `len` pixels, neighbours sharing
+/// at least `overlap` pixels
+fn tile_starts(len: u32, tile: u32, overlap: u32) -> Vec<u32> {
+    if len <= tile {
+        return vec![0];
+    }
+
+    let step = tile - overlap;
+    let mut starts: Vec<u32> = (0..)
+        .map(|i| i * step)
+        .take_while(|s| s + tile < len)
+        .collect();
+    starts.push(len - tile);
+    starts
+}
+
+/// Weight of a pixel `pos` along a tile of `len` pixels, ramping up over the
+/// overlap on the sides that have a neighbour
+fn feather(pos: u32, len: u32, overlap: u32, lead: bool, trail: bool) -> f32 {
+    let ramp = (overlap + 1) as f32;
+    let mut weight: f32 = 1.0;
+    if lead {
+        weight = weight.min((pos + 1) as f32 / ramp);
+    }
+    if trail {
+        weight = weight.min((len - pos) as f32 / ramp);
+    }
+    weight
+}
+
+/// Compose tiles into a `width` x `height` image, averaging the overlaps
+fn blend(width: u32, height: u32, tiles: &[Tile], overlap: u32) -> RgbaImage {
+    let mut sums = vec![[0f32; 4]; (width * height) as usize];
+    let mut weights = vec![0f32; (width * height) as usize];
+
+    for tile in tiles {
+        let (tile_width, tile_height) = tile.image.dimensions();
+        for (px, py, pixel) in tile.image.enumerate_pixels() {
+            let (x, y) = (tile.x + px, tile.y + py);
+            if x >= width || y >= height {
+                continue;
+            }
+            let wx = feather(
+                px,
+                tile_width,
+                overlap,
+                tile.x > 0,
+                tile.x + tile_width < width,
+            );
+            let wy = feather(
+                py,
+                tile_height,
+                overlap,
+                tile.y > 0,
+                tile.y + tile_height < height,
+            );
+            let weight = wx * wy;
+
+            let i = (y * width + x) as usize;
+            for (sum, channel) in sums[i].iter_mut().zip(pixel.0) {
+                *sum += channel as f32 * weight;
+            }
+            weights[i] += weight;
+        }
+    }
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let i = (y * width + x) as usize;
+        let weight = weights[i].max(f32::EPSILON);
+        image::Rgba(sums[i].map(|sum| (sum / weight).round().clamp(0.0, 255.0) as u8))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_starts_are_covering_the_whole_length() {
+        assert_eq!(tile_starts(300, 512, 64), vec![0]);
+        assert_eq!(tile_starts(1024, 512, 64), vec![0, 448, 512]);
+        for len in 513..2000 {
+            let starts = tile_starts(len, 512, 64);
+            assert_eq!(starts.last().unwrap() + 512, len);
+            assert!(starts.windows(2).all(|w| w[0] + 512 >= w[1] + 64));
+        }
+    }
+
+    #[test]
+    fn blend_is_reassembling_unscaled_tiles_exactly() {
+        let source = RgbaImage::from_fn(300, 200, |x, y| {
+            image::Rgba([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8, 255])
+        });
+        let mut tiles = Vec::new();
+        for y in tile_starts(200, 128, 16) {
+            for x in tile_starts(300, 128, 16) {
+                let image = image::imageops::crop_imm(&source, x, y, 128, 128).to_image();
+                tiles.push(Tile { x, y, image });
+            }
+        }
+
+        assert_eq!(blend(300, 200, &tiles, 16), source);
+    }
+
+    #[tokio::test]
+    async fn generate_tiled_is_reaching_the_target_width() {
+        let path =
+            std::env::temp_dir().join(format!("stability_rs_tiled_{}.png", std::process::id()));
+        RgbaImage::new(600, 300).save(&path).unwrap();
+        let upscaler = crate::upscale::UpscalerBuilder::new()
+            .image(&path)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let transport = crate::mock::transport();
+        let image = transport
+            .scope(upscaler.generate_tiled(UpscaleEngine::EsrganV1X2Plus, 1000))
+            .await
+            .unwrap();
+
+        assert_eq!(image.dimensions(), (1000, 500));
+        // one pass over two overlapping 512px columns
+        assert_eq!(transport.requests().len(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,222 @@
+//! Preparing init and mask images before they are uploaded.
+
+#[cfg(feature = "image")]
+use image::{metadata::Orientation, DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+#[cfg(feature = "image")]
+use std::io::{self, Cursor};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// PNG chunks that can carry EXIF, XMP or free-form text
+const PNG_METADATA_CHUNKS: [&[u8]; 4] = [b"eXIf", b"tEXt", b"zTXt", b"iTXt"];
+const JPEG_EXIF_HEADER: &[u8] = b"Exif\0\0";
+const JPEG_XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// A copy of a JPEG, PNG or WebP file without its EXIF and XMP metadata
+///
+/// Files in other formats, or too malformed to walk, give `None` and are
+/// uploaded unchanged.
+pub(crate) fn stripped(bytes: &[u8]) -> Option<Vec<u8>> {
+    if bytes.starts_with(&[0xff, 0xd8]) {
+        strip_jpeg(bytes)
+    } else if bytes.starts_with(PNG_SIGNATURE) {
+        strip_png(bytes)
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        strip_webp(bytes)
+    } else {
+        None
+    }
+}
+
+fn strip_jpeg(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut out = bytes[..2].to_vec();
+    let mut pos = 2;
+    loop {
+        if *bytes.get(pos)? != 0xff {
+            return None;
+        }
+        let marker = *bytes.get(pos + 1)?;
+        // the entropy-coded scan follows start of scan, copy it verbatim
+        if marker == 0xda {
+            out.extend_from_slice(&bytes[pos..]);
+            return Some(out);
+        }
+        if marker == 0xd9 || marker == 0x01 || (0xd0..=0xd7).contains(&marker) {
+            out.extend_from_slice(&bytes[pos..pos + 2]);
+            pos += 2;
+            if marker == 0xd9 {
+                return Some(out);
+            }
+            continue;
+        }
+
+        let len = u16::from_be_bytes([*bytes.get(pos + 2)?, *bytes.get(pos + 3)?]) as usize;
+        let segment = bytes.get(pos..pos + 2 + len)?;
+        let payload = segment.get(4..)?;
+        let metadata = marker == 0xe1
+            && (payload.starts_with(JPEG_EXIF_HEADER) || payload.starts_with(JPEG_XMP_HEADER));
+        if !metadata {
+            out.extend_from_slice(segment);
+        }
+        pos += 2 + len;
+    }
+}
+
+fn strip_png(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut out = PNG_SIGNATURE.to_vec();
+    let mut pos = PNG_SIGNATURE.len();
+    while pos < bytes.len() {
+        let len = u32::from_be_bytes(bytes.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        // length, type, data and crc
+        let chunk = bytes.get(pos..pos + 12 + len)?;
+        if !PNG_METADATA_CHUNKS.contains(&&chunk[4..8]) {
+            out.extend_from_slice(chunk);
+        }
+        pos += 12 + len;
+    }
+    Some(out)
+}
+
+fn strip_webp(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut out = bytes[..12].to_vec();
+    let mut pos = 12;
+    while pos < bytes.len() {
+        let len = u32::from_le_bytes(bytes.get(pos + 4..pos + 8)?.try_into().ok()?) as usize;
+        // chunks are padded to an even length
+        let chunk = bytes.get(pos..(pos + 8 + len + len % 2).min(bytes.len()))?;
+        match &chunk[..4] {
+            b"EXIF" | b"XMP " => {}
+            b"VP8X" => {
+                let mut chunk = chunk.to_vec();
+                // clear the EXIF and XMP presence flags
+                *chunk.get_mut(8)? &= !0x0c;
+                out.extend_from_slice(&chunk);
+            }
+            _ => out.extend_from_slice(chunk),
+        }
+        pos += chunk.len();
+    }
+    let riff_len = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&riff_len.to_le_bytes());
+    Some(out)
+}
+
+/// Re-encode `bytes` as an upright PNG when its EXIF orientation asks for a
+/// rotation or flip; `None` when it is already upright
+#[cfg(feature = "image")]
+pub(crate) fn oriented_png(bytes: &[u8]) -> io::Result<Option<Vec<u8>>> {
+    let mut decoder = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()?
+        .into_decoder()
+        .map_err(io::Error::other)?;
+    let orientation = decoder.orientation().map_err(io::Error::other)?;
+    if orientation == Orientation::NoTransforms {
+        return Ok(None);
+    }
+
+    let mut image = DynamicImage::from_decoder(decoder).map_err(io::Error::other)?;
+    image.apply_orientation(orientation);
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(io::Error::other)?;
+    Ok(Some(png))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A JPEG skeleton holding an EXIF segment, an XMP segment and a comment
+    fn jpeg_with_metadata() -> Vec<u8> {
+        let mut jpeg = vec![0xff, 0xd8];
+        for payload in [
+            [JPEG_EXIF_HEADER, b"GPS"].concat(),
+            [JPEG_XMP_HEADER, b"<x/>"].concat(),
+        ] {
+            jpeg.extend_from_slice(&[0xff, 0xe1]);
+            jpeg.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+            jpeg.extend_from_slice(&payload);
+        }
+        jpeg.extend_from_slice(&[0xff, 0xfe, 0x00, 0x04, b'h', b'i']);
+        jpeg.extend_from_slice(&[0xff, 0xda, 0x00, 0x02, 0x12, 0x34, 0xff, 0xd9]);
+        jpeg
+    }
+
+    #[test]
+    fn jpeg_exif_and_xmp_segments_are_removed() {
+        assert_eq!(
+            stripped(&jpeg_with_metadata()).unwrap(),
+            [
+                0xff, 0xd8, 0xff, 0xfe, 0x00, 0x04, b'h', b'i', 0xff, 0xda, 0x00, 0x02, 0x12, 0x34,
+                0xff, 0xd9
+            ]
+        );
+    }
+
+    #[test]
+    fn png_text_and_exif_chunks_are_removed() {
+        let chunk = |kind: &[u8], data: &[u8]| {
+            [&(data.len() as u32).to_be_bytes()[..], kind, data, &[0; 4]].concat()
+        };
+        let png = [
+            PNG_SIGNATURE.to_vec(),
+            chunk(b"IHDR", &[1; 13]),
+            chunk(b"eXIf", b"GPS"),
+            chunk(b"tEXt", b"Serial\0SN-1234"),
+            chunk(b"IEND", &[]),
+        ]
+        .concat();
+
+        let expected = [
+            PNG_SIGNATURE.to_vec(),
+            chunk(b"IHDR", &[1; 13]),
+            chunk(b"IEND", &[]),
+        ]
+        .concat();
+        assert_eq!(stripped(&png).unwrap(), expected);
+    }
+
+    #[test]
+    fn unknown_formats_are_left_alone() {
+        assert!(stripped(b"GIF89a").is_none());
+    }
+
+    /// A 2x1 JPEG tagged with the given EXIF orientation
+    #[cfg(feature = "image")]
+    fn jpeg_with_orientation(orientation: u16) -> Vec<u8> {
+        let mut jpeg = Vec::new();
+        DynamicImage::new_rgb8(2, 1)
+            .write_to(&mut Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+            .unwrap();
+
+        let mut tiff = b"MM\0\x2a\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01".to_vec();
+        tiff.extend_from_slice(&orientation.to_be_bytes());
+        tiff.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend_from_slice(&tiff);
+
+        let mut out = jpeg[..2].to_vec();
+        out.extend_from_slice(&[0xff, 0xe1]);
+        out.extend_from_slice(&((app1.len() + 2) as u16).to_be_bytes());
+        out.extend_from_slice(&app1);
+        out.extend_from_slice(&jpeg[2..]);
+        out
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn rotated_photos_are_reencoded_upright() {
+        use image::GenericImageView;
+
+        let png = oriented_png(&jpeg_with_orientation(6)).unwrap().unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!(image.dimensions(), (1, 2));
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn upright_photos_are_left_alone() {
+        assert!(oriented_png(&jpeg_with_orientation(1)).unwrap().is_none());
+    }
+}
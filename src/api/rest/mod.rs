@@ -1,4 +1,12 @@
+pub mod artifact;
 pub mod client;
+pub mod connect;
+#[cfg(feature = "engines")]
 pub mod engine;
+pub(crate) mod exchange;
 pub mod generation;
+pub mod pagination;
+pub(crate) mod pool;
+#[cfg(feature = "user")]
 pub mod user;
+pub mod version;
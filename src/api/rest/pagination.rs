@@ -0,0 +1,145 @@
+//! Paging through list endpoints.
+//!
+//! List endpoints such as fine-tunes or generation history answer a page at
+//! a time, along with a cursor for the next one. [`Paginated`] hides the
+//! cursor: endpoint modules give it a function fetching the [`Page`] after a
+//! cursor, and callers either ask for one page at a time with
+//! [`Paginated::next_page`] or stream every item with
+//! [`Paginated::into_stream`].
+//!
+//! ```
+//! use futures_util::TryStreamExt;
+//! use stability_rs::api::rest::pagination::{Page, Paginated};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> stability_rs::Result<()> {
+//! let numbers = Paginated::new(|cursor: Option<String>| async move {
+//!     let start = cursor.map_or(0, |c| c.parse().unwrap());
+//!     Ok(Page {
+//!         items: vec![start, start + 1],
+//!         next: (start < 2).then(|| (start + 2).to_string()),
+//!     })
+//! });
+//!
+//! assert_eq!(numbers.into_stream().try_collect::<Vec<u32>>().await?, [0, 1, 2, 3]);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::prelude::*;
+use futures_util::future::BoxFuture;
+use futures_util::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use std::future::Future;
+
+type Fetch<T> = Box<dyn FnMut(Option<String>) -> BoxFuture<'static, Result<Page<T>>> + Send>;
+
+/// One page of a list, and the cursor of the page after it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// `None` on the last page
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Cursor {
+    Start,
+    At(String),
+    Done,
+}
+
+/// The pages of a list endpoint, fetched as they are asked for
+pub struct Paginated<T> {
+    fetch: Fetch<T>,
+    cursor: Cursor,
+}
+
+impl<T: Send + 'static> Paginated<T> {
+    /// Page through a list with `fetch`, which is given the cursor of the
+    /// page wanted, `None` for the first
+    pub fn new<F, Fut>(mut fetch: F) -> Self
+    where
+        F: FnMut(Option<String>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<Page<T>>> + Send + 'static,
+    {
+        Self {
+            fetch: Box::new(move |cursor| Box::pin(fetch(cursor))),
+            cursor: Cursor::Start,
+        }
+    }
+
+    /// The items of the next page, or `None` once the last page was returned
+    ///
+    /// A failed request leaves the cursor where it was, so it can be retried.
+    pub async fn next_page(&mut self) -> Result<Option<Vec<T>>> {
+        let cursor = match &self.cursor {
+            Cursor::Start => None,
+            Cursor::At(cursor) => Some(cursor.clone()),
+            Cursor::Done => return Ok(None),
+        };
+
+        let page = (self.fetch)(cursor).await?;
+        self.cursor = page.next.map_or(Cursor::Done, Cursor::At);
+        Ok(Some(page.items))
+    }
+
+    /// Stream the remaining pages, ending after the first error
+    pub fn pages(self) -> BoxStream<'static, Result<Vec<T>>> {
+        stream::try_unfold(self, |mut paginated| async move {
+            Ok(paginated.next_page().await?.map(|items| (items, paginated)))
+        })
+        .boxed()
+    }
+
+    /// Stream the items of the remaining pages, ending after the first error
+    pub fn into_stream(self) -> BoxStream<'static, Result<T>> {
+        self.pages()
+            .map_ok(|items| stream::iter(items.into_iter().map(Ok)))
+            .try_flatten()
+            .boxed()
+    }
+}
+
+impl<T> std::fmt::Debug for Paginated<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Paginated")
+            .field("cursor", &self.cursor)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn next_page_is_retrying_the_same_cursor_after_an_error() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let seen = calls.clone();
+        let mut paginated = Paginated::new(move |cursor: Option<String>| {
+            let call = seen.fetch_add(1, Ordering::SeqCst);
+            async move {
+                match (call, cursor.as_deref()) {
+                    (0, None) => Ok(Page {
+                        items: vec!["a"],
+                        next: Some("2".to_string()),
+                    }),
+                    (1, Some("2")) => Err("rate limited".into()),
+                    (2, Some("2")) => Ok(Page {
+                        items: vec!["b"],
+                        next: None,
+                    }),
+                    other => panic!("unexpected fetch {:?}", other),
+                }
+            }
+        });
+
+        assert_eq!(paginated.next_page().await.unwrap(), Some(vec!["a"]));
+        assert!(paginated.next_page().await.is_err());
+        assert_eq!(paginated.next_page().await.unwrap(), Some(vec!["b"]));
+        assert_eq!(paginated.next_page().await.unwrap(), None);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,208 @@
+//! Keep-alive connections shared by the clients of the process.
+//!
+//! Once the [`global`](crate::global) client is initialized, requests are
+//! sent over a connection left idle by an earlier request to the same
+//! origin when there is one, and the connection is put back once the
+//! response is read, rather than dialing and negotiating TLS for each
+//! request. A connection the server closed while idle is skipped, and a
+//! request which couldn't go out on one is sent over a new connection.
+//! Clients with a [resolver](crate::api::rest::client::ClientBuilder::resolver)
+//! of their own stay out of the pool, so they only ever talk to the
+//! addresses their resolver picks.
+
+use crate::api::rest::client::Phases;
+use crate::api::rest::exchange::read_response;
+use crate::prelude::*;
+use crate::support::TokioIo;
+use crate::upload_progress::ProgressBody;
+use hyper::body::Bytes;
+use hyper::client::conn::http1::{handshake, SendRequest};
+use hyper::{Request, Response};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Idle connections kept to each origin unless the config says otherwise
+pub(crate) const DEFAULT_MAX_IDLE: usize = 8;
+
+#[derive(Debug)]
+pub(crate) struct Pool {
+    max_idle: usize,
+    /// By origin, such as `https://api.stability.ai`
+    idle: Mutex<HashMap<String, Vec<SendRequest<ProgressBody>>>>,
+}
+
+impl Pool {
+    /// A pool keeping up to `max_idle` idle connections to each origin
+    pub(crate) fn new(max_idle: usize) -> Self {
+        Self {
+            max_idle,
+            idle: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Send `req` over an idle connection to `origin`, or a new one from
+    /// `connect`, and read the response, erring once its body grows past
+    /// `limit` bytes
+    pub(crate) async fn send<I, F>(
+        &self,
+        origin: &str,
+        connect: F,
+        mut req: Request<ProgressBody>,
+        limit: usize,
+    ) -> Result<Response<Bytes>>
+    where
+        I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        F: Future<Output = Result<I>>,
+    {
+        while let Some(mut sender) = self.checkout(origin) {
+            if sender.ready().await.is_err() {
+                continue;
+            }
+            let started = Instant::now();
+            match sender.try_send_request(req).await {
+                Ok(res) => {
+                    let res = read_response(res, started, limit).await?;
+                    self.checkin(origin, sender);
+                    return Ok(res);
+                }
+                Err(mut e) => match e.take_message() {
+                    // the server closed the connection before the request
+                    // went out, so it can go out on another
+                    Some(unsent) => req = unsent,
+                    None => return Err(Box::new(e.into_error())),
+                },
+            }
+        }
+
+        let started = Instant::now();
+        let (mut sender, conn) = handshake(TokioIo::new(connect.await?)).await?;
+        tokio::spawn(async move {
+            if let Err(e) = conn.await {
+                tracing::debug!("pooled connection error: {}", e);
+            }
+        });
+        let connected = started.elapsed();
+
+        let started = Instant::now();
+        let res = sender.send_request(req).await?;
+        let mut res = read_response(res, started, limit).await?;
+        if let Some(phases) = res.extensions_mut().get_mut::<Phases>() {
+            phases.connect = Some(connected);
+        }
+        self.checkin(origin, sender);
+        Ok(res)
+    }
+
+    /// Close the idle connections; those in use are still put back
+    pub(crate) fn close_idle(&self) {
+        self.idle.lock().unwrap().clear();
+    }
+
+    fn checkout(&self, origin: &str) -> Option<SendRequest<ProgressBody>> {
+        let mut idle = self.idle.lock().unwrap();
+        let senders = idle.get_mut(origin)?;
+        senders.retain(|sender| !sender.is_closed());
+        senders.pop()
+    }
+
+    fn checkin(&self, origin: &str, sender: SendRequest<ProgressBody>) {
+        if sender.is_closed() {
+            return;
+        }
+        let mut idle = self.idle.lock().unwrap();
+        let senders = idle.entry(origin.to_string()).or_default();
+        senders.retain(|sender| !sender.is_closed());
+        if senders.len() < self.max_idle {
+            senders.push(sender);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::Full;
+    use hyper::service::service_fn;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Answer every request with `ok`, counting the connections accepted
+    async fn serve(close: bool) -> (SocketAddr, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let count = accepted.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                count.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(
+                    hyper::server::conn::http1::Builder::new()
+                        .keep_alive(!close)
+                        .serve_connection(
+                            TokioIo::new(stream),
+                            service_fn(|_| async {
+                                Ok::<_, std::convert::Infallible>(Response::new(Full::new(
+                                    Bytes::from("ok"),
+                                )))
+                            }),
+                        ),
+                );
+            }
+        });
+        (addr, accepted)
+    }
+
+    async fn get(pool: &Pool, addr: SocketAddr) -> Result<Response<Bytes>> {
+        let req = Request::get("/")
+            .header("host", addr.to_string())
+            .body(ProgressBody::new(Bytes::new(), None))
+            .unwrap();
+        let connect = async move { Ok(TcpStream::connect(addr).await?) };
+        pool.send(&format!("http://{}", addr), connect, req, 1024).await
+    }
+
+    #[tokio::test]
+    async fn send_is_reusing_an_idle_connection() {
+        let (addr, accepted) = serve(false).await;
+        let pool = Pool::new(DEFAULT_MAX_IDLE);
+
+        let first = get(&pool, addr).await.unwrap();
+        let second = get(&pool, addr).await.unwrap();
+
+        assert_eq!(second.body().as_ref(), b"ok");
+        assert!(first.extensions().get::<Phases>().unwrap().connect.is_some());
+        assert!(second.extensions().get::<Phases>().unwrap().connect.is_none());
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn send_is_connecting_again_once_the_server_closed_the_connection() {
+        let (addr, accepted) = serve(true).await;
+        let pool = Pool::new(DEFAULT_MAX_IDLE);
+
+        for _ in 0..3 {
+            assert_eq!(get(&pool, addr).await.unwrap().body().as_ref(), b"ok");
+        }
+
+        assert_eq!(accepted.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn close_idle_is_dropping_the_idle_connections() {
+        let (addr, accepted) = serve(false).await;
+        let pool = Pool::new(DEFAULT_MAX_IDLE);
+
+        get(&pool, addr).await.unwrap();
+        pool.close_idle();
+        let res = get(&pool, addr).await.unwrap();
+
+        assert!(res.extensions().get::<Phases>().unwrap().connect.is_some());
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+    }
+}
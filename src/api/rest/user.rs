@@ -17,12 +17,12 @@ pub async fn get_user_account() -> Result<User> {
 
     let resp = c.send_request(Empty::<Bytes>::new()).await?;
 
-    let user = serde_json::from_slice::<User>(&resp.as_ref())?;
+    let user = crate::schema::from_slice::<User>(resp.as_ref())?;
 
     Ok(user)
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct User {
     email: String,
     id: String,
@@ -30,17 +30,64 @@ pub struct User {
     profile_picture: String,
 }
 
-#[derive(Debug, Deserialize)]
-struct Organization {
+impl User {
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The organizations the API key can bill requests to
+    pub fn organizations(&self) -> impl Iterator<Item = &Organization> {
+        self.organizations.iter()
+    }
+
+    /// The organization requests are billed to when none is specified
+    pub fn default_organization(&self) -> Option<&Organization> {
+        self.organizations.iter().find(|org| org.is_default)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Organization {
     id: String,
     is_default: bool,
     name: String,
     role: String,
 }
 
+impl Organization {
+    /// The id to pass to the `organization` setters of the request builders
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn role(&self) -> &str {
+        &self.role
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.is_default
+    }
+}
+
 /// Get the credit balance of the account/organizations associated with the API key
 pub async fn get_user_balance() -> Result<Balance> {
-    let cb = ClientBuilder::new()?;
+    user_balance(ClientBuilder::new()?).await
+}
+
+/// Get the credit balance of one of the organizations associated with the API key
+pub async fn get_organization_balance(organization: &str) -> Result<Balance> {
+    user_balance(ClientBuilder::new()?.organization(organization)?).await
+}
+
+async fn user_balance(cb: ClientBuilder) -> Result<Balance> {
     let c = cb
         .method(GET)?
         .path(BALANCE_PATH)?
@@ -49,12 +96,12 @@ pub async fn get_user_balance() -> Result<Balance> {
 
     let resp = c.send_request(Empty::<Bytes>::new()).await?;
 
-    let balance = serde_json::from_slice::<Balance>(&resp.as_ref())?;
+    let balance = crate::schema::from_slice::<Balance>(resp.as_ref())?;
 
     Ok(balance)
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Balance {
     credits: f64,
 }
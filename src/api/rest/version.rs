@@ -0,0 +1,68 @@
+//! Versions of the REST API.
+//!
+//! Every path is requested below a version prefix such as `/v1`, chosen with
+//! [`ClientBuilder::api_version`](super::client::ClientBuilder::api_version)
+//! rather than written into the path. When Stability promotes an endpoint,
+//! for instance from `v2beta` to `v2`, [`pin`] moves every request for the
+//! old version over without waiting on a release of this crate.
+//!
+//! ```
+//! use stability_rs::api::rest::version::{self, ApiVersion};
+//!
+//! version::pin(ApiVersion::V2Beta, ApiVersion::V2);
+//! assert_eq!(ApiVersion::V2Beta.resolve(), ApiVersion::V2);
+//! # version::reset_pins();
+//! ```
+
+use std::fmt;
+use std::sync::RwLock;
+
+static PINS: RwLock<Vec<(ApiVersion, ApiVersion)>> = RwLock::new(Vec::new());
+
+/// A version of the REST API, and the path prefix it is served under
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ApiVersion {
+    #[default]
+    V1,
+    V2Beta,
+    V2,
+}
+
+impl ApiVersion {
+    /// The path prefix, e.g. `/v1`
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "/v1",
+            ApiVersion::V2Beta => "/v2beta",
+            ApiVersion::V2 => "/v2",
+        }
+    }
+
+    /// The version requests for this one are sent to, after [`pin`]s
+    pub fn resolve(self) -> ApiVersion {
+        PINS.read()
+            .unwrap()
+            .iter()
+            .find(|(from, _)| *from == self)
+            .map_or(self, |(_, to)| *to)
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.prefix().trim_start_matches('/'))
+    }
+}
+
+/// Send every request for `from` to `to` instead
+pub fn pin(from: ApiVersion, to: ApiVersion) {
+    let mut pins = PINS.write().unwrap();
+    pins.retain(|(pinned, _)| *pinned != from);
+    if from != to {
+        pins.push((from, to));
+    }
+}
+
+pub fn reset_pins() {
+    PINS.write().unwrap().clear();
+}
@@ -0,0 +1,285 @@
+//! Writing output files so a crash never leaves a partial one behind.
+//!
+//! Every save helper of this crate writes to a temporary file next to the
+//! destination and renames it into place once the write has succeeded, so a
+//! file at the destination is always complete: either the previous version
+//! or the new one. Temporary files are named after the process and a
+//! counter, so retried or concurrent saves to the same path never share one,
+//! and they are removed when a write fails.
+//!
+//! Renaming makes the file whole, but not durable: after a power loss the
+//! operating system may not have written it to disk yet. [`enable_fsync`]
+//! makes every save flush the file and its directory to disk before
+//! returning, at a cost in latency.
+//!
+//! Saves replace an existing file unless given another [`OverwritePolicy`],
+//! as `Image::save_with_policy` and `BatchRunner::overwrite` take.
+
+use crate::error::Error;
+use crate::prelude::*;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+static FSYNC: AtomicBool = AtomicBool::new(false);
+
+/// Distinguishes the temporary files of one process
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+pub fn enable_fsync() {
+    FSYNC.store(true, Ordering::Relaxed);
+}
+
+pub fn disable_fsync() {
+    FSYNC.store(false, Ordering::Relaxed);
+}
+
+pub fn is_fsync_enabled() -> bool {
+    FSYNC.load(Ordering::Relaxed)
+}
+
+/// What a save does when a file exists at its destination already
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverwritePolicy {
+    /// Fail with [`Error::OutputExists`], leaving the file as it is
+    Error,
+    /// Replace the file
+    #[default]
+    Overwrite,
+    /// Save under the first free name with a `-1`, `-2`, ... suffix before
+    /// the extension, such as `image-1.png`
+    Rename,
+}
+
+/// A temporary file to write in place of `path`, removed when dropped
+/// unless [committed](TempPath::commit)
+#[derive(Debug)]
+pub(crate) struct TempPath {
+    /// Where the file ends up
+    target: PathBuf,
+    path: PathBuf,
+    committed: bool,
+}
+
+impl TempPath {
+    /// A hidden, unique name in the directory of `path`, so the rename stays
+    /// on one file system
+    pub fn new(path: &Path) -> Self {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let temp_path = path.with_file_name(format!(
+            ".{}.{}-{}.tmp",
+            name,
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        Self {
+            target: path.to_path_buf(),
+            path: temp_path,
+            committed: false,
+        }
+    }
+
+    /// Where to write the file's contents
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Move the written file into place, replacing any file there
+    ///
+    /// Whatever wrote the file must have closed or flushed it by now.
+    pub fn commit(self) -> Result<()> {
+        self.commit_with(OverwritePolicy::Overwrite)?;
+        Ok(())
+    }
+
+    /// Move the written file into place as `policy` says, flushing it to
+    /// disk first when [`is_fsync_enabled`], and return where it ended up
+    pub fn commit_with(mut self, policy: OverwritePolicy) -> Result<PathBuf> {
+        if is_fsync_enabled() {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(&self.path)?
+                .sync_all()?;
+        }
+        let placed = match policy {
+            OverwritePolicy::Overwrite => {
+                std::fs::rename(&self.path, &self.target)?;
+                self.target.clone()
+            }
+            OverwritePolicy::Error => {
+                if !self.place_new(&self.target)? {
+                    return Err(Box::new(Error::OutputExists(
+                        self.target.display().to_string(),
+                    )));
+                }
+                self.target.clone()
+            }
+            OverwritePolicy::Rename => {
+                let mut n = 0;
+                loop {
+                    let candidate = match n {
+                        0 => self.target.clone(),
+                        n => suffixed(&self.target, n),
+                    };
+                    if self.place_new(&candidate)? {
+                        break candidate;
+                    }
+                    n += 1;
+                }
+            }
+        };
+        self.committed = true;
+
+        // the rename itself is only durable once the directory is synced,
+        // which only Unix allows
+        #[cfg(unix)]
+        if is_fsync_enabled() {
+            let dir = match placed.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            std::fs::File::open(dir)?.sync_all()?;
+        }
+        Ok(placed)
+    }
+
+    /// Move the file to `target` unless a file is there, returning whether
+    /// it was moved
+    ///
+    /// Linking fails atomically when `target` exists, where checking first
+    /// and renaming after could race another save.
+    fn place_new(&self, target: &Path) -> io::Result<bool> {
+        match std::fs::hard_link(&self.path, target) {
+            Ok(()) => {
+                let _ = std::fs::remove_file(&self.path);
+                Ok(true)
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+            // file systems without hard links, such as FAT
+            Err(_) if target.exists() => Ok(false),
+            Err(_) => std::fs::rename(&self.path, target).map(|_| true),
+        }
+    }
+}
+
+/// `path` with `-n` appended to its file stem
+fn suffixed(path: &Path, n: usize) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match path.extension() {
+        Some(extension) => {
+            path.with_file_name(format!("{}-{}.{}", stem, n, extension.to_string_lossy()))
+        }
+        None => path.with_file_name(format!("{}-{}", stem, n)),
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Write `contents` to `path` through a [`TempPath`]
+// only the bench and prompt store modules write whole files synchronously
+#[cfg_attr(
+    not(any(feature = "text-to-image", feature = "image-to-image", feature = "prompt-store")),
+    allow(dead_code)
+)]
+pub(crate) fn write(path: &Path, contents: &[u8]) -> Result<()> {
+    let temp = TempPath::new(path);
+    std::fs::write(temp.path(), contents)?;
+    temp.commit()
+}
+
+/// Write `contents` to `path` through a [`TempPath`], without blocking the
+/// runtime on the write
+pub(crate) async fn write_async(
+    path: &Path,
+    contents: &[u8],
+    policy: OverwritePolicy,
+) -> Result<PathBuf> {
+    let temp = TempPath::new(path);
+    tokio::fs::write(temp.path(), contents).await?;
+    temp.commit_with(policy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "stability_rs_atomic_{}_{}",
+            name,
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn temp_path_is_removed_unless_committed() {
+        let dir = dir("dropped");
+        let path = dir.join("image.png");
+        std::fs::write(&path, b"old").unwrap();
+
+        let temp = TempPath::new(&path);
+        std::fs::write(temp.path(), b"partial").unwrap();
+        drop(temp);
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"old");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_is_replacing_the_file_whole() {
+        let dir = dir("write");
+        let path = dir.join("image.png");
+        std::fs::write(&path, b"old").unwrap();
+
+        write(&path, b"new").unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn commit_with_is_keeping_existing_files_unless_overwriting() {
+        let dir = dir("policy");
+        let path = dir.join("image.png");
+        std::fs::write(&path, b"old").unwrap();
+
+        let save = |policy| {
+            let temp = TempPath::new(&path);
+            std::fs::write(temp.path(), b"new").unwrap();
+            temp.commit_with(policy)
+        };
+        let err = save(OverwritePolicy::Error).unwrap_err();
+        let renamed = save(OverwritePolicy::Rename).unwrap();
+        let renamed_again = save(OverwritePolicy::Rename).unwrap();
+
+        assert_eq!(err.to_string(), format!("{} already exists", path.display()));
+        assert_eq!(renamed, dir.join("image-1.png"));
+        assert_eq!(renamed_again, dir.join("image-2.png"));
+        assert_eq!(std::fs::read(&path).unwrap(), b"old");
+        assert_eq!(std::fs::read(&renamed).unwrap(), b"new");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn temp_paths_are_unique_per_save() {
+        let path = Path::new("out/image.png");
+        assert_ne!(TempPath::new(path).path(), TempPath::new(path).path());
+    }
+}
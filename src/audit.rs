@@ -0,0 +1,559 @@
+//! An audit log of every request sent to the API.
+//!
+//! Once an [`AuditSink`] is [`install`]ed, or set on a single client with
+//! [`ClientBuilder::audit`](crate::api::rest::client::ClientBuilder::audit),
+//! every request is recorded as an [`AuditRecord`]: the endpoint, the
+//! request fields with prompt text hashed, the response status, the seeds of
+//! the returned artifacts and the estimated credits. [`JsonlAudit`] writes
+//! the records to a file or any other writer, one JSON object a line, which
+//! gives teams an account-level usage log without running a proxy.
+//!
+//! ```no_run
+//! use stability_rs::{audit, Result};
+//! use std::sync::Arc;
+//!
+//! fn main() -> Result<()> {
+//!     audit::install(Arc::new(audit::JsonlAudit::open("audit.jsonl")?));
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Prompt text is always hashed in audit records, whether or not
+//! [`crate::redaction`] is enabled. A sink failing to record is ignored, so
+//! auditing never fails a request.
+//!
+//! With the `encryption` feature, a [`JsonlAudit`] given a key, or running
+//! while one is [installed](crate::encryption::install), writes each record
+//! encrypted and base64-encoded instead, read back with [`decrypt_line`].
+
+use crate::credits;
+#[cfg(feature = "encryption")]
+use crate::encryption::EncryptionKey;
+use crate::prelude::*;
+use crate::provenance::rfc3339;
+use crate::redaction;
+use hyper::body::Bytes;
+use hyper::{Request, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Instant, SystemTime};
+
+static GLOBAL: RwLock<Option<Arc<dyn AuditSink>>> = RwLock::new(None);
+
+/// One request and what came back
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AuditRecord {
+    /// When the request was sent, in RFC 3339 UTC
+    pub timestamp: String,
+    pub method: String,
+    /// e.g. `/v1/generation/stable-diffusion-xl-1024-v1-0/text-to-image`
+    pub path: String,
+    /// The engine of a v1 generation request; `None` for endpoints not
+    /// naming one, such as the v2beta edit endpoints
+    pub engine: Option<String>,
+    /// The request's JSON or multipart fields, with prompt text hashed and
+    /// uploaded files left out
+    pub request: Value,
+    /// `None` when no response was received
+    pub status: Option<u16>,
+    /// Why no response was received
+    pub error: Option<String>,
+    /// The images returned, `None` when the response is not one the crate
+    /// knows how to count
+    pub artifacts: Option<usize>,
+    pub seeds: Vec<u32>,
+    /// `None` for endpoints without a known price
+    pub estimated_credits: Option<f64>,
+    pub latency_ms: u64,
+}
+
+/// Receives a record of every request
+pub trait AuditSink: Send + Sync {
+    fn record(&self, record: &AuditRecord) -> Result<()>;
+
+    /// Write out any records held back, called by
+    /// [`crate::lifecycle::shutdown`]
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for dyn AuditSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AuditSink")
+    }
+}
+
+/// Record every request sent by this crate to `sink`
+pub fn install(sink: Arc<dyn AuditSink>) {
+    *GLOBAL.write().unwrap() = Some(sink);
+}
+
+pub fn uninstall() {
+    *GLOBAL.write().unwrap() = None;
+}
+
+pub(crate) fn installed() -> Option<Arc<dyn AuditSink>> {
+    GLOBAL.read().unwrap().clone()
+}
+
+/// Writes records as JSON lines
+pub struct JsonlAudit<W> {
+    writer: Mutex<W>,
+    #[cfg(feature = "encryption")]
+    key: Option<EncryptionKey>,
+}
+
+impl JsonlAudit<std::fs::File> {
+    /// Append records to the file at `path`, creating it if needed
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        crate::preflight::create_parent_dirs(path)?;
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(file))
+    }
+}
+
+impl<W: Write + Send> JsonlAudit<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+            #[cfg(feature = "encryption")]
+            key: None,
+        }
+    }
+
+    /// Encrypt every line with `key`, whether or not a key is installed
+    #[cfg(feature = "encryption")]
+    pub fn encrypt(mut self, key: EncryptionKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer.into_inner().unwrap()
+    }
+}
+
+impl<W: Write + Send> AuditSink for JsonlAudit<W> {
+    fn record(&self, record: &AuditRecord) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        #[cfg(feature = "encryption")]
+        if let Some(key) = self.key.clone().or_else(crate::encryption::installed) {
+            line = key.encrypt_line(&line).into_bytes();
+        }
+        line.push(b'\n');
+        let mut writer = self.writer.lock().unwrap();
+        writer.write_all(&line)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.writer.lock().unwrap().flush()?;
+        Ok(())
+    }
+}
+
+/// The record of a line written by an encrypting [`JsonlAudit`]
+#[cfg(feature = "encryption")]
+pub fn decrypt_line(line: &str, key: &EncryptionKey) -> Result<AuditRecord> {
+    Ok(serde_json::from_slice(&key.decrypt_line(line)?)?)
+}
+
+/// A request on its way, recorded once its response is in
+pub(crate) struct Pending {
+    timestamp: String,
+    started: Instant,
+    method: String,
+    path: String,
+    request: Value,
+}
+
+impl Pending {
+    pub(crate) fn new(req: &Request<Bytes>) -> Self {
+        let content_type = req
+            .headers()
+            .get(hyper::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        let mut request = match content_type.split_once("boundary=") {
+            Some((_, boundary)) => multipart_fields(req.body(), boundary),
+            None => serde_json::from_slice(req.body()).unwrap_or(Value::Null),
+        };
+        redaction::redact(&mut request);
+
+        Self {
+            timestamp: rfc3339(SystemTime::now()),
+            started: Instant::now(),
+            method: req.method().to_string(),
+            path: req.uri().path().to_string(),
+            request,
+        }
+    }
+
+    /// The record of the exchange
+    pub(crate) fn into_record(
+        self,
+        res: std::result::Result<&Response<Bytes>, &(dyn std::error::Error + Send + Sync)>,
+    ) -> AuditRecord {
+        let endpoint = Endpoint::of(&self.path);
+        let (status, error, returned) = match res {
+            Ok(res) => (Some(res.status().as_u16()), None, Returned::of(res)),
+            Err(err) => (None, Some(err.to_string()), Some(Returned::default())),
+        };
+
+        // failed requests are not billed
+        let estimated_credits = match (&endpoint, status) {
+            (_, None | Some(300..)) => Some(0.0),
+            (Endpoint::Generation(engine), _) => Some(credits::estimate(
+                engine,
+                number_field(&self.request, "steps").unwrap_or(50),
+                number_field(&self.request, "samples").unwrap_or(1),
+            )),
+            (Endpoint::Edit(operation), _) => credits::estimate_edit(operation),
+            (Endpoint::Free, _) => Some(0.0),
+            (Endpoint::Other, _) => None,
+        };
+        let engine = match endpoint {
+            Endpoint::Generation(engine) => Some(engine.to_string()),
+            _ => None,
+        };
+        // listings are never counted as images, whatever their shape
+        let returned =
+            returned.or_else(|| matches!(endpoint, Endpoint::Free).then(Returned::default));
+        let (artifacts, seeds) = match returned {
+            Some(returned) => (Some(returned.artifacts), returned.seeds),
+            None => (None, Vec::new()),
+        };
+
+        AuditRecord {
+            timestamp: self.timestamp,
+            method: self.method,
+            path: self.path,
+            engine,
+            request: self.request,
+            status,
+            error,
+            artifacts,
+            seeds,
+            estimated_credits,
+            latency_ms: self.started.elapsed().as_millis() as u64,
+        }
+    }
+}
+
+/// What a request was sent to, as far as pricing goes
+enum Endpoint<'a> {
+    /// `/v1/generation/{engine}/...`
+    Generation(&'a str),
+    /// `/v2beta/stable-image/edit/{operation}`
+    Edit(&'a str),
+    /// Account and engine listings, and fetching the results of asynchronous
+    /// requests, billed when they started
+    Free,
+    Other,
+}
+
+impl<'a> Endpoint<'a> {
+    fn of(path: &'a str) -> Self {
+        let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+        match segments.as_slice() {
+            ["v1", "generation", engine, ..] => Endpoint::Generation(engine),
+            ["v2beta", "stable-image", "edit", operation] => Endpoint::Edit(operation),
+            ["v1", "user" | "engines", ..] | ["v2beta", "results", _] => Endpoint::Free,
+            _ => Endpoint::Other,
+        }
+    }
+}
+
+/// The images in a response
+#[derive(Debug, Default, PartialEq)]
+struct Returned {
+    artifacts: usize,
+    seeds: Vec<u32>,
+}
+
+/// The fields of a JSON response which say what it returned, the images
+/// themselves skipped rather than decoded
+#[derive(Deserialize)]
+struct JsonReturned {
+    artifacts: Option<Vec<JsonArtifact>>,
+    image: Option<serde::de::IgnoredAny>,
+    seed: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct JsonArtifact {
+    seed: Option<u32>,
+}
+
+impl Returned {
+    /// The images of `res`, or `None` when it is not a response the crate
+    /// knows how to count
+    fn of(res: &Response<Bytes>) -> Option<Self> {
+        if !res.status().is_success() {
+            return Some(Self::default());
+        }
+        let header = |name| res.headers().get(name).and_then(|v| v.to_str().ok());
+        let content_type = header(hyper::header::CONTENT_TYPE.as_str()).unwrap_or_default();
+
+        // the v2beta endpoints answer with the image itself, its seed in a
+        // header
+        if content_type.starts_with("image/") {
+            return Some(Self {
+                artifacts: 1,
+                seeds: header("seed").and_then(|v| v.parse().ok()).into_iter().collect(),
+            });
+        }
+
+        let json: JsonReturned = serde_json::from_slice(res.body()).ok()?;
+        Some(match (json.artifacts, json.image) {
+            (Some(artifacts), _) => Self {
+                artifacts: artifacts.len(),
+                seeds: artifacts.iter().filter_map(|a| a.seed).collect(),
+            },
+            (None, Some(_)) => Self {
+                artifacts: 1,
+                seeds: json.seed.into_iter().collect(),
+            },
+            // such as the id of an asynchronous request, or a balance
+            (None, None) => Self::default(),
+        })
+    }
+}
+
+/// A field sent as a JSON number or a multipart string
+fn number_field(request: &Value, name: &str) -> Option<u32> {
+    match &request[name] {
+        Value::Number(n) => n.as_u64().map(|n| n as u32),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// The text fields of a multipart body; file parts are left out, and prompt
+/// fields are keyed `text` so they are redacted
+fn multipart_fields(body: &[u8], boundary: &str) -> Value {
+    let body = String::from_utf8_lossy(body);
+    let mut fields = Map::new();
+    for part in body.split(&format!("--{}", boundary)) {
+        let Some((headers, value)) = part.split_once("\r\n\r\n") else {
+            continue;
+        };
+        if headers.contains("filename=") {
+            continue;
+        }
+        let Some(name) = headers
+            .split("name=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+        else {
+            continue;
+        };
+        let value = Value::String(value.trim_end_matches("\r\n").to_string());
+        if name.ends_with("[text]") {
+            let mut prompt = Map::new();
+            prompt.insert("text".to_string(), value);
+            fields.insert(name.to_string(), Value::Object(prompt));
+        } else {
+            fields.insert(name.to_string(), value);
+        }
+    }
+    Value::Object(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::StatusCode;
+
+    fn record(req: Request<Bytes>, res: Response<Bytes>) -> AuditRecord {
+        let sink = JsonlAudit::new(Vec::new());
+        sink.record(&Pending::new(&req).into_record(Ok(&res))).unwrap();
+        serde_json::from_slice(&sink.into_inner()).unwrap()
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypted_lines_are_hiding_the_record() {
+        let req = Request::get("/v1/user/balance").body(Bytes::new()).unwrap();
+        let res = Response::new(Bytes::from(r#"{"credits":10}"#));
+        let key = EncryptionKey::generate();
+        let sink = JsonlAudit::new(Vec::new()).encrypt(key.clone());
+        sink.record(&Pending::new(&req).into_record(Ok(&res))).unwrap();
+
+        let written = String::from_utf8(sink.into_inner()).unwrap();
+        assert!(!written.contains("/v1/user/balance"));
+        let record = decrypt_line(written.lines().next().unwrap(), &key).unwrap();
+        assert_eq!(record.path, "/v1/user/balance");
+    }
+
+    #[test]
+    fn json_requests_are_recorded_with_prompts_hashed() {
+        let req = Request::post("/v1/generation/stable-diffusion-xl-1024-v1-0/text-to-image")
+            .body(Bytes::from(
+                r#"{"text_prompts":[{"text":"a secret lighthouse","weight":1.0}],"steps":30,"samples":2}"#,
+            ))
+            .unwrap();
+        let res = Response::builder()
+            .status(StatusCode::OK)
+            .body(Bytes::from(
+                r#"{"artifacts":[{"base64":"","finishReason":"SUCCESS","seed":11},{"base64":"","finishReason":"SUCCESS","seed":12}]}"#,
+            ))
+            .unwrap();
+
+        let record = record(req, res);
+
+        assert_eq!(
+            record.engine.as_deref(),
+            Some("stable-diffusion-xl-1024-v1-0")
+        );
+        assert_eq!(record.status, Some(200));
+        assert_eq!(record.seeds, vec![11, 12]);
+        assert_eq!(
+            record.estimated_credits,
+            Some(credits::estimate("stable-diffusion-xl-1024-v1-0", 30, 2))
+        );
+        assert!(!record.request.to_string().contains("lighthouse"));
+        assert_eq!(record.request["samples"], 2);
+    }
+
+    #[test]
+    fn multipart_requests_are_recorded_without_files() {
+        let body = "--b\r\nContent-Disposition: form-data; name=\"text_prompts[0][text]\"\r\n\r\na secret crab\r\n\
+                    --b\r\nContent-Disposition: form-data; name=\"steps\"\r\n\r\n20\r\n\
+                    --b\r\nContent-Disposition: form-data; name=\"init_image\"; filename=\"init.png\"\r\nContent-Type: image/png\r\n\r\nPNG\r\n\
+                    --b--\r\n";
+        let req = Request::post("/v1/generation/stable-diffusion-v1-6/image-to-image")
+            .header(
+                hyper::header::CONTENT_TYPE,
+                "multipart/form-data; boundary=b",
+            )
+            .body(Bytes::from(body))
+            .unwrap();
+        let res = Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Bytes::from(
+                r#"{"id":"x","name":"bad_request","message":"no"}"#,
+            ))
+            .unwrap();
+
+        let record = record(req, res);
+
+        assert_eq!(record.status, Some(400));
+        assert_eq!(record.request["steps"], "20");
+        assert!(record.request.get("init_image").is_none());
+        assert!(!record.request.to_string().contains("crab"));
+        assert_eq!(record.artifacts, Some(0));
+        assert_eq!(record.estimated_credits, Some(0.0));
+    }
+
+    #[test]
+    fn inpaint_requests_are_recorded_with_every_prompt_hashed() {
+        let body = "--b\r\nContent-Disposition: form-data; name=\"prompt\"\r\n\r\na secret crab\r\n\
+                    --b\r\nContent-Disposition: form-data; name=\"negative_prompt\"\r\n\r\na secret eel\r\n\
+                    --b\r\nContent-Disposition: form-data; name=\"output_format\"\r\n\r\npng\r\n\
+                    --b\r\nContent-Disposition: form-data; name=\"image\"; filename=\"image.png\"\r\nContent-Type: image/png\r\n\r\nPNG\r\n\
+                    --b--\r\n";
+        let req = Request::post("/v2beta/stable-image/edit/inpaint")
+            .header(
+                hyper::header::CONTENT_TYPE,
+                "multipart/form-data; boundary=b",
+            )
+            .body(Bytes::from(body))
+            .unwrap();
+        let res = Response::new(Bytes::from(r#"{"image":"","finish_reason":"SUCCESS","seed":3}"#));
+
+        let record = record(req, res);
+
+        assert_eq!(record.artifacts, Some(1));
+        assert_eq!(record.seeds, vec![3]);
+        assert_eq!(record.estimated_credits, credits::estimate_edit("inpaint"));
+        assert_eq!(record.request["output_format"], "png");
+        assert_eq!(record.request["negative_prompt"], redaction::prompt_hash("a secret eel"));
+        assert!(!record.request.to_string().contains("secret"));
+    }
+
+    #[test]
+    fn relight_requests_are_recorded_with_every_prompt_hashed() {
+        let body = "--b\r\nContent-Disposition: form-data; name=\"background_prompt\"\r\n\r\na secret beach\r\n\
+                    --b\r\nContent-Disposition: form-data; name=\"foreground_prompt\"\r\n\r\na secret bottle\r\n\
+                    --b\r\nContent-Disposition: form-data; name=\"negative_prompt\"\r\n\r\na secret crowd\r\n\
+                    --b\r\nContent-Disposition: form-data; name=\"light_source_direction\"\r\n\r\nleft\r\n\
+                    --b--\r\n";
+        let req = Request::post("/v2beta/stable-image/edit/replace-background-and-relight")
+            .header(
+                hyper::header::CONTENT_TYPE,
+                "multipart/form-data; boundary=b",
+            )
+            .body(Bytes::from(body))
+            .unwrap();
+        let res = Response::new(Bytes::from(r#"{"id":"a1b2"}"#));
+
+        let record = record(req, res);
+
+        assert_eq!(record.artifacts, Some(0));
+        assert_eq!(record.estimated_credits, Some(8.0));
+        assert_eq!(record.request["light_source_direction"], "left");
+        assert_eq!(record.request["foreground_prompt"], redaction::prompt_hash("a secret bottle"));
+        assert!(!record.request.to_string().contains("secret"));
+    }
+
+    #[test]
+    fn search_and_replace_requests_are_recorded_with_every_prompt_hashed() {
+        let body = "--b\r\nContent-Disposition: form-data; name=\"prompt\"\r\n\r\na secret dog\r\n\
+                    --b\r\nContent-Disposition: form-data; name=\"search_prompt\"\r\n\r\na secret cat\r\n\
+                    --b--\r\n";
+        let req = Request::post("/v2beta/stable-image/edit/search-and-replace")
+            .header(
+                hyper::header::CONTENT_TYPE,
+                "multipart/form-data; boundary=b",
+            )
+            .body(Bytes::from(body))
+            .unwrap();
+        let res = Response::new(Bytes::from(r#"{"image":"","finish_reason":"SUCCESS","seed":3}"#));
+
+        let record = record(req, res);
+
+        assert_eq!(record.request["search_prompt"], redaction::prompt_hash("a secret cat"));
+        assert!(!record.request.to_string().contains("secret"));
+    }
+
+    #[test]
+    fn edit_images_are_counted_from_the_response_headers() {
+        let req = Request::post("/v2beta/stable-image/edit/erase")
+            .body(Bytes::new())
+            .unwrap();
+        let res = Response::builder()
+            .header(hyper::header::CONTENT_TYPE, IMAGE_PNG)
+            .header("seed", "77")
+            .body(Bytes::from_static(b"PNG"))
+            .unwrap();
+
+        let record = record(req, res);
+
+        assert_eq!(record.engine, None);
+        assert_eq!(record.artifacts, Some(1));
+        assert_eq!(record.seeds, vec![77]);
+        assert_eq!(record.estimated_credits, Some(3.0));
+    }
+
+    #[test]
+    fn unknown_endpoints_are_recorded_as_unknown_rather_than_free() {
+        let req = Request::post("/v2beta/stable-image/generate/core")
+            .body(Bytes::new())
+            .unwrap();
+        let res = Response::new(Bytes::from_static(b"not json"));
+
+        let record = record(req, res);
+
+        assert_eq!(record.artifacts, None);
+        assert_eq!(record.estimated_credits, None);
+    }
+}
@@ -0,0 +1,167 @@
+//! Cancelling the items of a batch run which haven't been sent yet.
+
+use super::BatchItem;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[cfg(all(test, feature = "text-to-image"))]
+mod tests {
+    use super::*;
+    use crate::text_to_img::TextToImageBuilder;
+    use crate::StylePreset;
+
+    #[test]
+    fn items_are_forgotten_once_their_run_returns() {
+        let request = TextToImageBuilder::new()
+            .style_preset(StylePreset::Enhance)
+            .unwrap()
+            .text_prompt("a lighthouse", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+        let item = BatchItem::new("item0", "stable-diffusion-xl-1024-v1-0", request).tag("alice");
+        let canceller = BatchCanceller::new();
+
+        let registration = canceller.register(&[item.clone(), item]);
+        assert!(registration.start(0));
+        assert_eq!(canceller.cancel_tag("alice").cancelled, ["item0"]);
+        assert!(!registration.start(1));
+        drop(registration);
+
+        assert_eq!(canceller.cancel_all(), CancelReport::default());
+        assert!(canceller.runs.lock().unwrap().live.is_empty());
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Queued,
+    Cancelled,
+    InFlight,
+    Done,
+}
+
+#[derive(Debug)]
+struct Entry {
+    id: String,
+    tags: Vec<String>,
+    state: State,
+}
+
+/// What a cancellation reached, by item id
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CancelReport {
+    /// Items which hadn't been sent, and now won't be
+    pub cancelled: Vec<String>,
+    /// Items which had been sent already, and will complete as usual
+    pub in_flight: Vec<String>,
+}
+
+/// The entries of the runs in progress, by run
+#[derive(Debug, Default)]
+struct Runs {
+    next: u64,
+    live: HashMap<u64, Vec<Entry>>,
+}
+
+/// A cloneable handle cancelling the queued items of the runs of a
+/// [`BatchRunner`](super::BatchRunner) it is set on
+///
+/// Cancelled items are returned in
+/// [`BatchOutcome::cancelled`](super::BatchOutcome::cancelled). Items
+/// already completed are left out of a [`CancelReport`], and the items of a
+/// run are forgotten once it returns.
+#[derive(Debug, Clone, Default)]
+pub struct BatchCanceller {
+    runs: Arc<Mutex<Runs>>,
+}
+
+impl BatchCanceller {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the items with id `id`
+    pub fn cancel_id(&self, id: &str) -> CancelReport {
+        self.cancel(|entry| entry.id == id)
+    }
+
+    /// Cancel every item tagged `tag`, e.g. the items of a user who left
+    pub fn cancel_tag(&self, tag: &str) -> CancelReport {
+        self.cancel(|entry| entry.tags.iter().any(|t| t == tag))
+    }
+
+    pub fn cancel_all(&self) -> CancelReport {
+        self.cancel(|_| true)
+    }
+
+    fn cancel(&self, matches: impl Fn(&Entry) -> bool) -> CancelReport {
+        let mut report = CancelReport::default();
+        let mut runs = self.runs.lock().unwrap();
+        let entries = runs.live.values_mut().flatten();
+        for entry in entries.filter(|e| matches(e)) {
+            match entry.state {
+                State::Queued => {
+                    entry.state = State::Cancelled;
+                    report.cancelled.push(entry.id.clone());
+                }
+                State::InFlight => report.in_flight.push(entry.id.clone()),
+                State::Cancelled | State::Done => {}
+            }
+        }
+        report
+    }
+
+    /// Queue the items of a run until the returned registration is dropped
+    pub(crate) fn register(&self, items: &[BatchItem]) -> Registration {
+        let mut runs = self.runs.lock().unwrap();
+        let run = runs.next;
+        runs.next += 1;
+        let entries = items.iter().map(|item| Entry {
+            id: item.id.clone(),
+            tags: item.tags.clone(),
+            state: State::Queued,
+        });
+        runs.live.insert(run, entries.collect());
+        Registration {
+            runs: self.runs.clone(),
+            run,
+        }
+    }
+}
+
+/// The items of a run registered with a [`BatchCanceller`], by their index
+/// in the run
+#[derive(Debug)]
+pub(crate) struct Registration {
+    runs: Arc<Mutex<Runs>>,
+    run: u64,
+}
+
+impl Registration {
+    /// Mark the item at `index` as sent, unless it was cancelled
+    pub(crate) fn start(&self, index: usize) -> bool {
+        let mut runs = self.runs.lock().unwrap();
+        let entry = &mut runs.live.get_mut(&self.run).unwrap()[index];
+        if entry.state == State::Cancelled {
+            return false;
+        }
+        entry.state = State::InFlight;
+        true
+    }
+
+    /// Mark the item at `index` as done, or queued again to be retried
+    pub(crate) fn finish(&self, index: usize, retry: bool) {
+        let mut runs = self.runs.lock().unwrap();
+        runs.live.get_mut(&self.run).unwrap()[index].state = match retry {
+            true => State::Queued,
+            false => State::Done,
+        };
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.runs.lock().unwrap().live.remove(&self.run);
+    }
+}
@@ -0,0 +1,1032 @@
+//! Batch generation runs.
+//!
+//! A [`BatchRunner`] generates a list of [`BatchItem`]s, saves every returned
+//! artifact under an output directory and records one [`BatchRecord`] per
+//! artifact, optionally written out as a JSONL or CSV report. An item which
+//! fails doesn't stop the others: the run returns a [`BatchOutcome`] holding
+//! the records of the items which succeeded and the errors of those which
+//! didn't.
+//!
+//! Completed items are recorded in a progress file in the output directory, so
+//! a run that was interrupted can be restarted with [`BatchRunner::resume`]
+//! and only the missing items are generated again.
+//!
+//! When a [`crate::limiter`] is installed, batch requests are queued in the
+//! background lane so interactive requests made meanwhile are served first.
+//!
+//! A [`BatchCanceller`] set with [`BatchRunner::canceller`] cancels the items
+//! not sent yet, by id or by one of their [tags](BatchItem::tag), e.g. when a
+//! user abandons their jobs.
+//!
+//! With a [`RetryPolicy`], items failing on a rate limit, an outage or,
+//! optionally, the safety filter are sent again once the rest of the batch
+//! has been tried. Those still failing after the last attempt are returned
+//! with [`BatchError::RetriesExhausted`], telling them apart from items which
+//! failed for good on their first try.
+//!
+//! A [`BatchSchedule`] set with [`BatchRunner::schedule`] sends items only
+//! within daily [`TimeWindow`]s, such as off-peak hours, and can pause and
+//! resume a run.
+
+mod cancel;
+mod outcome;
+pub mod progress;
+pub mod report;
+mod retry;
+mod schedule;
+
+pub use cancel::{BatchCanceller, CancelReport};
+pub use outcome::BatchOutcome;
+pub use report::ReportFormat;
+pub use retry::RetryPolicy;
+pub use schedule::{BatchSchedule, TimeWindow};
+
+#[cfg(feature = "image-to-image")]
+use crate::api::rest::generation::img_to_img::ImageToImage;
+#[cfg(feature = "text-to-image")]
+use crate::api::rest::generation::text_to_img::TextToImage;
+use crate::api::rest::generation::filter_retry::is_filtered;
+use crate::api::rest::generation::{ImageResponse, OverwritePolicy, PromptGroups, TextPrompt};
+use crate::alt_text;
+use crate::credits;
+use crate::error::BatchError;
+use crate::limiter::{self, Priority};
+use crate::prelude::*;
+use crate::provenance::Provenance;
+use crate::scoring::{self, ArtifactScorer};
+use crate::Seed;
+use futures_util::{stream, StreamExt};
+use progress::Progress;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+#[cfg(all(test, feature = "text-to-image"))]
+mod tests {
+    use super::*;
+    use crate::testing::{image_response, json_response, FakeTransport};
+    use crate::text_to_img::TextToImageBuilder;
+    use hyper::StatusCode;
+    use crate::StylePreset;
+
+    #[tokio::test]
+    async fn run_is_saving_artifacts_and_reporting_them() {
+        let dir = std::env::temp_dir().join(format!("stability_rs_batch_{}", std::process::id()));
+        let request = TextToImageBuilder::new()
+            .style_preset(StylePreset::Photographic)
+            .unwrap()
+            .text_prompt("a lighthouse at dusk", 1.0)
+            .unwrap()
+            .text_prompt("fog", -0.5)
+            .unwrap()
+            .samples(2)
+            .unwrap()
+            .build()
+            .unwrap();
+        let items = vec![BatchItem::new("item0", "stable-diffusion-xl-1024-v1-0", request)];
+
+        let transport = FakeTransport::with_response(&image_response(&[11, 12]));
+        let records = transport
+            .scope(
+                BatchRunner::new(&dir)
+                    .report(dir.join("report.csv"), ReportFormat::Csv)
+                    .unwrap()
+                    .run(items),
+            )
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap()
+            .concat();
+
+        assert_eq!(records.iter().map(|r| r.seed).collect::<Vec<_>>(), vec![11, 12]);
+        assert_eq!(records[0].prompts.positive[0].text, "a lighthouse at dusk");
+        assert_eq!(records[0].prompts.negative[0].weight, -0.5);
+        assert!(dir.join("item0_1.png").exists());
+        let report = std::fs::read_to_string(dir.join("report.csv")).unwrap();
+        assert_eq!(report.lines().count(), 3);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_is_reporting_progress_after_each_item() {
+        let dir = std::env::temp_dir()
+            .join(format!("stability_rs_batch_progress_{}", std::process::id()));
+        let request = TextToImageBuilder::new()
+            .style_preset(StylePreset::Photographic)
+            .unwrap()
+            .text_prompt("a lighthouse at dusk", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+        let items = ["a", "b"]
+            .iter()
+            .map(|id| BatchItem::new(*id, "stable-diffusion-xl-1024-v1-0", request.clone()))
+            .collect();
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let sink = seen.clone();
+        let runner = BatchRunner::new(&dir)
+            .on_progress(move |progress| sink.lock().unwrap().push(progress))
+            .unwrap();
+        FakeTransport::with_response(&image_response(&[1]))
+            .scope(runner.run(items))
+            .await
+            .unwrap();
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(
+            seen.iter().map(|p| (p.completed, p.total)).collect::<Vec<_>>(),
+            vec![(1, 2), (2, 2)]
+        );
+        assert_eq!(seen[1].item_id, "b");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_is_failing_items_whose_id_leaves_the_output_directory() {
+        let dir = std::env::temp_dir()
+            .join(format!("stability_rs_batch_ids_{}/out", std::process::id()));
+        let request = TextToImageBuilder::new()
+            .style_preset(StylePreset::Photographic)
+            .unwrap()
+            .text_prompt("a lighthouse at dusk", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+        let items = ["../escaped", r"..\escaped", "C:escaped", "kept"]
+            .iter()
+            .map(|id| BatchItem::new(*id, "stable-diffusion-xl-1024-v1-0", request.clone()))
+            .collect();
+
+        let transport = FakeTransport::with_response(&image_response(&[1]));
+        let outcome = transport
+            .scope(BatchRunner::new(&dir).run(items))
+            .await
+            .unwrap();
+
+        let failed: Vec<_> = outcome.failed.iter().map(|(item, _)| item.id.as_str()).collect();
+        assert_eq!(failed, ["../escaped", r"..\escaped", "C:escaped"]);
+        assert!(outcome
+            .failed
+            .iter()
+            .all(|(_, e)| matches!(e.downcast_ref(), Some(BatchError::ItemIdInvalid(_)))));
+        assert_eq!(transport.requests().len(), 1);
+        assert!(dir.join("kept_0.png").exists());
+        assert!(!dir.parent().unwrap().join("escaped_0.png").exists());
+        std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_is_saving_only_the_best_artifact_when_scored() {
+        let dir = std::env::temp_dir()
+            .join(format!("stability_rs_batch_keep_best_{}", std::process::id()));
+        let request = TextToImageBuilder::new()
+            .style_preset(StylePreset::Photographic)
+            .unwrap()
+            .text_prompt("a lighthouse at dusk", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+        let items = vec![BatchItem::new("a", "stable-diffusion-xl-1024-v1-0", request)];
+
+        struct HighestSeed;
+        impl ArtifactScorer for HighestSeed {
+            fn score(&self, image: &crate::api::rest::generation::Image) -> f64 {
+                image.seed as f64
+            }
+        }
+        let runner = BatchRunner::new(&dir).keep_best(HighestSeed).unwrap();
+        let records = FakeTransport::with_response(&image_response(&[3, 7, 5]))
+            .scope(runner.run(items))
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap()
+            .concat();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].seed, 7);
+        assert!(dir.join("a_0.png").exists());
+        assert!(!dir.join("a_1.png").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_is_writing_alt_text_sidecars() {
+        let dir = std::env::temp_dir()
+            .join(format!("stability_rs_batch_alt_text_{}", std::process::id()));
+        let request = TextToImageBuilder::new()
+            .style_preset(StylePreset::Photographic)
+            .unwrap()
+            .text_prompt("a lighthouse at dusk", 1.0)
+            .unwrap()
+            .text_prompt("blurry", -1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+        let items = vec![BatchItem::new("a", "stable-diffusion-xl-1024-v1-0", request)];
+
+        let runner = BatchRunner::new(&dir).alt_text(true).unwrap();
+        FakeTransport::with_response(&image_response(&[1]))
+            .scope(runner.run(items))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(dir.join("a_0.txt")).unwrap(),
+            "A lighthouse at dusk"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_is_renaming_images_instead_of_overwriting_when_asked() {
+        let dir = std::env::temp_dir()
+            .join(format!("stability_rs_batch_overwrite_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a_0.png"), b"old").unwrap();
+        let request = TextToImageBuilder::new()
+            .style_preset(StylePreset::Photographic)
+            .unwrap()
+            .text_prompt("a lighthouse at dusk", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+        let items = vec![BatchItem::new("a", "stable-diffusion-xl-1024-v1-0", request)];
+
+        let runner = BatchRunner::new(&dir)
+            .overwrite(OverwritePolicy::Rename)
+            .unwrap()
+            .alt_text(true)
+            .unwrap();
+        let records = FakeTransport::with_response(&image_response(&[1]))
+            .scope(runner.run(items))
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap()
+            .concat();
+
+        assert_eq!(std::fs::read(dir.join("a_0.png")).unwrap(), b"old");
+        assert_eq!(records[0].output_path, dir.join("a_0-1.png").to_string_lossy());
+        assert!(dir.join("a_0-1.txt").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_is_keeping_the_items_which_succeeded_when_one_fails() {
+        let dir = std::env::temp_dir()
+            .join(format!("stability_rs_batch_outcome_{}", std::process::id()));
+        let items = ["a lighthouse", "poison", "a fox"]
+            .iter()
+            .enumerate()
+            .map(|(i, prompt)| {
+                let request = TextToImageBuilder::new()
+                    .style_preset(StylePreset::Photographic)
+                    .unwrap()
+                    .text_prompt(prompt, 1.0)
+                    .unwrap()
+                    .build()
+                    .unwrap();
+                BatchItem::new(format!("item{}", i), "stable-diffusion-xl-1024-v1-0", request)
+            })
+            .collect();
+
+        let ok = serde_json::to_vec(&image_response(&[1])).unwrap();
+        let transport = FakeTransport::new(move |req| {
+            match String::from_utf8_lossy(&req.body).contains("poison") {
+                true => json_response(
+                    StatusCode::BAD_REQUEST,
+                    r#"{"id":"x","name":"invalid_prompts","message":"blocked"}"#.into(),
+                ),
+                false => json_response(StatusCode::OK, ok.clone().into()),
+            }
+        });
+        let runner = BatchRunner::new(&dir)
+            .report(dir.join("report.jsonl"), ReportFormat::Jsonl)
+            .unwrap();
+        let outcome = transport.scope(runner.run(items)).await.unwrap();
+
+        let ids = |items: Vec<&BatchItem>| items.iter().map(|i| i.id.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            ids(outcome.succeeded.iter().map(|(item, _)| item).collect()),
+            vec!["item0", "item2"]
+        );
+        assert_eq!(ids(outcome.failed.iter().map(|(item, _)| item).collect()), vec!["item1"]);
+        let report = std::fs::read_to_string(dir.join("report.jsonl")).unwrap();
+        assert_eq!(report.lines().count(), 2);
+        assert!(outcome.into_result().is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_is_retrying_transient_failures_until_out_of_attempts() {
+        let dir = std::env::temp_dir()
+            .join(format!("stability_rs_batch_retry_{}", std::process::id()));
+        let items = ["flaky", "throttled", "poison"]
+            .iter()
+            .map(|prompt| {
+                let request = TextToImageBuilder::new()
+                    .style_preset(StylePreset::Photographic)
+                    .unwrap()
+                    .text_prompt(prompt, 1.0)
+                    .unwrap()
+                    .build()
+                    .unwrap();
+                BatchItem::new(*prompt, "stable-diffusion-xl-1024-v1-0", request)
+            })
+            .collect();
+
+        let ok = serde_json::to_vec(&image_response(&[1])).unwrap();
+        let flaky_failed = AtomicUsize::new(0);
+        let error = |status, name: &str| {
+            let body = format!(r#"{{"id":"x","name":"{}","message":"failed"}}"#, name);
+            json_response(status, body.into())
+        };
+        let transport = FakeTransport::new(move |req| {
+            let body = String::from_utf8_lossy(&req.body);
+            if body.contains("poison") {
+                error(StatusCode::BAD_REQUEST, "invalid_prompts")
+            } else if body.contains("throttled") {
+                error(StatusCode::TOO_MANY_REQUESTS, "rate_limit_exceeded")
+            } else if flaky_failed.fetch_add(1, Ordering::Relaxed) == 0 {
+                error(StatusCode::SERVICE_UNAVAILABLE, "service_unavailable")
+            } else {
+                json_response(StatusCode::OK, ok.clone().into())
+            }
+        });
+        let retry = RetryPolicy::new(3)
+            .backoff(std::time::Duration::ZERO, std::time::Duration::ZERO)
+            .unwrap();
+        let runner = BatchRunner::new(&dir).retry(retry).unwrap();
+        let outcome = transport.scope(runner.run(items)).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(transport.requests().len(), 6);
+        assert_eq!(outcome.succeeded[0].0.id, "flaky");
+        let exhausted: Vec<_> =
+            outcome.exhausted().map(|(item, e)| (&item.id, e.to_string())).collect();
+        assert_eq!(exhausted.len(), 1);
+        assert_eq!(exhausted[0].0, "throttled");
+        assert!(exhausted[0].1.starts_with("gave up after 3 attempts"));
+        let hard: Vec<_> = outcome.hard_failures().map(|(item, _)| &item.id).collect();
+        assert_eq!(hard, ["poison"]);
+    }
+
+    #[tokio::test]
+    async fn run_is_holding_items_back_while_paused() {
+        let dir = std::env::temp_dir()
+            .join(format!("stability_rs_batch_schedule_{}", std::process::id()));
+        let request = TextToImageBuilder::new()
+            .style_preset(StylePreset::Photographic)
+            .unwrap()
+            .text_prompt("a lighthouse", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+        let items = vec![BatchItem::new("item0", "stable-diffusion-xl-1024-v1-0", request)];
+
+        let schedule = BatchSchedule::new();
+        schedule.pause();
+        let transport = FakeTransport::with_response(&image_response(&[1]));
+        let runner = BatchRunner::new(&dir).schedule(schedule.clone()).unwrap();
+        let run = transport.scope(runner.run(items));
+        let resume = async {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            assert!(transport.requests().is_empty());
+            schedule.resume();
+        };
+        let (outcome, _) = tokio::join!(run, resume);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(outcome.unwrap().succeeded.len(), 1);
+        assert_eq!(transport.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn run_is_skipping_items_cancelled_before_being_sent() {
+        let dir = std::env::temp_dir()
+            .join(format!("stability_rs_batch_cancel_{}", std::process::id()));
+        let items = ["alice", "bob", "alice"]
+            .iter()
+            .enumerate()
+            .map(|(i, user)| {
+                let request = TextToImageBuilder::new()
+                    .style_preset(StylePreset::Photographic)
+                    .unwrap()
+                    .text_prompt("a lighthouse", 1.0)
+                    .unwrap()
+                    .build()
+                    .unwrap();
+                BatchItem::new(format!("item{}", i), "stable-diffusion-xl-1024-v1-0", request)
+                    .tag(*user)
+            })
+            .collect();
+
+        let canceller = BatchCanceller::new();
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let ok = serde_json::to_vec(&image_response(&[1])).unwrap();
+        let transport = {
+            let (canceller, reports) = (canceller.clone(), reports.clone());
+            FakeTransport::new(move |_| {
+                reports.lock().unwrap().push(canceller.cancel_tag("alice"));
+                json_response(StatusCode::OK, ok.clone().into())
+            })
+        };
+        let runner = BatchRunner::new(&dir).canceller(canceller).unwrap();
+        let outcome = transport.scope(runner.run(items)).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(
+            reports[0],
+            CancelReport {
+                cancelled: vec!["item2".to_string()],
+                in_flight: vec!["item0".to_string()],
+            }
+        );
+        assert_eq!(reports[1], CancelReport::default());
+        assert_eq!(outcome.succeeded.len(), 2);
+        assert_eq!(outcome.cancelled.len(), 1);
+        assert_eq!(outcome.cancelled[0].id, "item2");
+        assert_eq!(
+            outcome.into_result().unwrap_err().to_string(),
+            "1 batch items were cancelled before being sent"
+        );
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum BatchRequest {
+    #[cfg(feature = "text-to-image")]
+    TextToImage(TextToImage),
+    #[cfg(feature = "image-to-image")]
+    ImageToImage(ImageToImage),
+}
+
+impl BatchRequest {
+    pub async fn generate(&self, engine: &str) -> Result<ImageResponse> {
+        match self {
+            #[cfg(feature = "text-to-image")]
+            BatchRequest::TextToImage(req) => req.generate(engine).await,
+            #[cfg(feature = "image-to-image")]
+            BatchRequest::ImageToImage(req) => req.generate(engine).await,
+        }
+    }
+
+    /// The same request pinned to `seed`
+    pub(crate) fn with_seed(&self, seed: impl Into<Seed>) -> Result<Self> {
+        let request = match self {
+            #[cfg(feature = "text-to-image")]
+            BatchRequest::TextToImage(req) => req.to_builder().seed(seed)?.build()?.into(),
+            #[cfg(feature = "image-to-image")]
+            BatchRequest::ImageToImage(req) => req.to_builder().seed(seed)?.build()?.into(),
+        };
+        Ok(request)
+    }
+
+    fn text_prompts(&self) -> &[TextPrompt] {
+        match self {
+            #[cfg(feature = "text-to-image")]
+            BatchRequest::TextToImage(req) => &req.text_prompts,
+            #[cfg(feature = "image-to-image")]
+            BatchRequest::ImageToImage(req) => &req.text_prompts,
+        }
+    }
+
+    /// The sampler as the API names it, unless the API picks one
+    fn sampler(&self) -> Option<String> {
+        let sampler = match self {
+            #[cfg(feature = "text-to-image")]
+            BatchRequest::TextToImage(req) => &req.sampler,
+            #[cfg(feature = "image-to-image")]
+            BatchRequest::ImageToImage(req) => &req.sampler,
+        };
+        (!sampler.is_none()).then(|| sampler.api_name())
+    }
+
+    fn clip_guidance_preset(&self) -> String {
+        match self {
+            #[cfg(feature = "text-to-image")]
+            BatchRequest::TextToImage(req) => req.clip_guidance_preset.api_name(),
+            #[cfg(feature = "image-to-image")]
+            BatchRequest::ImageToImage(req) => req.clip_guidance_preset.api_name(),
+        }
+    }
+
+    pub(crate) fn prompt_groups(&self) -> PromptGroups {
+        match self {
+            #[cfg(feature = "text-to-image")]
+            BatchRequest::TextToImage(req) => req.prompt_groups(),
+            #[cfg(feature = "image-to-image")]
+            BatchRequest::ImageToImage(req) => req.prompt_groups(),
+        }
+    }
+
+    pub(crate) fn steps(&self) -> u32 {
+        match self {
+            #[cfg(feature = "text-to-image")]
+            BatchRequest::TextToImage(req) => req.steps,
+            #[cfg(feature = "image-to-image")]
+            BatchRequest::ImageToImage(req) => req.steps,
+        }
+    }
+
+    fn params(&self) -> Result<serde_json::Value> {
+        let params = match self {
+            #[cfg(feature = "text-to-image")]
+            BatchRequest::TextToImage(req) => serde_json::to_value(req)?,
+            #[cfg(feature = "image-to-image")]
+            BatchRequest::ImageToImage(req) => serde_json::to_value(req)?,
+        };
+        Ok(params)
+    }
+
+    pub(crate) fn prompt(&self) -> String {
+        self.text_prompts()
+            .iter()
+            .map(|p| p.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+}
+
+#[cfg(feature = "text-to-image")]
+impl From<TextToImage> for BatchRequest {
+    fn from(req: TextToImage) -> Self {
+        BatchRequest::TextToImage(req)
+    }
+}
+
+#[cfg(feature = "image-to-image")]
+impl From<ImageToImage> for BatchRequest {
+    fn from(req: ImageToImage) -> Self {
+        BatchRequest::ImageToImage(req)
+    }
+}
+
+/// A single generation in a batch, saved as `<id>_<n>.png`
+///
+/// An id holding a path separator, `:` or `..` fails its item with
+/// [`BatchError::ItemIdInvalid`] before anything is sent, so no image is
+/// written outside the output directory.
+#[derive(Debug, Clone)]
+pub struct BatchItem {
+    pub id: String,
+    pub engine: String,
+    pub request: BatchRequest,
+    /// Labels the item can be cancelled by, such as the user it is for
+    pub tags: Vec<String>,
+}
+
+impl BatchItem {
+    pub fn new(id: impl Into<String>, engine: &str, request: impl Into<BatchRequest>) -> Self {
+        Self {
+            id: id.into(),
+            engine: engine.to_string(),
+            request: request.into(),
+            tags: Vec::new(),
+        }
+    }
+
+    /// Add `tag` to the item, see [`BatchCanceller::cancel_tag`]
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+}
+
+/// How far a batch run has got, reported after each item completes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchProgress {
+    pub completed: usize,
+    pub total: usize,
+    /// The item which just completed
+    pub item_id: String,
+}
+
+#[derive(Clone)]
+struct ProgressCallback(Arc<dyn Fn(BatchProgress) + Send + Sync>);
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// One row of a batch report, describing a single saved artifact
+///
+/// Together, `prompts`, `sampler`, `clip_guidance_preset` and `params` hold
+/// everything needed to send the same request again.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BatchRecord {
+    pub item_id: String,
+    /// Every prompt text joined with ` | `, for a quick read
+    pub prompt: String,
+    #[serde(default)]
+    pub prompts: PromptGroups,
+    pub seed: u32,
+    pub engine: String,
+    /// The sampler as the API names it, e.g. `K_DPMPP_2M`, unless left to
+    /// the API
+    #[serde(default)]
+    pub sampler: Option<String>,
+    /// The CLIP guidance preset as the API names it, e.g. `FAST_BLUE`
+    #[serde(default)]
+    pub clip_guidance_preset: Option<String>,
+    pub params: serde_json::Value,
+    pub output_path: String,
+    pub finish_reason: String,
+    pub latency_ms: u64,
+    pub estimated_credits: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchRunner {
+    out_dir: PathBuf,
+    concurrency: usize,
+    report: Option<(PathBuf, ReportFormat)>,
+    resume: bool,
+    priority: Priority,
+    provenance: bool,
+    alt_text: bool,
+    overwrite: OverwritePolicy,
+    #[cfg(feature = "image")]
+    watermark: Option<crate::watermark::Watermark>,
+    on_progress: Option<ProgressCallback>,
+    keep_best: Option<Arc<dyn ArtifactScorer>>,
+    canceller: Option<BatchCanceller>,
+    retry: Option<RetryPolicy>,
+    schedule: Option<BatchSchedule>,
+}
+
+impl BatchRunner {
+    pub fn new(out_dir: impl AsRef<Path>) -> Self {
+        Self {
+            out_dir: out_dir.as_ref().to_path_buf(),
+            concurrency: 1,
+            report: None,
+            resume: false,
+            priority: Priority::Background,
+            provenance: false,
+            alt_text: false,
+            overwrite: OverwritePolicy::Overwrite,
+            #[cfg(feature = "image")]
+            watermark: None,
+            on_progress: None,
+            keep_best: None,
+            canceller: None,
+            retry: None,
+            schedule: None,
+        }
+    }
+
+    /// The directory artifacts, progress and recipe inputs are written to
+    pub fn out_dir(&self) -> &Path {
+        &self.out_dir
+    }
+
+    /// How many items may be in flight at the same time
+    pub fn concurrency(mut self, concurrency: usize) -> Result<Self> {
+        if concurrency == 0 {
+            return Err(Box::new(BatchError::ConcurrencyZero));
+        }
+
+        self.concurrency = concurrency;
+
+        Ok(self)
+    }
+
+    /// Write a report of the run to `path` once every item has completed
+    pub fn report(mut self, path: impl AsRef<Path>, format: ReportFormat) -> Result<Self> {
+        self.report = Some((path.as_ref().to_path_buf(), format));
+        Ok(self)
+    }
+
+    /// Skip items already completed by a previous run into the same output
+    /// directory, reusing their recorded results
+    ///
+    /// Pin seeds on the requests so a resumed run reproduces the same batch.
+    pub fn resume(mut self, resume: bool) -> Result<Self> {
+        self.resume = resume;
+        Ok(self)
+    }
+
+    /// The limiter lane batch requests are queued in, background by default
+    pub fn priority(mut self, priority: Priority) -> Result<Self> {
+        self.priority = priority;
+        Ok(self)
+    }
+
+    /// Embed a [`Provenance`](crate::provenance::Provenance) manifest into
+    /// every saved image
+    pub fn provenance(mut self, embed: bool) -> Result<Self> {
+        self.provenance = embed;
+        Ok(self)
+    }
+
+    /// What to do when an image's file exists already, overwriting it by
+    /// default
+    ///
+    /// With [`OverwritePolicy::Rename`], records hold the path each image
+    /// was saved under.
+    pub fn overwrite(mut self, policy: OverwritePolicy) -> Result<Self> {
+        self.overwrite = policy;
+        Ok(self)
+    }
+
+    /// Write the item's prompts as [`alt_text`] to a `.txt` sidecar next to
+    /// every saved image
+    pub fn alt_text(mut self, alt_text: bool) -> Result<Self> {
+        self.alt_text = alt_text;
+        Ok(self)
+    }
+
+    /// Stamp `watermark` onto every saved image
+    #[cfg(feature = "image")]
+    pub fn watermark(mut self, watermark: crate::watermark::Watermark) -> Result<Self> {
+        self.watermark = Some(watermark);
+        Ok(self)
+    }
+
+    /// Call `callback` each time an item completes, whether it succeeded or
+    /// not, resumed and cancelled items included
+    ///
+    /// With the `progress` feature,
+    /// [`progress_bar::batch`](crate::progress_bar::batch) turns an
+    /// `indicatif` progress bar into such a callback.
+    pub fn on_progress(
+        mut self,
+        callback: impl Fn(BatchProgress) + Send + Sync + 'static,
+    ) -> Result<Self> {
+        self.on_progress = Some(ProgressCallback(Arc::new(callback)));
+        Ok(self)
+    }
+
+    /// Save only the best artifact of each item according to `scorer`,
+    /// rather than every sample
+    ///
+    /// Items without a successful artifact still have all of theirs saved.
+    pub fn keep_best(mut self, scorer: impl ArtifactScorer + 'static) -> Result<Self> {
+        self.keep_best = Some(Arc::new(scorer));
+        Ok(self)
+    }
+
+    /// Let `canceller` cancel the items of this runner's runs which haven't
+    /// been sent yet
+    pub fn canceller(mut self, canceller: BatchCanceller) -> Result<Self> {
+        self.canceller = Some(canceller);
+        Ok(self)
+    }
+
+    /// Send items failing for a passing reason again, as `policy` says
+    pub fn retry(mut self, policy: RetryPolicy) -> Result<Self> {
+        self.retry = Some(policy);
+        Ok(self)
+    }
+
+    /// Send items only while `schedule` is open, waiting for it otherwise
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stability_rs::batch::{BatchRunner, BatchSchedule, TimeWindow};
+    /// # fn main() -> stability_rs::Result<()> {
+    ///
+    /// let off_peak = BatchSchedule::new().window(TimeWindow::new("01:00", "06:00")?)?;
+    /// let runner = BatchRunner::new("out").schedule(off_peak.clone())?;
+    ///
+    /// // e.g. from an admin endpoint, while the run goes on
+    /// off_peak.pause();
+    /// off_peak.resume();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn schedule(mut self, schedule: BatchSchedule) -> Result<Self> {
+        self.schedule = Some(schedule);
+        Ok(self)
+    }
+
+    /// Generate every item, returning the records of each in item order
+    ///
+    /// Items which fail are returned with their error in
+    /// [`BatchOutcome::failed`] and left out of the report, which lists the
+    /// records of the items which succeeded; a resumed run generates them
+    /// again, as well as the items in [`BatchOutcome::cancelled`]. Errors
+    /// setting up the run, such as an output directory which can't be
+    /// created, fail it as a whole.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(feature = "text-to-image", doc = "```no_run")]
+    #[cfg_attr(not(feature = "text-to-image"), doc = "```ignore")]
+    /// use stability_rs::{batch::*, text_to_img::*, Result, StylePreset};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///     let engine = "stable-diffusion-xl-1024-v1-0";
+    ///     let mut items = Vec::new();
+    ///
+    ///     for (i, prompt) in ["a lighthouse at dusk", "a fox in the snow"].iter().enumerate() {
+    ///         let request = TextToImageBuilder::new()
+    ///             .style_preset(StylePreset::Photographic)?
+    ///             .text_prompt(prompt, 1.0)?
+    ///             .seed(42)?
+    ///             .build()?;
+    ///         items.push(BatchItem::new(format!("item{}", i), engine, request));
+    ///     }
+    ///
+    ///     let outcome = BatchRunner::new("out")
+    ///         .concurrency(2)?
+    ///         .report("out/report.jsonl", ReportFormat::Jsonl)?
+    ///         .run(items)
+    ///         .await?;
+    ///
+    ///     println!("{} artifacts saved", outcome.outputs().flatten().count());
+    ///     for (item, e) in &outcome.failed {
+    ///         println!("{} failed: {}", item.id, e);
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn run(
+        &self,
+        items: Vec<BatchItem>,
+    ) -> Result<BatchOutcome<BatchItem, Vec<BatchRecord>>> {
+        tokio::fs::create_dir_all(&self.out_dir).await?;
+        let progress = Progress::open(&self.out_dir, self.resume)?;
+        let completed = AtomicUsize::new(0);
+        let total = items.len();
+
+        let registration = self.canceller.as_ref().map(|c| c.register(&items));
+
+        let (progress, completed) = (&progress, &completed);
+        let registration = registration.as_ref();
+        // `None` for the items cancelled before being sent
+        type ItemResult = Option<Result<Vec<BatchRecord>>>;
+        let mut results: Vec<Option<(BatchItem, ItemResult)>> = (0..total).map(|_| None).collect();
+        let mut queue: Vec<(usize, BatchItem)> = items.into_iter().enumerate().collect();
+        let mut attempt = 1;
+        while !queue.is_empty() {
+            if let (Some(retry), true) = (&self.retry, attempt > 1) {
+                tokio::time::sleep(retry.delay(attempt)).await;
+            }
+            let sent: Vec<(usize, BatchItem, ItemResult, bool)> = limiter::with_priority(
+                self.priority,
+                stream::iter(queue.into_iter().map(|(i, item)| async move {
+                    if let Some(schedule) = &self.schedule {
+                        schedule.wait().await;
+                    }
+                    let records = match registration {
+                        Some(registration) if !registration.start(i) => None,
+                        _ => Some(self.run_item(&item, progress).await),
+                    };
+                    let retry = match &records {
+                        Some(Err(e)) => self.retries(e.as_ref(), attempt),
+                        _ => false,
+                    };
+                    if let Some(registration) = registration {
+                        registration.finish(i, retry);
+                    }
+                    if let (Some(callback), false) = (&self.on_progress, retry) {
+                        (callback.0)(BatchProgress {
+                            completed: completed.fetch_add(1, Ordering::Relaxed) + 1,
+                            total,
+                            item_id: item.id.clone(),
+                        });
+                    }
+                    (i, item, records, retry)
+                }))
+                .buffered(self.concurrency)
+                .collect(),
+            )
+            .await;
+
+            queue = Vec::new();
+            for (i, mut item, records, retry) in sent {
+                match (records, retry) {
+                    (Some(Err(e)), true) => {
+                        if matches!(e.downcast_ref(), Some(BatchError::Filtered)) {
+                            item.request = item.request.with_seed(Seed::Random)?;
+                        }
+                        queue.push((i, item));
+                    }
+                    (records, _) => {
+                        let records = records.map(|r| self.give_up(r, attempt));
+                        results[i] = Some((item, records));
+                    }
+                }
+            }
+            attempt += 1;
+        }
+
+        let mut outcome = BatchOutcome::default();
+        for (item, records) in results.into_iter().flatten() {
+            match records {
+                Some(records) => outcome.push(item, records),
+                None => outcome.cancelled.push(item),
+            }
+        }
+
+        if let Some((path, format)) = &self.report {
+            let records: Vec<BatchRecord> = outcome.outputs().flatten().cloned().collect();
+            report::write_to_path(&records, path, *format)?;
+        }
+
+        Ok(outcome)
+    }
+
+    /// Whether an item failing with `err` on `attempt` is sent again
+    fn retries(&self, err: &(dyn std::error::Error + 'static), attempt: u32) -> bool {
+        self.retry
+            .as_ref()
+            .is_some_and(|retry| attempt < retry.max_attempts() && retry.is_retryable(err))
+    }
+
+    /// `result`, marking the errors which would have been retried had
+    /// attempts remained
+    fn give_up(&self, result: Result<Vec<BatchRecord>>, attempts: u32) -> Result<Vec<BatchRecord>> {
+        match result {
+            Err(last) if self.retry.as_ref().is_some_and(|r| r.is_retryable(last.as_ref())) => {
+                Err(Box::new(BatchError::RetriesExhausted { attempts, last }))
+            }
+            result => result,
+        }
+    }
+
+    async fn run_item(&self, item: &BatchItem, progress: &Progress) -> Result<Vec<BatchRecord>> {
+        check_item_id(&item.id)?;
+        let hash = progress::item_hash(item)?;
+        if let Some(records) = progress.completed(&hash) {
+            return Ok(records.clone());
+        }
+
+        let started = Instant::now();
+        let mut resp = item.request.generate(&item.engine).await?;
+        if self.retry.as_ref().is_some_and(RetryPolicy::retries_filtered) && is_filtered(&resp) {
+            return Err(Box::new(BatchError::Filtered));
+        }
+        if let Some(scorer) = &self.keep_best {
+            scoring::keep_best(&mut resp, scorer.as_ref());
+        }
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        let params = item.request.params()?;
+        let estimated_credits = credits::estimate(&item.engine, item.request.steps(), 1);
+
+        let mut records = Vec::with_capacity(resp.artifacts.len());
+        for (i, image) in resp.artifacts.iter().enumerate() {
+            let path = self.out_dir.join(format!("{}_{}.png", item.id, i));
+            let mut artifact = image.to_artifact()?;
+            #[cfg(feature = "image")]
+            if let Some(watermark) = &self.watermark {
+                artifact = artifact.watermarked(watermark)?;
+            }
+            if self.provenance {
+                let provenance =
+                    Provenance::new(&item.engine, &item.request.prompt()).seed(image.seed)?;
+                artifact = artifact.with_provenance(&provenance)?;
+            }
+            let path = artifact.save_with_policy(&path, self.overwrite).await?;
+            let output_path = path.to_string_lossy().into_owned();
+            if self.alt_text {
+                let text = alt_text::from_prompts(&item.request.prompt_groups());
+                alt_text::write_sidecar(&path, &text).await?;
+            }
+
+            records.push(BatchRecord {
+                item_id: item.id.clone(),
+                prompt: item.request.prompt(),
+                prompts: item.request.prompt_groups(),
+                seed: image.seed,
+                engine: item.engine.clone(),
+                sampler: item.request.sampler(),
+                clip_guidance_preset: Some(item.request.clip_guidance_preset()),
+                params: params.clone(),
+                output_path,
+                finish_reason: image.finish_reason.clone(),
+                latency_ms,
+                estimated_credits,
+            });
+        }
+
+        progress.record(&hash, &records)?;
+
+        Ok(records)
+    }
+}
+
+/// Err unless `id` stays a file name within the output directory once
+/// `_<n>.png` is appended
+fn check_item_id(id: &str) -> Result<()> {
+    if id.contains(['/', '\\', ':', '\0']) || id.contains("..") {
+        return Err(Box::new(BatchError::ItemIdInvalid(id.to_string())));
+    }
+    Ok(())
+}
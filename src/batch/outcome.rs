@@ -0,0 +1,92 @@
+//! Results of fan-out runs which keep every success.
+
+use crate::api::rest::generation::ImageResponse;
+use crate::error::BatchError;
+use crate::prelude::*;
+
+/// What became of each request of a fan-out run
+///
+/// One request failing doesn't discard the others, which completed and were
+/// paid for: they stay in `succeeded` while the failure is recorded in
+/// `failed`, each in the order the requests were given.
+#[derive(Debug)]
+pub struct BatchOutcome<R, T = ImageResponse> {
+    pub succeeded: Vec<(R, T)>,
+    pub failed: Vec<(R, Box<dyn std::error::Error + Send + Sync>)>,
+    /// Requests cancelled before they were sent
+    pub cancelled: Vec<R>,
+}
+
+impl<R, T> Default for BatchOutcome<R, T> {
+    fn default() -> Self {
+        Self {
+            succeeded: Vec::new(),
+            failed: Vec::new(),
+            cancelled: Vec::new(),
+        }
+    }
+}
+
+impl<R, T> BatchOutcome<R, T> {
+    /// Sort each request into `succeeded` or `failed` by its result
+    pub fn from_results(results: impl IntoIterator<Item = (R, Result<T>)>) -> Self {
+        let mut outcome = Self::default();
+        for (request, result) in results {
+            outcome.push(request, result);
+        }
+        outcome
+    }
+
+    pub fn push(&mut self, request: R, result: Result<T>) {
+        match result {
+            Ok(output) => self.succeeded.push((request, output)),
+            Err(e) => self.failed.push((request, e)),
+        }
+    }
+
+    /// Whether every request succeeded
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty() && self.cancelled.is_empty()
+    }
+
+    /// The failed requests which were retried until out of attempts, see
+    /// [`RetryPolicy`](super::RetryPolicy)
+    pub fn exhausted(
+        &self,
+    ) -> impl Iterator<Item = &(R, Box<dyn std::error::Error + Send + Sync>)> {
+        self.failed.iter().filter(|(_, e)| is_exhausted(e.as_ref()))
+    }
+
+    /// The failed requests which weren't retried, or failed for good on a
+    /// retry
+    pub fn hard_failures(
+        &self,
+    ) -> impl Iterator<Item = &(R, Box<dyn std::error::Error + Send + Sync>)> {
+        self.failed.iter().filter(|(_, e)| !is_exhausted(e.as_ref()))
+    }
+
+    /// The outputs of the requests which succeeded
+    pub fn outputs(&self) -> impl Iterator<Item = &T> {
+        self.succeeded.iter().map(|(_, output)| output)
+    }
+
+    /// The outputs of every request, or the error of the first one which
+    /// failed, or [`BatchError::Cancelled`] when some were cancelled
+    pub fn into_result(self) -> Result<Vec<T>> {
+        if !self.cancelled.is_empty() && self.failed.is_empty() {
+            return Err(Box::new(BatchError::Cancelled(self.cancelled.len())));
+        }
+        match self.failed.into_iter().next() {
+            Some((_, e)) => Err(e),
+            None => Ok(self
+                .succeeded
+                .into_iter()
+                .map(|(_, output)| output)
+                .collect()),
+        }
+    }
+}
+
+fn is_exhausted(err: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    matches!(err.downcast_ref(), Some(BatchError::RetriesExhausted { .. }))
+}
@@ -0,0 +1,227 @@
+use super::{BatchItem, BatchRecord};
+#[cfg(feature = "encryption")]
+use crate::encryption::EncryptionKey;
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Name of the progress file kept in a batch output directory
+pub const PROGRESS_FILE: &str = ".batch_progress.jsonl";
+
+#[cfg(all(test, feature = "text-to-image"))]
+mod tests {
+    use super::*;
+    use crate::text_to_img::TextToImageBuilder;
+    use crate::StylePreset;
+
+    fn item(id: &str, seed: u32) -> BatchItem {
+        let request = TextToImageBuilder::new()
+            .style_preset(StylePreset::Photographic)
+            .unwrap()
+            .text_prompt("a lighthouse at dusk", 1.0)
+            .unwrap()
+            .seed(seed)
+            .unwrap()
+            .build()
+            .unwrap();
+        BatchItem::new(id, "stable-diffusion-xl-1024-v1-0", request)
+    }
+
+    #[test]
+    fn item_hash_is_stable_and_depends_on_params() {
+        assert_eq!(
+            item_hash(&item("a", 1)).unwrap(),
+            item_hash(&item("a", 1)).unwrap()
+        );
+        assert_ne!(
+            item_hash(&item("a", 1)).unwrap(),
+            item_hash(&item("a", 2)).unwrap()
+        );
+        assert_ne!(
+            item_hash(&item("a", 1)).unwrap(),
+            item_hash(&item("b", 1)).unwrap()
+        );
+    }
+
+    #[test]
+    fn progress_is_reloaded_when_resuming() {
+        let dir =
+            std::env::temp_dir().join(format!("stability_rs_progress_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let progress = Progress::open(&dir, false).unwrap();
+        progress.record("abc", &[]).unwrap();
+        drop(progress);
+
+        // a crash can leave a partially written last line behind
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.join(PROGRESS_FILE))
+            .unwrap();
+        file.write_all(b"{\"hash\":\"de").unwrap();
+
+        assert!(Progress::open(&dir, true)
+            .unwrap()
+            .completed("abc")
+            .is_some());
+        assert!(Progress::open(&dir, false)
+            .unwrap()
+            .completed("abc")
+            .is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypted_progress_is_reloaded_when_resuming() {
+        let dir = std::env::temp_dir().join(format!(
+            "stability_rs_progress_encrypted_{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let key = EncryptionKey::generate();
+
+        let progress = Progress::open_with_key(&dir, false, Some(key.clone())).unwrap();
+        progress.record("abc", &[]).unwrap();
+        drop(progress);
+
+        let written = std::fs::read_to_string(dir.join(PROGRESS_FILE)).unwrap();
+        assert!(!written.contains("abc"));
+        assert!(Progress::open_with_key(&dir, true, Some(key))
+            .unwrap()
+            .completed("abc")
+            .is_some());
+        // without the key the lines are unreadable and the batch starts over
+        assert!(Progress::open_with_key(&dir, true, None)
+            .unwrap()
+            .completed("abc")
+            .is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Entry {
+    hash: String,
+    records: Vec<BatchRecord>,
+}
+
+/// Completed items of a batch, appended to as items finish
+///
+/// Lines are encrypted one by one when an
+/// [encryption key](crate::encryption::install) is installed, as the records
+/// hold the prompts.
+#[derive(Debug)]
+pub(crate) struct Progress {
+    file: Mutex<File>,
+    completed: HashMap<String, Vec<BatchRecord>>,
+    #[cfg(feature = "encryption")]
+    key: Option<EncryptionKey>,
+}
+
+impl Progress {
+    /// Open the progress file in `out_dir`, loading it when `resume` is set
+    /// and starting over otherwise
+    #[cfg(feature = "encryption")]
+    pub(crate) fn open(out_dir: &Path, resume: bool) -> Result<Self> {
+        Self::open_with_key(out_dir, resume, crate::encryption::installed())
+    }
+
+    /// Open the progress file in `out_dir`, loading it when `resume` is set
+    /// and starting over otherwise
+    #[cfg(not(feature = "encryption"))]
+    pub(crate) fn open(out_dir: &Path, resume: bool) -> Result<Self> {
+        let (file, completed) =
+            Self::load(out_dir, resume, |line| serde_json::from_str(line).ok())?;
+        Ok(Self {
+            file: Mutex::new(file),
+            completed,
+        })
+    }
+
+    #[cfg(feature = "encryption")]
+    fn open_with_key(out_dir: &Path, resume: bool, key: Option<EncryptionKey>) -> Result<Self> {
+        let (file, completed) = Self::load(out_dir, resume, |line| match &key {
+            Some(key) => serde_json::from_slice(&key.decrypt_line(line).ok()?).ok(),
+            None => serde_json::from_str(line).ok(),
+        })?;
+        Ok(Self {
+            file: Mutex::new(file),
+            completed,
+            key,
+        })
+    }
+
+    fn load(
+        out_dir: &Path,
+        resume: bool,
+        parse: impl Fn(&str) -> Option<Entry>,
+    ) -> Result<(File, HashMap<String, Vec<BatchRecord>>)> {
+        let path = out_dir.join(PROGRESS_FILE);
+        let mut completed = HashMap::new();
+
+        if resume && path.exists() {
+            for line in BufReader::new(File::open(&path)?).lines() {
+                // lines which fail to parse were cut short by an interrupted run
+                if let Some(entry) = parse(&line?) {
+                    completed.insert(entry.hash, entry.records);
+                }
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(resume)
+            .write(true)
+            .truncate(!resume)
+            .open(&path)?;
+
+        Ok((file, completed))
+    }
+
+    pub(crate) fn completed(&self, hash: &str) -> Option<&Vec<BatchRecord>> {
+        self.completed.get(hash)
+    }
+
+    pub(crate) fn record(&self, hash: &str, records: &[BatchRecord]) -> Result<()> {
+        let mut line = serde_json::to_vec(&Entry {
+            hash: hash.to_string(),
+            records: records.to_vec(),
+        })?;
+        #[cfg(feature = "encryption")]
+        if let Some(key) = &self.key {
+            line = key.encrypt_line(&line).into_bytes();
+        }
+        line.push(b'\n');
+
+        // unwrap warranted because the lock is only held for a single write
+        let mut file = self.file.lock().unwrap();
+        file.write_all(&line)?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+/// SHA-256 over the item id, engine and request parameters
+pub fn item_hash(item: &BatchItem) -> Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(item.id.as_bytes());
+    hasher.update([0]);
+    hasher.update(item.engine.as_bytes());
+    hasher.update([0]);
+    // serde_json::Value keeps object keys sorted, so extras hash deterministically
+    hasher.update(item.request.params()?.to_string().as_bytes());
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
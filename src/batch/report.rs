@@ -0,0 +1,213 @@
+use super::BatchRecord;
+use crate::atomic_write::TempPath;
+#[cfg(feature = "encryption")]
+use crate::encryption::EncryptionKey;
+use crate::prelude::*;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const CSV_HEADER: &str = "item_id,prompt,positive_prompts,negative_prompts,seed,engine,sampler,\
+                          clip_guidance_preset,params,output_path,finish_reason,latency_ms,\
+                          estimated_credits";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::rest::generation::{PromptGroups, WeightedPrompt};
+
+    fn record(prompt: &str) -> BatchRecord {
+        BatchRecord {
+            item_id: "item0".to_string(),
+            prompt: prompt.to_string(),
+            prompts: PromptGroups {
+                positive: vec![WeightedPrompt {
+                    text: prompt.to_string(),
+                    weight: 1.0,
+                }],
+                negative: Vec::new(),
+            },
+            seed: 42,
+            engine: "stable-diffusion-xl-1024-v1-0".to_string(),
+            sampler: Some("K_DPMPP_2M".to_string()),
+            clip_guidance_preset: Some("NONE".to_string()),
+            params: serde_json::json!({ "steps": 30 }),
+            output_path: "out/item0_0.png".to_string(),
+            finish_reason: "SUCCESS".to_string(),
+            latency_ms: 1200,
+            estimated_credits: 0.2,
+        }
+    }
+
+    #[test]
+    fn jsonl_report_is_one_record_per_line() {
+        let records = vec![record("a fox"), record("a crab")];
+        let mut out = Vec::new();
+        write_jsonl(&records, &mut out).unwrap();
+
+        let lines: Vec<BatchRecord> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines, records);
+    }
+
+    #[test]
+    fn csv_report_is_quoting_fields_with_commas_and_quotes() {
+        let mut out = Vec::new();
+        write_csv(&[record("a \"red\" fox, running")], &mut out).unwrap();
+
+        let csv = String::from_utf8(out).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some(CSV_HEADER));
+        assert_eq!(
+            lines.next(),
+            Some(
+                "item0,\"a \"\"red\"\" fox, running\",\
+                 \"[{\"\"text\"\":\"\"a \\\"\"red\\\"\" fox, running\"\",\"\"weight\"\":1.0}]\",[],\
+                 42,stable-diffusion-xl-1024-v1-0,K_DPMPP_2M,NONE,\"{\"\"steps\"\":30}\",out/item0_0.png,SUCCESS,1200,0.2"
+            )
+        );
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypted_reports_are_hiding_the_prompts() {
+        let dir = std::env::temp_dir().join(format!(
+            "stability_rs_report_encrypted_{}",
+            std::process::id()
+        ));
+        let key = EncryptionKey::generate();
+        let records = vec![record("a secret fox"), record("a secret crab")];
+
+        let jsonl = dir.join("report.jsonl");
+        write_encrypted_to_path(&records, &jsonl, ReportFormat::Jsonl, &key).unwrap();
+        let written = std::fs::read_to_string(&jsonl).unwrap();
+        assert!(!written.contains("secret"));
+        let read: Vec<BatchRecord> = written
+            .lines()
+            .map(|l| decrypt_line(l, &key).unwrap())
+            .collect();
+        assert_eq!(read, records);
+
+        let csv = dir.join("report.csv");
+        write_encrypted_to_path(&records, &csv, ReportFormat::Csv, &key).unwrap();
+        let csv = key.decrypt(&std::fs::read(&csv).unwrap()).unwrap();
+        assert!(String::from_utf8(csv).unwrap().contains("a secret crab"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Jsonl,
+    Csv,
+}
+
+pub fn write<W: Write>(records: &[BatchRecord], writer: W, format: ReportFormat) -> Result<()> {
+    match format {
+        ReportFormat::Jsonl => write_jsonl(records, writer),
+        ReportFormat::Csv => write_csv(records, writer),
+    }
+}
+
+/// Write the report to the file at `path`, encrypted with
+/// [`write_encrypted_to_path`] when an
+/// [encryption key](crate::encryption::install) is installed, as it holds
+/// the prompts
+pub fn write_to_path(records: &[BatchRecord], path: &Path, format: ReportFormat) -> Result<()> {
+    #[cfg(feature = "encryption")]
+    if let Some(key) = crate::encryption::installed() {
+        return write_encrypted_to_path(records, path, format, &key);
+    }
+    crate::preflight::create_parent_dirs(path)?;
+    let temp = TempPath::new(path);
+    let mut writer = BufWriter::new(File::create(temp.path())?);
+    write(records, &mut writer, format)?;
+    writer.flush()?;
+    drop(writer);
+    temp.commit()?;
+    Ok(())
+}
+
+/// Write the report to the file at `path` encrypted with `key`: a JSONL
+/// report line by line, read back with [`decrypt_line`], and a CSV report
+/// whole, read back with [`EncryptionKey::read`]
+#[cfg(feature = "encryption")]
+pub fn write_encrypted_to_path(
+    records: &[BatchRecord],
+    path: &Path,
+    format: ReportFormat,
+    key: &EncryptionKey,
+) -> Result<()> {
+    let mut sealed = Vec::new();
+    match format {
+        ReportFormat::Jsonl => {
+            for record in records {
+                sealed.extend_from_slice(key.encrypt_line(&serde_json::to_vec(record)?).as_bytes());
+                sealed.push(b'\n');
+            }
+        }
+        ReportFormat::Csv => {
+            let mut csv = Vec::new();
+            write_csv(records, &mut csv)?;
+            sealed = key.encrypt(&csv);
+        }
+    }
+    crate::preflight::create_parent_dirs(path)?;
+    crate::atomic_write::write(path, &sealed)?;
+    Ok(())
+}
+
+/// The record of a line of a JSONL report written encrypted
+#[cfg(feature = "encryption")]
+pub fn decrypt_line(line: &str, key: &EncryptionKey) -> Result<BatchRecord> {
+    Ok(serde_json::from_slice(&key.decrypt_line(line)?)?)
+}
+
+/// Write one JSON object per line
+pub fn write_jsonl<W: Write>(records: &[BatchRecord], mut writer: W) -> Result<()> {
+    for record in records {
+        serde_json::to_writer(&mut writer, record)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write a header row followed by one row per record, the prompt groups and
+/// `params` being JSON encoded
+pub fn write_csv<W: Write>(records: &[BatchRecord], mut writer: W) -> Result<()> {
+    writeln!(writer, "{}", CSV_HEADER)?;
+    for r in records {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            csv_field(&r.item_id),
+            csv_field(&r.prompt),
+            csv_field(&serde_json::to_string(&r.prompts.positive)?),
+            csv_field(&serde_json::to_string(&r.prompts.negative)?),
+            r.seed,
+            csv_field(&r.engine),
+            csv_field(r.sampler.as_deref().unwrap_or_default()),
+            csv_field(r.clip_guidance_preset.as_deref().unwrap_or_default()),
+            csv_field(&r.params.to_string()),
+            csv_field(&r.output_path),
+            csv_field(&r.finish_reason),
+            r.latency_ms,
+            r.estimated_credits,
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
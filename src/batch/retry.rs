@@ -0,0 +1,99 @@
+//! Retrying batch items which failed for a passing reason.
+
+use crate::error::{self, BatchError};
+use crate::prelude::*;
+use std::time::Duration;
+
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(2);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{ApiResponseError, Error};
+
+    #[test]
+    fn delay_is_doubling_up_to_the_cap() {
+        let retry = RetryPolicy::new(6)
+            .backoff(Duration::from_secs(1), Duration::from_secs(5))
+            .unwrap();
+        let delays: Vec<u64> = (2..=6).map(|attempt| retry.delay(attempt).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 5, 5]);
+    }
+
+    #[test]
+    fn filtered_items_are_retried_only_when_asked() {
+        let rate_limited = Error::ClientSendRequestError(ApiResponseError {
+            id: "id".to_string(),
+            name: "rate_limit_exceeded".to_string(),
+            message: "slow down".to_string(),
+        });
+        let retry = RetryPolicy::new(3);
+
+        assert!(retry.is_retryable(&rate_limited));
+        assert!(!retry.is_retryable(&BatchError::Filtered));
+        assert!(retry.filtered(true).unwrap().is_retryable(&BatchError::Filtered));
+    }
+}
+
+/// Which failed items a batch run sends again, and how long it waits first
+///
+/// Items failing with a [transient](crate::error::is_transient) error, such
+/// as a rate limit or an outage, are put in a retry lane and sent again once
+/// the rest of the batch has been tried, the wait doubling after each pass.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+    filtered: bool,
+}
+
+impl RetryPolicy {
+    /// Send an item up to `max_attempts` times in all, the first included
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            backoff: DEFAULT_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            filtered: false,
+        }
+    }
+
+    /// Wait `initial` before the first retry, doubling up to `max`, 2 and
+    /// 60 seconds by default
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Result<Self> {
+        self.backoff = initial;
+        self.max_backoff = max.max(initial);
+        Ok(self)
+    }
+
+    /// Also retry items whose every artifact was blurred by the safety
+    /// filter, at a random seed, rather than saving the blurred artifacts
+    pub fn filtered(mut self, filtered: bool) -> Result<Self> {
+        self.filtered = filtered;
+        Ok(self)
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub(crate) fn retries_filtered(&self) -> bool {
+        self.filtered
+    }
+
+    /// How long to wait before sending `attempt`, the first retry being
+    /// attempt 2
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let doublings = attempt.saturating_sub(2).min(31);
+        self.backoff
+            .saturating_mul(1 << doublings)
+            .min(self.max_backoff)
+    }
+
+    pub(crate) fn is_retryable(&self, err: &(dyn std::error::Error + 'static)) -> bool {
+        let filtered = matches!(err.downcast_ref(), Some(BatchError::Filtered));
+        error::is_transient(err) || (self.filtered && filtered)
+    }
+}
@@ -0,0 +1,205 @@
+//! Running batch items only within daily time windows.
+
+use crate::error::BatchError;
+use crate::prelude::*;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+
+const DAY: u32 = 86_400;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_is_crossing_midnight_when_it_ends_before_it_starts() {
+        let night = TimeWindow::new("22:30", "04:00").unwrap();
+        assert!(night.contains(23 * 3_600));
+        assert!(night.contains(3_600));
+        assert!(!night.contains(4 * 3_600));
+        assert_eq!(night.until_open(12 * 3_600), 10 * 3_600 + 1_800);
+        assert_eq!(night.until_open(23 * 3_600), 0);
+
+        let morning = TimeWindow::new("01:00", "06:00").unwrap();
+        assert_eq!(morning.until_open(7 * 3_600), 18 * 3_600);
+    }
+
+    #[test]
+    fn window_is_rejecting_invalid_and_empty_spans() {
+        assert!(TimeWindow::new("1am", "06:00").is_err());
+        assert!(TimeWindow::new("24:00", "06:00").is_err());
+        assert!(TimeWindow::new("06:00", "06:00").is_err());
+    }
+}
+
+/// A daily span of wall-clock time, crossing midnight when it ends before
+/// it starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeWindow {
+    /// Seconds after midnight
+    start: u32,
+    end: u32,
+}
+
+impl TimeWindow {
+    /// From `start` up to `end`, both written `HH:MM`, e.g. `"01:00"` and
+    /// `"06:00"`
+    pub fn new(start: &str, end: &str) -> Result<Self> {
+        let (start, end) = (time_of_day(start)?, time_of_day(end)?);
+        if start == end {
+            return Err(Box::new(BatchError::TimeWindowEmpty));
+        }
+        Ok(Self { start, end })
+    }
+
+    fn contains(&self, secs: u32) -> bool {
+        match self.start < self.end {
+            true => (self.start..self.end).contains(&secs),
+            false => secs >= self.start || secs < self.end,
+        }
+    }
+
+    /// Seconds from `secs` after midnight until the window is next open
+    fn until_open(&self, secs: u32) -> u32 {
+        match self.contains(secs) {
+            true => 0,
+            false => (self.start + DAY - secs) % DAY,
+        }
+    }
+}
+
+/// `HH:MM` as seconds after midnight
+fn time_of_day(time: &str) -> Result<u32> {
+    let invalid = || BatchError::TimeOfDayInvalid(time.to_string());
+    let (hours, minutes) = time.split_once(':').ok_or_else(invalid)?;
+    let hours: u32 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u32 = minutes.parse().map_err(|_| invalid())?;
+    if hours > 23 || minutes > 59 {
+        return Err(Box::new(invalid()));
+    }
+    Ok(hours * 3_600 + minutes * 60)
+}
+
+/// A cloneable handle to when the items of a batch run may be sent
+///
+/// Items are sent within any of the schedule's windows, read in local time
+/// unless [`BatchSchedule::utc`] is set, or at any time for a schedule
+/// without windows. [`BatchSchedule::pause`] holds the items back until
+/// [`BatchSchedule::resume`]. Items sent before a window closes or the
+/// schedule is paused complete as usual.
+#[derive(Debug, Clone)]
+pub struct BatchSchedule {
+    windows: Vec<TimeWindow>,
+    utc: bool,
+    paused: Arc<watch::Sender<bool>>,
+}
+
+impl Default for BatchSchedule {
+    fn default() -> Self {
+        Self {
+            windows: Vec::new(),
+            utc: false,
+            paused: Arc::new(watch::Sender::new(false)),
+        }
+    }
+}
+
+impl BatchSchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also send items within `window`
+    pub fn window(mut self, window: TimeWindow) -> Result<Self> {
+        self.windows.push(window);
+        Ok(self)
+    }
+
+    /// Read the windows in UTC rather than local time
+    pub fn utc(mut self, utc: bool) -> Result<Self> {
+        self.utc = utc;
+        Ok(self)
+    }
+
+    /// Stop sending items until [`BatchSchedule::resume`]
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Whether an item would be sent now
+    pub fn is_open(&self) -> bool {
+        !self.is_paused() && self.until_open().is_zero()
+    }
+
+    /// How long until one of the windows opens, zero while one is
+    fn until_open(&self) -> Duration {
+        let now = self.seconds_of_day();
+        self.windows
+            .iter()
+            .map(|window| window.until_open(now))
+            .min()
+            .map_or(Duration::ZERO, |secs| Duration::from_secs(secs.into()))
+    }
+
+    fn seconds_of_day(&self) -> u32 {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        let offset = if self.utc { 0 } else { utc_offset(secs) };
+        (secs + offset).rem_euclid(DAY.into()) as u32
+    }
+
+    /// Wait until the schedule is open
+    pub(crate) async fn wait(&self) {
+        let mut paused = self.paused.subscribe();
+        loop {
+            if *paused.borrow_and_update() {
+                // the sender lives in `self`, so it is never dropped here
+                let _ = paused.changed().await;
+                continue;
+            }
+            let until_open = self.until_open();
+            if until_open.is_zero() {
+                return;
+            }
+            // wake up on a pause as well, to wait for the resume after it
+            tokio::select! {
+                _ = tokio::time::sleep(until_open) => {}
+                _ = paused.changed() => {}
+            }
+        }
+    }
+}
+
+/// The offset of local time from UTC at `secs` after the epoch, in seconds
+#[cfg(unix)]
+fn utc_offset(secs: i64) -> i64 {
+    let time = secs as libc::time_t;
+    let mut tm = std::mem::MaybeUninit::<libc::tm>::uninit();
+    // SAFETY: `tm` is only read once localtime_r has filled it in
+    let tm = unsafe {
+        if libc::localtime_r(&time, tm.as_mut_ptr()).is_null() {
+            return 0;
+        }
+        tm.assume_init()
+    };
+    // the field type differs between platforms
+    #[allow(clippy::useless_conversion)]
+    i64::from(tm.tm_gmtoff)
+}
+
+/// The offset of local time from UTC, unknown here so taken as none
+#[cfg(not(unix))]
+fn utc_offset(_secs: i64) -> i64 {
+    0
+}
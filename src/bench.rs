@@ -0,0 +1,266 @@
+//! Side-by-side engine comparisons.
+//!
+//! [`compare`] sends the same request, at the same seeds, to several engines
+//! at once, saves every artifact under an output directory and writes a
+//! [`Comparison`] manifest of latencies, filtered artifacts and output paths
+//! next to them. An engine which fails is recorded in the manifest rather
+//! than failing the comparison.
+//!
+#![cfg_attr(feature = "text-to-image", doc = "```no_run")]
+#![cfg_attr(not(feature = "text-to-image"), doc = "```ignore")]
+//! use stability_rs::{bench, text_to_img::*, Result, StylePreset};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     let request = TextToImageBuilder::new()
+//!         .style_preset(StylePreset::Photographic)?
+//!         .text_prompt("a lighthouse at dusk", 1.0)?
+//!         .build()?;
+//!
+//!     let comparison = bench::compare(
+//!         &["stable-diffusion-v1-6", "stable-diffusion-xl-1024-v1-0"],
+//!         request,
+//!         &[1, 2, 3],
+//!         "bench",
+//!     )
+//!     .await?;
+//!
+//!     for engine in &comparison.engines {
+//!         println!("{}: {} ms", engine.engine, engine.mean_latency_ms);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::api::rest::generation::PromptGroups;
+use crate::batch::BatchRequest;
+use crate::credits;
+use crate::prelude::*;
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Instant;
+
+/// Name of the manifest [`compare`] writes into its output directory
+pub const MANIFEST_FILE: &str = "comparison.json";
+
+const FINISH_CONTENT_FILTERED: &str = "CONTENT_FILTERED";
+
+/// One engine's generation at one seed
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BenchRun {
+    pub seed: u32,
+    pub latency_ms: u64,
+    pub artifacts: usize,
+    /// Artifacts the API blurred with a `CONTENT_FILTERED` finish reason
+    pub filtered: usize,
+    pub output_paths: Vec<String>,
+    /// Why the generation failed, if it did
+    pub error: Option<String>,
+}
+
+/// How one engine fared across every seed
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct EngineBench {
+    pub engine: String,
+    pub runs: Vec<BenchRun>,
+    /// The mean latency of the runs which succeeded
+    pub mean_latency_ms: u64,
+    pub filtered: usize,
+    pub failed: usize,
+    pub estimated_credits: f64,
+}
+
+/// The manifest of a [`compare`] run
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Comparison {
+    pub prompt: String,
+    pub prompts: PromptGroups,
+    pub seeds: Vec<u32>,
+    /// In the order the engines were given
+    pub engines: Vec<EngineBench>,
+}
+
+impl Comparison {
+    pub fn write_to_path(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        crate::preflight::create_parent_dirs(path)?;
+        crate::atomic_write::write(path, &serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Generate `request` on every engine at every seed, the engines
+/// concurrently and each engine's seeds in turn, and save the artifacts as
+/// `<engine>_<seed>_<n>.png` under `out_dir` along with a [`MANIFEST_FILE`]
+pub async fn compare(
+    engines: &[&str],
+    request: impl Into<BatchRequest>,
+    seeds: &[u32],
+    out_dir: impl AsRef<Path>,
+) -> Result<Comparison> {
+    let out_dir = out_dir.as_ref();
+    tokio::fs::create_dir_all(out_dir).await?;
+    let request = request.into();
+    let requests = seeds
+        .iter()
+        .map(|&seed| Ok((seed, request.with_seed(seed)?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let engines = join_all(
+        engines
+            .iter()
+            .map(|engine| bench_engine(engine, &requests, out_dir)),
+    )
+    .await;
+
+    let comparison = Comparison {
+        prompt: request.prompt(),
+        prompts: request.prompt_groups(),
+        seeds: seeds.to_vec(),
+        engines,
+    };
+    comparison.write_to_path(out_dir.join(MANIFEST_FILE))?;
+
+    Ok(comparison)
+}
+
+async fn bench_engine(
+    engine: &str,
+    requests: &[(u32, BatchRequest)],
+    out_dir: &Path,
+) -> EngineBench {
+    let mut runs = Vec::with_capacity(requests.len());
+    let mut estimated_credits = 0.0;
+    for (seed, request) in requests {
+        let started = Instant::now();
+        let run = match generate(engine, *seed, request, out_dir).await {
+            Ok((artifacts, filtered, output_paths)) => {
+                estimated_credits += credits::estimate(engine, request.steps(), 1);
+                BenchRun {
+                    seed: *seed,
+                    latency_ms: started.elapsed().as_millis() as u64,
+                    artifacts,
+                    filtered,
+                    output_paths,
+                    error: None,
+                }
+            }
+            Err(err) => BenchRun {
+                seed: *seed,
+                latency_ms: started.elapsed().as_millis() as u64,
+                artifacts: 0,
+                filtered: 0,
+                output_paths: Vec::new(),
+                error: Some(err.to_string()),
+            },
+        };
+        runs.push(run);
+    }
+
+    let succeeded: Vec<&BenchRun> = runs.iter().filter(|run| run.error.is_none()).collect();
+    let mean_latency_ms = match succeeded.len() {
+        0 => 0,
+        n => succeeded.iter().map(|run| run.latency_ms).sum::<u64>() / n as u64,
+    };
+
+    EngineBench {
+        engine: engine.to_string(),
+        mean_latency_ms,
+        filtered: runs.iter().map(|run| run.filtered).sum(),
+        failed: runs.len() - succeeded.len(),
+        estimated_credits,
+        runs,
+    }
+}
+
+/// Generate and save one run, returning its artifact and filtered counts
+/// and output paths
+async fn generate(
+    engine: &str,
+    seed: u32,
+    request: &BatchRequest,
+    out_dir: &Path,
+) -> Result<(usize, usize, Vec<String>)> {
+    let resp = request.generate(engine).await?;
+    let mut output_paths = Vec::with_capacity(resp.artifacts.len());
+    for (i, image) in resp.artifacts.iter().enumerate() {
+        let path = out_dir.join(format!("{}_{}_{}.png", engine, seed, i));
+        image.to_artifact()?.save(&path).await?;
+        output_paths.push(path.to_string_lossy().into_owned());
+    }
+    let filtered = resp
+        .artifacts
+        .iter()
+        .filter(|image| image.finish_reason == FINISH_CONTENT_FILTERED)
+        .count();
+
+    Ok((resp.artifacts.len(), filtered, output_paths))
+}
+
+#[cfg(all(test, feature = "text-to-image"))]
+mod tests {
+    use super::*;
+    use crate::testing::{image, json_response, FakeTransport};
+    use crate::text_to_img::TextToImageBuilder;
+    use crate::{ImageResponse, StylePreset};
+    use hyper::body::Bytes;
+    use hyper::StatusCode;
+
+    #[tokio::test]
+    async fn compare_is_recording_every_engine_and_seed() {
+        let dir = std::env::temp_dir().join(format!("stability_rs_bench_{}", std::process::id()));
+        let request = TextToImageBuilder::new()
+            .style_preset(StylePreset::Photographic)
+            .unwrap()
+            .text_prompt("a lighthouse at dusk", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+        let transport = FakeTransport::new(|req| {
+            if req.uri.path().contains("/broken/") {
+                return json_response(
+                    StatusCode::NOT_FOUND,
+                    Bytes::from(r#"{"id":"x","name":"not_found","message":"engine not found"}"#),
+                );
+            }
+            let seed = serde_json::from_slice::<serde_json::Value>(&req.body).unwrap()["seed"]
+                .as_u64()
+                .unwrap() as u32;
+            let resp = ImageResponse {
+                artifacts: vec![image(seed, FINISH_CONTENT_FILTERED)],
+                metadata: Default::default(),
+            };
+            json_response(
+                StatusCode::OK,
+                Bytes::from(serde_json::to_vec(&resp).unwrap()),
+            )
+        });
+
+        let comparison = transport
+            .scope(compare(&["sdxl", "broken"], request, &[5, 6], &dir))
+            .await
+            .unwrap();
+
+        let sdxl = &comparison.engines[0];
+        assert_eq!(
+            sdxl.runs.iter().map(|r| r.seed).collect::<Vec<_>>(),
+            vec![5, 6]
+        );
+        assert_eq!(sdxl.filtered, 2);
+        assert!(dir.join("sdxl_6_0.png").exists());
+        let broken = &comparison.engines[1];
+        assert_eq!(broken.failed, 2);
+        assert!(broken.runs[0]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("engine not found"));
+        let manifest: Comparison =
+            serde_json::from_slice(&std::fs::read(dir.join(MANIFEST_FILE)).unwrap()).unwrap();
+        assert_eq!(manifest, comparison);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,33 @@
+//! Synchronous entry points for callers without an async runtime.
+//!
+#![cfg_attr(feature = "text-to-image", doc = "```no_run")]
+#![cfg_attr(not(feature = "text-to-image"), doc = "```ignore")]
+//! use stability_rs::{blocking, text_to_img::*, Result};
+//!
+//! fn main() -> Result<()> {
+//!     let image = TextToImageBuilder::new()
+//!         .text_prompt("A crab on the moon surrounded by many stars", 1.0)?
+//!         .build()?;
+//!
+//!     let resp = blocking::block_on(image.generate("stable-diffusion-xl-1024-v1-0"))?;
+//!
+//!     println!("{} images", resp.artifacts.len());
+//!     Ok(())
+//! }
+//! ```
+
+use crate::prelude::*;
+use std::future::Future;
+
+/// Run a request to completion on a private single-threaded runtime
+///
+/// Must not be called from within an async runtime.
+pub fn block_on<T, F>(f: F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(f)
+}
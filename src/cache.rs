@@ -0,0 +1,184 @@
+//! Conditional requests for account and engine metadata.
+//!
+//! Engine lists, account details and balances are polled often but change
+//! rarely. Once a [`MetadataCache`] is [`install`]ed, `GET` responses from
+//! the `/engines` and `/user` endpoints which carry an `ETag` or
+//! `Last-Modified` header are kept, and the next request for the same path
+//! sends `If-None-Match` or `If-Modified-Since`. A `304 Not Modified` answer
+//! is then served from the cache without transferring the body again.
+//!
+//! ```
+//! use stability_rs::cache::{self, MetadataCache};
+//!
+//! cache::install(MetadataCache::new());
+//! ```
+//!
+//! Entries are kept per API key and organization, so clients of different
+//! accounts never share them.
+
+use hyper::body::Bytes;
+use hyper::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use hyper::http::request::Parts;
+use hyper::{HeaderMap, Method};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Paths, below the API version, whose responses may be cached
+const CACHEABLE_PATHS: [&str; 2] = ["/v1/engines/", "/v1/user/"];
+
+static GLOBAL: RwLock<Option<MetadataCache>> = RwLock::new(None);
+
+/// A cached response and the validators to revalidate it with
+#[derive(Debug, Clone)]
+pub(crate) struct Entry {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    pub(crate) headers: HeaderMap,
+    pub(crate) body: Bytes,
+}
+
+/// A cloneable handle to a shared cache of metadata responses
+#[derive(Debug, Clone, Default)]
+pub struct MetadataCache {
+    entries: Arc<Mutex<HashMap<String, Entry>>>,
+}
+
+impl MetadataCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many responses are cached
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// The response cached under `key`, whose validators are added to the
+    /// request `headers`
+    pub(crate) fn revalidate(&self, key: &str, headers: &mut HeaderMap) -> Option<Entry> {
+        let entry = self.lock().get(key).cloned()?;
+        if let Some(etag) = entry.etag.as_ref().and_then(|v| v.parse().ok()) {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        if let Some(date) = entry.last_modified.as_ref().and_then(|v| v.parse().ok()) {
+            headers.insert(IF_MODIFIED_SINCE, date);
+        }
+        Some(entry)
+    }
+
+    /// Keep a successful response under `key`, if it can be revalidated
+    /// later
+    pub(crate) fn store(&self, key: String, headers: &HeaderMap, body: &Bytes) {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let (etag, last_modified) = (header(ETAG), header(LAST_MODIFIED));
+        if etag.is_none() && last_modified.is_none() {
+            return;
+        }
+
+        self.lock().insert(
+            key,
+            Entry {
+                etag,
+                last_modified,
+                headers: headers.clone(),
+                body: body.clone(),
+            },
+        );
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Entry>> {
+        // unwrap warranted because the lock is never held across a panic point
+        self.entries.lock().unwrap()
+    }
+}
+
+/// Cache metadata responses for every client which does not set its own
+pub fn install(cache: MetadataCache) {
+    *GLOBAL.write().unwrap() = Some(cache);
+}
+
+pub fn uninstall() {
+    *GLOBAL.write().unwrap() = None;
+}
+
+pub(crate) fn installed() -> Option<MetadataCache> {
+    GLOBAL.read().unwrap().clone()
+}
+
+/// The path of a cacheable request, followed by a hash of the credentials it
+/// is sent with
+pub(crate) fn key(parts: &Parts) -> Option<String> {
+    let path = parts.uri.path();
+    if parts.method != Method::GET || !CACHEABLE_PATHS.iter().any(|p| path.starts_with(p)) {
+        return None;
+    }
+
+    let mut hasher = Sha256::new();
+    for name in ["authorization", "organization"] {
+        for value in parts.headers.get_all(name) {
+            hasher.update(value.as_bytes());
+        }
+        hasher.update(b"\n");
+    }
+    Some(format!("{} {:x}", path, hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Request;
+
+    fn parts(path: &str, key: &str) -> Parts {
+        Request::get(format!("https://api.stability.ai{}", path))
+            .header("authorization", key)
+            .body(())
+            .unwrap()
+            .into_parts()
+            .0
+    }
+
+    fn validated() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, "\"v1\"".parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn revalidate_is_adding_the_etag_for_the_same_key_only() {
+        let cache = MetadataCache::new();
+        let key_a = key(&parts("/v1/engines/list", "key-a")).unwrap();
+        cache.store(key_a.clone(), &validated(), &Bytes::from("[]"));
+
+        let mut headers = HeaderMap::new();
+        let entry = cache.revalidate(&key_a, &mut headers).unwrap();
+        assert_eq!(entry.body, Bytes::from("[]"));
+        assert_eq!(headers[IF_NONE_MATCH], "\"v1\"");
+
+        let key_b = key(&parts("/v1/engines/list", "key-b")).unwrap();
+        assert!(cache.revalidate(&key_b, &mut HeaderMap::new()).is_none());
+    }
+
+    #[test]
+    fn only_metadata_responses_with_validators_are_cached() {
+        let cache = MetadataCache::new();
+        let balance = key(&parts("/v1/user/balance", "key")).unwrap();
+        cache.store(balance, &HeaderMap::new(), &Bytes::new());
+
+        assert!(cache.is_empty());
+        assert!(key(&parts("/v1/generation/sdxl/text-to-image", "key")).is_none());
+    }
+}
@@ -0,0 +1,157 @@
+//! What each engine accepts.
+//!
+//! [`for_engine`] describes the parameter limits of an engine, so front-ends
+//! can build their forms from it instead of hard-coding them; the validators
+//! in [`crate::validation`] check requests against the same values.
+//!
+//! ```
+//! use stability_rs::capabilities::{self, Dimensions};
+//!
+//! let caps = capabilities::for_engine("stable-diffusion-xl-1024-v1-0");
+//! assert_eq!(caps.steps, 10..=150);
+//! assert!(matches!(caps.dimensions, Dimensions::Fixed(sizes) if sizes.contains(&(1152, 896))));
+//! ```
+
+use serde::Serialize;
+use std::ops::RangeInclusive;
+
+/// Dimensions accepted by the SDXL 1024 engines, as (width, height)
+pub const SDXL_DIMENSIONS: [(u32, u32); 9] = [
+    (1024, 1024),
+    (1152, 896),
+    (896, 1152),
+    (1216, 832),
+    (832, 1216),
+    (1344, 768),
+    (768, 1344),
+    (1536, 640),
+    (640, 1536),
+];
+
+/// Smallest and largest side accepted by Stable Diffusion v1.6
+pub const SD_V1_6_SIDE_RANGE: (u32, u32) = (320, 1536);
+
+/// Sides must be a multiple of this on every engine
+pub const SIDE_MULTIPLE: u32 = 64;
+/// Smallest side accepted on every engine
+pub const MIN_SIDE: u32 = 128;
+/// Largest side [`crate::validation::fit_dimensions`] picks for engines
+/// without their own limit
+pub const FIT_MAX_SIDE: u32 = 2048;
+
+pub const MAX_SAMPLES: u32 = 10;
+pub const STEPS: RangeInclusive<u32> = 10..=150;
+pub const CFG_SCALE: RangeInclusive<u32> = 0..=35;
+/// Pixels the mask of the v2beta edit endpoints may be grown by
+pub const MAX_GROW_MASK: u32 = 100;
+/// Smallest side an image can be upscaled to
+pub const UPSCALE_MIN_SIDE: u32 = 512;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dimensions {
+    /// Only these (width, height) pairs
+    Fixed(&'static [(u32, u32)]),
+    /// Any width and height of at least `min`, at most `max` if set, which
+    /// are multiples of `multiple_of`
+    Sides {
+        min: u32,
+        max: Option<u32>,
+        multiple_of: u32,
+    },
+}
+
+impl Dimensions {
+    pub fn contains(&self, width: u32, height: u32) -> bool {
+        match self {
+            Dimensions::Fixed(sizes) => sizes.contains(&(width, height)),
+            Dimensions::Sides {
+                min,
+                max,
+                multiple_of,
+            } => [width, height].iter().all(|side| {
+                side >= min
+                    && max.is_none_or(|max| *side <= max)
+                    && side.is_multiple_of(*multiple_of)
+            }),
+        }
+    }
+}
+
+/// The parameter limits of an engine
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Capabilities {
+    pub max_samples: u32,
+    pub steps: RangeInclusive<u32>,
+    pub cfg_scale: RangeInclusive<u32>,
+    pub dimensions: Dimensions,
+    /// The side of the square the engine was trained on, the size to aim for
+    pub native_side: u32,
+    /// Whether the engine serves the masking endpoint
+    pub inpainting: bool,
+    /// Whether the engine honours CLIP guidance presets
+    pub clip_guidance: bool,
+}
+
+/// The limits of `engine`, recognised from its id
+///
+/// Engines this crate doesn't know get the limits shared by every engine.
+pub fn for_engine(engine: &str) -> Capabilities {
+    let engine = engine.to_lowercase();
+    let sdxl = engine.contains("xl-1024");
+
+    let dimensions = if sdxl {
+        Dimensions::Fixed(&SDXL_DIMENSIONS)
+    } else if engine.contains("v1-6") {
+        Dimensions::Sides {
+            min: SD_V1_6_SIDE_RANGE.0,
+            max: Some(SD_V1_6_SIDE_RANGE.1),
+            multiple_of: SIDE_MULTIPLE,
+        }
+    } else {
+        Dimensions::Sides {
+            min: MIN_SIDE,
+            max: None,
+            multiple_of: SIDE_MULTIPLE,
+        }
+    };
+    let native_side = if engine.contains("512") {
+        512
+    } else if engine.contains("768") {
+        768
+    } else {
+        1024
+    };
+
+    Capabilities {
+        max_samples: MAX_SAMPLES,
+        steps: STEPS,
+        cfg_scale: CFG_SCALE,
+        dimensions,
+        native_side,
+        inpainting: !engine.contains("upscaler") && !engine.starts_with("esrgan"),
+        // SDXL ignores the presets, which only steer the older samplers
+        clip_guidance: !sdxl,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn engines_are_recognised_from_their_ids() {
+        let v1_6 = for_engine("stable-diffusion-v1-6");
+        assert!(v1_6.dimensions.contains(320, 1536));
+        assert!(!v1_6.dimensions.contains(256, 512));
+        assert!(for_engine("stable-diffusion-512-v2-1")
+            .dimensions
+            .contains(4096, 128));
+
+        let sd_768 = for_engine("stable-diffusion-768-v2-1");
+        assert_eq!(sd_768.native_side, 768);
+        assert!(sd_768.clip_guidance);
+
+        assert!(!for_engine("esrgan-v1-x2plus").inpainting);
+    }
+}
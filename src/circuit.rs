@@ -0,0 +1,154 @@
+//! A circuit breaker for API outages.
+//!
+//! After a run of consecutive server errors or failed connections, a
+//! [`CircuitBreaker`] opens: further requests fail straight away with
+//! [`Error::CircuitOpen`] instead of waiting on an API that is down, until a
+//! cool-down has passed. Requests are then let through again, and the first
+//! one to fail opens the circuit once more, while the first to succeed closes
+//! it.
+//!
+//! Once [`install`]ed, the breaker guards every request sent by this crate;
+//! [`ClientBuilder::circuit_breaker`](crate::api::rest::client::ClientBuilder::circuit_breaker)
+//! sets one for a single client instead.
+//!
+//! ```
+//! use stability_rs::circuit::{self, CircuitBreaker};
+//! use std::time::Duration;
+//!
+//! // stop calling for 30 seconds after 5 failures in a row
+//! circuit::install(CircuitBreaker::new(5, Duration::from_secs(30)));
+//! ```
+
+use crate::error::Error;
+use crate::prelude::*;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+static GLOBAL: RwLock<Option<CircuitBreaker>> = RwLock::new(None);
+
+#[derive(Debug)]
+struct State {
+    failures: u32,
+    opened_at: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    threshold: u32,
+    cool_down: Duration,
+    state: Mutex<State>,
+}
+
+/// A cloneable handle to a shared circuit breaker
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    inner: Arc<Inner>,
+}
+
+impl CircuitBreaker {
+    /// Open after `threshold` consecutive failures, for `cool_down`
+    pub fn new(threshold: u32, cool_down: Duration) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                threshold: threshold.max(1),
+                cool_down,
+                state: Mutex::new(State {
+                    failures: 0,
+                    opened_at: None,
+                }),
+            }),
+        }
+    }
+
+    /// Whether requests are being short-circuited right now
+    pub fn is_open(&self) -> bool {
+        self.retry_in().is_some()
+    }
+
+    /// Err with [`Error::CircuitOpen`] while the circuit is open
+    pub(crate) fn check(&self) -> Result<()> {
+        match self.retry_in() {
+            Some(retry_in) => Err(Box::new(Error::CircuitOpen { retry_in })),
+            None => Ok(()),
+        }
+    }
+
+    /// Record how a request went; 5xx responses and requests which got no
+    /// response count as failures
+    pub(crate) fn record(&self, status: Option<u16>) {
+        let mut state = self.lock();
+        if status.is_some_and(|status| status < 500) {
+            state.failures = 0;
+            state.opened_at = None;
+            return;
+        }
+
+        state.failures = state.failures.saturating_add(1);
+        if state.failures >= self.inner.threshold {
+            state.opened_at = Some(Instant::now());
+        }
+    }
+
+    fn retry_in(&self) -> Option<Duration> {
+        let opened_at = self.lock().opened_at?;
+        let retry_in = self.inner.cool_down.saturating_sub(opened_at.elapsed());
+        (!retry_in.is_zero()).then_some(retry_in)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        // unwrap warranted because the lock is never held across a panic point
+        self.inner.state.lock().unwrap()
+    }
+}
+
+/// Guard every request sent by this crate with `breaker`
+pub fn install(breaker: CircuitBreaker) {
+    *GLOBAL.write().unwrap() = Some(breaker);
+}
+
+pub fn uninstall() {
+    *GLOBAL.write().unwrap() = None;
+}
+
+pub(crate) fn installed() -> Option<CircuitBreaker> {
+    GLOBAL.read().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breaker_is_opening_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+        breaker.record(Some(503));
+        breaker.record(None);
+        breaker.record(Some(400));
+        breaker.record(Some(500));
+        breaker.record(Some(502));
+        assert!(breaker.check().is_ok());
+
+        breaker.record(None);
+
+        let err = breaker.check().unwrap_err();
+        assert!(err.to_string().starts_with("the API failed repeatedly"));
+    }
+
+    #[test]
+    fn breaker_is_reopening_on_the_first_failure_after_the_cool_down() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(10));
+        breaker.record(None);
+        breaker.record(None);
+        assert!(breaker.is_open());
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!breaker.is_open());
+        breaker.record(Some(500));
+        assert!(breaker.is_open());
+
+        std::thread::sleep(Duration::from_millis(20));
+        breaker.record(Some(200));
+        breaker.record(Some(500));
+        assert!(!breaker.is_open());
+    }
+}
@@ -0,0 +1,30 @@
+//! Client-side credit estimates.
+//!
+//! The API does not report what a generation cost, so these figures are
+//! approximations of the published pricing: roughly 0.2 credits for a
+//! 30-step v1 image, scaling linearly with the number of steps, and a flat
+//! price per v2beta edit.
+
+const CREDITS_PER_30_STEPS: f64 = 0.2;
+const UPSCALE_CREDITS: f64 = 0.2;
+
+/// Estimate the credits spent by a generation request
+pub fn estimate(engine: &str, steps: u32, samples: u32) -> f64 {
+    if engine.starts_with("esrgan") || engine.contains("upscaler") {
+        return UPSCALE_CREDITS * samples.max(1) as f64;
+    }
+
+    CREDITS_PER_30_STEPS * (steps as f64 / 30.0) * samples.max(1) as f64
+}
+
+/// Estimate the credits spent by a v2beta edit, by its operation such as
+/// `inpaint`; `None` for an operation without a known price
+pub fn estimate_edit(operation: &str) -> Option<f64> {
+    match operation {
+        "remove-background" => Some(2.0),
+        "erase" | "inpaint" => Some(3.0),
+        "outpaint" | "search-and-replace" => Some(4.0),
+        "replace-background-and-relight" => Some(8.0),
+        _ => None,
+    }
+}
@@ -0,0 +1,294 @@
+//! Preparing a local image directory as a fine-tuning dataset.
+//!
+//! [`Dataset::scan`] checks every file of a directory against
+//! [`DatasetRules`] without changing anything, so it doubles as a dry run:
+//! the [`Dataset`] lists the images which will be packaged, with the caption
+//! read from a `.txt` sidecar of the same name, and every file rejected along
+//! with the reason. [`Dataset::write_zip`] then converts the accepted images
+//! to PNG and packages them with their captions, ready to upload.
+//!
+//! ```no_run
+//! use stability_rs::dataset::{Dataset, DatasetRules};
+//! use stability_rs::Result;
+//!
+//! fn main() -> Result<()> {
+//!     let dataset = Dataset::scan("photos", &DatasetRules::default())?;
+//!     print!("{}", dataset);
+//!
+//!     if dataset.rejected.is_empty() {
+//!         dataset.write_zip("dataset.zip")?;
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::atomic_write::TempPath;
+use crate::error::Error;
+use crate::prelude::*;
+use image::ImageFormat;
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+const CAPTION_EXTENSION: &str = "txt";
+
+/// What an image must satisfy to be part of a dataset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatasetRules {
+    /// The shortest side an image may have, in pixels
+    pub min_side: u32,
+    /// The longest side an image may have, in pixels
+    pub max_side: u32,
+    /// Reject images without a caption sidecar
+    pub require_captions: bool,
+}
+
+impl Default for DatasetRules {
+    fn default() -> Self {
+        Self {
+            min_side: 512,
+            max_side: 4096,
+            require_captions: false,
+        }
+    }
+}
+
+/// An image which will be packaged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    pub path: PathBuf,
+    pub caption: Option<String>,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RejectReason {
+    /// Neither an image nor a caption sidecar
+    UnsupportedFormat,
+    Unreadable(String),
+    TooSmall {
+        width: u32,
+        height: u32,
+    },
+    TooLarge {
+        width: u32,
+        height: u32,
+    },
+    MissingCaption,
+    /// Another image has the same file stem, so both would be packaged under
+    /// one name
+    DuplicateName,
+    /// A caption sidecar without an image
+    OrphanedCaption,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::UnsupportedFormat => write!(f, "not a supported image format"),
+            RejectReason::Unreadable(e) => write!(f, "unreadable: {}", e),
+            RejectReason::TooSmall { width, height } => {
+                write!(f, "{}x{} is below the minimum side", width, height)
+            }
+            RejectReason::TooLarge { width, height } => {
+                write!(f, "{}x{} is above the maximum side", width, height)
+            }
+            RejectReason::MissingCaption => write!(f, "no caption sidecar"),
+            RejectReason::DuplicateName => write!(f, "another image has the same name"),
+            RejectReason::OrphanedCaption => write!(f, "a caption without an image"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejected {
+    pub path: PathBuf,
+    pub reason: RejectReason,
+}
+
+/// The outcome of checking a directory, see [`Dataset::scan`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dataset {
+    pub accepted: Vec<Sample>,
+    pub rejected: Vec<Rejected>,
+}
+
+impl Dataset {
+    /// Check the files directly inside `dir` against `rules`
+    pub fn scan(dir: impl AsRef<Path>, rules: &DatasetRules) -> Result<Self> {
+        let mut paths = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        paths.retain(|path| path.is_file());
+        paths.sort();
+
+        let mut dataset = Dataset::default();
+        let mut stems = HashSet::new();
+        for path in &paths {
+            if is_caption(path) {
+                if !paths
+                    .iter()
+                    .any(|p| !is_caption(p) && p.file_stem() == path.file_stem())
+                {
+                    dataset.reject(path, RejectReason::OrphanedCaption);
+                }
+                continue;
+            }
+
+            if ImageFormat::from_path(path).is_err() {
+                dataset.reject(path, RejectReason::UnsupportedFormat);
+                continue;
+            }
+            match check(path, rules) {
+                Ok(_) if !stems.insert(path.file_stem().map(|s| s.to_os_string())) => {
+                    dataset.reject(path, RejectReason::DuplicateName)
+                }
+                Ok(sample) => dataset.accepted.push(sample),
+                Err(reason) => dataset.reject(path, reason),
+            }
+        }
+        Ok(dataset)
+    }
+
+    /// Package the accepted images as PNG files, each followed by its caption
+    /// as a `.txt` file of the same name
+    pub fn write_zip(&self, path: impl AsRef<Path>) -> Result<()> {
+        if self.accepted.is_empty() {
+            return Err(Box::new(Error::DatasetEmpty));
+        }
+
+        let path = path.as_ref();
+        crate::preflight::create_parent_dirs(path)?;
+        let temp = TempPath::new(path);
+        let mut zip = ZipWriter::new(std::fs::File::create(temp.path())?);
+        // PNG data is compressed already
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        for sample in &self.accepted {
+            // unwrap warranted because scanned paths are files, so have a stem
+            let stem = sample.path.file_stem().unwrap().to_string_lossy();
+            zip.start_file(format!("{}.png", stem), options)?;
+            zip.write_all(&to_png(&sample.path)?)?;
+            if let Some(caption) = &sample.caption {
+                zip.start_file(format!("{}.{}", stem, CAPTION_EXTENSION), options)?;
+                zip.write_all(caption.as_bytes())?;
+            }
+        }
+        zip.finish()?;
+        temp.commit()?;
+        Ok(())
+    }
+
+    fn reject(&mut self, path: &Path, reason: RejectReason) {
+        self.rejected.push(Rejected {
+            path: path.to_path_buf(),
+            reason,
+        });
+    }
+}
+
+impl fmt::Display for Dataset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} accepted, {} rejected",
+            self.accepted.len(),
+            self.rejected.len()
+        )?;
+        for rejected in &self.rejected {
+            writeln!(f, "  {}: {}", rejected.path.display(), rejected.reason)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_caption(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(CAPTION_EXTENSION))
+}
+
+fn check(path: &Path, rules: &DatasetRules) -> std::result::Result<Sample, RejectReason> {
+    let (width, height) =
+        image::image_dimensions(path).map_err(|e| RejectReason::Unreadable(e.to_string()))?;
+    if width.min(height) < rules.min_side {
+        return Err(RejectReason::TooSmall { width, height });
+    }
+    if width.max(height) > rules.max_side {
+        return Err(RejectReason::TooLarge { width, height });
+    }
+
+    let caption = match std::fs::read_to_string(path.with_extension(CAPTION_EXTENSION)) {
+        Ok(caption) => Some(caption.trim().to_string()),
+        Err(_) if !rules.require_captions => None,
+        Err(_) => return Err(RejectReason::MissingCaption),
+    };
+    Ok(Sample {
+        path: path.to_path_buf(),
+        caption,
+        width,
+        height,
+    })
+}
+
+/// The image at `path` as PNG data, copied as is when it is one already
+fn to_png(path: &Path) -> Result<Vec<u8>> {
+    if ImageFormat::from_path(path)? == ImageFormat::Png {
+        return Ok(std::fs::read(path)?);
+    }
+    let mut png = Vec::new();
+    image::open(path)?.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    #[test]
+    fn scan_is_reporting_every_rejected_file() {
+        let dir = std::env::temp_dir().join(format!("stability_rs_dataset_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        RgbImage::new(64, 64).save(dir.join("crab.jpg")).unwrap();
+        std::fs::write(dir.join("crab.txt"), "a crab on a beach\n").unwrap();
+        RgbImage::new(16, 64).save(dir.join("narrow.png")).unwrap();
+        std::fs::write(dir.join("notes.md"), "").unwrap();
+        std::fs::write(dir.join("lost.txt"), "").unwrap();
+        let rules = DatasetRules {
+            min_side: 32,
+            ..DatasetRules::default()
+        };
+
+        let dataset = Dataset::scan(&dir, &rules).unwrap();
+        let zip = dir.join("out/dataset.zip");
+        dataset.write_zip(&zip).unwrap();
+        let archive = zip::ZipArchive::new(std::fs::File::open(&zip).unwrap()).unwrap();
+
+        assert_eq!(dataset.accepted.len(), 1);
+        assert_eq!(
+            dataset.accepted[0].caption.as_deref(),
+            Some("a crab on a beach")
+        );
+        let reasons: Vec<_> = dataset.rejected.iter().map(|r| r.reason.clone()).collect();
+        assert_eq!(
+            reasons,
+            [
+                RejectReason::OrphanedCaption,
+                RejectReason::TooSmall {
+                    width: 16,
+                    height: 64
+                },
+                RejectReason::UnsupportedFormat,
+            ]
+        );
+        assert_eq!(
+            archive.file_names().collect::<HashSet<_>>(),
+            HashSet::from(["crab.png", "crab.txt"])
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+//! Advance notice of engines the API is retiring.
+//!
+//! [`DEPRECATED_ENGINES`] lists the engine ids with a sunset date and the
+//! engine to move to. Generating with one of them logs a `tracing` warning,
+//! once per engine and process, rather than letting the first sign be a 404
+//! on the sunset date. In [`schema`](crate::schema) strict mode it fails with
+//! [`Error::EngineDeprecated`] instead.
+//!
+//! ```
+//! use stability_rs::deprecation;
+//!
+//! let deprecation = deprecation::lookup("stable-diffusion-512-v2-1").unwrap();
+//! assert_eq!(deprecation.replacement, "stable-diffusion-xl-1024-v1-0");
+//! ```
+
+// engines are only checked by the endpoint modules
+#![cfg_attr(
+    not(any(feature = "text-to-image", feature = "image-to-image")),
+    allow(dead_code)
+)]
+
+use crate::error::Error;
+use crate::prelude::*;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+/// An engine id the API is retiring
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deprecation {
+    pub engine: &'static str,
+    /// The date requests to the engine start failing, as `YYYY-MM-DD`
+    pub sunset: &'static str,
+    pub replacement: &'static str,
+}
+
+pub const DEPRECATED_ENGINES: [Deprecation; 5] = [
+    Deprecation {
+        engine: "stable-diffusion-v1-5",
+        sunset: "2023-11-01",
+        replacement: "stable-diffusion-v1-6",
+    },
+    Deprecation {
+        engine: "stable-diffusion-512-v2-1",
+        sunset: "2024-07-24",
+        replacement: "stable-diffusion-xl-1024-v1-0",
+    },
+    Deprecation {
+        engine: "stable-diffusion-768-v2-1",
+        sunset: "2024-07-24",
+        replacement: "stable-diffusion-xl-1024-v1-0",
+    },
+    Deprecation {
+        engine: "stable-diffusion-xl-beta-v2-2-2",
+        sunset: "2024-07-24",
+        replacement: "stable-diffusion-xl-1024-v1-0",
+    },
+    Deprecation {
+        engine: "stable-diffusion-xl-1024-v0-9",
+        sunset: "2024-07-24",
+        replacement: "stable-diffusion-xl-1024-v1-0",
+    },
+];
+
+/// The engines warned about so far
+static WARNED: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+/// The deprecation of `engine`, if it is being retired
+pub fn lookup(engine: &str) -> Option<&'static Deprecation> {
+    DEPRECATED_ENGINES
+        .iter()
+        .find(|d| d.engine.eq_ignore_ascii_case(engine))
+}
+
+/// Warn about, or in strict mode refuse, a deprecated `engine`
+pub(crate) fn check(engine: &str) -> Result<()> {
+    check_with(engine, crate::schema::is_strict())
+}
+
+fn check_with(engine: &str, strict: bool) -> Result<()> {
+    let Some(deprecation) = lookup(engine) else {
+        return Ok(());
+    };
+
+    if strict {
+        return Err(Box::new(Error::EngineDeprecated {
+            engine: deprecation.engine,
+            sunset: deprecation.sunset,
+            replacement: deprecation.replacement,
+        }));
+    }
+    let first = WARNED
+        .lock()
+        .map(|mut warned| warned.insert(deprecation.engine))
+        .unwrap_or(true);
+    if first {
+        tracing::warn!(
+            engine = deprecation.engine,
+            sunset = deprecation.sunset,
+            replacement = deprecation.replacement,
+            "engine is deprecated"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_is_ignoring_case() {
+        assert!(lookup("Stable-Diffusion-V1-5").is_some());
+        assert!(lookup("stable-diffusion-xl-1024-v1-0").is_none());
+    }
+
+    #[test]
+    fn check_is_erring_on_a_deprecated_engine_when_strict() {
+        let err = check_with("stable-diffusion-768-v2-1", true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "engine stable-diffusion-768-v2-1 is deprecated and stops working on 2024-07-24, \
+             use stable-diffusion-xl-1024-v1-0 instead"
+        );
+        assert!(check_with("stable-diffusion-768-v2-1", false).is_ok());
+        assert!(check_with("stable-diffusion-v1-6", true).is_ok());
+    }
+}
@@ -0,0 +1,95 @@
+//! Progress of response downloads.
+//!
+//! Raw image responses, such as those of
+//! [`generate_once`](crate::text_to_img::TextToImage::generate_once), can run
+//! to many megabytes at 4K. Requests sent within [`with_download_progress`]
+//! call back with a [`DownloadProgress`] as each part of the response body
+//! arrives, so applications can draw a progress bar, and notice a stalled
+//! download when the callbacks stop.
+//!
+#![cfg_attr(feature = "text-to-image", doc = "```no_run")]
+#![cfg_attr(not(feature = "text-to-image"), doc = "```ignore")]
+//! use stability_rs::download_progress::with_download_progress;
+//! use stability_rs::{text_to_img::*, Result};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     let request = TextToImageBuilder::new()
+//!         .text_prompt("a lighthouse in a storm", 1.0)?
+//!         .build()?;
+//!
+//!     let bytes = with_download_progress(
+//!         |progress| match progress.fraction() {
+//!             Some(fraction) => println!("{:.0}%", fraction * 100.0),
+//!             None => println!("{} bytes", progress.received),
+//!         },
+//!         request.generate_once("stable-diffusion-xl-1024-v1-0"),
+//!     )
+//!     .await?;
+//!     # let _ = bytes;
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use std::future::Future;
+use std::sync::Arc;
+
+type Callback = Arc<dyn Fn(DownloadProgress) + Send + Sync>;
+
+tokio::task_local! {
+    static CALLBACK: Callback;
+}
+
+/// How much of a response body has been received
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DownloadProgress {
+    pub received: u64,
+    /// The `content-length` of the response, when it declared one
+    pub total: Option<u64>,
+}
+
+impl DownloadProgress {
+    /// From 0 to 1, when the length of the body is known; an empty body
+    /// counts as fully received
+    pub fn fraction(&self) -> Option<f64> {
+        match self.total? {
+            0 => Some(1.0),
+            total => Some(self.received as f64 / total as f64),
+        }
+    }
+}
+
+/// Run `f`, calling `callback` as the body of every response it receives is
+/// downloaded
+pub async fn with_download_progress<C, F>(callback: C, f: F) -> F::Output
+where
+    C: Fn(DownloadProgress) + Send + Sync + 'static,
+    F: Future,
+{
+    CALLBACK.scope(Arc::new(callback), f).await
+}
+
+pub(crate) fn current() -> Option<Callback> {
+    CALLBACK.try_with(|callback| callback.clone()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fraction_is_unknown_without_a_declared_length() {
+        let progress = DownloadProgress {
+            received: 10,
+            total: None,
+        };
+        assert_eq!(progress.fraction(), None);
+
+        let progress = DownloadProgress {
+            received: 10,
+            total: Some(40),
+        };
+        assert_eq!(progress.fraction(), Some(0.25));
+    }
+}
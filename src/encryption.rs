@@ -0,0 +1,207 @@
+//! Encryption of saved artifacts and audit logs at rest.
+//!
+//! Once an [`EncryptionKey`] is [`install`]ed, every artifact the crate
+//! writes to disk, through [`Artifact::save`](crate::api::rest::artifact::Artifact::save),
+//! [`Image::save`](crate::api::rest::generation::Image::save) or a batch run,
+//! is encrypted with AES-256-GCM, and so is every file holding prompts: the
+//! lines written by a [`JsonlAudit`](crate::audit::JsonlAudit), the
+//! [alt-text sidecars](crate::alt_text::write_sidecar), and a batch run's
+//! [report](crate::batch::report::write_to_path) and progress file.
+//! Generated content holding sensitive user data then never reaches the disk
+//! in the clear, without changing the code that saves it.
+//!
+//! JSON lines files are encrypted line by line, read back with
+//! [`EncryptionKey::decrypt_line`]; other files are encrypted whole, read
+//! back with [`EncryptionKey::read`].
+//!
+//! ```no_run
+//! use stability_rs::encryption::{self, EncryptionKey};
+//! use stability_rs::Result;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     let key = EncryptionKey::from_env()?;
+//!     encryption::install(key.clone());
+//!
+//!     // ... generate and save images as usual, then read one back:
+//!     let png = key.read("image_0.png").await?;
+//!     # let _ = png;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! An encrypted file is the bytes `SRE1`, a random 12-byte nonce and the
+//! ciphertext with its 16-byte tag. Files staged in a
+//! [`TempStore`](crate::staging::TempStore) to be sent back to the API are
+//! left unencrypted, and removed once the store is dropped.
+
+use crate::error::Error;
+use crate::prelude::*;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose, Engine as _};
+use std::fmt;
+use std::path::Path;
+use std::sync::RwLock;
+
+/// The bytes every encrypted file starts with
+const MAGIC: &[u8; 4] = b"SRE1";
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+static GLOBAL: RwLock<Option<EncryptionKey>> = RwLock::new(None);
+
+/// A 256-bit AES-GCM key
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey(Key<Aes256Gcm>);
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(<redacted>)")
+    }
+}
+
+impl EncryptionKey {
+    pub fn new(bytes: [u8; KEY_LEN]) -> Self {
+        Self(bytes.into())
+    }
+
+    /// A new random key, to be kept somewhere safe: what it encrypts can't
+    /// be read without it
+    pub fn generate() -> Self {
+        Self(Aes256Gcm::generate_key(OsRng))
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        let bytes: [u8; KEY_LEN] = bytes
+            .try_into()
+            .map_err(|_| Error::EncryptionKeyLength(bytes.len()))?;
+        Ok(Self::new(bytes))
+    }
+
+    /// A key given as standard base64, such as the output of
+    /// `openssl rand -base64 32`
+    pub fn from_base64(encoded: &str) -> Result<Self> {
+        Self::from_slice(&general_purpose::STANDARD.decode(encoded.trim())?)
+    }
+
+    /// The key read from `STABILITY_ENCRYPTION_KEY`, in base64
+    pub fn from_env() -> Result<Self> {
+        Self::from_base64(&std::env::var("STABILITY_ENCRYPTION_KEY")?)
+    }
+
+    pub fn to_base64(&self) -> String {
+        general_purpose::STANDARD.encode(self.0)
+    }
+
+    /// `plaintext` encrypted under a fresh random nonce
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = Aes256Gcm::generate_nonce(OsRng);
+        // unwrap warranted because encrypting only fails for inputs of 64 GiB
+        let ciphertext = Aes256Gcm::new(&self.0).encrypt(&nonce, plaintext).unwrap();
+
+        let mut sealed = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(MAGIC);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    /// The plaintext of data produced by [`EncryptionKey::encrypt`]
+    pub fn decrypt(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        let rest = sealed.strip_prefix(MAGIC).ok_or(Error::DecryptionFailed)?;
+        if rest.len() < NONCE_LEN {
+            return Err(Box::new(Error::DecryptionFailed));
+        }
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let plaintext = Aes256Gcm::new(&self.0)
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| Error::DecryptionFailed)?;
+        Ok(plaintext)
+    }
+
+    /// `line` encrypted and base64-encoded, so it stays a single line of
+    /// a JSON lines file
+    pub fn encrypt_line(&self, line: &[u8]) -> String {
+        general_purpose::STANDARD.encode(self.encrypt(line))
+    }
+
+    /// The plaintext of a line produced by [`EncryptionKey::encrypt_line`]
+    pub fn decrypt_line(&self, line: &str) -> Result<Vec<u8>> {
+        self.decrypt(&general_purpose::STANDARD.decode(line.trim())?)
+    }
+
+    /// The decrypted contents of the file at `path`
+    pub async fn read(&self, path: impl AsRef<Path>) -> Result<Vec<u8>> {
+        self.decrypt(&tokio::fs::read(path).await?)
+    }
+}
+
+/// Whether `data` starts like the output of [`EncryptionKey::encrypt`]
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.len() >= MAGIC.len() + NONCE_LEN && data.starts_with(MAGIC)
+}
+
+/// Encrypt every artifact, audit line, sidecar, report and progress file
+/// written from now on with `key`
+pub fn install(key: EncryptionKey) {
+    *GLOBAL.write().unwrap() = Some(key);
+}
+
+pub fn uninstall() {
+    *GLOBAL.write().unwrap() = None;
+}
+
+pub(crate) fn installed() -> Option<EncryptionKey> {
+    GLOBAL.read().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decrypt_is_returning_what_was_encrypted() {
+        let key = EncryptionKey::generate();
+        let sealed = key.encrypt(b"a secret lighthouse");
+
+        assert!(is_encrypted(&sealed));
+        assert_ne!(key.encrypt(b"a secret lighthouse"), sealed);
+        assert_eq!(key.decrypt(&sealed).unwrap(), b"a secret lighthouse");
+    }
+
+    #[test]
+    fn decrypt_is_failing_when_the_key_or_data_differs() {
+        let key = EncryptionKey::generate();
+        let mut sealed = key.encrypt(b"a secret lighthouse");
+
+        assert!(EncryptionKey::generate().decrypt(&sealed).is_err());
+        *sealed.last_mut().unwrap() ^= 1;
+        assert!(key.decrypt(&sealed).is_err());
+        assert!(key.decrypt(b"SRE1").is_err());
+    }
+
+    #[test]
+    fn decrypt_line_is_returning_what_was_encrypted() {
+        let key = EncryptionKey::generate();
+        let line = key.encrypt_line(b"{\"prompt\":\"a secret lighthouse\"}");
+
+        assert!(!line.contains('\n'));
+        assert_eq!(
+            key.decrypt_line(&line).unwrap(),
+            b"{\"prompt\":\"a secret lighthouse\"}"
+        );
+    }
+
+    #[test]
+    fn from_base64_is_rejecting_keys_of_the_wrong_length() {
+        let key = EncryptionKey::generate();
+        assert_eq!(EncryptionKey::from_base64(&key.to_base64()).unwrap(), key);
+
+        let err = EncryptionKey::from_base64("c2hvcnQ=").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "an encryption key is 32 bytes long, but this one is 5 bytes"
+        );
+    }
+}
@@ -1,14 +1,34 @@
 use std::fmt;
 use serde::Deserialize;
+use crate::model::ArtifactKind;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct ApiResponseError {
     pub id: String,
     pub name: String,
+    /// v2beta endpoints answer with a list of `errors` instead, joined here
+    #[serde(alias = "errors", deserialize_with = "message_or_errors")]
     pub message: String,
 
 }
 
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MessageOrErrors {
+    Message(String),
+    Errors(Vec<String>),
+}
+
+fn message_or_errors<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(match MessageOrErrors::deserialize(deserializer)? {
+        MessageOrErrors::Message(message) => message,
+        MessageOrErrors::Errors(errors) => errors.join("; "),
+    })
+}
+
 impl fmt::Display for ApiResponseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "id: {}, name: {}, message: {}", self.id, self.name, self.message)
@@ -21,6 +41,225 @@ pub enum Error {
     ClientBuildError(String),
     #[error("{:?}", .0)]
     ClientSendRequestError(ApiResponseError),
+    #[error("an engine fallback must list at least one engine")]
+    EngineFallbackEmpty,
+    #[error("unsupported artifact content type: {0}")]
+    UnsupportedContentType(String),
+    #[error("expected a {expected:?} artifact, but content type was {found}")]
+    UnexpectedContentType {
+        expected: ArtifactKind,
+        found: String,
+    },
+    #[error("watermark opacity must be between 0 and 1, but was {0}")]
+    WatermarkOpacityOutOfRange(f32),
+    #[error("watermark scale must be between 1 and {max}, but was {scale}")]
+    WatermarkScaleOutOfRange { scale: u32, max: u32 },
+    #[error("the response body exceeded the limit of {limit} bytes")]
+    ResponseTooLarge { limit: usize },
+    #[error("the download was cut off after {received} of {expected} bytes")]
+    DownloadTruncated { expected: usize, received: usize },
+    #[error("the download's SHA-256 was {found}, expected {expected}")]
+    ChecksumMismatch { expected: String, found: String },
+    #[error("the connection closed before a response was read")]
+    ConnectionClosed,
+    #[error("no address was found for {0}")]
+    HostNotResolved(String),
+    #[error("base URL {0} is not https://api.stability.ai, an http:// address or a unix: socket path")]
+    UnsupportedBaseUrl(String),
+    #[error("an animation needs at least one frame")]
+    AnimationEmpty,
+    #[error("{0} is not a GIF or WebP file")]
+    AnimationFormatUnsupported(String),
+    #[error("output directory {dir} is not writable: {reason}")]
+    OutputDirNotWritable { dir: String, reason: String },
+    #[error("{0} already exists")]
+    OutputExists(String),
+    #[error("{dir} has {available} bytes free, but about {needed} are needed")]
+    InsufficientDiskSpace {
+        dir: String,
+        needed: u64,
+        available: u64,
+    },
+    #[error("the response contained no artifacts")]
+    NoArtifacts,
+    #[error("no interrogator is installed")]
+    InterrogatorNotInstalled,
+    #[error("requests are no longer sent during a shutdown")]
+    ShuttingDown,
+    #[error("the API failed repeatedly, so requests are paused for another {retry_in:?}")]
+    CircuitOpen { retry_in: std::time::Duration },
+    #[error("the {type_name} response carried unknown fields: {}", fields.join(", "))]
+    UnknownFields {
+        type_name: &'static str,
+        fields: Vec<String>,
+    },
+    #[error("no image of the dataset was accepted")]
+    DatasetEmpty,
+    #[error("the result {id} has expired, as the API keeps results for 24 hours")]
+    ResultExpired { id: String },
+    #[error("engine {engine} is deprecated and stops working on {sunset}, use {replacement} instead")]
+    EngineDeprecated {
+        engine: &'static str,
+        sunset: &'static str,
+        replacement: &'static str,
+    },
+    #[error("the prompt syntax is invalid at byte {position}: {message}")]
+    PromptSyntax {
+        position: usize,
+        message: &'static str,
+    },
+    #[error("the prompt was rejected before sending: {0}")]
+    PromptRejected(String),
+    #[error("an encryption key is 32 bytes long, but this one is {0} bytes")]
+    EncryptionKeyLength(usize),
+    #[error("the data was not encrypted with this key, or has been altered")]
+    DecryptionFailed,
+    #[error("tenant {tenant} has used up its quota")]
+    QuotaExceeded { tenant: String },
+    #[error("{0} is not supported over gRPC")]
+    UnsupportedOverGrpc(&'static str),
+}
+
+/// API error names which mean the account has run out of credits
+const OUT_OF_CREDITS: [&str; 2] = ["insufficient_balance", "payment_required"];
+/// API error names which mean the safety filter rejected the request
+pub(crate) const FLAGGED: [&str; 3] = ["invalid_prompts", "content_moderation", "content_filtered"];
+const UNAUTHORIZED: [&str; 3] = ["unauthorized", "permission_denied", "organization_not_found"];
+const RATE_LIMITED: [&str; 2] = ["rate_limit_exceeded", "too_many_requests"];
+const UNAVAILABLE: [&str; 5] = [
+    "not_found",
+    "engine_not_found",
+    "service_unavailable",
+    "maintenance",
+    "server_error",
+];
+/// API error names of outages which pass by themselves
+const OUTAGE: [&str; 3] = ["service_unavailable", "maintenance", "server_error"];
+
+const GENERIC_MESSAGE: &str = "Something went wrong while creating the image. Please try again.";
+
+impl Error {
+    /// A short, non-technical description of the error, safe to show to
+    /// end users
+    ///
+    /// Unlike the `Display` output, it never includes paths, sizes or API
+    /// details.
+    pub fn user_message(&self) -> &'static str {
+        match self {
+            Error::ClientSendRequestError(err) => {
+                let name = err.name.as_str();
+                if OUT_OF_CREDITS.contains(&name) {
+                    "You're out of credits."
+                } else if FLAGGED.contains(&name) {
+                    "The image was flagged by the safety filter."
+                } else if UNAUTHORIZED.contains(&name) {
+                    "This account isn't allowed to create images right now."
+                } else if RATE_LIMITED.contains(&name) {
+                    "Too many images are being created right now. Please try again in a moment."
+                } else if UNAVAILABLE.contains(&name) {
+                    "The image service is unavailable right now. Please try again later."
+                } else {
+                    GENERIC_MESSAGE
+                }
+            }
+            Error::ResponseTooLarge { .. }
+            | Error::DownloadTruncated { .. }
+            | Error::ChecksumMismatch { .. }
+            | Error::ConnectionClosed => "The connection was interrupted. Please try again.",
+            Error::HostNotResolved(_) => {
+                "The image service couldn't be reached. Please check your network connection."
+            }
+            Error::CircuitOpen { .. } => {
+                "The image service is unavailable right now. Please try again later."
+            }
+            Error::NoArtifacts => "No image came back. Please try again.",
+            Error::ShuttingDown => "The app is shutting down, so no more images can be created.",
+            Error::UnsupportedContentType(_)
+            | Error::UnexpectedContentType { .. }
+            | Error::AnimationFormatUnsupported(_) => "This file type isn't supported.",
+            Error::OutputDirNotWritable { .. } => "The images couldn't be saved to that folder.",
+            Error::OutputExists(_) => "A file with that name already exists.",
+            Error::InsufficientDiskSpace { .. } => {
+                "There isn't enough free disk space to save the images."
+            }
+            Error::ClientBuildError(_)
+            | Error::EngineFallbackEmpty
+            | Error::WatermarkOpacityOutOfRange(_)
+            | Error::WatermarkScaleOutOfRange { .. }
+            | Error::AnimationEmpty
+            | Error::InterrogatorNotInstalled
+            | Error::UnsupportedBaseUrl(_)
+            | Error::UnknownFields { .. }
+            | Error::UnsupportedOverGrpc(_) => GENERIC_MESSAGE,
+            Error::DatasetEmpty => "None of the images can be used for training.",
+            Error::ResultExpired { .. } => "This image has expired. Please create it again.",
+            Error::EngineDeprecated { .. } => {
+                "This image model is being retired. Please choose another one."
+            }
+            Error::PromptSyntax { .. } => "The prompt has a bracket that isn't closed or opened.",
+            Error::PromptRejected(_) => "This prompt isn't allowed.",
+            Error::EncryptionKeyLength(_) => "The encryption key isn't valid.",
+            Error::DecryptionFailed => "This file couldn't be decrypted with the key given.",
+            Error::QuotaExceeded { .. } => "You've reached your usage limit.",
+        }
+    }
+
+    /// Whether the request may succeed when sent again later, after a rate
+    /// limit, an outage or a dropped connection
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::ClientSendRequestError(err) => {
+                let name = err.name.as_str();
+                RATE_LIMITED.contains(&name) || OUTAGE.contains(&name)
+            }
+            Error::ConnectionClosed
+            | Error::DownloadTruncated { .. }
+            | Error::CircuitOpen { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// [`Error::is_transient`] for any error returned by this crate, counting
+/// failed connections as transient too
+pub fn is_transient(err: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(err) = err.downcast_ref::<Error>() {
+        return err.is_transient();
+    }
+    if let Some(err) = err.downcast_ref::<std::io::Error>() {
+        use std::io::ErrorKind;
+        return matches!(
+            err.kind(),
+            ErrorKind::ConnectionRefused
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::TimedOut
+        );
+    }
+    #[cfg(feature = "client")]
+    if err.is::<hyper::Error>() {
+        return true;
+    }
+    false
+}
+
+/// [`Error::user_message`] for any error returned by this crate
+///
+/// Invalid builder settings read as a settings problem, and errors from
+/// other sources as a generic failure.
+pub fn user_message(err: &(dyn std::error::Error + 'static)) -> &'static str {
+    if let Some(err) = err.downcast_ref::<Error>() {
+        return err.user_message();
+    }
+    if err.is::<ImageBuilderError>() {
+        return "Some of the image settings aren't supported.";
+    }
+    if let Some(err) = err.downcast_ref::<std::io::Error>() {
+        if err.kind() == std::io::ErrorKind::NotFound {
+            return "The image file couldn't be found.";
+        }
+    }
+    GENERIC_MESSAGE
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -33,6 +272,12 @@ pub enum ImageBuilderError {
     WidthNotMultipleOf64(u32),
     #[error("width must not be less than 128, but was {0}")]
     WidthLessThan128(u32),
+    #[error("{width}x{height} is not a size supported by {engine}")]
+    DimensionsNotSupportedByEngine {
+        engine: String,
+        width: u32,
+        height: u32,
+    },
     #[error("cfg_scale must be no greater than 35, but was {0}")]
     CfgScaleGreaterThan35(u32),
     #[error("samples must be no greater than 10, but was {0}")]
@@ -45,6 +290,8 @@ pub enum ImageBuilderError {
     StylePresetNotSet,
     #[error("a text prompt must not be empty")]
     TextPromptEmpty,
+    #[error("a text prompt weight must be a finite number, but was {0}")]
+    TextPromptWeightNotFinite(f32),
     #[error("failed to read init image: {0}")]
     InitImageReadError(String),
     #[error("init image path must be set")]
@@ -61,4 +308,231 @@ pub enum ImageBuilderError {
     MaskSourceNotSet,
     #[error("mask image path must be set when using a black or white mask source")]
     MaskImagePathNotSet,
+    #[error("grow_mask must be no greater than 100, but was {0}")]
+    GrowMaskGreaterThan100(u32),
+    #[error("a background prompt or background reference image must be set")]
+    BackgroundNotSet,
+    #[error("{name} must be between 0 and 1, but was {value}")]
+    FractionOutOfRange { name: &'static str, value: f32 },
+    #[error("an image may be outpainted by no more than 2000 pixels, but {side} was {pixels}")]
+    OutpaintGreaterThan2000 { side: &'static str, pixels: u32 },
+    #[error("a seed of 0 asks the API for a random seed, use Seed::Random instead")]
+    FixedSeedZero,
+    #[error("images can be upscaled 2 or 4 times, but not {0} times")]
+    UpscaleFactorUnsupported(u32),
+}
+
+/// A stable identifier for each kind of [`ImageBuilderError`], to look up
+/// localized messages by instead of matching on the English `Display` text
+///
+/// Codes are never renumbered or renamed; new ones are only appended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+#[repr(u16)]
+pub enum ImageBuilderErrorCode {
+    HeightNotMultipleOf64 = 1,
+    HeightLessThan128 = 2,
+    WidthNotMultipleOf64 = 3,
+    WidthLessThan128 = 4,
+    DimensionsNotSupportedByEngine = 5,
+    CfgScaleGreaterThan35 = 6,
+    SamplesGreaterThan10 = 7,
+    StepsGreaterThan150 = 8,
+    StepsLessThan10 = 9,
+    StylePresetNotSet = 10,
+    TextPromptEmpty = 11,
+    TextPromptWeightNotFinite = 12,
+    InitImageReadError = 13,
+    InitImagePathNotSet = 14,
+    UpscaleHeightLessThan512 = 15,
+    UpscaleWidthLessThan512 = 16,
+    UpscaleImagePathNotSet = 17,
+    UpscaleWidthHeightConflict = 18,
+    MaskSourceNotSet = 19,
+    MaskImagePathNotSet = 20,
+    GrowMaskGreaterThan100 = 21,
+    BackgroundNotSet = 22,
+    FractionOutOfRange = 23,
+    OutpaintGreaterThan2000 = 24,
+    FixedSeedZero = 25,
+    UpscaleFactorUnsupported = 26,
+}
+
+impl ImageBuilderErrorCode {
+    /// The code as a snake_case string, e.g. `height_not_multiple_of_64`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageBuilderErrorCode::HeightNotMultipleOf64 => "height_not_multiple_of_64",
+            ImageBuilderErrorCode::HeightLessThan128 => "height_less_than_128",
+            ImageBuilderErrorCode::WidthNotMultipleOf64 => "width_not_multiple_of_64",
+            ImageBuilderErrorCode::WidthLessThan128 => "width_less_than_128",
+            ImageBuilderErrorCode::DimensionsNotSupportedByEngine => "dimensions_not_supported_by_engine",
+            ImageBuilderErrorCode::CfgScaleGreaterThan35 => "cfg_scale_greater_than_35",
+            ImageBuilderErrorCode::SamplesGreaterThan10 => "samples_greater_than_10",
+            ImageBuilderErrorCode::StepsGreaterThan150 => "steps_greater_than_150",
+            ImageBuilderErrorCode::StepsLessThan10 => "steps_less_than_10",
+            ImageBuilderErrorCode::StylePresetNotSet => "style_preset_not_set",
+            ImageBuilderErrorCode::TextPromptEmpty => "text_prompt_empty",
+            ImageBuilderErrorCode::TextPromptWeightNotFinite => "text_prompt_weight_not_finite",
+            ImageBuilderErrorCode::InitImageReadError => "init_image_read_error",
+            ImageBuilderErrorCode::InitImagePathNotSet => "init_image_path_not_set",
+            ImageBuilderErrorCode::UpscaleHeightLessThan512 => "upscale_height_less_than_512",
+            ImageBuilderErrorCode::UpscaleWidthLessThan512 => "upscale_width_less_than_512",
+            ImageBuilderErrorCode::UpscaleImagePathNotSet => "upscale_image_path_not_set",
+            ImageBuilderErrorCode::UpscaleWidthHeightConflict => "upscale_width_height_conflict",
+            ImageBuilderErrorCode::MaskSourceNotSet => "mask_source_not_set",
+            ImageBuilderErrorCode::MaskImagePathNotSet => "mask_image_path_not_set",
+            ImageBuilderErrorCode::GrowMaskGreaterThan100 => "grow_mask_greater_than_100",
+            ImageBuilderErrorCode::BackgroundNotSet => "background_not_set",
+            ImageBuilderErrorCode::FractionOutOfRange => "fraction_out_of_range",
+            ImageBuilderErrorCode::OutpaintGreaterThan2000 => "outpaint_greater_than_2000",
+            ImageBuilderErrorCode::FixedSeedZero => "fixed_seed_zero",
+            ImageBuilderErrorCode::UpscaleFactorUnsupported => "upscale_factor_unsupported",
+        }
+    }
+}
+
+impl fmt::Display for ImageBuilderErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl ImageBuilderError {
+    /// The stable code of the error; its fields hold the values a localized
+    /// message needs
+    pub fn code(&self) -> ImageBuilderErrorCode {
+        match self {
+            ImageBuilderError::HeightNotMultipleOf64(_) => ImageBuilderErrorCode::HeightNotMultipleOf64,
+            ImageBuilderError::HeightLessThan128(_) => ImageBuilderErrorCode::HeightLessThan128,
+            ImageBuilderError::WidthNotMultipleOf64(_) => ImageBuilderErrorCode::WidthNotMultipleOf64,
+            ImageBuilderError::WidthLessThan128(_) => ImageBuilderErrorCode::WidthLessThan128,
+            ImageBuilderError::DimensionsNotSupportedByEngine { .. } => ImageBuilderErrorCode::DimensionsNotSupportedByEngine,
+            ImageBuilderError::CfgScaleGreaterThan35(_) => ImageBuilderErrorCode::CfgScaleGreaterThan35,
+            ImageBuilderError::SamplesGreaterThan10(_) => ImageBuilderErrorCode::SamplesGreaterThan10,
+            ImageBuilderError::StepsGreaterThan150(_) => ImageBuilderErrorCode::StepsGreaterThan150,
+            ImageBuilderError::StepsLessThan10(_) => ImageBuilderErrorCode::StepsLessThan10,
+            ImageBuilderError::StylePresetNotSet => ImageBuilderErrorCode::StylePresetNotSet,
+            ImageBuilderError::TextPromptEmpty => ImageBuilderErrorCode::TextPromptEmpty,
+            ImageBuilderError::TextPromptWeightNotFinite(_) => ImageBuilderErrorCode::TextPromptWeightNotFinite,
+            ImageBuilderError::InitImageReadError(_) => ImageBuilderErrorCode::InitImageReadError,
+            ImageBuilderError::InitImagePathNotSet => ImageBuilderErrorCode::InitImagePathNotSet,
+            ImageBuilderError::UpscaleHeightLessThan512(_) => ImageBuilderErrorCode::UpscaleHeightLessThan512,
+            ImageBuilderError::UpscaleWidthLessThan512(_) => ImageBuilderErrorCode::UpscaleWidthLessThan512,
+            ImageBuilderError::UpscaleImagePathNotSet => ImageBuilderErrorCode::UpscaleImagePathNotSet,
+            ImageBuilderError::UpscaleWidthHeightConflict => ImageBuilderErrorCode::UpscaleWidthHeightConflict,
+            ImageBuilderError::MaskSourceNotSet => ImageBuilderErrorCode::MaskSourceNotSet,
+            ImageBuilderError::MaskImagePathNotSet => ImageBuilderErrorCode::MaskImagePathNotSet,
+            ImageBuilderError::GrowMaskGreaterThan100(_) => ImageBuilderErrorCode::GrowMaskGreaterThan100,
+            ImageBuilderError::BackgroundNotSet => ImageBuilderErrorCode::BackgroundNotSet,
+            ImageBuilderError::FractionOutOfRange { .. } => ImageBuilderErrorCode::FractionOutOfRange,
+            ImageBuilderError::OutpaintGreaterThan2000 { .. } => ImageBuilderErrorCode::OutpaintGreaterThan2000,
+            ImageBuilderError::FixedSeedZero => ImageBuilderErrorCode::FixedSeedZero,
+            ImageBuilderError::UpscaleFactorUnsupported(_) => ImageBuilderErrorCode::UpscaleFactorUnsupported,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum BatchError {
+    #[error("batch concurrency must be at least 1")]
+    ConcurrencyZero,
+    #[error("{0} batch items were cancelled before being sent")]
+    Cancelled(usize),
+    #[error("every artifact was blurred by the safety filter")]
+    Filtered,
+    #[error("gave up after {attempts} attempts: {last}")]
+    RetriesExhausted {
+        attempts: u32,
+        last: Box<dyn std::error::Error + Send + Sync>,
+    },
+    #[error("{0} is not a time of day written HH:MM")]
+    TimeOfDayInvalid(String),
+    #[error("a time window must end at another time than it starts")]
+    TimeWindowEmpty,
+    #[error("batch item id {0:?} can't name a file, as it holds a path separator, `:` or `..`")]
+    ItemIdInvalid(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_error(name: &str) -> Error {
+        Error::ClientSendRequestError(ApiResponseError {
+            id: "id".to_string(),
+            name: name.to_string(),
+            message: "engine stable-diffusion-xl-1024-v1-0 rejected /tmp/a.png".to_string(),
+        })
+    }
+
+    #[test]
+    fn api_errors_of_v2beta_endpoints_are_joining_their_errors() {
+        let err: ApiResponseError = serde_json::from_str(
+            r#"{"id":"x","name":"bad_request","errors":["prompt: is required","seed: too large"]}"#,
+        )
+        .unwrap();
+        assert_eq!(err.message, "prompt: is required; seed: too large");
+    }
+
+    #[test]
+    fn user_message_is_naming_the_cause_of_api_errors() {
+        assert_eq!(
+            api_error("insufficient_balance").user_message(),
+            "You're out of credits."
+        );
+        assert_eq!(
+            api_error("invalid_prompts").user_message(),
+            "The image was flagged by the safety filter."
+        );
+        assert_eq!(api_error("bad_request").user_message(), GENERIC_MESSAGE);
+    }
+
+    #[test]
+    fn user_message_is_leaving_out_technical_details() {
+        let err = Error::InsufficientDiskSpace {
+            dir: "/home/alice/out".to_string(),
+            needed: 10,
+            available: 1,
+        };
+        assert!(!err.user_message().contains("alice"));
+    }
+
+    #[test]
+    fn is_transient_is_true_for_rate_limits_and_outages_only() {
+        assert!(api_error("rate_limit_exceeded").is_transient());
+        assert!(api_error("server_error").is_transient());
+        assert!(!api_error("engine_not_found").is_transient());
+        assert!(!api_error("invalid_prompts").is_transient());
+
+        let reset = std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+        assert!(is_transient(&reset));
+        assert!(!is_transient(&ImageBuilderError::StylePresetNotSet));
+    }
+
+    #[test]
+    fn code_is_stable_for_builder_errors() {
+        let err = ImageBuilderError::HeightNotMultipleOf64(1023);
+        assert_eq!(err.code(), ImageBuilderErrorCode::HeightNotMultipleOf64);
+        assert_eq!(err.code().as_str(), "height_not_multiple_of_64");
+        assert_eq!(err.code() as u16, 1);
+        assert_eq!(
+            ImageBuilderErrorCode::MaskImagePathNotSet.to_string(),
+            "mask_image_path_not_set"
+        );
+    }
+
+    #[test]
+    fn user_message_is_downcasting_boxed_errors() {
+        let boxed: Box<dyn std::error::Error + Send + Sync> =
+            Box::new(api_error("insufficient_balance"));
+        assert_eq!(user_message(boxed.as_ref()), "You're out of credits.");
+
+        let boxed: Box<dyn std::error::Error + Send + Sync> =
+            Box::new(ImageBuilderError::StylePresetNotSet);
+        assert_eq!(
+            user_message(boxed.as_ref()),
+            "Some of the image settings aren't supported."
+        );
+    }
 }
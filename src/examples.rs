@@ -0,0 +1,203 @@
+//! A gallery of ready recipes, compiled and tested with the crate.
+//!
+//! Each example is a complete workflow across several parts of the crate,
+//! written as a plain function: call it as is, or read it as a starting point
+//! for a flow of your own. They run as a [`Pipeline`], so a failed or
+//! over-budget stage stops the ones after it and the returned
+//! [`PipelineReport`] tells which stage did what.
+//!
+//! - [`logo_sketch_to_render`]: a rough logo sketch rendered with
+//!   image-to-image, then upscaled
+//! - [`photo_restyle`]: the masked part of a photo repainted in a style
+//!   preset, then upscaled
+//!
+//! ```no_run
+//! use stability_rs::{examples, Result};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     let engine = "stable-diffusion-xl-1024-v1-0";
+//!     let report = examples::logo_sketch_to_render(engine, "sketch.png", "a fox logo").await?;
+//!
+//!     match report.output {
+//!         Some(resp) => resp.artifacts[0].save("logo.png").await?,
+//!         None => eprintln!("stopped early: {:?}", report.stages),
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::api::rest::generation::img_to_img::{ImageMode, ImageToImageBuilder};
+use crate::api::rest::generation::masking::{MaskSource, MaskerBuilder};
+use crate::api::rest::generation::upscale::{UpscaleEngine, UpscalerBuilder};
+use crate::api::rest::generation::{ImageResponse, StylePreset};
+use crate::credits;
+use crate::error::Error;
+use crate::pipeline::{Budget, Pipeline, PipelineReport, StageFuture};
+use crate::prelude::*;
+use crate::staging::TempStore;
+use std::path::Path;
+use std::time::Duration;
+
+/// Low enough for the render to replace the pencil lines, high enough to
+/// keep the layout of the sketch
+const SKETCH_STRENGTH: f32 = 0.25;
+/// How long a generation stage may take before the pipeline gives up on it
+const STAGE_LATENCY: Duration = Duration::from_secs(90);
+const STEPS: u32 = 50;
+const UPSCALE_ENGINE: UpscaleEngine = UpscaleEngine::EsrganV1X2Plus;
+
+/// `sketch` rendered as a finished logo described by `prompt`, upscaled
+/// twice its size
+///
+/// The output of the report is the upscaled render.
+pub async fn logo_sketch_to_render(
+    engine: &str,
+    sketch: impl AsRef<Path>,
+    prompt: &str,
+) -> Result<PipelineReport> {
+    let request = ImageToImageBuilder::new()
+        .init_image_path(sketch)?
+        .init_image_mode(ImageMode::ImageStrength)?
+        .image_strength(SKETCH_STRENGTH)?
+        .steps(STEPS)?
+        .style_preset(StylePreset::DigitalArt)?
+        .text_prompt(prompt, 1.0)?
+        .text_prompt("clean vector logo, flat colors, centered", 0.6)?
+        .text_prompt("pencil lines, paper texture, blurry", -0.8)?
+        .build()?;
+
+    let engine = engine.to_string();
+    let pipeline = Pipeline::new()
+        .stage(
+            "render",
+            credits::estimate(&engine, STEPS, 1),
+            Budget::new().max_latency(STAGE_LATENCY)?,
+            move |_| Box::pin(async move { request.generate(&engine).await }),
+        )?
+        .stage("upscale", upscale_credits(), Budget::new(), upscale)?;
+    Ok(pipeline.run().await)
+}
+
+/// The parts of `photo` that are white in `mask` repainted as `prompt` in
+/// `style`, upscaled twice its size
+///
+/// The output of the report is the upscaled photo.
+pub async fn photo_restyle(
+    engine: &str,
+    photo: impl AsRef<Path>,
+    mask: impl AsRef<Path>,
+    prompt: &str,
+    style: StylePreset,
+) -> Result<PipelineReport> {
+    let request = MaskerBuilder::new()
+        .init_image_path(photo)?
+        .mask_source(MaskSource::MaskImageWhite)?
+        .mask_image(mask)?
+        .steps(STEPS)?
+        .style_preset(style)?
+        .text_prompt(prompt, 1.0)?
+        .build()?;
+
+    let engine = engine.to_string();
+    let pipeline = Pipeline::new()
+        .stage(
+            "restyle",
+            credits::estimate(&engine, STEPS, 1),
+            Budget::new().max_latency(STAGE_LATENCY)?,
+            move |_| Box::pin(async move { request.generate(&engine).await }),
+        )?
+        .stage("upscale", upscale_credits(), Budget::new(), upscale)?;
+    Ok(pipeline.run().await)
+}
+
+fn upscale_credits() -> f64 {
+    credits::estimate(&UPSCALE_ENGINE.to_string(), 0, 1)
+}
+
+/// The stage upscaling the first artifact of the stage before it
+fn upscale(previous: Option<&ImageResponse>) -> StageFuture {
+    let artifact = previous
+        .and_then(|resp| resp.artifacts.first())
+        .map(|image| image.to_artifact());
+    Box::pin(async move {
+        let artifact = artifact.ok_or(Error::NoArtifacts)??;
+        let staging = TempStore::new()?;
+        let path = staging.save("generated.png", &artifact).await?;
+        UpscalerBuilder::new()
+            .image(&path)?
+            .build()?
+            .generate(UPSCALE_ENGINE)
+            .await
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::png_1x1;
+    use std::path::PathBuf;
+
+    const ENGINE: &str = "stable-diffusion-xl-1024-v1-0";
+
+    fn fixture(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "stability_rs_examples_{}_{}.png",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, png_1x1()).unwrap();
+        path
+    }
+
+    fn paths(transport: &crate::testing::FakeTransport) -> Vec<String> {
+        transport
+            .requests()
+            .iter()
+            .map(|request| request.uri.path().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn logo_sketch_to_render_is_upscaling_the_render() {
+        let sketch = fixture("sketch");
+        let transport = crate::mock::transport();
+        let report = transport
+            .scope(logo_sketch_to_render(ENGINE, &sketch, "a fox logo"))
+            .await
+            .unwrap();
+        std::fs::remove_file(&sketch).unwrap();
+
+        assert!(report.is_complete(), "{:?}", report.stages);
+        assert_eq!(
+            paths(&transport),
+            [
+                format!("/v1/generation/{}/image-to-image", ENGINE),
+                "/v1/generation/esrgan-v1-x2plus/image-to-image/upscale".to_string(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn photo_restyle_is_masking_then_upscaling() {
+        let photo = fixture("photo");
+        let mask = fixture("mask");
+        let transport = crate::mock::transport();
+        let report = transport
+            .scope(photo_restyle(ENGINE, &photo, &mask, "a red coat", StylePreset::Cinematic))
+            .await
+            .unwrap();
+        std::fs::remove_file(&photo).unwrap();
+        std::fs::remove_file(&mask).unwrap();
+
+        assert!(report.is_complete(), "{:?}", report.stages);
+        assert_eq!(
+            paths(&transport),
+            [
+                format!("/v1/generation/{}/image-to-image/masking", ENGINE),
+                "/v1/generation/esrgan-v1-x2plus/image-to-image/upscale".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! Machine-readable descriptions of the request parameters.
+//!
+//! Each request type describes its fields with a `schema()` function, such as
+//! [`TextToImage::schema`](crate::model::text_to_img::TextToImage::schema):
+//! their types, ranges, defaults and choices, taken from the same limits the
+//! builders validate against. GUI and web front-ends can generate their forms
+//! from it, or serialize it to JSON for a browser, and stay in sync with the
+//! crate.
+//!
+//! Choices and defaults are given as their JSON forms, which deserialize back
+//! into the crate's enums.
+//!
+//! ```
+//! use stability_rs::form::FieldKind;
+//! use stability_rs::model::text_to_img::TextToImage;
+//!
+//! let schema = TextToImage::schema();
+//! let steps = schema.field("steps").unwrap();
+//! assert_eq!(steps.kind, FieldKind::Integer { min: 10, max: Some(150), multiple_of: None });
+//! assert_eq!(steps.default, Some(50.into()));
+//! ```
+
+use crate::capabilities::{CFG_SCALE, MAX_SAMPLES, STEPS};
+use crate::model::{ClipGuidancePreset, Sampler, StylePreset};
+use serde::Serialize;
+use serde_json::Value;
+
+/// The fields of a request type
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RequestSchema {
+    /// The name of the request type, e.g. `TextToImage`
+    pub name: &'static str,
+    pub fields: Vec<FieldSchema>,
+}
+
+impl RequestSchema {
+    pub fn field(&self, name: &str) -> Option<&FieldSchema> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+/// One field of a request, named as its builder setter
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldSchema {
+    pub name: &'static str,
+    pub kind: FieldKind,
+    /// Whether the request can't be built without it
+    pub required: bool,
+    /// The value used when the field is left unset, if the crate picks one
+    pub default: Option<Value>,
+    pub description: &'static str,
+}
+
+/// The type of a field, with the values it accepts
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FieldKind {
+    Integer {
+        min: u32,
+        max: Option<u32>,
+        multiple_of: Option<u32>,
+    },
+    Number {
+        min: f32,
+        max: f32,
+    },
+    /// A seed, where 0 leaves the seed to the API
+    Seed,
+    /// One of a list of values
+    Choice { choices: Vec<Value> },
+    /// A path to an image file
+    Image,
+    /// Weighted text prompts, negative weights describing what to avoid
+    Prompts,
+}
+
+impl FieldSchema {
+    pub(crate) fn new(name: &'static str, kind: FieldKind, description: &'static str) -> Self {
+        Self {
+            name,
+            kind,
+            required: false,
+            default: None,
+            description,
+        }
+    }
+
+    pub(crate) fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    pub(crate) fn default(mut self, default: impl Serialize) -> Self {
+        self.default = Some(json(&default));
+        self
+    }
+}
+
+impl FieldKind {
+    pub(crate) fn integer(min: u32, max: Option<u32>) -> Self {
+        FieldKind::Integer {
+            min,
+            max,
+            multiple_of: None,
+        }
+    }
+
+    pub(crate) fn choice<T: Serialize>(choices: &[T]) -> Self {
+        FieldKind::Choice {
+            choices: choices.iter().map(json).collect(),
+        }
+    }
+}
+
+fn json(value: &impl Serialize) -> Value {
+    // unwrap warranted because request values always serialize
+    serde_json::to_value(value).unwrap()
+}
+
+/// The sampling fields shared by the text-to-image, image-to-image and
+/// masking requests, with the defaults of their builders
+pub(crate) fn generation_fields() -> Vec<FieldSchema> {
+    vec![
+        FieldSchema::new("text_prompts", FieldKind::Prompts, "What the image shows").required(),
+        FieldSchema::new(
+            "style_preset",
+            FieldKind::choice(&StylePreset::ALL),
+            "The style the image is drawn in",
+        )
+        .required(),
+        FieldSchema::new(
+            "cfg_scale",
+            FieldKind::integer(*CFG_SCALE.start(), Some(*CFG_SCALE.end())),
+            "How strictly the image follows the prompts",
+        )
+        .default(7),
+        FieldSchema::new(
+            "clip_guidance_preset",
+            FieldKind::choice(&ClipGuidancePreset::ALL),
+            "CLIP guidance, ignored by SDXL engines",
+        )
+        .default(ClipGuidancePreset::None),
+        FieldSchema::new(
+            "sampler",
+            FieldKind::choice(&Sampler::ALL),
+            "The sampler, picked by the API when unset",
+        ),
+        FieldSchema::new(
+            "samples",
+            FieldKind::integer(1, Some(MAX_SAMPLES)),
+            "How many images to generate",
+        )
+        .default(1),
+        FieldSchema::new("seed", FieldKind::Seed, "The seed of the noise").default(0),
+        FieldSchema::new(
+            "steps",
+            FieldKind::integer(*STEPS.start(), Some(*STEPS.end())),
+            "How many diffusion steps to run",
+        )
+        .default(50),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "text-to-image")]
+    #[test]
+    fn defaults_are_the_values_a_built_request_sends() {
+        use crate::text_to_img::{TextToImage, TextToImageBuilder};
+
+        let request = TextToImageBuilder::new()
+            .style_preset(StylePreset::Anime)
+            .unwrap()
+            .text_prompt("a fox", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+        let sent = serde_json::to_value(&request).unwrap();
+
+        for field in TextToImage::schema().fields {
+            if let Some(default) = field.default {
+                assert_eq!(sent[field.name], default, "{}", field.name);
+            }
+        }
+    }
+
+    #[test]
+    fn choices_are_deserializing_into_the_enums() {
+        let FieldKind::Choice { choices } = FieldKind::choice(&Sampler::ALL) else {
+            unreachable!()
+        };
+
+        assert_eq!(choices[2], "K_DPMPP_2M");
+        for choice in choices {
+            serde_json::from_value::<Sampler>(choice).unwrap();
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! A default client shared by the whole process.
+//!
+//! Without one, each request reads `STABILITY_API_KEY` from the environment,
+//! picks up the other settings from their own globals and opens a connection
+//! of its own. Once [`init`] is called, every client built by this crate,
+//! including the ones behind free functions such as
+//! [`get_engines`](crate::api::rest::engine::get_engines) and the
+//! [`quick`](crate::quick) helpers, starts from the [`Config`] instead, with
+//! no plumbing through the application. Settings left unset in the config
+//! fall back to the usual defaults, and per-request settings such as a
+//! builder's organization still take precedence.
+//!
+//! The clients also share a pool of keep-alive connections: a request goes
+//! out over a connection an earlier one left idle when there is one, saving
+//! the connection and TLS handshakes.
+//!
+//! ```no_run
+//! use stability_rs::global::{self, Config};
+//!
+//! # fn main() -> stability_rs::Result<()> {
+//! global::init(
+//!     Config::from_env()?
+//!         .organization("org-123")?
+//!         .user_agent_product("my-app/1.2")?,
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::api::rest::client::{BaseUrl, ClientBuilder, HeaderValue};
+use crate::api::rest::pool::{self, Pool};
+use crate::prelude::*;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+static GLOBAL: RwLock<Option<Shared>> = RwLock::new(None);
+
+/// What the clients share once [`init`]ed
+#[derive(Debug, Clone)]
+struct Shared {
+    config: Arc<Config>,
+    pool: Arc<Pool>,
+}
+
+/// The settings every client starts from once [`init`]ed
+#[derive(Clone, Default, PartialEq)]
+pub struct Config {
+    api_key: Option<String>,
+    organization: Option<String>,
+    base_url: Option<String>,
+    user_agent_product: Option<String>,
+    max_response_size: Option<usize>,
+    max_idle_connections: Option<usize>,
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("api_key", &self.api_key.as_ref().map(|_| "<redacted>"))
+            .field("organization", &self.organization)
+            .field("base_url", &self.base_url)
+            .field("user_agent_product", &self.user_agent_product)
+            .field("max_response_size", &self.max_response_size)
+            .field("max_idle_connections", &self.max_idle_connections)
+            .finish()
+    }
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A config holding the API key read from `STABILITY_API_KEY`, once
+    pub fn from_env() -> Result<Self> {
+        Self::new().api_key(&std::env::var("STABILITY_API_KEY")?)
+    }
+
+    pub fn api_key(mut self, api_key: &str) -> Result<Self> {
+        api_key.parse::<HeaderValue>()?;
+        self.api_key = Some(api_key.to_string());
+        Ok(self)
+    }
+
+    /// Bill requests to one of the organizations the API key belongs to,
+    /// unless a request names its own
+    pub fn organization(mut self, organization: &str) -> Result<Self> {
+        organization.parse::<HeaderValue>()?;
+        self.organization = Some(organization.to_string());
+        Ok(self)
+    }
+
+    /// Send requests to `url`, in any of the forms of
+    /// [`set_base_url`](crate::api::rest::client::set_base_url)
+    pub fn base_url(mut self, url: &str) -> Result<Self> {
+        url.parse::<BaseUrl>()?;
+        self.base_url = Some(url.to_string());
+        Ok(self)
+    }
+
+    /// Append `product`, such as `my-app/1.2`, to the User-Agent
+    pub fn user_agent_product(mut self, product: &str) -> Result<Self> {
+        product.parse::<HeaderValue>()?;
+        self.user_agent_product = Some(product.to_string());
+        Ok(self)
+    }
+
+    /// Cap the size of response bodies, in bytes
+    pub fn max_response_size(mut self, bytes: usize) -> Result<Self> {
+        self.max_response_size = Some(bytes);
+        Ok(self)
+    }
+
+    /// Keep up to `connections` idle connections to each host for reuse, 8
+    /// by default; 0 opens a new connection for every request
+    pub fn max_idle_connections(mut self, connections: usize) -> Result<Self> {
+        self.max_idle_connections = Some(connections);
+        Ok(self)
+    }
+
+    /// Whether the config holds an API key, so clients need not read one
+    /// from the environment
+    pub(crate) fn has_api_key(&self) -> bool {
+        self.api_key.is_some()
+    }
+
+    /// Set the settings of this config on `cb`
+    pub(crate) fn apply(&self, mut cb: ClientBuilder) -> Result<ClientBuilder> {
+        if let Some(api_key) = &self.api_key {
+            cb = cb.api_key(api_key)?;
+        }
+        if let Some(organization) = &self.organization {
+            cb = cb.organization(organization)?;
+        }
+        if let Some(url) = &self.base_url {
+            cb = cb.base_url(url)?;
+        }
+        if let Some(product) = &self.user_agent_product {
+            cb = cb.user_agent_product(product)?;
+        }
+        if let Some(bytes) = self.max_response_size {
+            cb = cb.max_response_size(bytes)?;
+        }
+        Ok(cb)
+    }
+}
+
+/// Build every client from `config` and share connections between them,
+/// replacing the config and connections of an earlier call
+pub fn init(config: Config) {
+    let max_idle = config.max_idle_connections.unwrap_or(pool::DEFAULT_MAX_IDLE);
+    *GLOBAL.write().unwrap() = Some(Shared {
+        config: Arc::new(config),
+        pool: Arc::new(Pool::new(max_idle)),
+    });
+}
+
+/// Go back to building each client on its own, closing the idle connections
+pub fn reset() {
+    *GLOBAL.write().unwrap() = None;
+}
+
+pub fn config() -> Option<Arc<Config>> {
+    GLOBAL.read().unwrap().as_ref().map(|shared| shared.config.clone())
+}
+
+pub(crate) fn pool() -> Option<Arc<Pool>> {
+    GLOBAL.read().unwrap().as_ref().map(|shared| shared.pool.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_is_letting_the_request_organization_win() {
+        let config = Config::new()
+            .api_key("sk-global")
+            .unwrap()
+            .organization("org-global")
+            .unwrap()
+            .base_url("http://127.0.0.1:8080")
+            .unwrap();
+        let client = config
+            .apply(ClientBuilder::default())
+            .unwrap()
+            .organization("org-request")
+            .unwrap()
+            .path("/user/balance")
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(client.headers["authorization"], "sk-global");
+        assert_eq!(client.headers.get_all("organization").iter().count(), 1);
+        assert_eq!(client.headers["organization"], "org-request");
+        assert_eq!(
+            client.base_url,
+            Some(BaseUrl::Http {
+                host: "127.0.0.1".to_string(),
+                port: 8080
+            })
+        );
+    }
+
+    #[test]
+    fn debug_is_hiding_the_api_key() {
+        let config = Config::new().api_key("sk-secret").unwrap();
+        assert!(!format!("{:?}", config).contains("sk-secret"));
+    }
+}
@@ -0,0 +1,65 @@
+//! Image interrogation: suggesting a prompt that describes an image.
+//!
+//! The Stability REST API has no image-to-text endpoint, so this crate ships
+//! no interrogator of its own. Implement [`Interrogator`] over a CLIP/BLIP
+//! service of your choice and [`install`] it; [`interrogate`] then routes
+//! every call through it, and a first-party backend can slot in the same way
+//! once the API offers one.
+
+use crate::api::rest::artifact::Artifact;
+use crate::error::Error;
+use crate::prelude::*;
+use futures_util::future::BoxFuture;
+use std::sync::{Arc, RwLock};
+
+static GLOBAL: RwLock<Option<Arc<dyn Interrogator>>> = RwLock::new(None);
+
+/// A backend turning an image into suggested prompt text
+pub trait Interrogator: Send + Sync {
+    fn interrogate<'a>(&'a self, image: &'a Artifact) -> BoxFuture<'a, Result<String>>;
+}
+
+/// Make `interrogator` the backend used by [`interrogate`]
+pub fn install(interrogator: Arc<dyn Interrogator>) {
+    *GLOBAL.write().unwrap() = Some(interrogator);
+}
+
+pub fn uninstall() {
+    *GLOBAL.write().unwrap() = None;
+}
+
+/// Suggest a prompt describing `image` with the installed backend
+pub async fn interrogate(image: &Artifact) -> Result<String> {
+    let Some(interrogator) = GLOBAL.read().unwrap().clone() else {
+        return Err(Box::new(Error::InterrogatorNotInstalled));
+    };
+    interrogator.interrogate(image).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::body::Bytes;
+
+    struct Fixed;
+
+    impl Interrogator for Fixed {
+        fn interrogate<'a>(&'a self, image: &'a Artifact) -> BoxFuture<'a, Result<String>> {
+            let prompt = format!("a {} picture", image.extension());
+            Box::pin(async move { Ok(prompt) })
+        }
+    }
+
+    #[tokio::test]
+    async fn interrogate_is_using_the_installed_backend() {
+        let image = Artifact::new("image/png", Bytes::new()).unwrap();
+
+        uninstall();
+        let err = interrogate(&image).await.unwrap_err();
+        assert_eq!(err.to_string(), "no interrogator is installed");
+
+        install(Arc::new(Fixed));
+        assert_eq!(interrogate(&image).await.unwrap(), "a png picture");
+        uninstall();
+    }
+}
@@ -8,8 +8,9 @@
 //!
 //! ## Text to Image
 //!
-//! ```no_run
-//! use stability_rs::{text_to_img::*, Result, ClipGuidancePreset, Sampler, StylePreset};
+#![cfg_attr(feature = "text-to-image", doc = "```no_run")]
+#![cfg_attr(not(feature = "text-to-image"), doc = "```ignore")]
+//! use stability_rs::{text_to_img::*, Result, ClipGuidancePreset, Sampler, Seed, StylePreset};
 //!
 //!    #[tokio::main]
 //!    async fn main() -> Result<()> {
@@ -20,7 +21,7 @@
 //!            .clip_guidance_preset(ClipGuidancePreset::FastBlue)?
 //!            .sampler(Sampler::KDpmpp2sAncestral)?
 //!            .samples(2)?
-//!            .seed(0)?
+//!            .seed(Seed::Random)?
 //!            .steps(33)?
 //!            .style_preset(StylePreset::DigitalArt)?
 //!            .text_prompt("A scholar tired at his desk, a raven on a bust", 1.0)?
@@ -37,8 +38,9 @@
 //!  ```
 //! ### Image to Image
 //!
-//! ```no_run
-//! use stability_rs::{img_to_img::*, Result, ClipGuidancePreset, Sampler, StylePreset,};
+#![cfg_attr(feature = "image-to-image", doc = "```no_run")]
+#![cfg_attr(not(feature = "image-to-image"), doc = "```ignore")]
+//! use stability_rs::{img_to_img::*, Result, ClipGuidancePreset, Sampler, Seed, StylePreset,};
 //!
 //!    #[tokio::main]
 //!    async fn main() -> Result<()> {
@@ -50,7 +52,7 @@
 //!            .clip_guidance_preset(ClipGuidancePreset::FastBlue)?
 //!            .sampler(Sampler::KDpm2Ancestral)?
 //!            .samples(3)?
-//!            .seed(0)?
+//!            .seed(Seed::Random)?
 //!            .steps(20)?
 //!            .style_preset(StylePreset::FantasyArt)?
 //!            .text_prompt("A crab relaxing on a beach", 0.5)?
@@ -69,7 +71,8 @@
 //!
 //! ### Image Upscaling
 //!
-//! ```no_run
+#![cfg_attr(feature = "upscale", doc = "```no_run")]
+#![cfg_attr(not(feature = "upscale"), doc = "```ignore")]
 //! use stability_rs::{upscale::*, Result,};
 //!
 //!      #[tokio::main]
@@ -89,7 +92,8 @@
 //!
 //! ### Image Masking
 //!
-//! ```no_run
+#![cfg_attr(feature = "masking", doc = "```no_run")]
+#![cfg_attr(not(feature = "masking"), doc = "```ignore")]
 //! use stability_rs::{masking::*, Result, StylePreset, ClipGuidancePreset};
 //!
 //!      #[tokio::main]
@@ -113,13 +117,115 @@
 //!      }
 // ```
 
+#[cfg(feature = "client")]
 pub use crate::api::rest::generation::*;
+#[cfg(feature = "client")]
 pub use crate::api::rest::generation;
+#[cfg(feature = "text-to-image")]
 pub use crate::api::rest::generation::text_to_img;
+#[cfg(feature = "image-to-image")]
 pub use crate::api::rest::generation::img_to_img;
+#[cfg(feature = "inpaint")]
+pub use crate::api::rest::generation::inpaint;
+#[cfg(feature = "relight")]
+pub use crate::api::rest::generation::relight;
 pub use crate::prelude::Result;
 
+#[cfg(all(feature = "client", not(any(feature = "native-tls", feature = "rustls"))))]
+compile_error!("either the `native-tls` or the `rustls` feature must be enabled");
+
+#[cfg(feature = "client")]
+pub mod alt_text;
+#[cfg(feature = "anim")]
+pub mod animation;
+#[cfg(feature = "client")]
 pub mod api;
+#[cfg(feature = "client")]
+pub mod atomic_write;
+#[cfg(feature = "client")]
+pub mod audit;
+#[cfg(any(feature = "text-to-image", feature = "image-to-image"))]
+pub mod batch;
+#[cfg(any(feature = "text-to-image", feature = "image-to-image"))]
+pub mod bench;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "client")]
+pub mod cache;
+pub mod capabilities;
+#[cfg(feature = "client")]
+pub mod circuit;
+#[cfg(feature = "client")]
+pub mod credits;
+#[cfg(feature = "dataset")]
+pub mod dataset;
+#[cfg(feature = "client")]
+pub mod deprecation;
+#[cfg(feature = "client")]
+pub mod download_progress;
+#[cfg(feature = "encryption")]
+pub mod encryption;
 pub mod error;
+#[cfg(feature = "examples")]
+pub mod examples;
+pub mod form;
+#[cfg(feature = "client")]
+pub mod global;
+#[cfg(feature = "client")]
+pub mod interrogate;
+#[cfg(feature = "client")]
+pub mod lifecycle;
+#[cfg(feature = "client")]
+pub mod limiter;
+pub mod model;
+#[cfg(any(all(test, feature = "client"), feature = "mock"))]
+pub mod mock;
+#[cfg(feature = "client")]
+pub mod pipeline;
+#[cfg(feature = "client")]
+pub mod preflight;
 pub mod prelude;
+#[cfg(feature = "progress")]
+pub mod progress_bar;
+#[cfg(feature = "client")]
+pub mod progressive;
+#[cfg(feature = "client")]
+pub mod prompt_screen;
+#[cfg(feature = "prompt-store")]
+pub mod prompt_store;
+#[cfg(feature = "prompt-syntax")]
+pub mod prompt_syntax;
+#[cfg(feature = "client")]
+pub mod provenance;
+#[cfg(any(feature = "text-to-image", feature = "image-to-image"))]
+pub mod quick;
+#[cfg(any(feature = "text-to-image", feature = "image-to-image"))]
+pub mod recipes;
+pub mod redaction;
+#[cfg(feature = "client")]
+pub mod resolver;
+#[cfg(feature = "client")]
+pub mod schema;
+#[cfg(feature = "client")]
+pub mod scoring;
+#[cfg(feature = "client")]
+pub mod signing;
+#[cfg(any(all(test, feature = "client"), feature = "testing"))]
+pub mod snapshot;
+#[cfg(feature = "client")]
+pub mod staging;
+#[cfg(feature = "client")]
 pub mod support;
+#[cfg(feature = "client")]
+pub mod tenancy;
+#[cfg(any(all(test, feature = "client"), feature = "testing"))]
+pub mod testing;
+#[cfg(feature = "client")]
+pub mod upload_progress;
+#[cfg(feature = "client")]
+pub mod usage;
+pub mod validation;
+#[cfg(feature = "viewer")]
+pub mod viewer;
+#[cfg(all(feature = "client", feature = "image"))]
+pub mod watermark;
@@ -0,0 +1,180 @@
+//! Graceful shutdown.
+//!
+//! [`shutdown`] stops new requests, which fail with [`Error::ShuttingDown`],
+//! waits for those already in flight to complete and flushes the installed
+//! [`crate::audit`] sink, so a service can terminate without aborting
+//! generations it has already paid for. It also closes the keep-alive
+//! connections the [`global`](crate::global) client left idle; requests
+//! abandoned past the timeout may still hold one of their own.
+//!
+//! ```no_run
+//! use stability_rs::{lifecycle, Result};
+//! use std::time::Duration;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     tokio::signal::ctrl_c().await?;
+//!
+//!     let report = lifecycle::shutdown(Duration::from_secs(30)).await?;
+//!     if !report.is_clean() {
+//!         eprintln!("{} requests were still running", report.abandoned);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::audit;
+use crate::error::Error;
+use crate::prelude::*;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+static GLOBAL: Lifecycle = Lifecycle::new();
+
+/// What a [`shutdown`] left behind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Requests still in flight when the timeout ran out
+    pub abandoned: usize,
+}
+
+impl ShutdownReport {
+    /// Whether every request in flight completed
+    pub fn is_clean(&self) -> bool {
+        self.abandoned == 0
+    }
+}
+
+/// Refuse new requests, wait up to `timeout` for those in flight, then close
+/// the idle connections and flush the installed audit sink
+///
+/// Sinks set on a single client with
+/// [`ClientBuilder::audit`](crate::api::rest::client::ClientBuilder::audit)
+/// are not reachable from here, and are left to their owner to flush.
+pub async fn shutdown(timeout: Duration) -> Result<ShutdownReport> {
+    let abandoned = GLOBAL.shutdown(timeout).await;
+    if let Some(pool) = crate::global::pool() {
+        pool.close_idle();
+    }
+    if let Some(sink) = audit::installed() {
+        sink.flush()?;
+    }
+    Ok(ShutdownReport { abandoned })
+}
+
+/// Accept requests again after a [`shutdown`]
+pub fn resume() {
+    GLOBAL.shutting_down.store(false, Ordering::SeqCst);
+}
+
+pub fn is_shutting_down() -> bool {
+    GLOBAL.shutting_down.load(Ordering::SeqCst)
+}
+
+/// How many requests are in flight
+pub fn in_flight() -> usize {
+    GLOBAL.in_flight.load(Ordering::SeqCst)
+}
+
+/// Counts a request as in flight until dropped
+pub(crate) struct InFlight(&'static Lifecycle);
+
+impl InFlight {
+    /// Err with [`Error::ShuttingDown`] once a shutdown has begun
+    pub(crate) fn enter() -> Result<Self> {
+        GLOBAL.enter()
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.drained.notify_waiters();
+        }
+    }
+}
+
+struct Lifecycle {
+    shutting_down: AtomicBool,
+    in_flight: AtomicUsize,
+    drained: Notify,
+}
+
+impl Lifecycle {
+    const fn new() -> Self {
+        Self {
+            shutting_down: AtomicBool::new(false),
+            in_flight: AtomicUsize::new(0),
+            drained: Notify::const_new(),
+        }
+    }
+
+    fn enter(&'static self) -> Result<InFlight> {
+        // counted first, so a shutdown starting meanwhile waits for it
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let guard = InFlight(self);
+        if self.shutting_down.load(Ordering::SeqCst) {
+            return Err(Box::new(Error::ShuttingDown));
+        }
+        Ok(guard)
+    }
+
+    /// Returns how many requests were still in flight after `timeout`
+    async fn shutdown(&self, timeout: Duration) -> usize {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        let drained = async {
+            loop {
+                // created before the check, so a drop in between still wakes it
+                let notified = self.drained.notified();
+                if self.in_flight.load(Ordering::SeqCst) == 0 {
+                    return;
+                }
+                notified.await;
+            }
+        };
+        let _ = tokio::time::timeout(timeout, drained).await;
+        self.in_flight.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lifecycle() -> &'static Lifecycle {
+        Box::leak(Box::new(Lifecycle::new()))
+    }
+
+    #[tokio::test]
+    async fn shutdown_is_waiting_for_requests_in_flight() {
+        let lifecycle = lifecycle();
+        let request = lifecycle.enter().unwrap();
+        let finished = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(request);
+        });
+
+        let abandoned = lifecycle.shutdown(Duration::from_secs(5)).await;
+
+        assert_eq!(abandoned, 0);
+        finished.await.unwrap();
+        let err = lifecycle.enter().err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "requests are no longer sent during a shutdown"
+        );
+        assert_eq!(lifecycle.in_flight.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn shutdown_is_reporting_requests_past_the_timeout() {
+        let lifecycle = lifecycle();
+        let _request = lifecycle.enter().unwrap();
+
+        let abandoned = lifecycle.shutdown(Duration::from_millis(10)).await;
+
+        assert_eq!(abandoned, 1);
+    }
+}
@@ -0,0 +1,432 @@
+//! Process-wide request pacing.
+//!
+//! A [`RateLimiter`] caps how many requests are in flight and how quickly new
+//! ones may start. Once [`install`]ed, every request sent by this crate waits
+//! for a permit, whichever client or module it comes from. Requests run in the
+//! [`Priority::Interactive`] lane unless wrapped in [`with_priority`]; the
+//! batch runner uses [`Priority::Background`], so interactive requests are
+//! always served ahead of queued batch items.
+//!
+//! A [`RateLimiter::adaptive`] limiter also tunes itself to the account's
+//! quota: every 429 response halves how many requests it lets run and
+//! doubles the interval between their starts, and every successful response
+//! gives a little of that back, up to the configured maximum. A batch job run
+//! through one settles at whatever rate the account actually allows:
+//!
+//! ```
+//! use stability_rs::limiter::{self, RateLimiter};
+//! use std::time::Duration;
+//!
+//! limiter::install(RateLimiter::adaptive(10, Duration::from_millis(67)));
+//! ```
+//!
+//! A [`ConcurrencyLimit`] only caps how many requests are in flight, without
+//! pacing their starts. Applications mixing text-to-image, upscale and
+//! masking calls from many tasks can share one with
+//! [`ConcurrencyLimit::global`] instead of coordinating the calls themselves:
+//!
+//! ```
+//! use stability_rs::limiter::ConcurrencyLimit;
+//!
+//! // at most four connections to the API, whichever endpoint they are for
+//! ConcurrencyLimit::global(4);
+//! ```
+
+use std::future::Future;
+use std::pin::pin;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+/// The API allows 150 requests every 10 seconds
+const DEFAULT_MIN_INTERVAL: Duration = Duration::from_millis(67);
+const DEFAULT_MAX_CONCURRENT: usize = 10;
+/// The start interval an adaptive limiter backs off from when its minimum is
+/// shorter
+const BACKOFF_INTERVAL: Duration = DEFAULT_MIN_INTERVAL;
+const MAX_BACKOFF_INTERVAL: Duration = Duration::from_secs(10);
+/// 429s this soon after a back-off come from requests started before it, so
+/// don't back off again
+const BACKOFF_QUIET_PERIOD: Duration = Duration::from_secs(1);
+
+static GLOBAL: RwLock<Option<RateLimiter>> = RwLock::new(None);
+static CONCURRENCY: RwLock<Option<ConcurrencyLimit>> = RwLock::new(None);
+
+tokio::task_local! {
+    static PRIORITY: Priority;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn interactive_requests_preempt_background_requests() {
+        let limiter = RateLimiter::new(1, Duration::ZERO);
+        let permit = limiter.acquire(Priority::Background).await;
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        for priority in [Priority::Background, Priority::Interactive] {
+            let limiter = limiter.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let _permit = limiter.acquire(priority).await;
+                tx.send(priority).unwrap();
+            });
+            tokio::task::yield_now().await;
+        }
+
+        drop(permit);
+        assert_eq!(rx.recv().await, Some(Priority::Interactive));
+        assert_eq!(rx.recv().await, Some(Priority::Background));
+    }
+
+    #[tokio::test]
+    async fn starts_are_paced_by_the_minimum_interval() {
+        let limiter = RateLimiter::new(10, Duration::from_millis(50));
+        let started = Instant::now();
+        for _ in 0..3 {
+            let _permit = limiter.acquire(Priority::Interactive).await;
+        }
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn concurrency_limit_is_holding_requests_over_the_limit() {
+        let limit = ConcurrencyLimit::new(2);
+        let first = limit.acquire().await;
+        let _second = limit.acquire().await;
+
+        let third = tokio::time::timeout(Duration::from_millis(20), limit.acquire()).await;
+        assert!(third.is_err());
+
+        drop(first);
+        let third = tokio::time::timeout(Duration::from_millis(20), limit.acquire()).await;
+        assert!(third.is_ok());
+        assert_eq!(limit.available(), 0);
+    }
+
+    #[tokio::test]
+    async fn adaptive_limiter_is_backing_off_on_429_and_recovering() {
+        let limiter = RateLimiter::adaptive(8, Duration::ZERO);
+        let permit = limiter.acquire(Priority::Background).await;
+
+        permit.record_status(429);
+        permit.record_status(429);
+        assert_eq!(limiter.concurrency(), 4);
+        assert_eq!(limiter.interval(), BACKOFF_INTERVAL);
+
+        for _ in 0..5 {
+            permit.record_status(200);
+        }
+        assert_eq!(limiter.concurrency(), 5);
+        assert!(limiter.interval() < BACKOFF_INTERVAL);
+
+        let fixed = RateLimiter::new(8, Duration::ZERO);
+        fixed.acquire(Priority::Background).await.record_status(429);
+        assert_eq!(fixed.concurrency(), 8);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    Interactive,
+    Background,
+}
+
+#[derive(Debug)]
+struct State {
+    in_flight: usize,
+    waiting_interactive: usize,
+    next_start: Instant,
+    /// How many requests may run, below `max_concurrent` while backing off;
+    /// fractional so successes can add it back a bit at a time
+    concurrency: f64,
+    interval: Duration,
+    last_backoff: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    max_concurrent: usize,
+    min_interval: Duration,
+    adaptive: bool,
+    state: Mutex<State>,
+    notify: Notify,
+}
+
+/// A cloneable handle to a shared limiter
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    inner: Arc<Inner>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_CONCURRENT, DEFAULT_MIN_INTERVAL)
+    }
+}
+
+impl RateLimiter {
+    /// Allow at most `max_concurrent` requests in flight, starting at most one
+    /// every `min_interval`
+    pub fn new(max_concurrent: usize, min_interval: Duration) -> Self {
+        Self::with_mode(max_concurrent, min_interval, false)
+    }
+
+    /// Like [`RateLimiter::new`], backing off below those limits on 429
+    /// responses and recovering on successful ones
+    pub fn adaptive(max_concurrent: usize, min_interval: Duration) -> Self {
+        Self::with_mode(max_concurrent, min_interval, true)
+    }
+
+    fn with_mode(max_concurrent: usize, min_interval: Duration, adaptive: bool) -> Self {
+        let max_concurrent = max_concurrent.max(1);
+        Self {
+            inner: Arc::new(Inner {
+                max_concurrent,
+                min_interval,
+                adaptive,
+                state: Mutex::new(State {
+                    in_flight: 0,
+                    waiting_interactive: 0,
+                    next_start: Instant::now(),
+                    concurrency: max_concurrent as f64,
+                    interval: min_interval,
+                    last_backoff: None,
+                }),
+                notify: Notify::new(),
+            }),
+        }
+    }
+
+    /// How many requests may be in flight right now
+    pub fn concurrency(&self) -> usize {
+        self.lock().concurrency as usize
+    }
+
+    /// The interval between request starts right now
+    pub fn interval(&self) -> Duration {
+        self.lock().interval
+    }
+
+    /// Wait for a permit in the given lane; the request slot is released when
+    /// the permit is dropped
+    pub async fn acquire(&self, priority: Priority) -> Permit {
+        let interactive = priority == Priority::Interactive;
+        let mut waiting = WaitingInteractive {
+            inner: &self.inner,
+            registered: interactive,
+        };
+        if interactive {
+            self.lock().waiting_interactive += 1;
+        }
+
+        loop {
+            let mut notified = pin!(self.inner.notify.notified());
+            notified.as_mut().enable();
+
+            let retry_at = {
+                let mut state = self.lock();
+                let blocked = state.in_flight >= state.concurrency as usize
+                    || (!interactive && state.waiting_interactive > 0);
+
+                if blocked {
+                    None
+                } else {
+                    let now = Instant::now();
+                    if state.next_start <= now {
+                        state.in_flight += 1;
+                        state.next_start = now + state.interval;
+                        if interactive {
+                            state.waiting_interactive -= 1;
+                            waiting.registered = false;
+                        }
+                        drop(state);
+                        // background waiters may have been held back by this request
+                        self.inner.notify.notify_waiters();
+                        return Permit {
+                            inner: self.inner.clone(),
+                        };
+                    }
+                    Some(state.next_start)
+                }
+            };
+
+            match retry_at {
+                Some(at) => {
+                    tokio::select! {
+                        _ = tokio::time::sleep_until(at) => {}
+                        _ = notified => {}
+                    }
+                }
+                None => notified.await,
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.inner.lock()
+    }
+}
+
+impl Inner {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        // unwrap warranted because the lock is never held across a panic point
+        self.state.lock().unwrap()
+    }
+
+    /// Halve the concurrency and double the interval, once per quiet period
+    fn back_off(&self) {
+        let mut state = self.lock();
+        let now = Instant::now();
+        if state
+            .last_backoff
+            .is_some_and(|at| now < at + BACKOFF_QUIET_PERIOD)
+        {
+            return;
+        }
+        state.last_backoff = Some(now);
+        state.concurrency = (state.concurrency / 2.0).max(1.0);
+        state.interval = (state.interval * 2)
+            .max(BACKOFF_INTERVAL)
+            .min(MAX_BACKOFF_INTERVAL);
+    }
+
+    /// Add back one request of concurrency per round of successes, and a
+    /// twentieth of the extra interval per success
+    fn recover(&self) {
+        let mut state = self.lock();
+        state.concurrency =
+            (state.concurrency + 1.0 / state.concurrency).min(self.max_concurrent as f64);
+        state.interval = (state.interval - state.interval / 20).max(self.min_interval);
+        drop(state);
+        self.notify.notify_waiters();
+    }
+}
+
+/// Keeps the interactive waiter count right when an acquire is cancelled
+struct WaitingInteractive<'a> {
+    inner: &'a Inner,
+    registered: bool,
+}
+
+impl Drop for WaitingInteractive<'_> {
+    fn drop(&mut self) {
+        if self.registered {
+            self.inner.state.lock().unwrap().waiting_interactive -= 1;
+            self.inner.notify.notify_waiters();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Permit {
+    inner: Arc<Inner>,
+}
+
+impl Permit {
+    /// Tell an adaptive limiter how the request went: a 429 makes it back
+    /// off and a 2xx lets it recover
+    pub fn record_status(&self, status: u16) {
+        if !self.inner.adaptive {
+            return;
+        }
+        match status {
+            429 => self.inner.back_off(),
+            200..=299 => self.inner.recover(),
+            _ => {}
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.inner.state.lock().unwrap().in_flight -= 1;
+        self.inner.notify.notify_waiters();
+    }
+}
+
+/// Make `limiter` the process-wide limiter used by every request
+pub fn install(limiter: RateLimiter) {
+    *GLOBAL.write().unwrap() = Some(limiter);
+}
+
+/// Remove the process-wide limiter, letting requests start unpaced
+pub fn uninstall() {
+    *GLOBAL.write().unwrap() = None;
+}
+
+pub fn global() -> Option<RateLimiter> {
+    GLOBAL.read().unwrap().clone()
+}
+
+/// Run `f` with every request it sends queued in the given lane
+pub async fn with_priority<F: Future>(priority: Priority, f: F) -> F::Output {
+    PRIORITY.scope(priority, f).await
+}
+
+/// The lane of the current task, [`Priority::Interactive`] by default
+pub fn current_priority() -> Priority {
+    PRIORITY.try_with(|p| *p).unwrap_or(Priority::Interactive)
+}
+
+/// A cloneable handle to a shared cap on requests in flight
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimit {
+    semaphore: Arc<Semaphore>,
+    limit: usize,
+}
+
+impl ConcurrencyLimit {
+    /// Allow at most `limit` requests in flight through this handle
+    pub fn new(limit: usize) -> Self {
+        let limit = limit.max(1);
+        Self {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            limit,
+        }
+    }
+
+    /// Make a limit of `limit` requests in flight the process-wide one, which
+    /// every request sent by this crate waits on, and return it
+    ///
+    /// Replacing a previous global limit lets requests waiting on it finish
+    /// under the old limit.
+    pub fn global(limit: usize) -> Self {
+        let concurrency = Self::new(limit);
+        *CONCURRENCY.write().unwrap() = Some(concurrency.clone());
+        concurrency
+    }
+
+    /// The process-wide limit, if one was set with [`ConcurrencyLimit::global`]
+    pub fn installed() -> Option<Self> {
+        CONCURRENCY.read().unwrap().clone()
+    }
+
+    /// Remove the process-wide limit, letting any number of requests run
+    pub fn uninstall() {
+        *CONCURRENCY.write().unwrap() = None;
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// How many more requests may start right away
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+
+    /// Wait for a free slot, which is released when the permit is dropped
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        // expect warranted because the semaphore is never closed
+        self.semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("concurrency semaphore closed")
+    }
+}
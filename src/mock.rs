@@ -0,0 +1,568 @@
+//! An offline stand-in for the v1 REST API.
+//!
+//! The mock validates requests the way the API does (JSON schema of
+//! text-to-image bodies, multipart field names, casing and boundaries of the
+//! image-to-image, upscale and masking uploads) and answers valid generations
+//! with fixture artifacts, one per requested sample. Invalid requests get a
+//! `400 bad_request` naming the offending field.
+//!
+//! Use [`transport`] to answer requests in-process, or [`serve`] to expose the
+//! mock over plain HTTP and point clients at it with
+//! [`set_base_url`](crate::api::rest::client::set_base_url).
+
+use crate::prelude::*;
+use crate::testing::{
+    image_response, json_response, parse_multipart, FakeTransport, RecordedRequest,
+};
+use hyper::body::{Bytes, Incoming};
+use hyper::{Method, Request, Response, StatusCode};
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+const SAMPLERS: [&str; 10] = [
+    "DDIM",
+    "DDPM",
+    "K_DPMPP_2M",
+    "K_DPMPP_2S_ANCESTRAL",
+    "K_DPM_2",
+    "K_DPM_2_ANCESTRAL",
+    "K_EULER",
+    "K_EULER_ANCESTRAL",
+    "K_HEUN",
+    "K_LMS",
+];
+const CLIP_GUIDANCE_PRESETS: [&str; 7] = [
+    "FAST_BLUE",
+    "FAST_GREEN",
+    "NONE",
+    "SIMPLE",
+    "SLOW",
+    "SLOWER",
+    "SLOWEST",
+];
+const STYLE_PRESETS: [&str; 17] = [
+    "3d-model",
+    "analog-film",
+    "anime",
+    "cinematic",
+    "comic-book",
+    "digital-art",
+    "enhance",
+    "fantasy-art",
+    "isometric",
+    "line-art",
+    "low-poly",
+    "modeling-compound",
+    "neon-punk",
+    "origami",
+    "photographic",
+    "pixel-art",
+    "tile-texture",
+];
+const INIT_IMAGE_MODES: [&str; 2] = ["IMAGE_STRENGTH", "STEP_SCHEDULE"];
+const MASK_SOURCES: [&str; 3] = ["MASK_IMAGE_BLACK", "MASK_IMAGE_WHITE", "INIT_IMAGE_ALPHA"];
+
+const TEXT_TO_IMAGE_FIELDS: [&str; 11] = [
+    "height",
+    "width",
+    "text_prompts",
+    "cfg_scale",
+    "clip_guidance_preset",
+    "sampler",
+    "samples",
+    "seed",
+    "steps",
+    "style_preset",
+    "extras",
+];
+const IMAGE_TO_IMAGE_FIELDS: [&str; 12] = [
+    "init_image",
+    "init_image_mode",
+    "image_strength",
+    "step_schedule_start",
+    "step_schedule_end",
+    "cfg_scale",
+    "clip_guidance_preset",
+    "sampler",
+    "samples",
+    "seed",
+    "steps",
+    "style_preset",
+];
+const UPSCALE_FIELDS: [&str; 6] = ["image", "height", "width", "cfg_scale", "seed", "steps"];
+const MASKING_FIELDS: [&str; 10] = [
+    "init_image",
+    "mask_source",
+    "mask_image",
+    "cfg_scale",
+    "clip_guidance_preset",
+    "sampler",
+    "samples",
+    "seed",
+    "steps",
+    "style_preset",
+];
+
+#[cfg(all(test, feature = "text-to-image", feature = "upscale", feature = "masking"))]
+mod tests {
+    use super::*;
+    use crate::img_to_img::{ImageMode, ImageToImageBuilder};
+    use crate::masking::{MaskSource, MaskerBuilder};
+    use crate::text_to_img::TextToImageBuilder;
+    use crate::upscale::{UpscaleEngine, UpscalerBuilder};
+    use crate::{ClipGuidancePreset, Sampler, StylePreset};
+    use std::path::PathBuf;
+
+    fn fixture_png(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "stability_rs_mock_{}_{}.png",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, crate::testing::png_1x1()).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn text_to_image_requests_are_accepted() {
+        let image = TextToImageBuilder::new()
+            .samples(3)
+            .unwrap()
+            .sampler(Sampler::KDpmpp2m)
+            .unwrap()
+            .clip_guidance_preset(ClipGuidancePreset::FastBlue)
+            .unwrap()
+            .style_preset(StylePreset::ThreeDModel)
+            .unwrap()
+            .text_prompt("a crab", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let resp = transport()
+            .scope(image.generate("stable-diffusion-xl-1024-v1-0"))
+            .await
+            .unwrap();
+        assert_eq!(resp.artifacts.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn image_to_image_multipart_is_accepted() {
+        let init = fixture_png("init");
+        let image = ImageToImageBuilder::new()
+            .init_image_path(&init)
+            .unwrap()
+            .init_image_mode(ImageMode::ImageStrength)
+            .unwrap()
+            .image_strength(0.35)
+            .unwrap()
+            .sampler(Sampler::KDpm2Ancestral)
+            .unwrap()
+            .clip_guidance_preset(ClipGuidancePreset::FastGreen)
+            .unwrap()
+            .style_preset(StylePreset::FantasyArt)
+            .unwrap()
+            .text_prompt("A crab relaxing on a beach", 0.5)
+            .unwrap()
+            .text_prompt("stones", -0.9)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let resp = transport()
+            .scope(image.generate("stable-diffusion-xl-1024-v1-0"))
+            .await
+            .unwrap();
+        assert_eq!(resp.artifacts.len(), 1);
+        std::fs::remove_file(init).unwrap();
+    }
+
+    #[tokio::test]
+    async fn masking_multipart_is_accepted() {
+        let init = fixture_png("masking_init");
+        let mask = fixture_png("masking_mask");
+        let image = MaskerBuilder::new()
+            .init_image_path(&init)
+            .unwrap()
+            .mask_source(MaskSource::MaskImageBlack)
+            .unwrap()
+            .mask_image(&mask)
+            .unwrap()
+            .sampler(Sampler::KEuler)
+            .unwrap()
+            .style_preset(StylePreset::FantasyArt)
+            .unwrap()
+            .text_prompt("a crab dancing", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        transport()
+            .scope(image.generate("stable-inpainting-512-v2-0"))
+            .await
+            .unwrap();
+        std::fs::remove_file(init).unwrap();
+        std::fs::remove_file(mask).unwrap();
+    }
+
+    #[tokio::test]
+    async fn upscale_multipart_is_accepted() {
+        let init = fixture_png("upscale");
+        let image = UpscalerBuilder::new()
+            .image(&init)
+            .unwrap()
+            .width(2048)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        transport()
+            .scope(image.generate(UpscaleEngine::EsrganV1X2Plus))
+            .await
+            .unwrap();
+        std::fs::remove_file(init).unwrap();
+    }
+
+    #[test]
+    fn multipart_without_closing_boundary_is_rejected() {
+        let body = b"--b\r\nContent-Disposition: form-data; name=\"seed\"\r\n\r\n1\r\n";
+        assert_eq!(
+            parse_multipart(body, "b").unwrap_err(),
+            "multipart body is missing its closing boundary"
+        );
+    }
+
+    #[test]
+    fn out_of_range_prompt_index_is_rejected() {
+        let body = "--b\r\nContent-Disposition: form-data; name=\"text_prompts[18446744073709551615][text]\"\r\n\r\na crab\r\n--b--\r\n";
+        let resp = handle(&RecordedRequest {
+            method: Method::POST,
+            uri: "/v1/generation/engine/image-to-image".parse().unwrap(),
+            headers: [(
+                hyper::header::CONTENT_TYPE,
+                "multipart/form-data; boundary=b".parse().unwrap(),
+            )]
+            .into_iter()
+            .collect(),
+            body: Bytes::from(body),
+        });
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert!(String::from_utf8_lossy(resp.body()).contains("prompt index out of range"));
+    }
+
+    #[test]
+    fn lowercase_sampler_is_rejected() {
+        let mut fields = Map::new();
+        fields.insert("sampler".to_string(), json!("k_euler"));
+        assert_eq!(
+            check_enum(&fields, "sampler", &SAMPLERS).unwrap_err(),
+            "sampler: unknown value \"k_euler\""
+        );
+    }
+}
+
+/// A transport answering every request from the mock
+pub fn transport() -> Arc<FakeTransport> {
+    FakeTransport::new(handle)
+}
+
+/// Answer requests from the mock over plain HTTP until the future is dropped
+pub async fn serve(listener: TcpListener) -> Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::task::spawn(async move {
+            let service = hyper::service::service_fn(|req: Request<Incoming>| async move {
+                use http_body_util::{BodyExt, Full};
+                let (parts, body) = req.into_parts();
+                let body = body.collect().await?.to_bytes();
+                let resp = handle(&RecordedRequest {
+                    method: parts.method,
+                    uri: parts.uri,
+                    headers: parts.headers,
+                    body,
+                });
+                Ok::<_, hyper::Error>(resp.map(Full::new))
+            });
+            if let Err(e) = hyper::server::conn::http1::Builder::new()
+                .serve_connection(crate::support::TokioIo::new(stream), service)
+                .await
+            {
+                tracing::warn!("mock connection error: {}", e);
+            }
+        });
+    }
+}
+
+/// Answer a single request
+pub fn handle(req: &RecordedRequest) -> Response<Bytes> {
+    match route(req) {
+        Ok(body) => json_response(StatusCode::OK, Bytes::from(body.to_string())),
+        Err((status, message)) => json_response(
+            status,
+            Bytes::from(
+                json!({
+                    "id": "mock",
+                    "name": if status == StatusCode::NOT_FOUND { "not_found" } else { "bad_request" },
+                    "message": message,
+                })
+                .to_string(),
+            ),
+        ),
+    }
+}
+
+type RouteResult = std::result::Result<Value, (StatusCode, String)>;
+
+fn route(req: &RecordedRequest) -> RouteResult {
+    let path = req.uri.path();
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+
+    match (&req.method, segments.as_slice()) {
+        (&Method::GET, ["v1", "engines", "list"]) => Ok(json!([{
+            "description": "Stability-AI Stable Diffusion XL v1.0",
+            "id": "stable-diffusion-xl-1024-v1-0",
+            "name": "Stable Diffusion XL v1.0",
+            "type": "PICTURE",
+        }])),
+        (&Method::GET, ["v1", "user", "account"]) => Ok(json!({
+            "email": "mock@example.com",
+            "id": "user-mock",
+            "organizations": [{ "id": "org-mock", "is_default": true, "name": "mock", "role": "OWNER" }],
+            "profile_picture": "",
+        })),
+        (&Method::GET, ["v1", "user", "balance"]) => Ok(json!({ "credits": 100.0 })),
+        (&Method::POST, ["v1", "generation", _, "text-to-image"]) => {
+            text_to_image(req).map_err(bad_request)
+        }
+        (&Method::POST, ["v1", "generation", _, "image-to-image"]) => {
+            multipart(req, &IMAGE_TO_IMAGE_FIELDS, image_to_image).map_err(bad_request)
+        }
+        (&Method::POST, ["v1", "generation", _, "image-to-image", "upscale"]) => {
+            multipart(req, &UPSCALE_FIELDS, upscale).map_err(bad_request)
+        }
+        (&Method::POST, ["v1", "generation", _, "image-to-image", "masking"]) => {
+            multipart(req, &MASKING_FIELDS, masking).map_err(bad_request)
+        }
+        _ => Err((
+            StatusCode::NOT_FOUND,
+            format!("no route for {} {}", req.method, path),
+        )),
+    }
+}
+
+fn bad_request(message: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, message)
+}
+
+fn artifacts(samples: u64) -> Value {
+    let seeds: Vec<u32> = (1..=samples.max(1) as u32).collect();
+    // unwrap warranted because ImageResponse always serializes
+    serde_json::to_value(image_response(&seeds)).unwrap()
+}
+
+fn content_type(req: &RecordedRequest) -> &str {
+    req.headers
+        .get(hyper::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+}
+
+fn text_to_image(req: &RecordedRequest) -> std::result::Result<Value, String> {
+    if content_type(req) != "application/json" {
+        return Err(format!(
+            "expected application/json, got {:?}",
+            content_type(req)
+        ));
+    }
+    let body: Value =
+        serde_json::from_slice(&req.body).map_err(|e| format!("invalid JSON body: {}", e))?;
+    let Value::Object(fields) = body else {
+        return Err("body must be a JSON object".to_string());
+    };
+
+    if let Some(unknown) = fields
+        .keys()
+        .find(|k| !TEXT_TO_IMAGE_FIELDS.contains(&k.as_str()))
+    {
+        return Err(format!("{}: unknown field", unknown));
+    }
+
+    let prompts = fields
+        .get("text_prompts")
+        .and_then(Value::as_array)
+        .filter(|p| !p.is_empty())
+        .ok_or("text_prompts: must be a non-empty array")?;
+    for (i, prompt) in prompts.iter().enumerate() {
+        if !prompt.get("text").is_some_and(Value::is_string) {
+            return Err(format!("text_prompts[{}][text]: must be a string", i));
+        }
+        if !prompt.get("weight").is_some_and(Value::is_number) {
+            return Err(format!("text_prompts[{}][weight]: must be a number", i));
+        }
+    }
+
+    for dimension in ["height", "width"] {
+        if let Some(v) = fields.get(dimension) {
+            let v = v
+                .as_u64()
+                .ok_or(format!("{}: must be an integer", dimension))?;
+            if v < 128 || v % 64 != 0 {
+                return Err(format!(
+                    "{}: must be a multiple of 64 no less than 128",
+                    dimension
+                ));
+            }
+        }
+    }
+
+    check_common(&fields)?;
+    Ok(artifacts(
+        fields.get("samples").and_then(Value::as_u64).unwrap_or(1),
+    ))
+}
+
+fn image_to_image(fields: &Map<String, Value>) -> std::result::Result<(), String> {
+    require_file(fields, "init_image")?;
+    check_enum(fields, "init_image_mode", &INIT_IMAGE_MODES)?;
+    check_range(fields, "image_strength", 0.0, 1.0)?;
+    check_common(fields)
+}
+
+fn upscale(fields: &Map<String, Value>) -> std::result::Result<(), String> {
+    require_file(fields, "image")?;
+    if fields.contains_key("height") && fields.contains_key("width") {
+        return Err("only one of height or width may be set".to_string());
+    }
+    check_range(fields, "height", 512.0, f64::MAX)?;
+    check_range(fields, "width", 512.0, f64::MAX)?;
+    check_common(fields)
+}
+
+fn masking(fields: &Map<String, Value>) -> std::result::Result<(), String> {
+    require_file(fields, "init_image")?;
+    check_enum(fields, "mask_source", &MASK_SOURCES)?;
+    match fields.get("mask_source").and_then(Value::as_str) {
+        None => return Err("mask_source: required".to_string()),
+        Some("INIT_IMAGE_ALPHA") => {}
+        Some(_) => require_file(fields, "mask_image")?,
+    }
+    check_common(fields)
+}
+
+/// Rules shared by every generation endpoint
+fn check_common(fields: &Map<String, Value>) -> std::result::Result<(), String> {
+    check_range(fields, "cfg_scale", 0.0, 35.0)?;
+    check_range(fields, "samples", 1.0, 10.0)?;
+    check_range(fields, "steps", 10.0, 150.0)?;
+    check_range(fields, "seed", 0.0, u32::MAX as f64)?;
+    check_enum(fields, "sampler", &SAMPLERS)?;
+    check_enum(fields, "clip_guidance_preset", &CLIP_GUIDANCE_PRESETS)?;
+    check_enum(fields, "style_preset", &STYLE_PRESETS)
+}
+
+fn check_enum(
+    fields: &Map<String, Value>,
+    name: &str,
+    allowed: &[&str],
+) -> std::result::Result<(), String> {
+    match fields.get(name) {
+        None => Ok(()),
+        Some(Value::String(v)) if allowed.contains(&v.as_str()) => Ok(()),
+        Some(Value::String(v)) => Err(format!("{}: unknown value {:?}", name, v)),
+        Some(_) => Err(format!("{}: must be a string", name)),
+    }
+}
+
+fn check_range(
+    fields: &Map<String, Value>,
+    name: &str,
+    min: f64,
+    max: f64,
+) -> std::result::Result<(), String> {
+    let Some(value) = fields.get(name) else {
+        return Ok(());
+    };
+    let number = match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse::<f64>().ok(),
+        _ => None,
+    }
+    .ok_or(format!("{}: must be a number", name))?;
+
+    if number < min || number > max {
+        return Err(format!("{}: {} is out of range", name, number));
+    }
+    Ok(())
+}
+
+fn require_file(fields: &Map<String, Value>, name: &str) -> std::result::Result<(), String> {
+    match fields.get(name) {
+        Some(Value::Object(file)) if file.contains_key("filename") => Ok(()),
+        _ => Err(format!("{}: a file is required", name)),
+    }
+}
+
+/// Validate a multipart upload, folding its text fields into a JSON object
+/// (`text_prompts[i][text]` style names become arrays) before running `check`
+fn multipart(
+    req: &RecordedRequest,
+    allowed: &[&str],
+    check: fn(&Map<String, Value>) -> std::result::Result<(), String>,
+) -> std::result::Result<Value, String> {
+    let boundary = content_type(req)
+        .strip_prefix("multipart/form-data; boundary=")
+        .ok_or_else(|| format!("expected multipart/form-data, got {:?}", content_type(req)))?;
+
+    let mut fields = Map::new();
+    let mut prompts: Vec<Map<String, Value>> = Vec::new();
+
+    let parts = parse_multipart(&req.body, boundary)?;
+    // every prompt takes a part of its own, so a higher index leaves gaps
+    let max_prompts = parts.len();
+    for part in parts {
+        if let Some(rest) = part.name.strip_prefix("text_prompts[") {
+            let (index, key) = rest
+                .strip_suffix(']')
+                .and_then(|r| r.split_once("]["))
+                .and_then(|(i, k)| Some((i.parse::<usize>().ok()?, k)))
+                .ok_or(format!("{}: malformed prompt field", part.name))?;
+            if !matches!(key, "text" | "weight") {
+                return Err(format!("{}: unknown field", part.name));
+            }
+            if index >= max_prompts {
+                return Err(format!("{}: prompt index out of range", part.name));
+            }
+            if prompts.len() <= index {
+                prompts.resize(index + 1, Map::new());
+            }
+            prompts[index].insert(key.to_string(), Value::String(part.text()?));
+            continue;
+        }
+
+        if !allowed.contains(&part.name.as_str()) && !part.name.starts_with("extras") {
+            return Err(format!("{}: unknown field", part.name));
+        }
+
+        let value = match &part.filename {
+            Some(filename) => json!({ "filename": filename, "size": part.data.len() }),
+            None => Value::String(part.text()?),
+        };
+        fields.insert(part.name, value);
+    }
+
+    for (i, prompt) in prompts.iter().enumerate() {
+        if !prompt.contains_key("text") {
+            return Err(format!("text_prompts[{}][text]: required", i));
+        }
+    }
+
+    check(&fields)?;
+    let samples = fields
+        .get("samples")
+        .and_then(Value::as_str)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(1);
+    Ok(artifacts(samples))
+}
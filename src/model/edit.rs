@@ -0,0 +1,426 @@
+//! The requests of the v2beta `stable-image/edit` endpoints.
+//!
+//! Every edit endpoint takes a multipart form of text settings and images,
+//! so each request only describes its form through [`EditRequest`]; sending
+//! it is shared by all of them, see `edit::edit`. Requests with more than a
+//! handful of settings, inpainting and relighting, have builders in their own
+//! modules; the rest are declared here with `edit_request!`, which keeps their
+//! setters, validation and form encoding in line with each other.
+
+use super::*;
+use crate::error::*;
+use crate::prelude::*;
+use crate::redaction::PromptText;
+use crate::validation::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(request: &impl EditRequest) -> Vec<(&'static str, String)> {
+        request
+            .fields()
+            .into_iter()
+            .filter_map(|(name, field)| match field {
+                EditField::Text(text) => Some((name, text)),
+                EditField::Image(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn erase_is_sending_the_image_then_the_settings_set() {
+        let erase = Erase::new("photo.png")
+            .mask("mask.png")
+            .unwrap()
+            .grow_mask(10)
+            .unwrap();
+        let fields = erase.fields();
+
+        assert_eq!(fields[0], ("image", EditField::Image(Path::new("photo.png"))));
+        assert_eq!(fields[1], ("mask", EditField::Image(Path::new("mask.png"))));
+        assert_eq!(
+            texts(&erase),
+            [
+                ("grow_mask", "10".to_string()),
+                ("seed", "0".to_string()),
+                ("output_format", "png".to_string()),
+            ]
+        );
+        assert!(Erase::new("photo.png").grow_mask(101).is_err());
+    }
+
+    #[test]
+    fn outpaint_is_sending_only_the_sides_set() {
+        let outpaint = Outpaint::new("photo.png")
+            .left(200)
+            .unwrap()
+            .up(100)
+            .unwrap();
+        assert_eq!(
+            texts(&outpaint),
+            [
+                ("left", "200".to_string()),
+                ("up", "100".to_string()),
+                ("seed", "0".to_string()),
+                ("output_format", "png".to_string()),
+            ]
+        );
+        assert!(Outpaint::new("photo.png").right(2001).is_err());
+    }
+
+    #[test]
+    fn remove_background_is_sending_only_the_output_format() {
+        let remove = RemoveBackground::new("photo.png").seed(7).unwrap();
+        assert_eq!(texts(&remove), [("output_format", "png".to_string())]);
+    }
+
+    #[test]
+    fn search_and_replace_is_erring_on_an_empty_search_prompt() {
+        let err = SearchAndReplace::new("photo.png", "a red car", "").unwrap_err();
+        assert_eq!(err.to_string(), "a text prompt must not be empty");
+    }
+}
+
+/// The largest number of pixels [`Outpaint`] may extend an image by on one
+/// side
+pub const MAX_OUTPAINT: u32 = 2000;
+
+/// A part of the multipart form of an edit request
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditField<'a> {
+    Text(String),
+    /// An image file, uploaded according to the request's
+    /// [`EditRequest::upload_options`]
+    Image(&'a Path),
+}
+
+/// A request to one of the `stable-image/edit` endpoints
+pub trait EditRequest {
+    /// The last segment of the endpoint path, e.g. `inpaint`
+    fn operation(&self) -> &'static str;
+
+    /// The named parts of the form, in order
+    fn fields(&self) -> Vec<(&'static str, EditField<'_>)>;
+
+    /// Whether the endpoint answers with the id of a result to poll, rather
+    /// than the image
+    fn is_async(&self) -> bool {
+        false
+    }
+
+    /// The organization the request is billed to, if not the default one
+    fn organization(&self) -> Option<&str> {
+        None
+    }
+
+    /// How the images are prepared before upload
+    fn upload_options(&self) -> UploadOptions {
+        UploadOptions::default()
+    }
+}
+
+/// The settings shared by the edit requests built here
+#[derive(Debug, Clone, Default)]
+struct Common {
+    seed: Seed,
+    output_format: OutputFormat,
+    organization: Option<String>,
+    upload: UploadOptions,
+}
+
+impl Common {
+    fn fields(&self) -> [(&'static str, EditField<'static>); 2] {
+        [
+            ("seed", EditField::Text(self.seed.as_u32().to_string())),
+            (
+                "output_format",
+                EditField::Text(self.output_format.to_string()),
+            ),
+        ]
+    }
+}
+
+/// Setters for the settings every edit request built here has
+macro_rules! common_setters {
+    () => {
+        pub fn seed(mut self, seed: impl Into<Seed>) -> Result<Self> {
+            let seed = seed.into();
+            validate_seed(seed)?;
+            self.common.seed = seed;
+            Ok(self)
+        }
+
+        pub fn output_format(mut self, output_format: OutputFormat) -> Result<Self> {
+            self.common.output_format = output_format;
+            Ok(self)
+        }
+
+        /// Bill the request to one of the organizations the API key belongs
+        /// to, instead of the default one
+        pub fn organization(mut self, organization: &str) -> Result<Self> {
+            self.common.organization = Some(organization.to_string());
+            Ok(self)
+        }
+
+        /// Rotate the images upright according to their EXIF orientation
+        /// before upload, which the API ignores; on by default
+        #[cfg(feature = "image")]
+        pub fn exif_orientation(mut self, apply: bool) -> Result<Self> {
+            self.common.upload.exif_orientation = apply;
+            Ok(self)
+        }
+
+        /// Map the images into memory rather than reading them into a buffer
+        /// first, which saves a copy for inputs of hundreds of megabytes; off
+        /// by default
+        ///
+        /// The files must not be changed or truncated until the request is built.
+        #[cfg(feature = "mmap")]
+        pub fn memory_map(mut self, map: bool) -> Result<Self> {
+            self.common.upload.memory_map = map;
+            Ok(self)
+        }
+
+        /// Remove EXIF and XMP metadata, such as GPS positions and camera
+        /// serial numbers, from the images before upload; on by default
+        pub fn strip_metadata(mut self, strip: bool) -> Result<Self> {
+            self.common.upload.strip_metadata = strip;
+            Ok(self)
+        }
+    };
+}
+
+/// Implements the [`EditRequest`] methods backed by [`Common`]
+macro_rules! common_request {
+    () => {
+        fn organization(&self) -> Option<&str> {
+            self.common.organization.as_deref()
+        }
+
+        fn upload_options(&self) -> UploadOptions {
+            self.common.upload
+        }
+    };
+}
+
+/// A setting of an edit request as a part of its form
+trait ToEditField {
+    fn to_edit_field(&self) -> EditField<'_>;
+}
+
+impl ToEditField for PathBuf {
+    fn to_edit_field(&self) -> EditField<'_> {
+        EditField::Image(self)
+    }
+}
+
+macro_rules! text_edit_field {
+    ($($ty:ty),*) => {
+        $(
+            impl ToEditField for $ty {
+                fn to_edit_field(&self) -> EditField<'_> {
+                    EditField::Text(self.to_string())
+                }
+            }
+        )*
+    };
+}
+
+text_edit_field!(u32, f32, String);
+
+/// The text of an `edit_request!` prompt, hidden from `Debug` output while
+/// [`crate::redaction`] is enabled
+#[derive(Clone)]
+struct EditPrompt(String);
+
+impl EditPrompt {
+    fn non_empty(text: &str) -> Option<Self> {
+        Some(Self(text.to_string())).filter(|prompt| !prompt.0.is_empty())
+    }
+}
+
+impl fmt::Debug for EditPrompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        PromptText(&self.0).fmt(f)
+    }
+}
+
+impl ToEditField for EditPrompt {
+    fn to_edit_field(&self) -> EditField<'_> {
+        EditField::Text(self.0.clone())
+    }
+}
+
+/// The type an `edit_request!` setting is held as: `Image` for a path to an
+/// image file, `Prompt` for prompt text, else the type given
+macro_rules! edit_field_type {
+    (Image) => {
+        PathBuf
+    };
+    (Prompt) => {
+        EditPrompt
+    };
+    ($ty:ty) => {
+        $ty
+    };
+}
+
+/// The setter of an `edit_request!` setting, checking the value with
+/// `$validate` if given; an empty prompt leaves the setting unset
+macro_rules! edit_setter {
+    ($(#[$meta:meta])* $field:ident: Image) => {
+        $(#[$meta])*
+        pub fn $field(mut self, $field: impl AsRef<Path>) -> Result<Self> {
+            self.$field = Some($field.as_ref().to_path_buf());
+            Ok(self)
+        }
+    };
+    ($(#[$meta:meta])* $field:ident: Prompt) => {
+        $(#[$meta])*
+        pub fn $field(mut self, $field: &str) -> Result<Self> {
+            self.$field = EditPrompt::non_empty($field);
+            Ok(self)
+        }
+    };
+    ($(#[$meta:meta])* $field:ident: $ty:ty $(=> $validate:expr)?) => {
+        $(#[$meta])*
+        pub fn $field(mut self, $field: $ty) -> Result<Self> {
+            $(($validate)($field)?;)?
+            self.$field = Some($field);
+            Ok(self)
+        }
+    };
+}
+
+/// The constructor of an `edit_request!` request, taking the image and the
+/// prompts it requires, which must not be empty
+macro_rules! edit_constructor {
+    ([$($field:ident),*]) => {
+        pub fn new(image: impl AsRef<Path>) -> Self {
+            Self {
+                image: image.as_ref().to_path_buf(),
+                $($field: None,)*
+                common: Common::default(),
+            }
+        }
+    };
+    ([$($field:ident),*] $($required:ident),+) => {
+        pub fn new(image: impl AsRef<Path>, $($required: &str),+) -> Result<Self> {
+            Ok(Self {
+                image: image.as_ref().to_path_buf(),
+                $(
+                    $required: EditPrompt::non_empty($required)
+                        .ok_or(ImageBuilderError::TextPromptEmpty)?,
+                )+
+                $($field: None,)*
+                common: Common::default(),
+            })
+        }
+    };
+}
+
+/// Declares an edit request taking an image and optional settings: the
+/// struct, its constructor, a setter per setting along with the
+/// `common_setters!`, and its [`EditRequest`] implementation
+///
+/// The form holds the image, then the prompts the constructor requires,
+/// listed in parentheses after the operation, then each setting that was set
+/// under its own name, then the [`Common`] settings, or only those listed in
+/// brackets. A setting is either `Image`, a path to an image file, `Prompt`,
+/// prompt text, or a type sent as its `Display` form, optionally followed by
+/// `=> validate` to check values before they are set.
+macro_rules! edit_request {
+    (
+        $(#[$meta:meta])*
+        $name:ident => $operation:literal $(($($required:ident),+))? $([$($common:ident),*])? {
+            $(
+                $(#[$field_meta:meta])*
+                $field:ident: $kind:tt $(=> $validate:expr)?
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone)]
+        pub struct $name {
+            image: PathBuf,
+            $($($required: EditPrompt,)+)?
+            $($field: Option<edit_field_type!($kind)>,)*
+            common: Common,
+        }
+
+        impl $name {
+            edit_constructor!([$($field),*] $($($required),+)?);
+
+            $(
+                edit_setter!($(#[$field_meta])* $field: $kind $(=> $validate)?);
+            )*
+
+            common_setters!();
+        }
+
+        impl EditRequest for $name {
+            fn operation(&self) -> &'static str {
+                $operation
+            }
+
+            fn fields(&self) -> Vec<(&'static str, EditField<'_>)> {
+                let mut fields = vec![("image", EditField::Image(&self.image))];
+                $($(
+                    fields.push((stringify!($required), self.$required.to_edit_field()));
+                )+)?
+                $(
+                    if let Some($field) = &self.$field {
+                        fields.push((stringify!($field), $field.to_edit_field()));
+                    }
+                )*
+                let only: &[&str] = &[$($(stringify!($common)),*)?];
+                let common = self.common.fields().into_iter();
+                fields.extend(common.filter(|(name, _)| only.is_empty() || only.contains(name)));
+                fields
+            }
+
+            common_request!();
+        }
+    };
+}
+
+edit_request! {
+    /// Remove the masked part of an image, filling it in with its surroundings
+    Erase => "erase" {
+        /// White pixels of the mask are erased; without a mask, the
+        /// transparent part of the image is
+        mask: Image,
+        grow_mask: u32 => validate_grow_mask,
+    }
+}
+
+edit_request! {
+    /// Extend an image beyond its borders
+    Outpaint => "outpaint" {
+        /// Pixels to add on the left, up to [`MAX_OUTPAINT`]
+        left: u32 => |pixels| validate_outpaint("left", pixels),
+        right: u32 => |pixels| validate_outpaint("right", pixels),
+        up: u32 => |pixels| validate_outpaint("up", pixels),
+        down: u32 => |pixels| validate_outpaint("down", pixels),
+        /// Describe what the new parts of the image show
+        prompt: Prompt,
+        /// How freely the new parts are invented, from 0 to 1
+        creativity: f32 => |creativity| validate_fraction("creativity", creativity),
+    }
+}
+
+edit_request! {
+    /// Replace what a search prompt finds in an image, without drawing a
+    /// mask: what `search_prompt` describes with what `prompt` does
+    SearchAndReplace => "search-and-replace" (prompt, search_prompt) {
+        negative_prompt: Prompt,
+        grow_mask: u32 => validate_grow_mask,
+    }
+}
+
+edit_request! {
+    /// Cut the subject of an image out onto a transparent background
+    // the only common setting which applies here is the format
+    RemoveBackground => "remove-background" [output_format] {}
+}
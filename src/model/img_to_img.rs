@@ -0,0 +1,337 @@
+use super::*;
+use crate::error::*;
+use crate::prelude::*;
+use crate::form::{generation_fields, FieldKind, FieldSchema, RequestSchema};
+use crate::validation::*;
+use std::collections::HashMap;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_builder_is_erring_when_init_image_path_is_not_set() {
+        let image = ImageToImageBuilder::new().build().unwrap_err();
+        assert_eq!(
+            image.to_string(),
+            "init image path must be set"
+        );
+
+    }
+
+    #[test]
+    fn to_builder_is_keeping_the_init_image_and_upload_options() {
+        let image = ImageToImageBuilder::new()
+            .init_image_path("init.png")
+            .unwrap()
+            .style_preset(StylePreset::Anime)
+            .unwrap()
+            .text_prompt("a crab", 1.0)
+            .unwrap()
+            .strip_metadata(false)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let tweaked = image.to_builder().cfg_scale(20).unwrap().build().unwrap();
+
+        assert_eq!(tweaked.init_image, Path::new("init.png"));
+        assert_eq!(tweaked.cfg_scale, 20);
+        assert_eq!(tweaked.upload_options(), image.upload_options());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn init_image_path_is_accepting_non_utf8_paths() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = std::ffi::OsStr::from_bytes(b"init_\xff.png");
+        let image = ImageToImageBuilder::new()
+            .init_image_path(path)
+            .unwrap()
+            .style_preset(StylePreset::Anime)
+            .unwrap()
+            .text_prompt("a crab", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(image.init_image.as_os_str(), path);
+        let json = serde_json::to_value(&image).unwrap();
+        assert_eq!(json["init_image"], "init_\u{fffd}.png");
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageToImage {
+    pub(crate) text_prompts: Vec<TextPrompt>,
+    #[serde(serialize_with = "serialize_path")]
+    pub(crate) init_image: PathBuf,
+    pub(crate) init_image_mode: ImageMode,
+    pub(crate) image_strength: f32,
+    pub(crate) cfg_scale: u32,
+    pub(crate) clip_guidance_preset: ClipGuidancePreset,
+    #[serde(skip_serializing_if = "Sampler::is_none")]
+    pub(crate) sampler: Sampler,
+    pub(crate) samples: u32,
+    pub(crate) seed: Seed,
+    pub(crate) steps: u32,
+    pub(crate) style_preset: StylePreset,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) extras: HashMap<String, String>,
+    #[serde(skip)]
+    pub(crate) organization: Option<String>,
+    #[serde(skip)]
+    pub(crate) upload: UploadOptions,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub enum ImageMode {
+    #[serde(rename = "image_strength")]
+    ImageStrength,
+    #[serde(rename = "step_schedule_*")]
+    StepSchedule,
+}
+
+impl fmt::Display for ImageMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageMode::ImageStrength => write!(f, "image_strength"),
+            ImageMode::StepSchedule => write!(f, "step_schedule"),
+        }
+    }
+}
+
+impl ImageToImage {
+    /// The fields of the request, for generating forms
+    pub fn schema() -> RequestSchema {
+        let mut fields = vec![
+            FieldSchema::new("init_image_path", FieldKind::Image, "The image to start from")
+                .required(),
+            FieldSchema::new(
+                "init_image_mode",
+                FieldKind::choice(&[ImageMode::ImageStrength, ImageMode::StepSchedule]),
+                "How the init image shapes the result",
+            )
+            .default(ImageMode::ImageStrength),
+            FieldSchema::new(
+                "image_strength",
+                FieldKind::Number { min: 0.0, max: 1.0 },
+                "How much the init image shapes the result",
+            )
+            .default(0.0),
+        ];
+        fields.extend(generation_fields());
+        RequestSchema {
+            name: "ImageToImage",
+            fields,
+        }
+    }
+
+    /// The text prompts, split into positive and negative ones
+    pub fn prompt_groups(&self) -> PromptGroups {
+        PromptGroups::from_text_prompts(&self.text_prompts)
+    }
+
+    /// The organization the request is billed to, if not the default one
+    pub fn organization(&self) -> Option<&str> {
+        self.organization.as_deref()
+    }
+
+    /// How the images are prepared before upload
+    pub fn upload_options(&self) -> UploadOptions {
+        self.upload
+    }
+
+    /// A builder holding this request's values, to tweak a field or two and
+    /// build a new request
+    pub fn to_builder(&self) -> ImageToImageBuilder {
+        ImageToImageBuilder {
+            init_image: Some(self.init_image.clone()),
+            init_image_mode: Some(self.init_image_mode.clone()),
+            image_strength: Some(self.image_strength),
+            text_prompts: self.text_prompts.clone(),
+            cfg_scale: Some(self.cfg_scale),
+            clip_guidance_preset: Some(self.clip_guidance_preset.clone()),
+            sampler: Some(self.sampler.clone()),
+            samples: Some(self.samples),
+            seed: Some(self.seed),
+            steps: Some(self.steps),
+            style_preset: Some(self.style_preset.clone()),
+            extras: Some(self.extras.clone()),
+            organization: self.organization.clone(),
+            upload: self.upload,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ImageToImageBuilder {
+    init_image: Option<PathBuf>,
+    init_image_mode: Option<ImageMode>,
+    image_strength: Option<f32>,
+    text_prompts: Vec<TextPrompt>,
+    cfg_scale: Option<u32>,
+    clip_guidance_preset: Option<ClipGuidancePreset>,
+    sampler: Option<Sampler>,
+    samples: Option<u32>,
+    seed: Option<Seed>,
+    steps: Option<u32>,
+    style_preset: Option<StylePreset>,
+    extras: Option<HashMap<String, String>>,
+    organization: Option<String>,
+    upload: UploadOptions,
+}
+
+impl ImageToImageBuilder {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn init_image_path(mut self, init_image_path: impl AsRef<Path>) -> Result<Self> {
+        self.init_image = Some(init_image_path.as_ref().to_path_buf());
+        Ok(self)
+    }
+
+    pub fn init_image_mode(mut self, init_image_mode: ImageMode) -> Result<Self> {
+        self.init_image_mode = Some(init_image_mode);
+        Ok(self)
+    }
+
+    pub fn image_strength(mut self, image_strength: f32) -> Result<Self> {
+        self.image_strength = Some(image_strength);
+        Ok(self)
+    }
+
+    pub fn cfg_scale(mut self, cfg_scale: u32) -> Result<Self> {
+        validate_cfg_scale(cfg_scale)?;
+
+        self.cfg_scale = Some(cfg_scale);
+
+        Ok(self)
+    }
+
+    pub fn clip_guidance_preset(mut self, clip_guidance_preset: ClipGuidancePreset) -> Result<Self> {
+        self.clip_guidance_preset = Some(clip_guidance_preset);
+        Ok(self)
+    }
+
+    pub fn sampler(mut self, sampler: Sampler) -> Result<Self> {
+        self.sampler = Some(sampler);
+        Ok(self)
+    }
+
+    pub fn samples(mut self, samples: u32) -> Result<Self> {
+        validate_samples(samples)?;
+
+        self.samples = Some(samples);
+
+        Ok(self)
+    }
+
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Result<Self> {
+        let seed = seed.into();
+        validate_seed(seed)?;
+
+        self.seed = Some(seed);
+        Ok(self)
+    }
+
+    pub fn steps(mut self, steps: u32) -> Result<Self> {
+        validate_steps(steps)?;
+
+        self.steps = Some(steps);
+
+        Ok(self)
+    }
+
+    pub fn style_preset(mut self, style_preset: StylePreset) -> Result<Self> {
+        self.style_preset = Some(style_preset);
+        Ok(self)
+    }
+
+    pub fn extras(mut self, extras: HashMap<String, String>) -> Result<Self> {
+        self.extras = Some(extras);
+        Ok(self)
+    }
+
+    pub fn text_prompt(mut self, text_prompt: &str, weight: f32) -> Result<Self> {
+        self.text_prompts.push(TextPrompt {
+            text: text_prompt.to_string(),
+            weight,
+        });
+        Ok(self)
+    }
+
+    /// Replace the prompts added so far with a list built elsewhere
+    pub fn text_prompts(mut self, text_prompts: Vec<TextPrompt>) -> Result<Self> {
+        self.text_prompts = text_prompts;
+        Ok(self)
+    }
+
+    /// Bill the request to one of the organizations the API key belongs to,
+    /// instead of the default one
+    pub fn organization(mut self, organization: &str) -> Result<Self> {
+        self.organization = Some(organization.to_string());
+        Ok(self)
+    }
+
+    /// Rotate the init image upright according to its EXIF orientation before
+    /// upload, which the API ignores; on by default
+    #[cfg(feature = "image")]
+    pub fn exif_orientation(mut self, apply: bool) -> Result<Self> {
+        self.upload.exif_orientation = apply;
+        Ok(self)
+    }
+
+    /// Map the init image into memory rather than reading it into a buffer
+    /// first, which saves a copy for inputs of hundreds of megabytes; off
+    /// by default
+    ///
+    /// The files must not be changed or truncated until the request is built.
+    #[cfg(feature = "mmap")]
+    pub fn memory_map(mut self, map: bool) -> Result<Self> {
+        self.upload.memory_map = map;
+        Ok(self)
+    }
+
+    /// Remove EXIF and XMP metadata, such as GPS positions and camera serial
+    /// numbers, from the init image before upload; on by default
+    pub fn strip_metadata(mut self, strip: bool) -> Result<Self> {
+        self.upload.strip_metadata = strip;
+        Ok(self)
+    }
+
+    pub fn build(self) -> Result<ImageToImage> {
+        if self.init_image.is_none() {
+            return Err(Box::new(ImageBuilderError::InitImagePathNotSet));
+        }
+        if self.style_preset.is_none() {
+            return Err(Box::new(ImageBuilderError::StylePresetNotSet));
+        }
+
+        if self.text_prompts.is_empty() || self.text_prompts[0].text.is_empty() {
+            return Err(Box::new(ImageBuilderError::TextPromptEmpty));
+        }
+
+        Ok(ImageToImage {
+            text_prompts: self.text_prompts,
+            init_image: self.init_image.unwrap(),
+            init_image_mode: self.init_image_mode.unwrap_or(ImageMode::ImageStrength),
+            image_strength: self.image_strength.unwrap_or(0.0),
+            cfg_scale: self.cfg_scale.unwrap_or(7),
+            clip_guidance_preset: self
+                .clip_guidance_preset
+                .unwrap_or(ClipGuidancePreset::None),
+            sampler: self.sampler.unwrap_or(Sampler::None),
+            samples: self.samples.unwrap_or(1),
+            seed: self.seed.unwrap_or_default(),
+            steps: self.steps.unwrap_or(50),
+            style_preset: self.style_preset.unwrap(),
+            extras: self.extras.unwrap_or_default(),
+            organization: self.organization,
+            upload: self.upload,
+        })
+    }
+}
@@ -0,0 +1,253 @@
+use super::edit::{EditField, EditRequest};
+use super::*;
+use crate::error::*;
+use crate::prelude::*;
+use crate::redaction::PromptText;
+use crate::validation::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inpaint_builder_is_erring_when_the_prompt_is_not_set() {
+        let err = InpaintBuilder::new()
+            .image("photo.png")
+            .unwrap()
+            .build()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "a text prompt must not be empty");
+    }
+
+    #[test]
+    fn grow_mask_is_bounded() {
+        let err = InpaintBuilder::new().grow_mask(101).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "grow_mask must be no greater than 100, but was 101"
+        );
+    }
+}
+
+/// A request to the v2beta inpainting endpoint, which fills the masked part
+/// of an image according to a prompt
+#[derive(Clone, Serialize)]
+pub struct Inpaint {
+    #[serde(serialize_with = "serialize_path")]
+    pub(crate) image: PathBuf,
+    /// Without a mask, the transparent part of the image is filled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) mask: Option<PathBuf>,
+    pub(crate) prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) negative_prompt: Option<String>,
+    pub(crate) grow_mask: u32,
+    pub(crate) seed: Seed,
+    pub(crate) output_format: OutputFormat,
+    #[serde(skip)]
+    pub(crate) organization: Option<String>,
+    #[serde(skip)]
+    pub(crate) upload: UploadOptions,
+}
+
+impl fmt::Debug for Inpaint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Inpaint")
+            .field("image", &self.image)
+            .field("mask", &self.mask)
+            .field("prompt", &PromptText(&self.prompt))
+            .field(
+                "negative_prompt",
+                &self.negative_prompt.as_deref().map(PromptText),
+            )
+            .field("grow_mask", &self.grow_mask)
+            .field("seed", &self.seed)
+            .field("output_format", &self.output_format)
+            .field("organization", &self.organization)
+            .field("upload", &self.upload)
+            .finish()
+    }
+}
+
+impl Inpaint {
+    pub fn builder() -> InpaintBuilder {
+        InpaintBuilder::new()
+    }
+
+    /// The organization the request is billed to, if not the default one
+    pub fn organization(&self) -> Option<&str> {
+        self.organization.as_deref()
+    }
+
+    /// How the images are prepared before upload
+    pub fn upload_options(&self) -> UploadOptions {
+        self.upload
+    }
+
+    /// A builder holding this request's values, to tweak a field or two and
+    /// build a new request
+    pub fn to_builder(&self) -> InpaintBuilder {
+        InpaintBuilder {
+            image: Some(self.image.clone()),
+            mask: self.mask.clone(),
+            prompt: Some(self.prompt.clone()),
+            negative_prompt: self.negative_prompt.clone(),
+            grow_mask: Some(self.grow_mask),
+            seed: Some(self.seed),
+            output_format: Some(self.output_format),
+            organization: self.organization.clone(),
+            upload: self.upload,
+        }
+    }
+}
+
+impl EditRequest for Inpaint {
+    fn operation(&self) -> &'static str {
+        "inpaint"
+    }
+
+    fn fields(&self) -> Vec<(&'static str, EditField<'_>)> {
+        let mut fields = vec![("prompt", EditField::Text(self.prompt.clone()))];
+        if let Some(negative_prompt) = &self.negative_prompt {
+            fields.push(("negative_prompt", EditField::Text(negative_prompt.clone())));
+        }
+        fields.extend([
+            ("grow_mask", EditField::Text(self.grow_mask.to_string())),
+            ("seed", EditField::Text(self.seed.as_u32().to_string())),
+            (
+                "output_format",
+                EditField::Text(self.output_format.to_string()),
+            ),
+            ("image", EditField::Image(&self.image)),
+        ]);
+        if let Some(mask) = &self.mask {
+            fields.push(("mask", EditField::Image(mask)));
+        }
+        fields
+    }
+
+    fn organization(&self) -> Option<&str> {
+        self.organization.as_deref()
+    }
+
+    fn upload_options(&self) -> UploadOptions {
+        self.upload
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InpaintBuilder {
+    image: Option<PathBuf>,
+    mask: Option<PathBuf>,
+    prompt: Option<String>,
+    negative_prompt: Option<String>,
+    grow_mask: Option<u32>,
+    seed: Option<Seed>,
+    output_format: Option<OutputFormat>,
+    organization: Option<String>,
+    upload: UploadOptions,
+}
+
+impl InpaintBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn image(mut self, image: impl AsRef<Path>) -> Result<Self> {
+        self.image = Some(image.as_ref().to_path_buf());
+        Ok(self)
+    }
+
+    /// White pixels of the mask are filled, black ones kept
+    pub fn mask(mut self, mask: impl AsRef<Path>) -> Result<Self> {
+        self.mask = Some(mask.as_ref().to_path_buf());
+        Ok(self)
+    }
+
+    pub fn prompt(mut self, prompt: &str) -> Result<Self> {
+        self.prompt = Some(prompt.to_string());
+        Ok(self)
+    }
+
+    pub fn negative_prompt(mut self, negative_prompt: &str) -> Result<Self> {
+        self.negative_prompt = Some(negative_prompt.to_string());
+        Ok(self)
+    }
+
+    /// Grow the edges of the mask outward by this many pixels, blurring them
+    /// into the image; 5 by default
+    pub fn grow_mask(mut self, grow_mask: u32) -> Result<Self> {
+        validate_grow_mask(grow_mask)?;
+
+        self.grow_mask = Some(grow_mask);
+
+        Ok(self)
+    }
+
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Result<Self> {
+        let seed = seed.into();
+        validate_seed(seed)?;
+
+        self.seed = Some(seed);
+        Ok(self)
+    }
+
+    pub fn output_format(mut self, output_format: OutputFormat) -> Result<Self> {
+        self.output_format = Some(output_format);
+        Ok(self)
+    }
+
+    /// Bill the request to one of the organizations the API key belongs to,
+    /// instead of the default one
+    pub fn organization(mut self, organization: &str) -> Result<Self> {
+        self.organization = Some(organization.to_string());
+        Ok(self)
+    }
+
+    /// Rotate the image and mask upright according to their EXIF orientation
+    /// before upload, which the API ignores; on by default
+    #[cfg(feature = "image")]
+    pub fn exif_orientation(mut self, apply: bool) -> Result<Self> {
+        self.upload.exif_orientation = apply;
+        Ok(self)
+    }
+
+    /// Map the image and mask into memory rather than reading them into a buffer
+    /// first, which saves a copy for inputs of hundreds of megabytes; off
+    /// by default
+    ///
+    /// The files must not be changed or truncated until the request is built.
+    #[cfg(feature = "mmap")]
+    pub fn memory_map(mut self, map: bool) -> Result<Self> {
+        self.upload.memory_map = map;
+        Ok(self)
+    }
+
+    /// Remove EXIF and XMP metadata, such as GPS positions and camera serial
+    /// numbers, from the image and mask before upload; on by default
+    pub fn strip_metadata(mut self, strip: bool) -> Result<Self> {
+        self.upload.strip_metadata = strip;
+        Ok(self)
+    }
+
+    pub fn build(self) -> Result<Inpaint> {
+        let Some(image) = self.image else {
+            return Err(Box::new(ImageBuilderError::InitImagePathNotSet));
+        };
+        let Some(prompt) = self.prompt.filter(|prompt| !prompt.is_empty()) else {
+            return Err(Box::new(ImageBuilderError::TextPromptEmpty));
+        };
+
+        Ok(Inpaint {
+            image,
+            mask: self.mask,
+            prompt,
+            negative_prompt: self.negative_prompt.filter(|prompt| !prompt.is_empty()),
+            grow_mask: self.grow_mask.unwrap_or(5),
+            seed: self.seed.unwrap_or_default(),
+            output_format: self.output_format.unwrap_or_default(),
+            organization: self.organization,
+            upload: self.upload,
+        })
+    }
+}
@@ -0,0 +1,299 @@
+use super::*;
+use crate::error::*;
+use crate::prelude::*;
+use crate::form::{generation_fields, FieldKind, FieldSchema, RequestSchema};
+use crate::validation::*;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Masker {
+    pub(crate) text_prompts: Vec<TextPrompt>,
+    #[serde(serialize_with = "serialize_path")]
+    pub(crate) init_image: PathBuf,
+    pub(crate) mask_source: MaskSource,
+    /// Empty when the mask comes from the init image's alpha channel
+    #[serde(serialize_with = "serialize_path")]
+    pub(crate) mask_image: PathBuf,
+    pub(crate) cfg_scale: u32,
+    pub(crate) clip_guidance_preset: ClipGuidancePreset,
+    #[serde(skip_serializing_if = "Sampler::is_none")]
+    pub(crate) sampler: Sampler,
+    pub(crate) samples: u32,
+    pub(crate) seed: Seed,
+    pub(crate) steps: u32,
+    pub(crate) style_preset: StylePreset,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) extras: HashMap<String, String>,
+    #[serde(skip)]
+    pub(crate) organization: Option<String>,
+    #[serde(skip)]
+    pub(crate) upload: UploadOptions,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MaskSource {
+    MaskImageBlack,
+    MaskImageWhite,
+    InitImageAlpha,
+}
+
+impl fmt::Display for MaskSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaskSource::MaskImageBlack => write!(f, "mask_image_black"),
+            MaskSource::MaskImageWhite => write!(f, "mask_image_white"),
+            MaskSource::InitImageAlpha => write!(f, "init_image_alpha"),
+        }
+    }
+}
+
+impl Masker {
+    /// The fields of the request, for generating forms
+    pub fn schema() -> RequestSchema {
+        let mut fields = vec![
+            FieldSchema::new("init_image_path", FieldKind::Image, "The image to paint into")
+                .required(),
+            FieldSchema::new(
+                "mask_source",
+                FieldKind::choice(&[
+                    MaskSource::MaskImageBlack,
+                    MaskSource::MaskImageWhite,
+                    MaskSource::InitImageAlpha,
+                ]),
+                "Which pixels are painted over",
+            )
+            .required(),
+            FieldSchema::new(
+                "mask_image",
+                FieldKind::Image,
+                "The mask, required unless the mask source is the init image's alpha",
+            ),
+        ];
+        fields.extend(generation_fields());
+        RequestSchema {
+            name: "Masker",
+            fields,
+        }
+    }
+
+    /// The text prompts, split into positive and negative ones
+    pub fn prompt_groups(&self) -> PromptGroups {
+        PromptGroups::from_text_prompts(&self.text_prompts)
+    }
+
+    /// The organization the request is billed to, if not the default one
+    pub fn organization(&self) -> Option<&str> {
+        self.organization.as_deref()
+    }
+
+    /// How the images are prepared before upload
+    pub fn upload_options(&self) -> UploadOptions {
+        self.upload
+    }
+
+    /// A builder holding this request's values, to tweak a field or two and
+    /// build a new request
+    pub fn to_builder(&self) -> MaskerBuilder {
+        MaskerBuilder {
+            text_prompts: self.text_prompts.clone(),
+            init_image: Some(self.init_image.clone()),
+            mask_source: Some(self.mask_source.clone()),
+            mask_image: (!self.mask_image.as_os_str().is_empty()).then(|| self.mask_image.clone()),
+            cfg_scale: Some(self.cfg_scale),
+            clip_guidance_preset: Some(self.clip_guidance_preset.clone()),
+            sampler: Some(self.sampler.clone()),
+            samples: Some(self.samples),
+            seed: Some(self.seed),
+            steps: Some(self.steps),
+            style_preset: Some(self.style_preset.clone()),
+            extras: Some(self.extras.clone()),
+            organization: self.organization.clone(),
+            upload: self.upload,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MaskerBuilder {
+    text_prompts: Vec<TextPrompt>,
+    init_image: Option<PathBuf>,
+    mask_source: Option<MaskSource>,
+    mask_image: Option<PathBuf>,
+    cfg_scale: Option<u32>,
+    clip_guidance_preset: Option<ClipGuidancePreset>,
+    sampler: Option<Sampler>,
+    samples: Option<u32>,
+    seed: Option<Seed>,
+    steps: Option<u32>,
+    style_preset: Option<StylePreset>,
+    extras: Option<HashMap<String, String>>,
+    organization: Option<String>,
+    upload: UploadOptions,
+}
+
+
+impl MaskerBuilder {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn init_image_path(mut self, init_image_path: impl AsRef<Path>) -> Result<Self> {
+        self.init_image = Some(init_image_path.as_ref().to_path_buf());
+        Ok(self)
+    }
+
+    pub fn mask_source(mut self, mask_src: MaskSource) -> Result<Self> {
+        self.mask_source = Some(mask_src);
+        Ok(self)
+    }
+
+    pub fn mask_image(mut self, mask_img: impl AsRef<Path>) -> Result<Self> {
+        self.mask_image = Some(mask_img.as_ref().to_path_buf());
+        Ok(self)
+    }
+
+    pub fn cfg_scale(mut self, cfg_scale: u32) -> Result<Self> {
+        validate_cfg_scale(cfg_scale)?;
+
+        self.cfg_scale = Some(cfg_scale);
+
+        Ok(self)
+    }
+
+    pub fn clip_guidance_preset(mut self, clip_guidance_preset: ClipGuidancePreset) -> Result<Self> {
+        self.clip_guidance_preset = Some(clip_guidance_preset);
+        Ok(self)
+    }
+
+    pub fn sampler(mut self, sampler: Sampler) -> Result<Self> {
+        self.sampler = Some(sampler);
+        Ok(self)
+    }
+
+    pub fn samples(mut self, samples: u32) -> Result<Self> {
+        validate_samples(samples)?;
+
+        self.samples = Some(samples);
+
+        Ok(self)
+    }
+
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Result<Self> {
+        let seed = seed.into();
+        validate_seed(seed)?;
+
+        self.seed = Some(seed);
+        Ok(self)
+    }
+
+    pub fn steps(mut self, steps: u32) -> Result<Self> {
+        validate_steps(steps)?;
+
+        self.steps = Some(steps);
+
+        Ok(self)
+    }
+
+    pub fn style_preset(mut self, style_preset: StylePreset) -> Result<Self> {
+        self.style_preset = Some(style_preset);
+        Ok(self)
+    }
+
+    pub fn extras(mut self, extras: HashMap<String, String>) -> Result<Self> {
+        self.extras = Some(extras);
+        Ok(self)
+    }
+
+    pub fn text_prompt(mut self, text_prompt: &str, weight: f32) -> Result<Self> {
+        self.text_prompts.push(TextPrompt {
+            text: text_prompt.to_string(),
+            weight,
+        });
+        Ok(self)
+    }
+
+    /// Replace the prompts added so far with a list built elsewhere
+    pub fn text_prompts(mut self, text_prompts: Vec<TextPrompt>) -> Result<Self> {
+        self.text_prompts = text_prompts;
+        Ok(self)
+    }
+
+    /// Bill the request to one of the organizations the API key belongs to,
+    /// instead of the default one
+    pub fn organization(mut self, organization: &str) -> Result<Self> {
+        self.organization = Some(organization.to_string());
+        Ok(self)
+    }
+
+    /// Rotate the init and mask images upright according to their EXIF orientation before
+    /// upload, which the API ignores; on by default
+    #[cfg(feature = "image")]
+    pub fn exif_orientation(mut self, apply: bool) -> Result<Self> {
+        self.upload.exif_orientation = apply;
+        Ok(self)
+    }
+
+    /// Map the init and mask images into memory rather than reading them into a buffer
+    /// first, which saves a copy for inputs of hundreds of megabytes; off
+    /// by default
+    ///
+    /// The files must not be changed or truncated until the request is built.
+    #[cfg(feature = "mmap")]
+    pub fn memory_map(mut self, map: bool) -> Result<Self> {
+        self.upload.memory_map = map;
+        Ok(self)
+    }
+
+    /// Remove EXIF and XMP metadata, such as GPS positions and camera serial
+    /// numbers, from the init and mask images before upload; on by default
+    pub fn strip_metadata(mut self, strip: bool) -> Result<Self> {
+        self.upload.strip_metadata = strip;
+        Ok(self)
+    }
+
+    pub fn build(self) -> Result<Masker> {
+        if self.init_image.is_none() {
+            return Err(Box::new(ImageBuilderError::InitImagePathNotSet));
+        }
+        if self.style_preset.is_none() {
+            return Err(Box::new(ImageBuilderError::StylePresetNotSet));
+        }
+
+        if self.text_prompts.is_empty() || self.text_prompts[0].text.is_empty() {
+            return Err(Box::new(ImageBuilderError::TextPromptEmpty));
+        }
+
+        if self.mask_source.is_none() {
+            return Err(Box::new(ImageBuilderError::MaskSourceNotSet));
+        }
+
+        if (self.mask_source == Some(MaskSource::MaskImageBlack)
+            || self.mask_source == Some(MaskSource::MaskImageWhite))
+            && self.mask_image.is_none()
+        {
+            return Err(Box::new(ImageBuilderError::MaskImagePathNotSet));
+        }
+
+        Ok(Masker {
+            text_prompts: self.text_prompts,
+            init_image: self.init_image.unwrap(),
+            mask_source: self.mask_source.unwrap(),
+            mask_image: self.mask_image.unwrap_or_default(),
+            cfg_scale: self.cfg_scale.unwrap_or(7),
+            clip_guidance_preset: self
+                .clip_guidance_preset
+                .unwrap_or(ClipGuidancePreset::None),
+            sampler: self.sampler.unwrap_or(Sampler::None),
+            samples: self.samples.unwrap_or(1),
+            seed: self.seed.unwrap_or_default(),
+            steps: self.steps.unwrap_or(50),
+            style_preset: self.style_preset.unwrap(),
+            extras: self.extras.unwrap_or_default(),
+            organization: self.organization,
+            upload: self.upload,
+        })
+    }
+
+}
@@ -0,0 +1,735 @@
+//! The request and response types of the generation endpoints.
+//!
+//! Nothing in this module touches the network, and it is built without the
+//! default `client` feature, so other transports or server-side validators
+//! can build, validate and (de)serialize requests without pulling in tokio,
+//! hyper or a TLS stack. Sending a request is implemented on these types by
+//! the endpoint modules under [`crate::api::rest::generation`].
+//!
+//! The `Debug` output of requests hides prompt text while
+//! [`crate::redaction`] is enabled.
+
+pub mod edit;
+pub mod img_to_img;
+pub mod inpaint;
+pub mod masking;
+pub mod relight;
+pub mod text_to_img;
+pub mod upscale;
+
+use crate::error::ImageBuilderError;
+use crate::prelude::Result;
+use crate::redaction::PromptText;
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Image {
+    pub base64: String,
+    #[serde(rename = "finishReason")]
+    pub finish_reason: String,
+    pub seed: u32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ImageResponse {
+    pub artifacts: Vec<Image>,
+    #[serde(default)]
+    pub metadata: GenerationMetadata,
+}
+
+/// The finish reason of an artifact generated as asked
+const FINISH_SUCCESS: &str = "SUCCESS";
+
+impl ImageResponse {
+    /// The artifacts generated as asked, skipping filtered or failed ones
+    pub fn successful(&self) -> impl Iterator<Item = &Image> {
+        self.filter_by_finish_reason(FINISH_SUCCESS)
+    }
+
+    /// The artifacts which finished with `reason`, such as
+    /// `CONTENT_FILTERED`
+    pub fn filter_by_finish_reason<'a>(
+        &'a self,
+        reason: &'a str,
+    ) -> impl Iterator<Item = &'a Image> {
+        self.artifacts
+            .iter()
+            .filter(move |image| image.finish_reason == reason)
+    }
+
+    /// The successful artifact scoring highest, the first of them on a tie
+    ///
+    /// Scores which can't be compared, such as `NaN`, never win.
+    pub fn best_by<S, F>(&self, mut score: F) -> Option<&Image>
+    where
+        S: PartialOrd,
+        F: FnMut(&Image) -> S,
+    {
+        let mut best: Option<(&Image, S)> = None;
+        for image in self.successful() {
+            let s = score(image);
+            // NaN isn't even comparable with itself
+            let better = match &best {
+                Some((_, b)) => s > *b,
+                None => s.partial_cmp(&s).is_some(),
+            };
+            if better {
+                best = Some((image, s));
+            }
+        }
+        best.map(|(image, _)| image)
+    }
+
+    /// The artifacts generated from one of `seeds`, in the order they were
+    /// returned
+    pub fn take_seeds<'a>(&'a self, seeds: &'a [u32]) -> impl Iterator<Item = &'a Image> {
+        self.artifacts
+            .iter()
+            .filter(move |image| seeds.contains(&image.seed))
+    }
+}
+
+/// How init and mask images are prepared before they are uploaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadOptions {
+    /// Rotate photos upright according to their EXIF orientation
+    #[cfg(feature = "image")]
+    pub exif_orientation: bool,
+    /// Remove EXIF and XMP metadata, such as GPS positions and camera serial
+    /// numbers, from JPEG, PNG and WebP files
+    pub strip_metadata: bool,
+    /// Map input files into memory rather than reading them into a buffer
+    /// first, for inputs of hundreds of megabytes; the files must not change
+    /// while the request is built
+    #[cfg(feature = "mmap")]
+    pub memory_map: bool,
+}
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        Self {
+            #[cfg(feature = "image")]
+            exif_orientation: true,
+            strip_metadata: true,
+            #[cfg(feature = "mmap")]
+            memory_map: false,
+        }
+    }
+}
+
+/// Client-side details about how a response was produced
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct GenerationMetadata {
+    /// The engine which served the request
+    pub engine: Option<String>,
+    /// How long the request took, split into its phases
+    #[serde(default)]
+    pub timing: Option<ResponseTiming>,
+    /// The changes made to the prompts before the attempt which produced
+    /// this response, when earlier attempts were filtered
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prompt_mutations: Vec<PromptMutation>,
+}
+
+/// A change made to the prompts of a request before it is retried
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PromptMutation {
+    /// Remove the positive prompt weighted lowest, the last of them on a
+    /// tie, unless it is the only one
+    DropLowestWeight,
+    /// Add a prompt, such as `safe for work` weighted 1 or `nsfw` weighted
+    /// -1
+    Append { text: String, weight: f32 },
+}
+
+impl PromptMutation {
+    /// Apply the mutation to `prompts`, returning whether it changed them
+    #[cfg(feature = "client")]
+    pub(crate) fn apply(&self, prompts: &mut Vec<TextPrompt>) -> bool {
+        match self {
+            PromptMutation::DropLowestWeight => {
+                let positive = prompts.iter().filter(|p| p.weight >= 0.0).count();
+                let lowest = prompts
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, p)| p.weight >= 0.0)
+                    .min_by(|(_, a), (_, b)| {
+                        // the last of equal weights is dropped
+                        a.weight.total_cmp(&b.weight).then(std::cmp::Ordering::Greater)
+                    })
+                    .map(|(i, _)| i);
+                match lowest {
+                    Some(i) if positive > 1 => {
+                        prompts.remove(i);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            PromptMutation::Append { text, weight } => {
+                prompts.push(TextPrompt {
+                    text: text.clone(),
+                    weight: *weight,
+                });
+                true
+            }
+        }
+    }
+}
+
+/// Where the time of a request went, in milliseconds
+///
+/// A long `ttfb_ms` with a `server_ms` close to it means the model was slow;
+/// a long `connect_ms` or `download_ms`, or a `ttfb_ms` well beyond
+/// `server_ms`, points at the network.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ResponseTiming {
+    /// Resolving the host, connecting and the TLS handshake; `None` when a
+    /// transport answered in place of the network or an idle connection was
+    /// reused
+    pub connect_ms: Option<u64>,
+    /// From sending the request until the response headers arrived
+    pub ttfb_ms: u64,
+    /// Reading the response body
+    pub download_ms: u64,
+    /// The time the server reports having spent, from the `Server-Timing`
+    /// or `x-envoy-upstream-service-time` response header
+    pub server_ms: Option<u64>,
+    /// The time the server reports the request waited in a queue, from a
+    /// `queue` metric of the `Server-Timing` header
+    pub queue_ms: Option<u64>,
+}
+
+/// Paths are serialized lossily, so requests naming a file whose path is not
+/// valid UTF-8 can still be recorded
+pub(crate) fn serialize_path<S: Serializer>(
+    path: &Path,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+/// A prompt and its weight, validated on construction
+///
+/// Negative weights steer the image away from the prompt.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct TextPrompt {
+    pub(crate) text: String,
+    pub(crate) weight: f32,
+}
+
+impl TextPrompt {
+    pub fn new(text: &str, weight: f32) -> Result<Self> {
+        if text.is_empty() {
+            return Err(Box::new(ImageBuilderError::TextPromptEmpty));
+        }
+        if !weight.is_finite() {
+            return Err(Box::new(ImageBuilderError::TextPromptWeightNotFinite(
+                weight,
+            )));
+        }
+
+        Ok(Self {
+            text: text.to_string(),
+            weight,
+        })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+}
+
+impl fmt::Debug for TextPrompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TextPrompt")
+            .field("text", &PromptText(&self.text))
+            .field("weight", &self.weight)
+            .finish()
+    }
+}
+
+/// A prompt and its weight, as sent to the API
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct WeightedPrompt {
+    pub text: String,
+    pub weight: f32,
+}
+
+impl fmt::Debug for WeightedPrompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WeightedPrompt")
+            .field("text", &PromptText(&self.text))
+            .field("weight", &self.weight)
+            .finish()
+    }
+}
+
+/// The prompts of a request, split by the sign of their weight
+///
+/// Each group keeps the order the prompts were added in, so a request can be
+/// rebuilt by adding the positive prompts followed by the negative ones.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct PromptGroups {
+    pub positive: Vec<WeightedPrompt>,
+    /// Prompts with a negative weight, steering the image away from them
+    pub negative: Vec<WeightedPrompt>,
+}
+
+impl PromptGroups {
+    pub(crate) fn from_text_prompts(prompts: &[TextPrompt]) -> Self {
+        let mut groups = Self::default();
+        for prompt in prompts {
+            let weighted = WeightedPrompt {
+                text: prompt.text.clone(),
+                weight: prompt.weight,
+            };
+            if prompt.weight < 0.0 {
+                groups.negative.push(weighted);
+            } else {
+                groups.positive.push(weighted);
+            }
+        }
+        groups
+    }
+}
+
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+    pub enum ClipGuidancePreset {
+        FastBlue,
+        FastGreen,
+        Simple,
+        Slow,
+        Slower,
+        Slowest,
+        None,
+    }
+
+impl fmt::Display for ClipGuidancePreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClipGuidancePreset::FastBlue => write!(f, "fast_blue"),
+            ClipGuidancePreset::FastGreen => write!(f, "fast_green"),
+            ClipGuidancePreset::Simple => write!(f, "simple"),
+            ClipGuidancePreset::Slow => write!(f, "slow"),
+            ClipGuidancePreset::Slower => write!(f, "slower"),
+            ClipGuidancePreset::Slowest => write!(f, "slowest"),
+            ClipGuidancePreset::None => write!(f, "none"),
+        }
+    }
+}
+
+    impl ClipGuidancePreset {
+        /// Every preset, in declaration order
+        pub const ALL: [Self; 7] = [
+            ClipGuidancePreset::FastBlue,
+            ClipGuidancePreset::FastGreen,
+            ClipGuidancePreset::Simple,
+            ClipGuidancePreset::Slow,
+            ClipGuidancePreset::Slower,
+            ClipGuidancePreset::Slowest,
+            ClipGuidancePreset::None,
+        ];
+
+        pub fn is_none(&self) -> bool {
+            matches!(self, ClipGuidancePreset::None)
+        }
+
+        /// The value the API expects, e.g. `FAST_BLUE`
+        pub fn api_name(&self) -> String {
+            self.to_string().to_ascii_uppercase()
+        }
+
+}
+
+    #[derive(Debug, Clone, Deserialize, Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    pub enum StylePreset {
+        #[serde(rename = "3d-model")]
+        ThreeDModel,
+        Anime,
+        AnalogFilm,
+        Cinematic,
+        ComicBook,
+        DigitalArt,
+        Enhance,
+        FantasyArt,
+        Isometric,
+        LineArt,
+        LowPoly,
+        ModelingCompound,
+        NeonPunk,
+        Origami,
+        Photographic,
+        PixelArt,
+        TileTexture,
+    }
+
+impl fmt::Display for StylePreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StylePreset::ThreeDModel => write!(f, "3d-model"),
+            StylePreset::Anime => write!(f, "anime"),
+            StylePreset::AnalogFilm => write!(f, "analog-film"),
+            StylePreset::Cinematic => write!(f, "cinematic"),
+            StylePreset::ComicBook => write!(f, "comic-book"),
+            StylePreset::DigitalArt => write!(f, "digital-art"),
+            StylePreset::Enhance => write!(f, "enhance"),
+            StylePreset::FantasyArt => write!(f, "fantasy-art"),
+            StylePreset::Isometric => write!(f, "isometric"),
+            StylePreset::LineArt => write!(f, "line-art"),
+            StylePreset::LowPoly => write!(f, "low-poly"),
+            StylePreset::ModelingCompound => write!(f, "modeling-compound"),
+            StylePreset::NeonPunk => write!(f, "neon-punk"),
+            StylePreset::Origami => write!(f, "origami"),
+            StylePreset::Photographic => write!(f, "photographic"),
+            StylePreset::PixelArt => write!(f, "pixel-art"),
+            StylePreset::TileTexture => write!(f, "tile-texture"),
+        }
+    }
+
+}
+
+impl StylePreset {
+    /// Every preset, in declaration order
+    pub const ALL: [Self; 17] = [
+        StylePreset::ThreeDModel,
+        StylePreset::Anime,
+        StylePreset::AnalogFilm,
+        StylePreset::Cinematic,
+        StylePreset::ComicBook,
+        StylePreset::DigitalArt,
+        StylePreset::Enhance,
+        StylePreset::FantasyArt,
+        StylePreset::Isometric,
+        StylePreset::LineArt,
+        StylePreset::LowPoly,
+        StylePreset::ModelingCompound,
+        StylePreset::NeonPunk,
+        StylePreset::Origami,
+        StylePreset::Photographic,
+        StylePreset::PixelArt,
+        StylePreset::TileTexture,
+    ];
+}
+
+/// The seed of a generation
+///
+/// The API reads a seed of 0 as "pick one at random", so a fixed seed of 0
+/// could never be pinned; builders reject `Seed::Fixed(0)` and requests use
+/// [`Seed::Random`] unless told otherwise. The seed actually used comes back
+/// on each [`Image`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Seed {
+    #[default]
+    Random,
+    Fixed(u32),
+}
+
+impl Seed {
+    /// The value sent to the API, 0 for a random seed
+    pub fn as_u32(self) -> u32 {
+        match self {
+            Seed::Random => 0,
+            Seed::Fixed(seed) => seed,
+        }
+    }
+
+    pub fn is_random(self) -> bool {
+        self == Seed::Random
+    }
+}
+
+impl From<u32> for Seed {
+    fn from(seed: u32) -> Self {
+        Seed::Fixed(seed)
+    }
+}
+
+impl fmt::Display for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Seed::Random => f.write_str("random"),
+            Seed::Fixed(seed) => write!(f, "{}", seed),
+        }
+    }
+}
+
+impl Serialize for Seed {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.as_u32())
+    }
+}
+
+impl<'de> Deserialize<'de> for Seed {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Ok(match u32::deserialize(deserializer)? {
+            0 => Seed::Random,
+            seed => Seed::Fixed(seed),
+        })
+    }
+}
+
+/// The modality of an artifact, going by its content type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtifactKind {
+    Image,
+    Video,
+    Model3D,
+    Audio,
+}
+
+impl ArtifactKind {
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        match mime.split_once('/')?.0 {
+            "image" => Some(ArtifactKind::Image),
+            "video" => Some(ArtifactKind::Video),
+            "model" => Some(ArtifactKind::Model3D),
+            "audio" => Some(ArtifactKind::Audio),
+            _ => None,
+        }
+    }
+}
+
+/// The file format the v2beta endpoints answer with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    Jpeg,
+    Webp,
+}
+
+impl OutputFormat {
+    /// The format of a response's `content-type`, ignoring parameters
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type.split(';').next()?.trim() {
+            "image/png" => Some(OutputFormat::Png),
+            "image/jpeg" => Some(OutputFormat::Jpeg),
+            "image/webp" => Some(OutputFormat::Webp),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::Png => write!(f, "png"),
+            OutputFormat::Jpeg => write!(f, "jpeg"),
+            OutputFormat::Webp => write!(f, "webp"),
+        }
+    }
+}
+
+    #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+    #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+    // Todo: Add more samplers K_DPMPP_SDE?
+    pub enum Sampler {
+        Ddim,
+        Ddpm,
+        #[serde(rename = "K_DPMPP_2M")]
+        KDpmpp2m,
+        #[serde(rename = "K_DPMPP_2S_ANCESTRAL")]
+        KDpmpp2sAncestral,
+        #[serde(rename = "K_DPM_2")]
+        KDpm2,
+        #[serde(rename = "K_DPM_2_ANCESTRAL")]
+        KDpm2Ancestral,
+        #[serde(rename = "K_EULER")]
+        KEuler,
+        #[serde(rename = "K_EULER_ANCESTRAL")]
+        KEAncestral,
+        #[serde(rename = "K_HEUN")]
+        KHeun,
+        #[serde(rename = "K_LMS")]
+        KLms,
+        None,
+    }
+
+impl fmt::Display for Sampler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sampler::Ddim => write!(f, "ddim"),
+            Sampler::Ddpm => write!(f, "ddpm"),
+            Sampler::KDpmpp2m => write!(f, "k_dpmpp_2m"),
+            Sampler::KDpmpp2sAncestral => write!(f, "k_dpmpp_2s_ancestral"),
+            Sampler::KDpm2 => write!(f, "k_dpm_2"),
+            Sampler::KDpm2Ancestral => write!(f, "k_dpm_2_ancestral"),
+            Sampler::KEuler => write!(f, "k_euler"),
+            Sampler::KEAncestral => write!(f, "k_euler_ancestral"),
+            Sampler::KHeun => write!(f, "k_heun"),
+            Sampler::KLms => write!(f, "k_lms"),
+            Sampler::None => write!(f, "none"),
+        }
+    }
+}
+
+    impl Sampler {
+        /// Every sampler the API names, leaving out [`Sampler::None`]
+        pub const ALL: [Self; 10] = [
+            Sampler::Ddim,
+            Sampler::Ddpm,
+            Sampler::KDpmpp2m,
+            Sampler::KDpmpp2sAncestral,
+            Sampler::KDpm2,
+            Sampler::KDpm2Ancestral,
+            Sampler::KEuler,
+            Sampler::KEAncestral,
+            Sampler::KHeun,
+            Sampler::KLms,
+        ];
+
+        pub fn is_none(&self) -> bool {
+            matches!(self, Sampler::None)
+        }
+
+        /// The value the API expects, e.g. `K_DPMPP_2M`
+        pub fn api_name(&self) -> String {
+            self.to_string().to_ascii_uppercase()
+        }
+
+        /// The most steps [`Sampler::recommended_for`] still considers few
+        pub const FEW_STEPS: u32 = 25;
+
+        /// The commonly recommended sampler for `steps` steps on `engine`,
+        /// for offering an automatic choice
+        ///
+        /// Ancestral samplers settle on a good image in few steps, so they are
+        /// picked up to [`Sampler::FEW_STEPS`], Euler ancestral on SDXL
+        /// engines and DPM++ 2S ancestral on the others. Past that, DPM++ 2M
+        /// gives the most detail for the steps spent.
+        pub fn recommended_for(engine: &str, steps: u32) -> Self {
+            if steps > Self::FEW_STEPS {
+                Sampler::KDpmpp2m
+            } else if engine.to_lowercase().contains("xl") {
+                Sampler::KEAncestral
+            } else {
+                Sampler::KDpmpp2sAncestral
+            }
+        }
+    }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serde and Display must agree, as JSON bodies use the former and
+    /// multipart bodies the latter
+    fn assert_round_trip<T>(values: &[T], api_name: impl Fn(&T) -> String)
+    where
+        T: Serialize + serde::de::DeserializeOwned + fmt::Display,
+    {
+        for value in values {
+            let json = serde_json::to_value(value).unwrap();
+            assert_eq!(json, api_name(value), "{}", value);
+            let back: T = serde_json::from_value(json).unwrap();
+            assert_eq!(back.to_string(), value.to_string());
+        }
+    }
+
+    #[test]
+    fn sampler_serde_names_are_the_api_names() {
+        use Sampler::*;
+        assert_round_trip(
+            &[
+                Ddim, Ddpm, KDpmpp2m, KDpmpp2sAncestral, KDpm2, KDpm2Ancestral, KEuler,
+                KEAncestral, KHeun, KLms, None,
+            ],
+            Sampler::api_name,
+        );
+    }
+
+    #[test]
+    fn recommended_for_is_picking_ancestral_samplers_for_few_steps() {
+        assert_eq!(
+            Sampler::recommended_for("stable-diffusion-xl-1024-v1-0", 20),
+            Sampler::KEAncestral
+        );
+        assert_eq!(
+            Sampler::recommended_for("stable-diffusion-v1-6", 20),
+            Sampler::KDpmpp2sAncestral
+        );
+        assert_eq!(
+            Sampler::recommended_for("stable-diffusion-xl-1024-v1-0", 50),
+            Sampler::KDpmpp2m
+        );
+    }
+
+    #[test]
+    fn clip_guidance_preset_serde_names_are_the_api_names() {
+        use ClipGuidancePreset::*;
+        assert_round_trip(
+            &[FastBlue, FastGreen, Simple, Slow, Slower, Slowest, None],
+            ClipGuidancePreset::api_name,
+        );
+    }
+
+    #[test]
+    fn style_preset_serde_names_are_the_api_names() {
+        use StylePreset::*;
+        assert_round_trip(
+            &[
+                ThreeDModel, Anime, AnalogFilm, Cinematic, ComicBook, DigitalArt, Enhance, FantasyArt,
+                Isometric, LineArt, LowPoly, ModelingCompound, NeonPunk, Origami, Photographic,
+                PixelArt, TileTexture,
+            ],
+            StylePreset::to_string,
+        );
+    }
+
+    fn response(artifacts: &[(u32, &str)]) -> ImageResponse {
+        ImageResponse {
+            artifacts: artifacts
+                .iter()
+                .map(|(seed, finish_reason)| Image {
+                    base64: String::new(),
+                    finish_reason: finish_reason.to_string(),
+                    seed: *seed,
+                })
+                .collect(),
+            metadata: GenerationMetadata::default(),
+        }
+    }
+
+    #[test]
+    fn successful_is_skipping_filtered_artifacts() {
+        let resp = response(&[(1, "SUCCESS"), (2, "CONTENT_FILTERED"), (3, "SUCCESS")]);
+        let seeds = |images: Vec<&Image>| images.iter().map(|i| i.seed).collect::<Vec<_>>();
+
+        assert_eq!(seeds(resp.successful().collect()), [1, 3]);
+        assert_eq!(seeds(resp.filter_by_finish_reason("CONTENT_FILTERED").collect()), [2]);
+        assert_eq!(seeds(resp.take_seeds(&[3, 2]).collect()), [2, 3]);
+    }
+
+    #[test]
+    fn best_by_is_picking_the_first_highest_successful_score() {
+        let resp = response(&[
+            (1, "SUCCESS"),
+            (2, "CONTENT_FILTERED"),
+            (3, "SUCCESS"),
+            (4, "SUCCESS"),
+        ]);
+        let score = |image: &Image| match image.seed {
+            1 => f32::NAN,
+            2 => 9.0,
+            _ => 1.0,
+        };
+
+        assert_eq!(resp.best_by(score).map(|i| i.seed), Some(3));
+        assert!(response(&[]).best_by(|i| i.seed).is_none());
+    }
+}
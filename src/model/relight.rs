@@ -0,0 +1,389 @@
+use super::edit::{EditField, EditRequest};
+use super::*;
+use crate::error::*;
+use crate::prelude::*;
+use crate::redaction::PromptText;
+use crate::validation::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relight_builder_is_erring_without_a_background() {
+        let err = RelightBuilder::new()
+            .subject_image("product.png")
+            .unwrap()
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "a background prompt or background reference image must be set"
+        );
+    }
+
+    #[test]
+    fn light_source_strength_is_bounded() {
+        let err = RelightBuilder::new()
+            .light_source_strength(1.5)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "light_source_strength must be between 0 and 1, but was 1.5"
+        );
+    }
+}
+
+/// Where the light of a relit image falls from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LightDirection {
+    Left,
+    Right,
+    Above,
+    Below,
+}
+
+impl fmt::Display for LightDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LightDirection::Left => write!(f, "left"),
+            LightDirection::Right => write!(f, "right"),
+            LightDirection::Above => write!(f, "above"),
+            LightDirection::Below => write!(f, "below"),
+        }
+    }
+}
+
+/// A request to the v2beta replace-background-and-relight endpoint, which
+/// places the subject of an image on a new background and relights it to
+/// match
+#[derive(Clone, Serialize)]
+pub struct Relight {
+    #[serde(serialize_with = "serialize_path")]
+    pub(crate) subject_image: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) background_prompt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) background_reference: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) foreground_prompt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) negative_prompt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) preserve_original_subject: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) original_background_depth: Option<f32>,
+    pub(crate) keep_original_background: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) light_source_direction: Option<LightDirection>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) light_reference: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) light_source_strength: Option<f32>,
+    pub(crate) seed: Seed,
+    pub(crate) output_format: OutputFormat,
+    #[serde(skip)]
+    pub(crate) organization: Option<String>,
+    #[serde(skip)]
+    pub(crate) upload: UploadOptions,
+}
+
+impl fmt::Debug for Relight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Relight")
+            .field("subject_image", &self.subject_image)
+            .field(
+                "background_prompt",
+                &self.background_prompt.as_deref().map(PromptText),
+            )
+            .field("background_reference", &self.background_reference)
+            .field(
+                "foreground_prompt",
+                &self.foreground_prompt.as_deref().map(PromptText),
+            )
+            .field(
+                "negative_prompt",
+                &self.negative_prompt.as_deref().map(PromptText),
+            )
+            .field("preserve_original_subject", &self.preserve_original_subject)
+            .field("original_background_depth", &self.original_background_depth)
+            .field("keep_original_background", &self.keep_original_background)
+            .field("light_source_direction", &self.light_source_direction)
+            .field("light_reference", &self.light_reference)
+            .field("light_source_strength", &self.light_source_strength)
+            .field("seed", &self.seed)
+            .field("output_format", &self.output_format)
+            .field("organization", &self.organization)
+            .field("upload", &self.upload)
+            .finish()
+    }
+}
+
+impl Relight {
+    pub fn builder() -> RelightBuilder {
+        RelightBuilder::new()
+    }
+
+    /// The organization the request is billed to, if not the default one
+    pub fn organization(&self) -> Option<&str> {
+        self.organization.as_deref()
+    }
+
+    /// How the images are prepared before upload
+    pub fn upload_options(&self) -> UploadOptions {
+        self.upload
+    }
+
+    /// A builder holding this request's values, to tweak a field or two and
+    /// build a new request
+    pub fn to_builder(&self) -> RelightBuilder {
+        RelightBuilder {
+            subject_image: Some(self.subject_image.clone()),
+            background_prompt: self.background_prompt.clone(),
+            background_reference: self.background_reference.clone(),
+            foreground_prompt: self.foreground_prompt.clone(),
+            negative_prompt: self.negative_prompt.clone(),
+            preserve_original_subject: self.preserve_original_subject,
+            original_background_depth: self.original_background_depth,
+            keep_original_background: self.keep_original_background,
+            light_source_direction: self.light_source_direction,
+            light_reference: self.light_reference.clone(),
+            light_source_strength: self.light_source_strength,
+            seed: Some(self.seed),
+            output_format: Some(self.output_format),
+            organization: self.organization.clone(),
+            upload: self.upload,
+        }
+    }
+}
+
+impl EditRequest for Relight {
+    fn operation(&self) -> &'static str {
+        "replace-background-and-relight"
+    }
+
+    fn fields(&self) -> Vec<(&'static str, EditField<'_>)> {
+        let texts = [
+            ("background_prompt", self.background_prompt.clone()),
+            ("foreground_prompt", self.foreground_prompt.clone()),
+            ("negative_prompt", self.negative_prompt.clone()),
+            (
+                "preserve_original_subject",
+                self.preserve_original_subject.map(|v| v.to_string()),
+            ),
+            (
+                "original_background_depth",
+                self.original_background_depth.map(|v| v.to_string()),
+            ),
+            (
+                "keep_original_background",
+                Some(self.keep_original_background.to_string()),
+            ),
+            (
+                "light_source_direction",
+                self.light_source_direction.map(|v| v.to_string()),
+            ),
+            (
+                "light_source_strength",
+                self.light_source_strength.map(|v| v.to_string()),
+            ),
+            ("seed", Some(self.seed.as_u32().to_string())),
+            ("output_format", Some(self.output_format.to_string())),
+        ];
+        let images = [
+            ("subject_image", Some(&self.subject_image)),
+            ("background_reference", self.background_reference.as_ref()),
+            ("light_reference", self.light_reference.as_ref()),
+        ];
+
+        let texts = texts
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, EditField::Text(value?))));
+        let images = images
+            .into_iter()
+            .filter_map(|(name, path)| Some((name, EditField::Image(path?))));
+        texts.chain(images).collect()
+    }
+
+    fn is_async(&self) -> bool {
+        true
+    }
+
+    fn organization(&self) -> Option<&str> {
+        self.organization.as_deref()
+    }
+
+    fn upload_options(&self) -> UploadOptions {
+        self.upload
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RelightBuilder {
+    subject_image: Option<PathBuf>,
+    background_prompt: Option<String>,
+    background_reference: Option<PathBuf>,
+    foreground_prompt: Option<String>,
+    negative_prompt: Option<String>,
+    preserve_original_subject: Option<f32>,
+    original_background_depth: Option<f32>,
+    keep_original_background: bool,
+    light_source_direction: Option<LightDirection>,
+    light_reference: Option<PathBuf>,
+    light_source_strength: Option<f32>,
+    seed: Option<Seed>,
+    output_format: Option<OutputFormat>,
+    organization: Option<String>,
+    upload: UploadOptions,
+}
+
+impl RelightBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The image whose subject is kept
+    pub fn subject_image(mut self, subject_image: impl AsRef<Path>) -> Result<Self> {
+        self.subject_image = Some(subject_image.as_ref().to_path_buf());
+        Ok(self)
+    }
+
+    /// Describe the new background; needed unless a background reference
+    /// image is set
+    pub fn background_prompt(mut self, background_prompt: &str) -> Result<Self> {
+        self.background_prompt = Some(background_prompt.to_string());
+        Ok(self)
+    }
+
+    /// An image whose style and content the new background follows
+    pub fn background_reference(mut self, background_reference: impl AsRef<Path>) -> Result<Self> {
+        self.background_reference = Some(background_reference.as_ref().to_path_buf());
+        Ok(self)
+    }
+
+    /// Describe the subject, to keep it from changing along with the
+    /// background
+    pub fn foreground_prompt(mut self, foreground_prompt: &str) -> Result<Self> {
+        self.foreground_prompt = Some(foreground_prompt.to_string());
+        Ok(self)
+    }
+
+    pub fn negative_prompt(mut self, negative_prompt: &str) -> Result<Self> {
+        self.negative_prompt = Some(negative_prompt.to_string());
+        Ok(self)
+    }
+
+    /// How closely the subject is kept, from 0 to 1
+    pub fn preserve_original_subject(mut self, preserve: f32) -> Result<Self> {
+        validate_fraction("preserve_original_subject", preserve)?;
+        self.preserve_original_subject = Some(preserve);
+        Ok(self)
+    }
+
+    /// How closely the depth of the original background is kept, from 0 to 1
+    pub fn original_background_depth(mut self, depth: f32) -> Result<Self> {
+        validate_fraction("original_background_depth", depth)?;
+        self.original_background_depth = Some(depth);
+        Ok(self)
+    }
+
+    /// Keep the original background and only relight the image
+    pub fn keep_original_background(mut self, keep: bool) -> Result<Self> {
+        self.keep_original_background = keep;
+        Ok(self)
+    }
+
+    pub fn light_source_direction(mut self, direction: LightDirection) -> Result<Self> {
+        self.light_source_direction = Some(direction);
+        Ok(self)
+    }
+
+    /// An image whose lighting the result follows
+    pub fn light_reference(mut self, light_reference: impl AsRef<Path>) -> Result<Self> {
+        self.light_reference = Some(light_reference.as_ref().to_path_buf());
+        Ok(self)
+    }
+
+    /// How strong the light source is, from 0 to 1; applies with a light
+    /// direction or reference only
+    pub fn light_source_strength(mut self, strength: f32) -> Result<Self> {
+        validate_fraction("light_source_strength", strength)?;
+        self.light_source_strength = Some(strength);
+        Ok(self)
+    }
+
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Result<Self> {
+        let seed = seed.into();
+        validate_seed(seed)?;
+
+        self.seed = Some(seed);
+        Ok(self)
+    }
+
+    pub fn output_format(mut self, output_format: OutputFormat) -> Result<Self> {
+        self.output_format = Some(output_format);
+        Ok(self)
+    }
+
+    /// Bill the request to one of the organizations the API key belongs to,
+    /// instead of the default one
+    pub fn organization(mut self, organization: &str) -> Result<Self> {
+        self.organization = Some(organization.to_string());
+        Ok(self)
+    }
+
+    /// Rotate the images upright according to their EXIF orientation before
+    /// upload, which the API ignores; on by default
+    #[cfg(feature = "image")]
+    pub fn exif_orientation(mut self, apply: bool) -> Result<Self> {
+        self.upload.exif_orientation = apply;
+        Ok(self)
+    }
+
+    /// Map the images into memory rather than reading them into a buffer
+    /// first, which saves a copy for inputs of hundreds of megabytes; off
+    /// by default
+    ///
+    /// The files must not be changed or truncated until the request is built.
+    #[cfg(feature = "mmap")]
+    pub fn memory_map(mut self, map: bool) -> Result<Self> {
+        self.upload.memory_map = map;
+        Ok(self)
+    }
+
+    /// Remove EXIF and XMP metadata, such as GPS positions and camera serial
+    /// numbers, from the images before upload; on by default
+    pub fn strip_metadata(mut self, strip: bool) -> Result<Self> {
+        self.upload.strip_metadata = strip;
+        Ok(self)
+    }
+
+    pub fn build(self) -> Result<Relight> {
+        let Some(subject_image) = self.subject_image else {
+            return Err(Box::new(ImageBuilderError::InitImagePathNotSet));
+        };
+        let background_prompt = self.background_prompt.filter(|p| !p.is_empty());
+        if background_prompt.is_none() && self.background_reference.is_none() {
+            return Err(Box::new(ImageBuilderError::BackgroundNotSet));
+        }
+
+        Ok(Relight {
+            subject_image,
+            background_prompt,
+            background_reference: self.background_reference,
+            foreground_prompt: self.foreground_prompt.filter(|p| !p.is_empty()),
+            negative_prompt: self.negative_prompt.filter(|p| !p.is_empty()),
+            preserve_original_subject: self.preserve_original_subject,
+            original_background_depth: self.original_background_depth,
+            keep_original_background: self.keep_original_background,
+            light_source_direction: self.light_source_direction,
+            light_reference: self.light_reference,
+            light_source_strength: self.light_source_strength,
+            seed: self.seed.unwrap_or_default(),
+            output_format: self.output_format.unwrap_or_default(),
+            organization: self.organization,
+            upload: self.upload,
+        })
+    }
+}
@@ -0,0 +1,385 @@
+use super::*;
+use crate::error::*;
+use crate::prelude::*;
+use crate::capabilities::{MIN_SIDE, SIDE_MULTIPLE};
+use crate::form::{generation_fields, FieldKind, FieldSchema, RequestSchema};
+use crate::validation::*;
+use std::collections::HashMap;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn height_is_erring_when_not_a_multiple_of_64() {
+        let image = TextToImageBuilder::new().height(1023).unwrap_err();
+        assert_eq!(
+            image.to_string(),
+            "height must be a multiple of 64, but was 1023"
+        );
+    }
+
+    #[test]
+    fn height_is_erring_when_less_than_128() {
+        let image = TextToImageBuilder::new().height(64).unwrap_err();
+        assert_eq!(
+            image.to_string(),
+            "height must not be less than 128, but was 64"
+        );
+    }
+
+    #[test]
+    fn width_is_erring_when_not_a_multiple_of_64() {
+        let image = TextToImageBuilder::new().width(1023).unwrap_err();
+        assert_eq!(
+            image.to_string(),
+            "width must be a multiple of 64, but was 1023"
+        );
+    }
+
+    #[test]
+    fn width_is_erring_when_less_than_128() {
+        let image = TextToImageBuilder::new().width(64).unwrap_err();
+        assert_eq!(
+            image.to_string(),
+            "width must not be less than 128, but was 64"
+        );
+    }
+
+    #[test]
+    fn cfg_scale_is_erring_when_greater_than_35() {
+        let image = TextToImageBuilder::new().cfg_scale(36).unwrap_err();
+        assert_eq!(
+            image.to_string(),
+            "cfg_scale must be no greater than 35, but was 36"
+        );
+    }
+
+    #[test]
+    fn samples_is_erring_when_greater_than_10() {
+        let image = TextToImageBuilder::new().samples(11).unwrap_err();
+        assert_eq!(
+            image.to_string(),
+            "samples must be no greater than 10, but was 11"
+        );
+    }
+
+    #[test]
+    fn seed_is_erring_when_fixed_to_0() {
+        let image = TextToImageBuilder::new().seed(0).unwrap_err();
+        assert_eq!(
+            image.to_string(),
+            "a seed of 0 asks the API for a random seed, use Seed::Random instead"
+        );
+    }
+
+    #[test]
+    fn seed_is_sent_as_0_when_random() {
+        let image = TextToImageBuilder::new()
+            .style_preset(StylePreset::DigitalArt)
+            .unwrap()
+            .text_prompt("a lighthouse", 1.0)
+            .unwrap()
+            .seed(Seed::Random)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(serde_json::to_value(&image).unwrap()["seed"], 0);
+        assert_eq!(serde_json::from_str::<Seed>("0").unwrap(), Seed::Random);
+        assert_eq!(serde_json::from_str::<Seed>("7").unwrap(), Seed::Fixed(7));
+    }
+
+    #[test]
+    fn steps_is_erring_when_greater_than_150() {
+        let image = TextToImageBuilder::new().steps(151).unwrap_err();
+        assert_eq!(
+            image.to_string(),
+            "steps must be no greater than 150, but was 151"
+        );
+    }
+
+    #[test]
+    fn tti_build_is_erring_when_style_preset_is_not_set() {
+        let image = TextToImageBuilder::new().build().unwrap_err();
+        assert_eq!(image.to_string(), "a style preset must be set");
+    }
+
+    #[test]
+    fn tti_build_is_erring_when_textprompt_is_empty() {
+        let image = TextToImageBuilder::new()
+            .style_preset(StylePreset::DigitalArt)
+            .unwrap()
+            .text_prompt("", 1.0)
+            .unwrap()
+            .build()
+            .unwrap_err();
+        assert_eq!(image.to_string(), "a text prompt must not be empty");
+    }
+
+    #[test]
+    fn text_prompt_new_is_erring_when_weight_is_not_finite() {
+        let prompt = TextPrompt::new("a lighthouse", f32::NAN).unwrap_err();
+        assert_eq!(
+            prompt.to_string(),
+            "a text prompt weight must be a finite number, but was NaN"
+        );
+    }
+
+    #[test]
+    fn text_prompts_is_replacing_the_prompts_added_so_far() {
+        let prompts = vec![
+            TextPrompt::new("a lighthouse", 1.0).unwrap(),
+            TextPrompt::new("fog", -0.5).unwrap(),
+        ];
+        let image = TextToImageBuilder::new()
+            .style_preset(StylePreset::DigitalArt)
+            .unwrap()
+            .text_prompt("a harbour", 1.0)
+            .unwrap()
+            .text_prompts(prompts)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let groups = image.prompt_groups();
+        assert_eq!(groups.positive[0].text, "a lighthouse");
+        assert_eq!(groups.negative[0].weight, -0.5);
+        assert_eq!(image.text_prompts.len(), 2);
+    }
+
+    #[test]
+    fn to_builder_is_keeping_every_field_but_the_tweaked_one() {
+        let image = TextToImageBuilder::new()
+            .style_preset(StylePreset::DigitalArt)
+            .unwrap()
+            .text_prompt("a lighthouse", 1.0)
+            .unwrap()
+            .height(768)
+            .unwrap()
+            .cfg_scale(12)
+            .unwrap()
+            .seed(1)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let tweaked = image.to_builder().seed(2).unwrap().build().unwrap();
+
+        let mut expected = serde_json::to_value(&image).unwrap();
+        expected["seed"] = 2.into();
+        assert_eq!(serde_json::to_value(&tweaked).unwrap(), expected);
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TextToImage {
+    pub(crate) height: u32,
+    pub(crate) width: u32,
+    pub(crate) text_prompts: Vec<TextPrompt>,
+    pub(crate) cfg_scale: u32,
+    pub(crate) clip_guidance_preset: ClipGuidancePreset,
+    #[serde(skip_serializing_if = "Sampler::is_none")]
+    pub(crate) sampler: Sampler,
+    pub(crate) samples: u32,
+    pub(crate) seed: Seed,
+    pub(crate) steps: u32,
+    pub(crate) style_preset: StylePreset,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) extras: HashMap<String, String>,
+    #[serde(skip)]
+    pub(crate) organization: Option<String>,
+}
+
+impl TextToImage {
+    /// The fields of the request, for generating forms
+    pub fn schema() -> RequestSchema {
+        let side = || FieldKind::Integer {
+            min: MIN_SIDE,
+            max: None,
+            multiple_of: Some(SIDE_MULTIPLE),
+        };
+        let mut fields = vec![
+            FieldSchema::new("height", side(), "The height of the image, in pixels").default(1024),
+            FieldSchema::new("width", side(), "The width of the image, in pixels").default(1024),
+        ];
+        fields.extend(generation_fields());
+        RequestSchema {
+            name: "TextToImage",
+            fields,
+        }
+    }
+
+    /// The text prompts, split into positive and negative ones
+    pub fn prompt_groups(&self) -> PromptGroups {
+        PromptGroups::from_text_prompts(&self.text_prompts)
+    }
+
+    /// The organization the request is billed to, if not the default one
+    pub fn organization(&self) -> Option<&str> {
+        self.organization.as_deref()
+    }
+
+    /// A builder holding this request's values, to tweak a field or two and
+    /// build a new request
+    pub fn to_builder(&self) -> TextToImageBuilder {
+        TextToImageBuilder {
+            height: Some(self.height),
+            width: Some(self.width),
+            text_prompts: self.text_prompts.clone(),
+            cfg_scale: Some(self.cfg_scale),
+            clip_guidance_preset: Some(self.clip_guidance_preset.clone()),
+            sampler: Some(self.sampler.clone()),
+            samples: Some(self.samples),
+            seed: Some(self.seed),
+            steps: Some(self.steps),
+            style_preset: Some(self.style_preset.clone()),
+            extras: Some(self.extras.clone()),
+            organization: self.organization.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TextToImageBuilder {
+    height: Option<u32>,
+    width: Option<u32>,
+    text_prompts: Vec<TextPrompt>,
+    cfg_scale: Option<u32>,
+    clip_guidance_preset: Option<ClipGuidancePreset>,
+    sampler: Option<Sampler>,
+    samples: Option<u32>,
+    seed: Option<Seed>,
+    steps: Option<u32>,
+    style_preset: Option<StylePreset>,
+    extras: Option<HashMap<String, String>>,
+    organization: Option<String>,
+}
+
+impl TextToImageBuilder {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn height(mut self, height: u32) -> Result<Self> {
+        validate_height(height)?;
+
+        self.height = Some(height);
+
+        Ok(self)
+    }
+
+    pub fn width(mut self, width: u32) -> Result<Self> {
+        validate_width(width)?;
+
+        self.width = Some(width);
+
+        Ok(self)
+    }
+
+    pub fn text_prompt(mut self, text_prompt: &str, weight: f32) -> Result<Self> {
+        self.text_prompts.push(TextPrompt {
+            text: text_prompt.to_string(),
+            weight,
+        });
+        Ok(self)
+    }
+
+    /// Replace the prompts added so far with a list built elsewhere
+    pub fn text_prompts(mut self, text_prompts: Vec<TextPrompt>) -> Result<Self> {
+        self.text_prompts = text_prompts;
+        Ok(self)
+    }
+
+    /// How strictly the diffusion process adheres to the prompt text
+    /// (higher values keep your image closer to your prompt)
+    pub fn cfg_scale(mut self, cfg_scale: u32) -> Result<Self> {
+        validate_cfg_scale(cfg_scale)?;
+
+        self.cfg_scale = Some(cfg_scale);
+
+        Ok(self)
+    }
+
+    pub fn clip_guidance_preset(
+        mut self,
+        clip_guidance_preset: ClipGuidancePreset,
+    ) -> Result<Self> {
+        self.clip_guidance_preset = Some(clip_guidance_preset);
+        Ok(self)
+    }
+
+    pub fn sampler(mut self, sampler: Sampler) -> Result<Self> {
+        self.sampler = Some(sampler);
+        Ok(self)
+    }
+
+    pub fn samples(mut self, samples: u32) -> Result<Self> {
+        validate_samples(samples)?;
+
+        self.samples = Some(samples);
+
+        Ok(self)
+    }
+
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Result<Self> {
+        let seed = seed.into();
+        validate_seed(seed)?;
+
+        self.seed = Some(seed);
+        Ok(self)
+    }
+
+    pub fn steps(mut self, steps: u32) -> Result<Self> {
+        validate_steps(steps)?;
+
+        self.steps = Some(steps);
+
+        Ok(self)
+    }
+
+    pub fn style_preset(mut self, style_preset: StylePreset) -> Result<Self> {
+        self.style_preset = Some(style_preset);
+        Ok(self)
+    }
+
+    pub fn extras(mut self, extras: HashMap<String, String>) -> Result<Self> {
+        self.extras = Some(extras);
+        Ok(self)
+    }
+
+    /// Bill the request to one of the organizations the API key belongs to,
+    /// instead of the default one
+    pub fn organization(mut self, organization: &str) -> Result<Self> {
+        self.organization = Some(organization.to_string());
+        Ok(self)
+    }
+
+    pub fn build(self) -> Result<TextToImage> {
+        if self.style_preset.is_none() {
+            return Err(Box::new(ImageBuilderError::StylePresetNotSet));
+        }
+
+        if self.text_prompts.is_empty() || self.text_prompts[0].text.is_empty() {
+            return Err(Box::new(ImageBuilderError::TextPromptEmpty));
+        }
+
+        Ok(TextToImage {
+            height: self.height.unwrap_or(1024),
+            width: self.width.unwrap_or(1024),
+            cfg_scale: self.cfg_scale.unwrap_or(7),
+            clip_guidance_preset: self
+                .clip_guidance_preset
+                .unwrap_or(ClipGuidancePreset::None),
+            sampler: self.sampler.unwrap_or(Sampler::None),
+            samples: self.samples.unwrap_or(1),
+            seed: self.seed.unwrap_or_default(),
+            steps: self.steps.unwrap_or(50),
+            style_preset: self.style_preset.unwrap(),
+            text_prompts: self.text_prompts,
+            extras: self.extras.unwrap_or_default(),
+            organization: self.organization,
+        })
+    }
+}
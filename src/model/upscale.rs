@@ -0,0 +1,250 @@
+use super::*;
+use crate::error::*;
+use crate::prelude::*;
+use crate::capabilities::{CFG_SCALE, STEPS, UPSCALE_MIN_SIDE};
+use crate::form::{FieldKind, FieldSchema, RequestSchema};
+use crate::validation::*;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Upscaler {
+    #[serde(serialize_with = "serialize_path")]
+    pub(crate) image: PathBuf,
+    pub(crate) height: u32,
+    pub(crate) width: u32,
+    pub(crate) text_prompts: Vec<TextPrompt>,
+    pub(crate) cfg_scale: u32,
+    pub(crate) seed: Seed,
+    pub(crate) steps: u32,
+    #[serde(skip)]
+    pub(crate) organization: Option<String>,
+    #[serde(skip)]
+    pub(crate) upload: UploadOptions,
+}
+
+impl Upscaler {
+    pub fn builder() -> UpscalerBuilder {
+        UpscalerBuilder::new()
+    }
+
+    /// The fields of the request, for generating forms
+    ///
+    /// Only one of `height` and `width` may be set; the other side follows
+    /// the aspect ratio of the image. The prompts, `cfg_scale`, `seed` and
+    /// `steps` are only read by the latent upscaler.
+    pub fn schema() -> RequestSchema {
+        RequestSchema {
+            name: "Upscaler",
+            fields: vec![
+                FieldSchema::new("image", FieldKind::Image, "The image to upscale").required(),
+                FieldSchema::new(
+                    "height",
+                    FieldKind::integer(UPSCALE_MIN_SIDE, None),
+                    "The height to upscale to, in pixels",
+                ),
+                FieldSchema::new(
+                    "width",
+                    FieldKind::integer(UPSCALE_MIN_SIDE, None),
+                    "The width to upscale to, in pixels",
+                ),
+                FieldSchema::new("text_prompts", FieldKind::Prompts, "What the image shows"),
+                FieldSchema::new(
+                    "cfg_scale",
+                    FieldKind::integer(*CFG_SCALE.start(), Some(*CFG_SCALE.end())),
+                    "How strictly the image follows the prompts",
+                )
+                .default(7),
+                FieldSchema::new("seed", FieldKind::Seed, "The seed of the noise").default(0),
+                FieldSchema::new(
+                    "steps",
+                    FieldKind::integer(*STEPS.start(), Some(*STEPS.end())),
+                    "How many diffusion steps to run",
+                )
+                .default(50),
+            ],
+        }
+    }
+
+    /// The text prompts, split into positive and negative ones
+    pub fn prompt_groups(&self) -> PromptGroups {
+        PromptGroups::from_text_prompts(&self.text_prompts)
+    }
+
+    /// The organization the request is billed to, if not the default one
+    pub fn organization(&self) -> Option<&str> {
+        self.organization.as_deref()
+    }
+
+    /// How the images are prepared before upload
+    pub fn upload_options(&self) -> UploadOptions {
+        self.upload
+    }
+
+    /// A builder holding this request's values, to tweak a field or two and
+    /// build a new request
+    pub fn to_builder(&self) -> UpscalerBuilder {
+        UpscalerBuilder {
+            image: Some(self.image.clone()),
+            // zero means the side was left for the API to derive
+            height: (self.height != 0).then_some(self.height),
+            width: (self.width != 0).then_some(self.width),
+            text_prompts: self.text_prompts.clone(),
+            cfg_scale: Some(self.cfg_scale),
+            seed: Some(self.seed),
+            steps: Some(self.steps),
+            organization: self.organization.clone(),
+            upload: self.upload,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default,)]
+pub struct UpscalerBuilder {
+    image: Option<PathBuf>,
+    height: Option<u32>,
+    width: Option<u32>,
+    text_prompts: Vec<TextPrompt>,
+    cfg_scale: Option<u32>,
+    seed: Option<Seed>,
+    steps: Option<u32>,
+    organization: Option<String>,
+    upload: UploadOptions,
+}
+
+impl UpscalerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn image(mut self, image: impl AsRef<Path>) -> Result<Self> {
+        self.image = Some(image.as_ref().to_path_buf());
+        Ok(self)
+    }
+
+    pub fn height(mut self, height: u32) -> Result<Self> {
+        validate_upscale_height(height)?;
+
+        self.height = Some(height);
+        Ok(self)
+    }
+
+    pub fn width(mut self, width: u32) -> Result<Self> {
+        validate_upscale_width(width)?;
+
+        self.width = Some(width);
+        Ok(self)
+    }
+
+    pub fn text_prompt(mut self, text: &str, weight: f32) -> Result<Self> {
+        self.text_prompts.push(TextPrompt { text: text.to_string(), weight });
+        Ok(self)
+    }
+
+    /// Replace the prompts added so far with a list built elsewhere
+    pub fn text_prompts(mut self, text_prompts: Vec<TextPrompt>) -> Result<Self> {
+        self.text_prompts = text_prompts;
+        Ok(self)
+    }
+
+    pub fn cfg_scale(mut self, cfg_scale: u32) -> Result<Self> {
+        validate_cfg_scale(cfg_scale)?;
+        self.cfg_scale = Some(cfg_scale);
+        Ok(self)
+    }
+
+    pub fn seed(mut self, seed: impl Into<Seed>) -> Result<Self> {
+        let seed = seed.into();
+        validate_seed(seed)?;
+
+        self.seed = Some(seed);
+        Ok(self)
+    }
+
+    pub fn steps(mut self, steps: u32) -> Result<Self> {
+        validate_steps(steps)?;
+
+
+        self.steps = Some(steps);
+        Ok(self)
+    }
+
+    /// Bill the request to one of the organizations the API key belongs to,
+    /// instead of the default one
+    pub fn organization(mut self, organization: &str) -> Result<Self> {
+        self.organization = Some(organization.to_string());
+        Ok(self)
+    }
+
+    /// Rotate the image upright according to its EXIF orientation before
+    /// upload, which the API ignores; on by default
+    #[cfg(feature = "image")]
+    pub fn exif_orientation(mut self, apply: bool) -> Result<Self> {
+        self.upload.exif_orientation = apply;
+        Ok(self)
+    }
+
+    /// Map the image into memory rather than reading it into a buffer
+    /// first, which saves a copy for inputs of hundreds of megabytes; off
+    /// by default
+    ///
+    /// The files must not be changed or truncated until the request is built.
+    #[cfg(feature = "mmap")]
+    pub fn memory_map(mut self, map: bool) -> Result<Self> {
+        self.upload.memory_map = map;
+        Ok(self)
+    }
+
+    /// Remove EXIF and XMP metadata, such as GPS positions and camera serial
+    /// numbers, from the image before upload; on by default
+    pub fn strip_metadata(mut self, strip: bool) -> Result<Self> {
+        self.upload.strip_metadata = strip;
+        Ok(self)
+    }
+
+    pub fn build(self) -> Result<Upscaler> {
+        if self.image.is_none() {
+            return Err(Box::new(ImageBuilderError::UpscaleImagePathNotSet))
+        }
+
+       if self.width.is_some() && self.height.is_some() {
+           return Err(Box::new(ImageBuilderError::UpscaleWidthHeightConflict))
+       }
+
+        Ok(Upscaler {
+            image: self.image.unwrap(),
+            height: self.height.unwrap_or_default(),
+            width: self.width.unwrap_or_default(),
+            text_prompts: self.text_prompts,
+            cfg_scale: self.cfg_scale.unwrap_or(7),
+            seed: self.seed.unwrap_or_default(),
+            steps: self.steps.unwrap_or(50),
+            organization: self.organization,
+            upload: self.upload,
+        })
+    }
+
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum UpscaleEngine {
+    EsrganV1X2Plus,
+    StableDiffusionX4LatentUpscaler,
+}
+
+impl UpscaleEngine {
+    /// How many times larger a single call of the engine makes an image
+    pub fn factor(&self) -> u32 {
+        match self {
+            UpscaleEngine::EsrganV1X2Plus => 2,
+            UpscaleEngine::StableDiffusionX4LatentUpscaler => 4,
+        }
+    }
+}
+
+impl fmt::Display for UpscaleEngine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpscaleEngine::EsrganV1X2Plus => write!(f, "esrgan-v1-x2plus"),
+            UpscaleEngine::StableDiffusionX4LatentUpscaler => write!(f, "stable-diffusion-x4-latent-upscaler"),
+        }
+    }
+}
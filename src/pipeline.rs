@@ -0,0 +1,432 @@
+//! Multi-stage generation runs with per-stage guardrails.
+//!
+//! A [`Pipeline`] runs named stages in order, each one handed the response
+//! of the stage before it, such as a generation followed by an upscale. Every
+//! stage can carry a [`Budget`]: a stage whose estimated credits exceed it is
+//! skipped, and one running past its latency budget is cancelled. Either way
+//! the remaining stages are skipped too, and the [`PipelineReport`] records
+//! what ran, what was skipped, and why.
+//!
+#![cfg_attr(all(feature = "text-to-image", feature = "upscale"), doc = "```no_run")]
+#![cfg_attr(not(all(feature = "text-to-image", feature = "upscale")), doc = "```ignore")]
+//! use stability_rs::pipeline::{Budget, Pipeline};
+//! use stability_rs::staging::TempStore;
+//! use stability_rs::{credits, text_to_img::*, upscale::*, Result, StylePreset};
+//! use std::time::Duration;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     let engine = "stable-diffusion-xl-1024-v1-0";
+//!     let request = TextToImageBuilder::new()
+//!         .style_preset(StylePreset::Photographic)?
+//!         .text_prompt("a lighthouse at dusk", 1.0)?
+//!         .build()?;
+//!
+//!     let report = Pipeline::new()
+//!         .max_credits(5.0)?
+//!         .stage(
+//!             "generate",
+//!             credits::estimate(engine, 50, 1),
+//!             Budget::new().max_latency(Duration::from_secs(30))?,
+//!             move |_| Box::pin(async move { request.generate(engine).await }),
+//!         )?
+//!         .stage("upscale", 0.2, Budget::new(), |previous| {
+//!             let generated = previous.map(|resp| resp.artifacts[0].to_artifact());
+//!             Box::pin(async move {
+//!                 let staging = TempStore::new()?;
+//!                 let generated = staging
+//!                     .save("generated.png", &generated.expect("runs after generate")?)
+//!                     .await?;
+//!                 UpscalerBuilder::new()
+//!                     .image(&generated)?
+//!                     .build()?
+//!                     .generate(UpscaleEngine::EsrganV1X2Plus)
+//!                     .await
+//!             })
+//!         })?
+//!         .run()
+//!         .await;
+//!
+//!     for stage in &report.stages {
+//!         println!("{}: {:?}", stage.name, stage.outcome);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::api::rest::generation::ImageResponse;
+use crate::prelude::*;
+use crate::scoring::{self, ArtifactScorer};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The future a stage runs
+pub type StageFuture = Pin<Box<dyn Future<Output = Result<ImageResponse>> + Send>>;
+
+type StageFn = Box<dyn FnOnce(Option<&ImageResponse>) -> StageFuture + Send>;
+
+/// Limits a single stage must stay within
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Budget {
+    max_latency: Option<Duration>,
+    max_credits: Option<f64>,
+}
+
+impl Budget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the stage once it has run for `max_latency`
+    pub fn max_latency(mut self, max_latency: Duration) -> Result<Self> {
+        self.max_latency = Some(max_latency);
+        Ok(self)
+    }
+
+    /// Skip the stage when its estimated credits exceed `max_credits`
+    pub fn max_credits(mut self, max_credits: f64) -> Result<Self> {
+        self.max_credits = Some(max_credits);
+        Ok(self)
+    }
+}
+
+/// Why a stage did not run
+#[derive(Debug, Clone, PartialEq)]
+pub enum SkipReason {
+    /// The stage's estimate exceeded its own credits budget
+    StageCredits { estimated: f64, budget: f64 },
+    /// Running the stage would have taken the pipeline past its credits
+    /// budget
+    PipelineCredits {
+        spent: f64,
+        estimated: f64,
+        budget: f64,
+    },
+    /// An earlier stage was skipped, failed or timed out
+    Stopped { stage: String },
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::StageCredits { estimated, budget } => write!(
+                f,
+                "estimated {} credits, over the stage budget of {}",
+                estimated, budget
+            ),
+            SkipReason::PipelineCredits {
+                spent,
+                estimated,
+                budget,
+            } => write!(
+                f,
+                "estimated {} credits after {} spent, over the pipeline budget of {}",
+                estimated, spent, budget
+            ),
+            SkipReason::Stopped { stage } => write!(f, "stage {} did not complete", stage),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StageOutcome {
+    Completed { latency: Duration, credits: f64 },
+    Skipped(SkipReason),
+    TimedOut { budget: Duration },
+    Failed(Box<dyn std::error::Error + Send + Sync>),
+}
+
+#[derive(Debug)]
+pub struct StageReport {
+    pub name: String,
+    pub outcome: StageOutcome,
+}
+
+/// What a pipeline run did, stage by stage
+#[derive(Debug)]
+pub struct PipelineReport {
+    pub stages: Vec<StageReport>,
+    /// The response of the last completed stage
+    pub output: Option<ImageResponse>,
+    /// The estimated credits of the stages which completed
+    pub credits_spent: f64,
+}
+
+impl PipelineReport {
+    /// Whether every stage completed
+    pub fn is_complete(&self) -> bool {
+        self.stages
+            .iter()
+            .all(|stage| matches!(stage.outcome, StageOutcome::Completed { .. }))
+    }
+}
+
+struct Stage {
+    name: String,
+    estimated_credits: f64,
+    budget: Budget,
+    run: StageFn,
+}
+
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+    max_credits: Option<f64>,
+    scorer: Option<Arc<dyn ArtifactScorer>>,
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pipeline")
+            .field(
+                "stages",
+                &self.stages.iter().map(|s| &s.name).collect::<Vec<_>>(),
+            )
+            .field("max_credits", &self.max_credits)
+            .field("scorer", &self.scorer)
+            .finish()
+    }
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop before a stage that would take the estimated credits of the
+    /// whole run past `max_credits`
+    pub fn max_credits(mut self, max_credits: f64) -> Result<Self> {
+        self.max_credits = Some(max_credits);
+        Ok(self)
+    }
+
+    /// Narrow the response of every stage to its best artifact according to
+    /// `scorer`, so later stages and the report's output only see that one
+    pub fn scorer(mut self, scorer: impl ArtifactScorer + 'static) -> Result<Self> {
+        self.scorer = Some(Arc::new(scorer));
+        Ok(self)
+    }
+
+    /// Add a stage, run with the response of the previous stage, or `None`
+    /// for the first
+    ///
+    /// `estimated_credits` is checked against the budgets before the stage
+    /// runs; [`crate::credits::estimate`] gives a figure for most requests.
+    pub fn stage<F>(
+        mut self,
+        name: &str,
+        estimated_credits: f64,
+        budget: Budget,
+        run: F,
+    ) -> Result<Self>
+    where
+        F: FnOnce(Option<&ImageResponse>) -> StageFuture + Send + 'static,
+    {
+        self.stages.push(Stage {
+            name: name.to_string(),
+            estimated_credits,
+            budget,
+            run: Box::new(run),
+        });
+        Ok(self)
+    }
+
+    /// Run the stages in order, stopping at the first one which does not
+    /// complete
+    pub async fn run(self) -> PipelineReport {
+        let mut report = PipelineReport {
+            stages: Vec::with_capacity(self.stages.len()),
+            output: None,
+            credits_spent: 0.0,
+        };
+        let mut stopped: Option<String> = None;
+
+        for stage in self.stages {
+            let name = stage.name.clone();
+            let outcome = match &stopped {
+                Some(earlier) => StageOutcome::Skipped(SkipReason::Stopped {
+                    stage: earlier.clone(),
+                }),
+                None => {
+                    Self::run_stage(stage, self.max_credits, self.scorer.as_deref(), &mut report)
+                        .await
+                }
+            };
+            if stopped.is_none() && !matches!(outcome, StageOutcome::Completed { .. }) {
+                stopped = Some(name.clone());
+            }
+            report.stages.push(StageReport { name, outcome });
+        }
+
+        report
+    }
+
+    async fn run_stage(
+        stage: Stage,
+        max_credits: Option<f64>,
+        scorer: Option<&dyn ArtifactScorer>,
+        report: &mut PipelineReport,
+    ) -> StageOutcome {
+        if let Some(budget) = stage.budget.max_credits {
+            if stage.estimated_credits > budget {
+                return StageOutcome::Skipped(SkipReason::StageCredits {
+                    estimated: stage.estimated_credits,
+                    budget,
+                });
+            }
+        }
+        if let Some(budget) = max_credits {
+            if report.credits_spent + stage.estimated_credits > budget {
+                return StageOutcome::Skipped(SkipReason::PipelineCredits {
+                    spent: report.credits_spent,
+                    estimated: stage.estimated_credits,
+                    budget,
+                });
+            }
+        }
+
+        let future = (stage.run)(report.output.as_ref());
+        let started = Instant::now();
+        let result = match stage.budget.max_latency {
+            Some(budget) => match tokio::time::timeout(budget, future).await {
+                Ok(result) => result,
+                Err(_) => return StageOutcome::TimedOut { budget },
+            },
+            None => future.await,
+        };
+
+        match result {
+            Ok(mut resp) => {
+                if let Some(scorer) = scorer {
+                    scoring::keep_best(&mut resp, scorer);
+                }
+                report.credits_spent += stage.estimated_credits;
+                report.output = Some(resp);
+                StageOutcome::Completed {
+                    latency: started.elapsed(),
+                    credits: stage.estimated_credits,
+                }
+            }
+            Err(err) => StageOutcome::Failed(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::image_response;
+
+    fn respond(seed: u32) -> impl FnOnce(Option<&ImageResponse>) -> StageFuture + Send {
+        move |_| Box::pin(async move { Ok(image_response(&[seed])) })
+    }
+
+    #[tokio::test]
+    async fn run_is_passing_each_response_to_the_next_stage() {
+        let report = Pipeline::new()
+            .stage("generate", 0.2, Budget::new(), respond(7))
+            .unwrap()
+            .stage("refine", 0.2, Budget::new(), |previous| {
+                let seed = previous.unwrap().artifacts[0].seed;
+                Box::pin(async move { Ok(image_response(&[seed + 1])) })
+            })
+            .unwrap()
+            .run()
+            .await;
+
+        assert!(report.is_complete());
+        assert_eq!(report.output.unwrap().artifacts[0].seed, 8);
+        assert!((report.credits_spent - 0.4).abs() < f64::EPSILON);
+    }
+
+    #[tokio::test]
+    async fn run_is_skipping_stages_over_budget_and_after() {
+        let report = Pipeline::new()
+            .max_credits(1.0)
+            .unwrap()
+            .stage("generate", 0.6, Budget::new(), respond(1))
+            .unwrap()
+            .stage("upscale", 0.6, Budget::new(), respond(2))
+            .unwrap()
+            .stage("watermark", 0.0, Budget::new(), respond(3))
+            .unwrap()
+            .run()
+            .await;
+
+        assert!(!report.is_complete());
+        assert!(matches!(
+            report.stages[0].outcome,
+            StageOutcome::Completed { .. }
+        ));
+        assert!(matches!(
+            report.stages[1].outcome,
+            StageOutcome::Skipped(SkipReason::PipelineCredits { .. })
+        ));
+        match &report.stages[2].outcome {
+            StageOutcome::Skipped(reason) => {
+                assert_eq!(reason.to_string(), "stage upscale did not complete")
+            }
+            other => panic!("unexpected outcome {:?}", other),
+        }
+        assert_eq!(report.output.unwrap().artifacts[0].seed, 1);
+    }
+
+    #[tokio::test]
+    async fn run_is_cancelling_stages_over_their_latency_budget() {
+        let budget = Budget::new()
+            .max_latency(Duration::from_millis(10))
+            .unwrap();
+        let report = Pipeline::new()
+            .stage("slow", 0.2, budget, |_| {
+                Box::pin(async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    Ok(image_response(&[1]))
+                })
+            })
+            .unwrap()
+            .run()
+            .await;
+
+        assert!(matches!(
+            report.stages[0].outcome,
+            StageOutcome::TimedOut { .. }
+        ));
+        assert!(report.output.is_none());
+        assert_eq!(report.credits_spent, 0.0);
+    }
+
+    #[tokio::test]
+    async fn run_is_handing_on_the_best_artifact_when_scored() {
+        struct LowestSeed;
+
+        impl ArtifactScorer for LowestSeed {
+            fn score(&self, image: &crate::api::rest::generation::Image) -> f64 {
+                -(image.seed as f64)
+            }
+        }
+
+        let report = Pipeline::new()
+            .scorer(LowestSeed)
+            .unwrap()
+            .stage("generate", 0.2, Budget::new(), |_| {
+                Box::pin(async { Ok(image_response(&[4, 2, 6])) })
+            })
+            .unwrap()
+            .stage("refine", 0.2, Budget::new(), |previous| {
+                let previous = previous.unwrap();
+                assert_eq!(previous.artifacts.len(), 1);
+                let seed = previous.artifacts[0].seed;
+                Box::pin(async move { Ok(image_response(&[seed, seed + 1])) })
+            })
+            .unwrap()
+            .run()
+            .await;
+
+        assert!(report.is_complete());
+        assert_eq!(report.output.unwrap().artifacts[0].seed, 2);
+    }
+}
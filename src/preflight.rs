@@ -0,0 +1,142 @@
+//! Checks to run before writing output.
+//!
+//! A long batch run that fails on its first save, or halfway through when
+//! the disk fills up, wastes the credits spent on the generations before it.
+//! [`check_output_dir`] catches both before any request is sent:
+//!
+//! ```no_run
+//! use stability_rs::{preflight, Result};
+//!
+//! fn main() -> Result<()> {
+//!     // 40 images of about 2 MiB each
+//!     preflight::check_output_dir("out", 40 * 2 * 1024 * 1024)?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! The save helpers of this crate create missing parent directories
+//! themselves, so the output directory need not exist beforehand.
+
+use crate::error::Error;
+use crate::prelude::*;
+use std::io;
+use std::path::Path;
+
+/// Verify that `dir` exists or can be created, that files can be written to
+/// it, and that at least `estimated_bytes` are free on its file system
+///
+/// Free space is only checked where the platform reports it, see
+/// [`available_space`].
+pub fn check_output_dir(dir: impl AsRef<Path>, estimated_bytes: u64) -> Result<()> {
+    let dir = dir.as_ref();
+    let not_writable = |err: io::Error| Error::OutputDirNotWritable {
+        dir: dir.display().to_string(),
+        reason: err.to_string(),
+    };
+
+    std::fs::create_dir_all(dir).map_err(not_writable)?;
+    let probe = dir.join(format!(".stability_rs_probe_{}", std::process::id()));
+    std::fs::write(&probe, b"").map_err(not_writable)?;
+    std::fs::remove_file(&probe).map_err(not_writable)?;
+
+    if let Some(available) = available_space(dir) {
+        if available < estimated_bytes {
+            return Err(Box::new(Error::InsufficientDiskSpace {
+                dir: dir.display().to_string(),
+                needed: estimated_bytes,
+                available,
+            }));
+        }
+    }
+
+    Ok(())
+}
+
+/// The bytes an unprivileged process may still write to the file system
+/// holding `path`, or `None` where that can't be determined
+#[cfg(unix)]
+pub fn available_space(path: impl AsRef<Path>) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_ref().as_os_str().as_bytes()).ok()?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is only read once statvfs
+    // has filled it in
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stat.assume_init()
+    };
+    // the field types differ between platforms
+    #[allow(clippy::useless_conversion)]
+    let (blocks, block_size) = (u64::from(stat.f_bavail), u64::from(stat.f_frsize));
+    blocks.checked_mul(block_size)
+}
+
+/// The bytes an unprivileged process may still write to the file system
+/// holding `path`, or `None` where that can't be determined
+#[cfg(not(unix))]
+pub fn available_space(_path: impl AsRef<Path>) -> Option<u64> {
+    None
+}
+
+/// Create the directories leading up to `path`, so a file can be created there
+pub(crate) fn create_parent_dirs(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => std::fs::create_dir_all(parent),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "stability_rs_preflight_{}_{}",
+            std::process::id(),
+            name
+        ))
+    }
+
+    #[test]
+    fn check_output_dir_is_creating_missing_directories() {
+        let root = temp_dir("create");
+        let dir = root.join("nested/out");
+
+        check_output_dir(&dir, 0).unwrap();
+
+        assert!(dir.is_dir());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_output_dir_is_erring_when_space_is_short() {
+        let dir = temp_dir("space");
+
+        let err = check_output_dir(&dir, u64::MAX).unwrap_err();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(err
+            .to_string()
+            .contains("bytes free, but about 18446744073709551615"));
+    }
+
+    #[test]
+    fn check_output_dir_is_erring_when_the_path_is_a_file() {
+        let file = temp_dir("file");
+        std::fs::write(&file, b"").unwrap();
+
+        let err = check_output_dir(&file, 0).unwrap_err();
+
+        std::fs::remove_file(&file).unwrap();
+        assert!(err.to_string().starts_with(&format!(
+            "output directory {} is not writable",
+            file.display()
+        )));
+    }
+}
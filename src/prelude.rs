@@ -7,4 +7,5 @@ pub const POST: &str = "POST";
 pub const ACCEPT: &str = "accept";
 pub const APPLICATION_JSON: &str = "application/json";
 pub const CONTENT_TYPE: &str = "Content-Type";
+pub const IMAGE_ANY: &str = "image/*";
 pub const IMAGE_PNG: &str = "image/png";
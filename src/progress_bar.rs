@@ -0,0 +1,113 @@
+//! `indicatif` progress bars driven by the crate's progress callbacks.
+//!
+//! Each function takes a [`ProgressBar`] and returns the callback to hand to
+//! [`BatchRunner::on_progress`](crate::batch::BatchRunner::on_progress),
+//! [`with_upload_progress`](crate::upload_progress::with_upload_progress) or
+//! [`with_download_progress`](crate::download_progress::with_download_progress).
+//! The bar is cheap to clone, so one bar can be styled, driven and finished
+//! by the caller as usual. [`items_style`] and [`bytes_style`] are templates
+//! suited to each kind of bar.
+//!
+#![cfg_attr(feature = "image-to-image", doc = "```no_run")]
+#![cfg_attr(not(feature = "image-to-image"), doc = "```ignore")]
+//! use indicatif::ProgressBar;
+//! use stability_rs::upload_progress::with_upload_progress;
+//! use stability_rs::{img_to_img::*, progress_bar, Result, StylePreset};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     let request = ImageToImageBuilder::new()
+//!         .init_image_path("large_photo.png")?
+//!         .style_preset(StylePreset::Photographic)?
+//!         .text_prompt("a watercolour harbour", 1.0)?
+//!         .build()?;
+//!
+//!     let bar = ProgressBar::new(0).with_style(progress_bar::bytes_style());
+//!     let resp = with_upload_progress(
+//!         progress_bar::upload(bar.clone()),
+//!         request.generate("stable-diffusion-xl-1024-v1-0"),
+//!     )
+//!     .await?;
+//!     bar.finish_and_clear();
+//!     # let _ = resp;
+//!
+//!     Ok(())
+//! }
+//! ```
+
+#[cfg(any(feature = "text-to-image", feature = "image-to-image"))]
+use crate::batch::BatchProgress;
+use crate::download_progress::DownloadProgress;
+use crate::upload_progress::UploadProgress;
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// A bar counting batch items, with the id of the last one completed
+pub fn items_style() -> ProgressStyle {
+    ProgressStyle::with_template("{bar:40} {pos}/{len} items, eta {eta} {msg}")
+        .expect("the template is valid")
+}
+
+/// A bar counting bytes, with the transfer rate
+pub fn bytes_style() -> ProgressStyle {
+    ProgressStyle::with_template("{bar:40} {bytes}/{total_bytes} at {bytes_per_sec}")
+        .expect("the template is valid")
+}
+
+/// Advance `bar` by one for each batch item completed
+#[cfg(any(feature = "text-to-image", feature = "image-to-image"))]
+pub fn batch(bar: ProgressBar) -> impl Fn(BatchProgress) + Send + Sync + 'static {
+    move |progress| {
+        bar.set_length(progress.total as u64);
+        bar.set_position(progress.completed as u64);
+        bar.set_message(progress.item_id);
+    }
+}
+
+/// Fill `bar` as request bodies are uploaded
+pub fn upload(bar: ProgressBar) -> impl Fn(UploadProgress) + Send + Sync + 'static {
+    move |progress| {
+        bar.set_length(progress.total);
+        bar.set_position(progress.sent);
+    }
+}
+
+/// Fill `bar` as response bodies arrive; without a `content-length` the
+/// length is left as it was, so a spinner style suits such downloads
+pub fn download(bar: ProgressBar) -> impl Fn(DownloadProgress) + Send + Sync + 'static {
+    move |progress| {
+        if let Some(total) = progress.total {
+            bar.set_length(total);
+        }
+        bar.set_position(progress.received);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upload_is_setting_the_length_and_position() {
+        let bar = ProgressBar::hidden();
+        upload(bar.clone())(UploadProgress {
+            sent: 64,
+            total: 256,
+        });
+        assert_eq!((bar.position(), bar.length()), (64, Some(256)));
+    }
+
+    #[test]
+    fn download_is_keeping_the_length_when_unknown() {
+        let bar = ProgressBar::hidden();
+        let callback = download(bar.clone());
+        callback(DownloadProgress {
+            received: 10,
+            total: Some(100),
+        });
+        callback(DownloadProgress {
+            received: 20,
+            total: None,
+        });
+        assert_eq!((bar.position(), bar.length()), (20, Some(100)));
+    }
+}
@@ -0,0 +1,117 @@
+//! Streamed generation progress for live previews.
+//!
+//! [`ProgressiveGeneration`] yields [`GenerationEvent`]s as a generation runs.
+//! The REST API only answers once the images are finished, so the REST
+//! request types implement it as a no-op fallback: the stream yields a single
+//! [`GenerationEvent::Completed`] and [`ProgressiveGeneration::streams_previews`]
+//! is `false`. UI clients can drive every transport the same way and simply
+//! show a spinner until the final images arrive.
+
+use crate::api::rest::artifact::Artifact;
+use crate::api::rest::generation::ImageResponse;
+use crate::prelude::*;
+use futures_util::stream::BoxStream;
+
+#[cfg(all(test, feature = "text-to-image"))]
+mod tests {
+    use super::*;
+    use crate::testing::{image_response, FakeTransport};
+    use crate::text_to_img::TextToImageBuilder;
+    use crate::StylePreset;
+    use futures_util::TryStreamExt;
+
+    #[tokio::test]
+    async fn rest_fallback_is_yielding_only_the_completed_response() {
+        let request = TextToImageBuilder::new()
+            .style_preset(StylePreset::Photographic)
+            .unwrap()
+            .text_prompt("a lighthouse at dusk", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let transport = FakeTransport::with_response(&image_response(&[7]));
+        let events = transport
+            .scope(
+                request
+                    .generate_progressive("stable-diffusion-xl-1024-v1-0")
+                    .try_collect::<Vec<_>>(),
+            )
+            .await
+            .unwrap();
+
+        assert!(!request.streams_previews());
+        assert_eq!(events.len(), 1);
+        let GenerationEvent::Completed(resp) = &events[0] else {
+            panic!("expected a completed event, got {:?}", events[0]);
+        };
+        assert_eq!(resp.artifacts[0].seed, 7);
+    }
+}
+
+#[derive(Debug)]
+pub enum GenerationEvent {
+    /// The generation advanced, optionally with an intermediate image
+    Progress {
+        /// How far along the generation is, from 0 to 100
+        percent: u8,
+        preview: Option<Artifact>,
+    },
+    /// The final images; always the last event of a successful stream
+    Completed(ImageResponse),
+}
+
+/// A request that can report its progress while generating
+pub trait ProgressiveGeneration {
+    /// Generate with `engine`, yielding events until the final response
+    fn generate_progressive<'a>(
+        &'a self,
+        engine: &'a str,
+    ) -> BoxStream<'a, Result<GenerationEvent>>;
+
+    /// Whether the stream yields [`GenerationEvent::Progress`] events at all
+    fn streams_previews(&self) -> bool {
+        false
+    }
+}
+
+/// Wrap a single-shot generation as a stream of one completed event
+#[cfg(any(feature = "text-to-image", feature = "image-to-image"))]
+fn completed_only<'a, F>(generation: F) -> BoxStream<'a, Result<GenerationEvent>>
+where
+    F: std::future::Future<Output = Result<ImageResponse>> + Send + 'a,
+{
+    use futures_util::stream::{self, StreamExt};
+
+    stream::once(async move { generation.await.map(GenerationEvent::Completed) }).boxed()
+}
+
+#[cfg(feature = "text-to-image")]
+impl ProgressiveGeneration for crate::text_to_img::TextToImage {
+    fn generate_progressive<'a>(
+        &'a self,
+        engine: &'a str,
+    ) -> BoxStream<'a, Result<GenerationEvent>> {
+        completed_only(self.generate(engine))
+    }
+}
+
+#[cfg(feature = "image-to-image")]
+impl ProgressiveGeneration for crate::img_to_img::ImageToImage {
+    fn generate_progressive<'a>(
+        &'a self,
+        engine: &'a str,
+    ) -> BoxStream<'a, Result<GenerationEvent>> {
+        completed_only(self.generate(engine))
+    }
+}
+
+#[cfg(feature = "masking")]
+impl ProgressiveGeneration for crate::masking::Masker {
+    fn generate_progressive<'a>(
+        &'a self,
+        engine: &'a str,
+    ) -> BoxStream<'a, Result<GenerationEvent>> {
+        completed_only(self.generate(engine))
+    }
+}
@@ -0,0 +1,222 @@
+//! Checking prompts locally before they are sent.
+//!
+//! Applications with content policies of their own can reject a request
+//! before it reaches the API, and before it costs credits, by implementing
+//! [`PromptScreen`] and [`install`]ing it. Every generation then passes its
+//! prompts through the screen first and fails with
+//! [`Error::PromptRejected`] if the screen refuses them. Nothing is screened
+//! until a screen is installed.
+//!
+//! With the `word-list` feature, `WordListScreen` rejects prompts containing
+//! any of a list of terms, for a quick setup.
+//!
+//! ```
+//! use stability_rs::api::rest::generation::PromptGroups;
+//! use stability_rs::prompt_screen::{self, PromptScreen};
+//! use std::sync::Arc;
+//!
+//! /// Rejects prompts longer than the application allows
+//! struct MaxLength(usize);
+//!
+//! impl PromptScreen for MaxLength {
+//!     fn screen(&self, prompts: &PromptGroups) -> Result<(), String> {
+//!         match prompts.positive.iter().any(|p| p.text.len() > self.0) {
+//!             true => Err(format!("prompts are limited to {} bytes", self.0)),
+//!             false => Ok(()),
+//!         }
+//!     }
+//! }
+//!
+//! prompt_screen::install(Arc::new(MaxLength(500)));
+//! ```
+
+use crate::api::rest::generation::PromptGroups;
+use crate::error::Error;
+use crate::prelude::*;
+use std::sync::{Arc, RwLock};
+
+static GLOBAL: RwLock<Option<Arc<dyn PromptScreen>>> = RwLock::new(None);
+
+/// A local policy check of the prompts of a request
+pub trait PromptScreen: Send + Sync {
+    /// `Ok` to send the request, or why it may not be sent
+    fn screen(&self, prompts: &PromptGroups) -> std::result::Result<(), String>;
+}
+
+/// Lets every prompt through, as when no screen is installed
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopScreen;
+
+impl PromptScreen for NoopScreen {
+    fn screen(&self, _: &PromptGroups) -> std::result::Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Screen the prompts of every request with `screen`
+pub fn install(screen: Arc<dyn PromptScreen>) {
+    *GLOBAL.write().unwrap() = Some(screen);
+}
+
+pub fn uninstall() {
+    *GLOBAL.write().unwrap() = None;
+}
+
+pub fn installed() -> Option<Arc<dyn PromptScreen>> {
+    GLOBAL.read().unwrap().clone()
+}
+
+/// Pass `prompts` through the installed screen, if there is one
+// only the endpoint modules send prompts
+#[cfg_attr(
+    not(any(feature = "text-to-image", feature = "image-to-image")),
+    allow(dead_code)
+)]
+pub(crate) fn check(prompts: &PromptGroups) -> Result<()> {
+    match installed().map(|screen| screen.screen(prompts)) {
+        Some(Err(reason)) => Err(Box::new(Error::PromptRejected(reason))),
+        _ => Ok(()),
+    }
+}
+
+/// Rejects prompts containing any of a list of words or phrases
+///
+/// Terms match whole words, ignoring case, so `ass` doesn't reject
+/// `a glass vase`. Only the positive prompts are screened, as negative ones
+/// name what the image should leave out.
+#[cfg(feature = "word-list")]
+#[derive(Debug, Clone, Default)]
+pub struct WordListScreen {
+    /// Each term split into lowercase words
+    terms: Vec<Vec<String>>,
+}
+
+#[cfg(feature = "word-list")]
+impl WordListScreen {
+    pub fn new<I, S>(terms: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let terms = terms
+            .into_iter()
+            .map(|term| words(term.as_ref()))
+            .filter(|words| !words.is_empty())
+            .collect();
+        Self { terms }
+    }
+
+    /// Read the terms from a file of one term per line, skipping blank lines
+    /// and lines starting with `#`
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let list = std::fs::read_to_string(path)?;
+        Ok(Self::new(
+            list.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#')),
+        ))
+    }
+}
+
+#[cfg(feature = "word-list")]
+impl PromptScreen for WordListScreen {
+    fn screen(&self, prompts: &PromptGroups) -> std::result::Result<(), String> {
+        for prompt in &prompts.positive {
+            let words = words(&prompt.text);
+            let banned = self
+                .terms
+                .iter()
+                .find(|term| words.windows(term.len()).any(|window| window == term.as_slice()));
+            if let Some(term) = banned {
+                return Err(format!("the prompt contains \"{}\"", term.join(" ")));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The lowercase words of `text`, split at anything but letters, digits and
+/// apostrophes
+#[cfg(feature = "word-list")]
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::rest::generation::WeightedPrompt;
+
+    fn prompts(positive: &str, negative: &str) -> PromptGroups {
+        PromptGroups {
+            positive: vec![WeightedPrompt {
+                text: positive.to_string(),
+                weight: 1.0,
+            }],
+            negative: vec![WeightedPrompt {
+                text: negative.to_string(),
+                weight: -1.0,
+            }],
+        }
+    }
+
+    #[cfg(feature = "text-to-image")]
+    #[tokio::test]
+    async fn generate_is_not_sending_prompts_the_screen_rejects() {
+        use crate::testing::{image_response, FakeTransport};
+        use crate::text_to_img::TextToImageBuilder;
+        use crate::StylePreset;
+
+        struct Rejecting;
+        impl PromptScreen for Rejecting {
+            fn screen(&self, prompts: &PromptGroups) -> std::result::Result<(), String> {
+                match prompts.positive[0].text.contains("zyzzyva") {
+                    true => Err("no zyzzyvas".to_string()),
+                    false => Ok(()),
+                }
+            }
+        }
+        let request = TextToImageBuilder::new()
+            .style_preset(StylePreset::Photographic)
+            .unwrap()
+            .text_prompt("a zyzzyva on a leaf", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        install(Arc::new(Rejecting));
+        let transport = FakeTransport::with_response(&image_response(&[1]));
+        let err = transport
+            .scope(request.generate("stable-diffusion-xl-1024-v1-0"))
+            .await
+            .unwrap_err();
+        uninstall();
+
+        assert_eq!(
+            err.to_string(),
+            "the prompt was rejected before sending: no zyzzyvas"
+        );
+        assert!(transport.requests().is_empty());
+    }
+
+    #[test]
+    fn noop_screen_is_letting_everything_through() {
+        assert!(NoopScreen.screen(&prompts("anything", "at all")).is_ok());
+    }
+
+    #[cfg(feature = "word-list")]
+    #[test]
+    fn word_list_screen_is_matching_whole_words_of_positive_prompts() {
+        let screen = WordListScreen::new(["Ass", "blood bath", ""]);
+
+        assert!(screen.screen(&prompts("a glass vase", "ass")).is_ok());
+        assert!(screen.screen(&prompts("a blood red bath", "")).is_ok());
+        assert_eq!(
+            screen.screen(&prompts("A BLOOD-BATH at dawn", "")),
+            Err("the prompt contains \"blood bath\"".to_string())
+        );
+    }
+}
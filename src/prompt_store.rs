@@ -0,0 +1,231 @@
+//! A small library of named prompts, kept in a JSON file.
+//!
+//! CLI and desktop tools built on this crate can save the prompts a user
+//! writes, along with negatives and tags, and load them back into any
+//! builder through its `text_prompts` setter.
+//!
+#![cfg_attr(feature = "text-to-image", doc = "```no_run")]
+#![cfg_attr(not(feature = "text-to-image"), doc = "```ignore")]
+//! use stability_rs::prompt_store::{PromptStore, SavedPrompt};
+//! use stability_rs::{text_to_img::*, Result, StylePreset};
+//!
+//! fn main() -> Result<()> {
+//!     let mut store = PromptStore::open("prompts.json")?;
+//!     store.save(
+//!         SavedPrompt::new("lighthouse")?
+//!             .positive("a lighthouse at dusk", 1.0)?
+//!             .negative("fog", 0.5)?
+//!             .tag("coast")?,
+//!     )?;
+//!
+//!     let saved = store.get("lighthouse").unwrap();
+//!     let request = TextToImageBuilder::new()
+//!         .style_preset(StylePreset::Photographic)?
+//!         .text_prompts(saved.text_prompts()?)?
+//!         .build()?;
+//!     # let _ = request;
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::api::rest::generation::{PromptGroups, TextPrompt, WeightedPrompt};
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// A named set of prompts and the tags it was filed under
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SavedPrompt {
+    pub name: String,
+    pub prompts: PromptGroups,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl SavedPrompt {
+    pub fn new(name: &str) -> Result<Self> {
+        Ok(Self {
+            name: name.to_string(),
+            prompts: PromptGroups::default(),
+            tags: Vec::new(),
+        })
+    }
+
+    pub fn positive(mut self, text: &str, weight: f32) -> Result<Self> {
+        let prompt = TextPrompt::new(text, weight.abs())?;
+        self.prompts.positive.push(WeightedPrompt {
+            text: prompt.text,
+            weight: prompt.weight,
+        });
+        Ok(self)
+    }
+
+    /// Add a prompt to steer away from; the weight is stored negated, so
+    /// either sign may be passed
+    pub fn negative(mut self, text: &str, weight: f32) -> Result<Self> {
+        let prompt = TextPrompt::new(text, -weight.abs())?;
+        self.prompts.negative.push(WeightedPrompt {
+            text: prompt.text,
+            weight: prompt.weight,
+        });
+        Ok(self)
+    }
+
+    pub fn tag(mut self, tag: &str) -> Result<Self> {
+        if !self.tags.iter().any(|t| t == tag) {
+            self.tags.push(tag.to_string());
+        }
+        Ok(self)
+    }
+
+    /// The prompts in the order a builder takes them, positive ones first
+    pub fn text_prompts(&self) -> Result<Vec<TextPrompt>> {
+        self.prompts
+            .positive
+            .iter()
+            .chain(&self.prompts.negative)
+            .map(|prompt| TextPrompt::new(&prompt.text, prompt.weight))
+            .collect()
+    }
+}
+
+/// Saved prompts by name, written back to their file on every change
+#[derive(Debug)]
+pub struct PromptStore {
+    path: PathBuf,
+    prompts: BTreeMap<String, SavedPrompt>,
+}
+
+impl PromptStore {
+    /// Load the store at `path`, or start an empty one if the file does not
+    /// exist yet
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let prompts = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice::<Vec<SavedPrompt>>(&bytes)?
+                .into_iter()
+                .map(|prompt| (prompt.name.clone(), prompt))
+                .collect(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(Box::new(err)),
+        };
+        Ok(Self { path, prompts })
+    }
+
+    /// Add `prompt`, replacing any saved under the same name
+    pub fn save(&mut self, prompt: SavedPrompt) -> Result<()> {
+        self.prompts.insert(prompt.name.clone(), prompt);
+        self.write()
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<Option<SavedPrompt>> {
+        let removed = self.prompts.remove(name);
+        if removed.is_some() {
+            self.write()?;
+        }
+        Ok(removed)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SavedPrompt> {
+        self.prompts.get(name)
+    }
+
+    /// Every saved prompt, ordered by name
+    pub fn iter(&self) -> impl Iterator<Item = &SavedPrompt> {
+        self.prompts.values()
+    }
+
+    /// The saved prompts filed under `tag`, ordered by name
+    pub fn tagged<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = &'a SavedPrompt> {
+        self.iter()
+            .filter(move |prompt| prompt.tags.iter().any(|t| t == tag))
+    }
+
+    /// Write to a temporary file first, so a crash mid-write can't lose the
+    /// prompts saved before
+    fn write(&self) -> Result<()> {
+        crate::preflight::create_parent_dirs(&self.path)?;
+        let prompts: Vec<&SavedPrompt> = self.prompts.values().collect();
+        crate::atomic_write::write(&self.path, &serde_json::to_vec_pretty(&prompts)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "stability_rs_prompts_{}_{}.json",
+            std::process::id(),
+            name
+        ))
+    }
+
+    #[test]
+    fn open_is_reading_back_saved_prompts() {
+        let path = temp_path("reopen");
+        let mut store = PromptStore::open(&path).unwrap();
+        store
+            .save(
+                SavedPrompt::new("lighthouse")
+                    .unwrap()
+                    .positive("a lighthouse", 1.0)
+                    .unwrap()
+                    .negative("fog", 0.5)
+                    .unwrap()
+                    .tag("coast")
+                    .unwrap(),
+            )
+            .unwrap();
+        store
+            .save(
+                SavedPrompt::new("fox")
+                    .unwrap()
+                    .positive("a fox", 1.0)
+                    .unwrap(),
+            )
+            .unwrap();
+
+        let store = PromptStore::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let saved = store.get("lighthouse").unwrap();
+        assert_eq!(saved.prompts.negative[0].weight, -0.5);
+        assert_eq!(
+            store
+                .tagged("coast")
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["lighthouse"]
+        );
+        assert_eq!(store.iter().count(), 2);
+    }
+
+    #[test]
+    fn text_prompts_is_listing_positive_prompts_first() {
+        let saved = SavedPrompt::new("crab")
+            .unwrap()
+            .negative("stones", -0.9)
+            .unwrap()
+            .positive("a crab", 0.5)
+            .unwrap();
+
+        let prompts = saved.text_prompts().unwrap();
+
+        assert_eq!(prompts[0].text(), "a crab");
+        assert_eq!(prompts[1].weight(), -0.9);
+    }
+
+    #[test]
+    fn positive_is_erring_on_empty_text() {
+        let err = SavedPrompt::new("empty")
+            .unwrap()
+            .positive("", 1.0)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "a text prompt must not be empty");
+    }
+}
@@ -0,0 +1,171 @@
+//! Weights written into the prompt text, in the syntax of A1111-style tools.
+//!
+//! `(text)` raises the weight of `text` by 1.1, `[text]` lowers it by the
+//! same factor, and `(text:1.5)` sets a weight of its own. Groups nest, their
+//! weights multiplying, and `\(` or `\[` stand for a literal bracket.
+//! [`parse`] splits the prompt at each change of weight into the
+//! [`TextPrompt`]s a builder's `text_prompts` setter takes.
+//!
+#![cfg_attr(feature = "text-to-image", doc = "```no_run")]
+#![cfg_attr(not(feature = "text-to-image"), doc = "```ignore")]
+//! use stability_rs::{prompt_syntax, text_to_img::*, Result, StylePreset};
+//!
+//! fn main() -> Result<()> {
+//!     let request = TextToImageBuilder::new()
+//!         .style_preset(StylePreset::Photographic)?
+//!         .text_prompts(prompt_syntax::parse(
+//!             "a lighthouse at dusk, (crashing waves:1.3), [fog]",
+//!         )?)?
+//!         .build()?;
+//!     # let _ = request;
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! As elsewhere in the API, a negative weight such as `(fog:-0.5)` turns
+//! the text into a negative prompt.
+
+use crate::api::rest::generation::TextPrompt;
+use crate::error::Error;
+use crate::prelude::*;
+
+/// The factor `(text)` multiplies the weight by, and `[text]` divides it by
+pub const EMPHASIS: f32 = 1.1;
+
+/// Split `prompt` into weighted prompts, one per run of text with the same
+/// weight
+///
+/// Plain text has a weight of 1. Commas and whitespace around the runs are
+/// dropped, and a prompt of brackets only yields no prompts at all.
+pub fn parse(prompt: &str) -> Result<Vec<TextPrompt>> {
+    let mut segments: Vec<(String, f32)> = Vec::new();
+    // the bracket, its position and the first segment inside it
+    let mut open: Vec<(char, usize, usize)> = Vec::new();
+    let mut text = String::new();
+
+    let mut chars = prompt.char_indices();
+    while let Some((position, c)) = chars.next() {
+        match c {
+            '\\' => text.push(chars.next().map_or('\\', |(_, escaped)| escaped)),
+            '(' | '[' => {
+                flush(&mut text, &mut segments);
+                open.push((c, position, segments.len()));
+            }
+            ')' | ']' => {
+                let expected = if c == ')' { '(' } else { '[' };
+                let Some((_, _, first)) = open.pop().filter(|(bracket, ..)| *bracket == expected)
+                else {
+                    return Err(Box::new(Error::PromptSyntax {
+                        position,
+                        message: "a closing bracket has no matching opening bracket",
+                    }));
+                };
+
+                let weight = if c == ']' {
+                    1.0 / EMPHASIS
+                } else {
+                    take_weight(&mut text).unwrap_or(EMPHASIS)
+                };
+                flush(&mut text, &mut segments);
+                for segment in &mut segments[first..] {
+                    segment.1 *= weight;
+                }
+            }
+            _ => text.push(c),
+        }
+    }
+    if let Some((_, position, _)) = open.first() {
+        return Err(Box::new(Error::PromptSyntax {
+            position: *position,
+            message: "a bracket is never closed",
+        }));
+    }
+    flush(&mut text, &mut segments);
+
+    let mut merged: Vec<(String, f32)> = Vec::new();
+    for (text, weight) in segments {
+        let text = text.trim_matches(|c: char| c.is_whitespace() || c == ',');
+        if text.is_empty() {
+            continue;
+        }
+        match merged.last_mut() {
+            Some(last) if last.1 == weight => {
+                last.0.push_str(", ");
+                last.0.push_str(text);
+            }
+            _ => merged.push((text.to_string(), weight)),
+        }
+    }
+
+    merged
+        .into_iter()
+        .map(|(text, weight)| TextPrompt::new(&text, weight))
+        .collect()
+}
+
+/// Strip a trailing `:1.5` from `text` and return the weight it gives
+fn take_weight(text: &mut String) -> Option<f32> {
+    let (rest, weight) = text.rsplit_once(':')?;
+    let weight = weight.trim().parse::<f32>().ok().filter(|w| w.is_finite())?;
+    text.truncate(rest.len());
+    Some(weight)
+}
+
+fn flush(text: &mut String, segments: &mut Vec<(String, f32)>) {
+    if !text.is_empty() {
+        segments.push((std::mem::take(text), 1.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weights(prompt: &str) -> Vec<(String, f32)> {
+        parse(prompt)
+            .unwrap()
+            .into_iter()
+            .map(|p| (p.text, p.weight))
+            .collect()
+    }
+
+    #[test]
+    fn parse_is_weighting_emphasis_and_explicit_weights() {
+        assert_eq!(
+            weights("a lighthouse, (waves), (storm clouds:1.5), [fog]"),
+            vec![
+                ("a lighthouse".to_string(), 1.0),
+                ("waves".to_string(), EMPHASIS),
+                ("storm clouds".to_string(), 1.5),
+                ("fog".to_string(), 1.0 / EMPHASIS),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_is_multiplying_nested_weights() {
+        assert_eq!(
+            weights("((a harbour:2) at night:0.5)"),
+            vec![("a harbour".to_string(), 1.0), ("at night".to_string(), 0.5)]
+        );
+    }
+
+    #[test]
+    fn parse_is_keeping_escaped_brackets() {
+        assert_eq!(
+            weights(r"a sign reading \(open\)"),
+            vec![("a sign reading (open)".to_string(), 1.0)]
+        );
+    }
+
+    #[test]
+    fn parse_is_erring_on_unbalanced_brackets() {
+        let err = parse("a lighthouse (at dusk").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the prompt syntax is invalid at byte 13: a bracket is never closed"
+        );
+        assert!(parse("a lighthouse (at dusk]").is_err());
+    }
+}
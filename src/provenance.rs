@@ -0,0 +1,536 @@
+//! Embedding C2PA (content credentials) manifests into generated PNGs.
+//!
+//! A [`Provenance`] records which generator and engine produced an image,
+//! a SHA-256 of the prompt and when it was created. It is embedded as a C2PA
+//! manifest store: a JUMBF box in a `caBX` chunk, holding one manifest with
+//! a `c2pa.actions` assertion whose `c2pa.created` action carries the IPTC
+//! digital source type `trainedAlgorithmicMedia`, a `stability_rs.generation`
+//! assertion with the engine, prompt hash and seed, a `c2pa.hash.data`
+//! assertion binding the manifest to the image, and the claim listing them.
+//!
+//! The manifest is not signed, as that takes a certificate the crate doesn't
+//! have, so its claim's `signature` reference is left unresolved. C2PA tools
+//! find and read it, but report it as unsigned: it labels an image without
+//! proving where the image came from.
+//!
+#![cfg_attr(feature = "text-to-image", doc = "```no_run")]
+#![cfg_attr(not(feature = "text-to-image"), doc = "```ignore")]
+//! use stability_rs::{provenance::Provenance, text_to_img::*, Result, StylePreset};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     let engine = "stable-diffusion-xl-1024-v1-0";
+//!     let prompt = "A lighthouse on a cliff";
+//!     let resp = TextToImageBuilder::new()
+//!         .style_preset(StylePreset::Photographic)?
+//!         .text_prompt(prompt, 1.0)?
+//!         .build()?
+//!         .generate(engine)
+//!         .await?;
+//!
+//!     let provenance = Provenance::new(engine, prompt);
+//!     for (i, image) in resp.artifacts.iter().enumerate() {
+//!         let artifact = image.to_artifact()?.with_provenance(&provenance)?;
+//!         artifact.save(&format!("image_{}.png", i)).await?;
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::api::rest::artifact::Artifact;
+use crate::error::Error;
+use crate::prelude::*;
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// The PNG chunk type C2PA manifest stores are embedded in
+pub const CHUNK_TYPE: &[u8; 4] = b"caBX";
+const TRAINED_ALGORITHMIC_MEDIA: &str =
+    "http://cv.iptc.org/newscodes/digitalsourcetype/trainedAlgorithmicMedia";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Provenance {
+    generator: String,
+    engine: String,
+    prompt_hash: String,
+    seed: Option<u32>,
+    created: SystemTime,
+}
+
+impl Provenance {
+    /// Provenance of an image generated now by `engine` from `prompt`
+    ///
+    /// Only the SHA-256 of the prompt is kept, so the prompt itself is not
+    /// published with the image.
+    pub fn new(engine: &str, prompt: &str) -> Self {
+        Self {
+            generator: format!("stability_rs/{}", env!("CARGO_PKG_VERSION")),
+            engine: engine.to_string(),
+            prompt_hash: hex(&Sha256::digest(prompt.as_bytes())),
+            seed: None,
+            created: SystemTime::now(),
+        }
+    }
+
+    /// Name the application which generated the image, instead of this crate
+    pub fn generator(mut self, generator: &str) -> Result<Self> {
+        self.generator = generator.to_string();
+        Ok(self)
+    }
+
+    /// Record the seed the API used, as read from the response, so the image
+    /// can be reproduced even when the request left the seed random
+    pub fn seed(mut self, seed: u32) -> Result<Self> {
+        self.seed = Some(seed);
+        Ok(self)
+    }
+
+    pub fn created(mut self, created: SystemTime) -> Result<Self> {
+        self.created = created;
+        Ok(self)
+    }
+
+    pub fn engine(&self) -> &str {
+        &self.engine
+    }
+
+    pub fn prompt_hash(&self) -> &str {
+        &self.prompt_hash
+    }
+
+    /// Insert the manifest store into `png` as a `caBX` chunk just before
+    /// IEND
+    pub fn embed_png(&self, png: &[u8]) -> Result<Vec<u8>> {
+        let Some(iend) = iend_offset(png) else {
+            return Err(Box::new(Error::UnsupportedContentType(
+                "a malformed PNG".to_string(),
+            )));
+        };
+
+        // the data hash covers the image but the chunk, which leaves the
+        // PNG as it is now; the chunk's length is part of the chunk, so the
+        // store is built again until that length is encoded in as many
+        // bytes as it was assumed to take
+        let hash = Sha256::digest(png);
+        let mut chunk_len = 0;
+        let store = loop {
+            let store = self.manifest_store(iend, chunk_len, &hash);
+            if store.len() + 12 == chunk_len {
+                break store;
+            }
+            chunk_len = store.len() + 12;
+        };
+
+        let mut out = png[..iend].to_vec();
+        out.extend_from_slice(&(store.len() as u32).to_be_bytes());
+        let crc_start = out.len();
+        out.extend_from_slice(CHUNK_TYPE);
+        out.extend_from_slice(&store);
+        let crc = crc32(&out[crc_start..]);
+        out.extend_from_slice(&crc.to_be_bytes());
+        out.extend_from_slice(&png[iend..]);
+        Ok(out)
+    }
+
+    /// The JUMBF manifest store, for a chunk of `chunk_len` bytes at `start`
+    /// in a PNG whose other bytes hash to `hash`
+    fn manifest_store(&self, start: usize, chunk_len: usize, hash: &[u8]) -> Vec<u8> {
+        let mut generation = vec![
+            ("engine", Cbor::text(&self.engine)),
+            ("prompt_sha256", Cbor::text(&self.prompt_hash)),
+        ];
+        if let Some(seed) = self.seed {
+            generation.push(("seed", Cbor::Int(seed.into())));
+        }
+        let assertions = [
+            (
+                "c2pa.actions",
+                Cbor::Map(vec![(
+                    "actions",
+                    Cbor::Array(vec![Cbor::Map(vec![
+                        ("action", Cbor::text("c2pa.created")),
+                        ("when", Cbor::Text(rfc3339(self.created))),
+                        ("softwareAgent", Cbor::text(&self.engine)),
+                        ("digitalSourceType", Cbor::text(TRAINED_ALGORITHMIC_MEDIA)),
+                    ])]),
+                )]),
+            ),
+            ("stability_rs.generation", Cbor::Map(generation)),
+            (
+                "c2pa.hash.data",
+                Cbor::Map(vec![
+                    (
+                        "exclusions",
+                        Cbor::Array(vec![Cbor::Map(vec![
+                            ("start", Cbor::Int(start as u64)),
+                            ("length", Cbor::Int(chunk_len as u64)),
+                        ])]),
+                    ),
+                    ("name", Cbor::text("jumbf manifest")),
+                    ("alg", Cbor::text("sha256")),
+                    ("hash", Cbor::Bytes(hash.to_vec())),
+                    ("pad", Cbor::Bytes(Vec::new())),
+                ]),
+            ),
+        ];
+
+        // each assertion is referenced from the claim by the hash of its
+        // box's contents, the description and the CBOR box
+        let mut boxes = Vec::new();
+        let mut references = Vec::new();
+        for (label, data) in assertions {
+            let assertion = superbox(b"cbor", label, &[jumbf_box(b"cbor", &data.encode())]);
+            references.push(Cbor::Map(vec![
+                ("url", Cbor::Text(format!("self#jumbf=c2pa.assertions/{}", label))),
+                ("hash", Cbor::Bytes(Sha256::digest(&assertion[8..]).to_vec())),
+            ]));
+            boxes.push(assertion);
+        }
+
+        let id = self.instance_id();
+        let claim = Cbor::Map(vec![
+            ("claim_generator", Cbor::text(&self.generator)),
+            ("signature", Cbor::text("self#jumbf=c2pa.signature")),
+            ("assertions", Cbor::Array(references)),
+            ("dc:format", Cbor::text(IMAGE_PNG)),
+            ("instanceID", Cbor::Text(format!("xmp:iid:{}", id))),
+            ("alg", Cbor::text("sha256")),
+        ]);
+        let manifest = superbox(
+            b"c2ma",
+            &format!("urn:uuid:{}", id),
+            &[
+                superbox(b"c2as", "c2pa.assertions", &boxes),
+                superbox(b"c2cl", "c2pa.claim", &[jumbf_box(b"cbor", &claim.encode())]),
+            ],
+        );
+        superbox(b"c2pa", "c2pa", &[manifest])
+    }
+
+    /// A UUID shaped like a random one, taken from the hash of the
+    /// provenance so the same image is always labelled the same
+    fn instance_id(&self) -> String {
+        let mut hasher = Sha256::new();
+        for part in [&self.generator, &self.engine, &self.prompt_hash] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        hasher.update(self.seed.unwrap_or_default().to_be_bytes());
+        hasher.update(rfc3339(self.created).as_bytes());
+        let mut bytes: [u8; 16] = hasher.finalize()[..16].try_into().unwrap();
+        bytes[6] = bytes[6] & 0x0f | 0x40;
+        bytes[8] = bytes[8] & 0x3f | 0x80;
+        let hex = hex(&bytes);
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+}
+
+impl Artifact {
+    /// A copy of this PNG artifact with `provenance` embedded
+    pub fn with_provenance(&self, provenance: &Provenance) -> Result<Artifact> {
+        if self.content_type() != IMAGE_PNG {
+            return Err(Box::new(Error::UnsupportedContentType(
+                self.content_type().to_string(),
+            )));
+        }
+        let png = provenance.embed_png(self.bytes())?;
+        Artifact::new(IMAGE_PNG, png.into())
+    }
+}
+
+/// Where the IEND chunk starts, if `png` can be walked up to it
+fn iend_offset(png: &[u8]) -> Option<usize> {
+    if !png.starts_with(PNG_SIGNATURE) {
+        return None;
+    }
+    let mut pos = PNG_SIGNATURE.len();
+    loop {
+        let len = u32::from_be_bytes(png.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        if png.get(pos + 4..pos + 8)? == b"IEND" {
+            return Some(pos);
+        }
+        pos += 12 + len;
+    }
+}
+
+/// A JUMBF box of type `kind` around `payload`
+fn jumbf_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut out = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(payload);
+    out
+}
+
+/// A JUMBF superbox of the C2PA content type `kind`, such as `c2cl` for a
+/// claim, labelled `label` and holding `contents`
+fn superbox(kind: &[u8; 4], label: &str, contents: &[Vec<u8>]) -> Vec<u8> {
+    // the C2PA content types are a four letter code followed by the same
+    // twelve bytes
+    let mut description = kind.to_vec();
+    description.extend_from_slice(&[
+        0x00, 0x11, 0x00, 0x10, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+    ]);
+    // requestable, with a label
+    description.push(0x03);
+    description.extend_from_slice(label.as_bytes());
+    description.push(0);
+
+    let mut payload = jumbf_box(b"jumd", &description);
+    for content in contents {
+        payload.extend_from_slice(content);
+    }
+    jumbf_box(b"jumb", &payload)
+}
+
+/// The few CBOR values a manifest is made of
+enum Cbor {
+    Int(u64),
+    Text(String),
+    Bytes(Vec<u8>),
+    Array(Vec<Cbor>),
+    Map(Vec<(&'static str, Cbor)>),
+}
+
+impl Cbor {
+    fn text(text: &str) -> Self {
+        Cbor::Text(text.to_string())
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out);
+        out
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        match self {
+            Cbor::Int(n) => cbor_head(out, 0, *n),
+            Cbor::Bytes(bytes) => {
+                cbor_head(out, 2, bytes.len() as u64);
+                out.extend_from_slice(bytes);
+            }
+            Cbor::Text(text) => {
+                cbor_head(out, 3, text.len() as u64);
+                out.extend_from_slice(text.as_bytes());
+            }
+            Cbor::Array(items) => {
+                cbor_head(out, 4, items.len() as u64);
+                for item in items {
+                    item.encode_into(out);
+                }
+            }
+            Cbor::Map(entries) => {
+                cbor_head(out, 5, entries.len() as u64);
+                for (key, value) in entries {
+                    Cbor::text(key).encode_into(out);
+                    value.encode_into(out);
+                }
+            }
+        }
+    }
+}
+
+/// The major type and argument every CBOR item starts with, the argument
+/// in as few bytes as it fits
+fn cbor_head(out: &mut Vec<u8>, major: u8, n: u64) {
+    let major = major << 5;
+    match n {
+        0..=23 => out.push(major | n as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, n as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend_from_slice(&(n as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend_from_slice(&n.to_be_bytes());
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The CRC-32 PNG chunks end with, over the chunk type and data
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// `time` in UTC as `YYYY-MM-DDTHH:MM:SSZ`
+pub(crate) fn rfc3339(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // days since the epoch to a civil date, after Howard Hinnant
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn png() -> Vec<u8> {
+        let chunk = |kind: &[u8], data: &[u8]| {
+            let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+            chunk.extend_from_slice(kind);
+            chunk.extend_from_slice(data);
+            let crc = crc32(&chunk[4..]);
+            chunk.extend_from_slice(&crc.to_be_bytes());
+            chunk
+        };
+        [
+            PNG_SIGNATURE.to_vec(),
+            chunk(b"IHDR", &[1; 13]),
+            chunk(b"IEND", &[]),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn crc32_is_matching_the_png_iend_crc() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+    }
+
+    /// The data of the `caBX` chunk `png` ends with before IEND, and where
+    /// the chunk starts
+    fn manifest_chunk(png: &[u8]) -> (usize, &[u8]) {
+        let end = png.len() - 12;
+        let len = png[..end]
+            .windows(4)
+            .rposition(|w| w == CHUNK_TYPE)
+            .map(|kind| end - kind - 8)
+            .unwrap();
+        let start = end - len - 12;
+        assert_eq!(&png[start..start + 4], (len as u32).to_be_bytes());
+        let crc = crc32(&png[start + 4..end - 4]);
+        assert_eq!(&png[end - 4..end], crc.to_be_bytes());
+        (start, &png[start + 8..end - 4])
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    #[test]
+    fn cbor_is_encoding_arguments_in_as_few_bytes_as_fit() {
+        assert_eq!(Cbor::Int(23).encode(), [0x17]);
+        assert_eq!(Cbor::Int(42).encode(), [0x18, 0x2a]);
+        assert_eq!(Cbor::Int(1_000).encode(), [0x19, 0x03, 0xe8]);
+        assert_eq!(
+            Cbor::Map(vec![("a", Cbor::Array(vec![Cbor::text("b")]))]).encode(),
+            [0xa1, 0x61, b'a', 0x81, 0x61, b'b']
+        );
+    }
+
+    #[test]
+    fn manifest_store_is_embedded_before_iend() {
+        let provenance = Provenance::new("stable-diffusion-xl-1024-v1-0", "a lighthouse")
+            .created(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+            .unwrap();
+        let out = provenance.embed_png(&png()).unwrap();
+
+        assert!(out.ends_with(&png()[png().len() - 12..]));
+        let (_, store) = manifest_chunk(&out);
+        assert_eq!(&store[4..8], b"jumb");
+        assert_eq!(&store[12..16], b"jumd");
+        assert_eq!(&store[16..20], b"c2pa");
+        for label in ["c2pa.assertions", "c2pa.claim", "c2pa.actions", "c2pa.hash.data"] {
+            assert!(contains(store, label.as_bytes()), "{} is missing", label);
+        }
+        assert!(contains(store, b"2023-11-14T22:13:20Z"));
+        assert!(contains(store, b"trainedAlgorithmicMedia"));
+        assert!(!contains(&out, b"a lighthouse"));
+    }
+
+    #[test]
+    fn data_hash_is_excluding_exactly_the_manifest_chunk() {
+        let png = png();
+        let out = Provenance::new("engine", "prompt").embed_png(&png).unwrap();
+
+        let (start, store) = manifest_chunk(&out);
+        let chunk_len = store.len() + 12;
+        let without = [&out[..start], &out[start + chunk_len..]].concat();
+        assert_eq!(without, png);
+        let exclusion = Cbor::Map(vec![
+            ("start", Cbor::Int(start as u64)),
+            ("length", Cbor::Int(chunk_len as u64)),
+        ]);
+        assert!(contains(store, &exclusion.encode()));
+        assert!(contains(store, &Cbor::Bytes(Sha256::digest(&png).to_vec()).encode()));
+    }
+
+    #[test]
+    fn claim_is_referencing_each_assertion_by_hash() {
+        let out = Provenance::new("engine", "prompt").embed_png(&png()).unwrap();
+        let (_, store) = manifest_chunk(&out);
+
+        let generation = Cbor::text("self#jumbf=c2pa.assertions/stability_rs.generation");
+        assert!(contains(store, &generation.encode()));
+        assert!(contains(store, &Cbor::text("self#jumbf=c2pa.signature").encode()));
+    }
+
+    #[test]
+    fn manifest_is_recording_the_seed_used() {
+        let seed = [Cbor::text("seed").encode(), Cbor::Int(42).encode()].concat();
+        let seeded = Provenance::new("engine", "prompt").seed(42).unwrap();
+        let out = seeded.embed_png(&png()).unwrap();
+        assert!(contains(manifest_chunk(&out).1, &seed));
+
+        let out = Provenance::new("engine", "prompt").embed_png(&png()).unwrap();
+        assert!(!contains(manifest_chunk(&out).1, &Cbor::text("seed").encode()));
+    }
+
+    #[test]
+    fn embed_png_is_erring_on_other_formats() {
+        assert!(Provenance::new("engine", "prompt")
+            .embed_png(b"GIF89a")
+            .is_err());
+    }
+}
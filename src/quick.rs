@@ -0,0 +1,149 @@
+//! One-line generations with sensible defaults.
+//!
+//! For scripts and first experiments which don't need to tune a request:
+//! each helper builds the request with the defaults below, sends it and
+//! returns the first successful image, or the first image if all of them were
+//! filtered. Reach for the builders as soon as a setting needs to change.
+//!
+//! - engine: `stable-diffusion-xl-1024-v1-0`
+//! - style preset: [`StylePreset::Enhance`]
+//! - one sample at a random seed, 1024x1024 for text-to-image
+//!
+#![cfg_attr(all(feature = "text-to-image", feature = "upscale"), doc = "```no_run")]
+#![cfg_attr(not(all(feature = "text-to-image", feature = "upscale")), doc = "```ignore")]
+//! use stability_rs::{quick, Result};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     let image = quick::text_to_image("A lighthouse in a storm").await?;
+//!     image.save("lighthouse.png").await?;
+//!
+//!     let image = quick::upscale("lighthouse.png", 2).await?;
+//!     image.save("lighthouse_2x.png").await?;
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::api::rest::generation::{Image, ImageResponse};
+use crate::error::Error;
+use crate::prelude::*;
+use crate::StylePreset;
+#[cfg(feature = "image-to-image")]
+use std::path::Path;
+
+/// The engine the text-to-image and image-to-image helpers generate with
+pub const DEFAULT_ENGINE: &str = "stable-diffusion-xl-1024-v1-0";
+
+/// How much the init image shapes an [`image_to_image`] result
+#[cfg(feature = "image-to-image")]
+pub const DEFAULT_IMAGE_STRENGTH: f32 = 0.35;
+
+/// Generate an image from `prompt`
+#[cfg(feature = "text-to-image")]
+pub async fn text_to_image(prompt: &str) -> Result<Image> {
+    use crate::text_to_img::TextToImageBuilder;
+
+    let request = TextToImageBuilder::new()
+        .style_preset(StylePreset::Enhance)?
+        .text_prompt(prompt, 1.0)?
+        .build()?;
+    first_image(request.generate(DEFAULT_ENGINE).await?)
+}
+
+/// Generate an image from the image at `path`, steered by `prompt`
+#[cfg(feature = "image-to-image")]
+pub async fn image_to_image(path: impl AsRef<Path>, prompt: &str) -> Result<Image> {
+    use crate::img_to_img::{ImageMode, ImageToImageBuilder};
+
+    let request = ImageToImageBuilder::new()
+        .init_image_path(path)?
+        .init_image_mode(ImageMode::ImageStrength)?
+        .image_strength(DEFAULT_IMAGE_STRENGTH)?
+        .style_preset(StylePreset::Enhance)?
+        .text_prompt(prompt, 1.0)?
+        .build()?;
+    first_image(request.generate(DEFAULT_ENGINE).await?)
+}
+
+/// Upscale the image at `path` `factor` times, with the engine for that
+/// factor: 2 or 4
+#[cfg(feature = "upscale")]
+pub async fn upscale(path: impl AsRef<Path>, factor: u32) -> Result<Image> {
+    use crate::error::ImageBuilderError;
+    use crate::upscale::{UpscaleEngine, UpscalerBuilder};
+
+    let engine = match factor {
+        2 => UpscaleEngine::EsrganV1X2Plus,
+        4 => UpscaleEngine::StableDiffusionX4LatentUpscaler,
+        _ => return Err(Box::new(ImageBuilderError::UpscaleFactorUnsupported(factor))),
+    };
+    let request = UpscalerBuilder::new().image(path)?.build()?;
+    first_image(request.generate(engine).await?)
+}
+
+/// The first successful artifact of `resp`, else its first artifact
+fn first_image(resp: ImageResponse) -> Result<Image> {
+    let mut artifacts = resp.artifacts;
+    let index = artifacts
+        .iter()
+        .position(|image| image.finish_reason == "SUCCESS")
+        .unwrap_or(0);
+    if index >= artifacts.len() {
+        return Err(Box::new(Error::NoArtifacts));
+    }
+    Ok(artifacts.swap_remove(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::rest::generation::GenerationMetadata;
+    use crate::testing::image;
+
+    fn response(finish_reasons: &[&str]) -> ImageResponse {
+        ImageResponse {
+            artifacts: finish_reasons
+                .iter()
+                .enumerate()
+                .map(|(seed, reason)| image(seed as u32, reason))
+                .collect(),
+            metadata: GenerationMetadata::default(),
+        }
+    }
+
+    #[test]
+    fn first_image_is_preferring_a_successful_artifact() {
+        let picked = first_image(response(&["CONTENT_FILTERED", "SUCCESS"])).unwrap();
+        assert_eq!(picked.seed, 1);
+
+        let picked = first_image(response(&["CONTENT_FILTERED", "ERROR"])).unwrap();
+        assert_eq!(picked.seed, 0);
+
+        assert!(first_image(response(&[])).is_err());
+    }
+
+    #[cfg(feature = "text-to-image")]
+    #[tokio::test]
+    async fn text_to_image_is_sending_the_defaults() {
+        use crate::testing::FakeTransport;
+
+        let transport = FakeTransport::with_response(&response(&["SUCCESS"]));
+        let image = transport
+            .scope(text_to_image("A lighthouse in a storm"))
+            .await
+            .unwrap();
+
+        assert_eq!(image.seed, 0);
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].uri.path().ends_with(&format!("/{}/text-to-image", DEFAULT_ENGINE)));
+    }
+
+    #[cfg(feature = "upscale")]
+    #[tokio::test]
+    async fn upscale_is_rejecting_factors_without_an_engine() {
+        let err = upscale("in.png", 3).await.unwrap_err();
+        assert_eq!(err.to_string(), "images can be upscaled 2 or 4 times, but not 3 times");
+    }
+}
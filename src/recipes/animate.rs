@@ -0,0 +1,139 @@
+//! Restyling an animation one frame at a time.
+
+use crate::animation::Animation;
+use crate::api::rest::generation::img_to_img::ImageToImage;
+use crate::api::rest::generation::Seed;
+use crate::batch::{BatchItem, BatchRunner};
+use crate::prelude::*;
+use std::path::Path;
+
+/// Run `base` over every frame of the animated GIF or WebP file at `input`
+/// and assemble the results, keeping the frame rate of the input
+///
+/// The frames are written under `source_frames/` in the output directory of
+/// `runner` and sent as a batch, so concurrency, reports and resuming apply
+/// as for any other run. Every frame uses the seed of `base`, or one picked
+/// for the whole animation when `base` leaves it to the API, which keeps the
+/// style from flickering between frames; pin a seed to resume a run.
+///
+/// ```no_run
+/// use stability_rs::{batch::BatchRunner, img_to_img::*, recipes, Result, StylePreset};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<()> {
+///     let base = ImageToImageBuilder::new()
+///         .init_image_path("unused.png")?
+///         .image_strength(0.6)?
+///         .style_preset(StylePreset::Origami)?
+///         .text_prompt("folded paper", 1.0)?
+///         .seed(7)?
+///         .build()?;
+///
+///     let runner = BatchRunner::new("out").concurrency(4)?;
+///     let animation =
+///         recipes::restyle_animation(&runner, &base, "stable-diffusion-v1-6", "input.gif").await?;
+///     animation.save_gif("out/origami.gif", animation.fps().unwrap_or(10))?;
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn restyle_animation(
+    runner: &BatchRunner,
+    base: &ImageToImage,
+    engine: &str,
+    input: impl AsRef<Path>,
+) -> Result<Animation> {
+    let source = Animation::open(input)?;
+    let frames_dir = runner.out_dir().join("source_frames");
+    std::fs::create_dir_all(&frames_dir)?;
+
+    let seed = match base.seed {
+        Seed::Random => Seed::Fixed(rand::random::<u32>().max(1)),
+        seed => seed,
+    };
+
+    let mut items = Vec::with_capacity(source.frames().len());
+    for (i, frame) in source.frames().iter().enumerate() {
+        let path = frames_dir.join(format!("frame_{:04}.png", i));
+        frame.save(&path)?;
+        let request = base
+            .to_builder()
+            .init_image_path(&path)?
+            .seed(seed)?
+            .samples(1)?
+            .build()?;
+        items.push(BatchItem::new(format!("frame_{:04}", i), engine, request));
+    }
+
+    // every frame is needed, so one failing fails the animation
+    let records = runner.run(items).await?.into_result()?;
+    let frames = records
+        .iter()
+        .flatten()
+        .map(|record| Ok(image::open(&record.output_path)?.to_rgba8()))
+        .collect::<Result<Vec<_>>>()?;
+
+    let animation = Animation::from_frames(frames)?;
+    Ok(match source.fps() {
+        Some(fps) => animation.with_fps(fps),
+        None => animation,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_to_img::ImageToImageBuilder;
+    use crate::testing::{image_response, FakeTransport};
+    use crate::StylePreset;
+    use image::{Rgba, RgbaImage};
+
+    #[tokio::test]
+    async fn restyle_animation_is_sending_every_frame_with_one_seed() {
+        let dir = std::env::temp_dir().join(format!("stability_rs_restyle_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.gif");
+        Animation::from_frames(vec![
+            RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255])),
+            RgbaImage::from_pixel(4, 4, Rgba([0, 255, 0, 255])),
+            RgbaImage::from_pixel(4, 4, Rgba([0, 0, 255, 255])),
+        ])
+        .unwrap()
+        .save_gif(&input, 5)
+        .unwrap();
+
+        let base = ImageToImageBuilder::new()
+            .init_image_path("unused.png")
+            .unwrap()
+            .style_preset(StylePreset::Origami)
+            .unwrap()
+            .text_prompt("folded paper", 1.0)
+            .unwrap()
+            .seed(7)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let transport = FakeTransport::with_response(&image_response(&[7]));
+        let runner = BatchRunner::new(dir.join("out"));
+        let animation = transport
+            .scope(restyle_animation(
+                &runner,
+                &base,
+                "stable-diffusion-v1-6",
+                &input,
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(animation.frames().len(), 3);
+        assert_eq!(animation.fps(), Some(5));
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests
+            .iter()
+            .all(|r| String::from_utf8_lossy(&r.body).contains("name=\"seed\"\r\n\r\n7\r\n")));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+//! Flows over a single init image.
+
+use crate::api::rest::generation::img_to_img::{ImageMode, ImageToImage, ImageToImageBuilder};
+#[cfg(feature = "upscale")]
+use crate::api::rest::generation::upscale::{UpscaleEngine, UpscalerBuilder};
+use crate::api::rest::generation::{ImageResponse, StylePreset};
+use crate::batch::BatchOutcome;
+#[cfg(feature = "upscale")]
+use crate::error::Error;
+use crate::prelude::*;
+#[cfg(feature = "upscale")]
+use crate::staging::TempStore;
+use futures_util::future::join_all;
+use std::path::Path;
+
+/// Image strengths variations are spread over; lower strays further from the
+/// init image
+const VARIATION_STRENGTHS: (f32, f32) = (0.3, 0.7);
+/// Keeps the composition while letting the style preset take over
+const RESTYLE_STRENGTH: f32 = 0.35;
+/// Stays close to the init image, only cleaning it up
+#[cfg(feature = "upscale")]
+const ENHANCE_STRENGTH: f32 = 0.65;
+
+/// One of the images made by [`variations`]
+#[derive(Debug, Clone)]
+pub struct Variation {
+    /// How strongly the init image shaped this variation
+    pub strength: f32,
+    pub response: ImageResponse,
+}
+
+/// The two stages of [`enhance_and_upscale`]
+#[cfg(feature = "upscale")]
+#[derive(Debug, Clone)]
+pub struct Enhanced {
+    pub enhanced: ImageResponse,
+    pub upscaled: ImageResponse,
+}
+
+/// `n` variations of `image`, from faithful to loose, ordered by descending
+/// strength, each with the request it was generated from
+pub async fn variations(
+    engine: &str,
+    image: impl AsRef<Path>,
+    prompt: &str,
+    n: usize,
+) -> Result<BatchOutcome<ImageToImage, Variation>> {
+    let image = image.as_ref();
+    let (low, high) = VARIATION_STRENGTHS;
+    let requests = (0..n)
+        .map(|i| {
+            let strength = match n {
+                1 => (low + high) / 2.0,
+                _ => high - (high - low) * i as f32 / (n - 1) as f32,
+            };
+            Ok((strength, image_to_image_request(image, prompt, StylePreset::Enhance, strength)?))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let results = join_all(requests.into_iter().map(|(strength, request)| async move {
+        let response = request
+            .generate(engine)
+            .await
+            .map(|response| Variation { strength, response });
+        (request, response)
+    }))
+    .await;
+    Ok(BatchOutcome::from_results(results))
+}
+
+/// `image` redrawn in `style`, keeping its composition
+pub async fn restyle(
+    engine: &str,
+    image: impl AsRef<Path>,
+    prompt: &str,
+    style: StylePreset,
+) -> Result<ImageResponse> {
+    image_to_image(engine, image, prompt, style, RESTYLE_STRENGTH).await
+}
+
+/// `image` cleaned up with the enhance preset, then upscaled twice its size
+#[cfg(feature = "upscale")]
+pub async fn enhance_and_upscale(
+    engine: &str,
+    image: impl AsRef<Path>,
+    prompt: &str,
+) -> Result<Enhanced> {
+    let enhanced = image_to_image(
+        engine,
+        image,
+        prompt,
+        StylePreset::Enhance,
+        ENHANCE_STRENGTH,
+    )
+    .await?;
+    let Some(first) = enhanced.artifacts.first() else {
+        return Err(Box::new(Error::NoArtifacts));
+    };
+
+    let staging = TempStore::new()?;
+    let path = staging
+        .save(&format!("enhanced_{}.png", first.seed), &first.to_artifact()?)
+        .await?;
+    let upscaled = UpscalerBuilder::new()
+        .image(&path)?
+        .build()?
+        .generate(UpscaleEngine::EsrganV1X2Plus)
+        .await?;
+
+    Ok(Enhanced { enhanced, upscaled })
+}
+
+async fn image_to_image(
+    engine: &str,
+    image: impl AsRef<Path>,
+    prompt: &str,
+    style: StylePreset,
+    strength: f32,
+) -> Result<ImageResponse> {
+    image_to_image_request(image, prompt, style, strength)?
+        .generate(engine)
+        .await
+}
+
+fn image_to_image_request(
+    image: impl AsRef<Path>,
+    prompt: &str,
+    style: StylePreset,
+    strength: f32,
+) -> Result<ImageToImage> {
+    ImageToImageBuilder::new()
+        .init_image_path(image)?
+        .init_image_mode(ImageMode::ImageStrength)?
+        .image_strength(strength)?
+        .style_preset(style)?
+        .text_prompt(prompt, 1.0)?
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::png_1x1;
+    use std::path::PathBuf;
+
+    const ENGINE: &str = "stable-diffusion-xl-1024-v1-0";
+
+    fn init_image(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "stability_rs_recipes_{}_{}.png",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, png_1x1()).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn variations_are_spread_from_faithful_to_loose() {
+        let image = init_image("variations");
+        let outcome = crate::mock::transport()
+            .scope(variations(ENGINE, &image, "a harbour", 3))
+            .await
+            .unwrap();
+        std::fs::remove_file(&image).unwrap();
+
+        let strengths: Vec<f32> = outcome.outputs().map(|v| v.strength).collect();
+        assert_eq!(strengths, vec![0.7, 0.5, 0.3]);
+        assert!(outcome.outputs().all(|v| v.response.artifacts.len() == 1));
+    }
+
+    #[cfg(feature = "upscale")]
+    #[tokio::test]
+    async fn enhance_and_upscale_is_upscaling_the_enhanced_image() {
+        let image = init_image("enhance");
+        let transport = crate::mock::transport();
+        let enhanced = transport
+            .scope(enhance_and_upscale(ENGINE, &image, "a harbour"))
+            .await
+            .unwrap();
+        std::fs::remove_file(&image).unwrap();
+
+        assert_eq!(enhanced.upscaled.artifacts.len(), 1);
+        let requests = transport.requests();
+        assert!(requests[0].uri.path().ends_with("/image-to-image"));
+        assert!(requests[1]
+            .uri
+            .path()
+            .ends_with("/esrgan-v1-x2plus/image-to-image/upscale"));
+    }
+}
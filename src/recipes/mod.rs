@@ -0,0 +1,38 @@
+//! Task-level generation flows.
+//!
+//! Each recipe picks the parameters for a common task and runs the requests
+//! it takes, so the caller only supplies an image or a base request, a prompt
+//! and an engine. [`seed_walk`] and [`strength_walk`] produce ordered frame
+//! sequences, which the `anim` feature can assemble into an animation with
+//! [`assemble_gif`] or [`crate::animation`], and [`restyle_animation`] runs
+//! image-to-image over every frame of an animated GIF or WebP file.
+//!
+#![cfg_attr(feature = "image-to-image", doc = "```no_run")]
+#![cfg_attr(not(feature = "image-to-image"), doc = "```ignore")]
+//! use stability_rs::{recipes, Result};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     let engine = "stable-diffusion-xl-1024-v1-0";
+//!     let variations = recipes::variations(engine, "portrait.png", "a portrait", 4).await?;
+//!
+//!     for (i, variation) in variations.outputs().enumerate() {
+//!         let image = &variation.response.artifacts[0];
+//!         image.save(&format!("variation_{}_{}.png", i, variation.strength)).await?;
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+#[cfg(all(feature = "anim", feature = "image-to-image"))]
+mod animate;
+#[cfg(feature = "image-to-image")]
+mod edit;
+mod walk;
+
+#[cfg(all(feature = "anim", feature = "image-to-image"))]
+pub use animate::*;
+#[cfg(feature = "image-to-image")]
+pub use edit::*;
+pub use walk::*;
@@ -0,0 +1,190 @@
+//! Frame sequences stepping one parameter at a time.
+//!
+//! The frames are generated concurrently, and a [`BatchOutcome`] keeps the
+//! ones which succeeded when others fail.
+
+#[cfg(feature = "image-to-image")]
+use crate::api::rest::generation::img_to_img::{ImageMode, ImageToImage};
+#[cfg(feature = "text-to-image")]
+use crate::api::rest::generation::text_to_img::TextToImage;
+use crate::api::rest::generation::ImageResponse;
+use crate::batch::BatchOutcome;
+use crate::prelude::*;
+#[cfg(any(feature = "text-to-image", feature = "image-to-image"))]
+use futures_util::future::join_all;
+
+/// The strengths [`strength_walk`] is commonly run over, from loose to
+/// faithful
+pub const STRENGTH_RANGE: (f32, f32) = (0.2, 0.8);
+
+/// One generation of a walk, in sequence order
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// The seed the API used for the frame's first image
+    pub seed: u32,
+    /// The image strength, for frames of a [`strength_walk`]
+    pub image_strength: Option<f32>,
+    pub response: ImageResponse,
+}
+
+/// `count` generations of `base` at consecutive seeds from `start_seed`,
+/// each with the request it was generated from
+#[cfg(feature = "text-to-image")]
+pub async fn seed_walk(
+    base: &TextToImage,
+    engine: &str,
+    start_seed: u32,
+    count: u32,
+) -> Result<BatchOutcome<TextToImage, Frame>> {
+    let requests = (0..count)
+        .map(|i| {
+            let seed = start_seed.wrapping_add(i);
+            Ok((seed, base.to_builder().seed(seed)?.build()?))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let results = join_all(requests.into_iter().map(|(seed, request)| async move {
+        let response = request.generate(engine).await.map(|response| Frame {
+            seed,
+            image_strength: None,
+            response,
+        });
+        (request, response)
+    }))
+    .await;
+    Ok(BatchOutcome::from_results(results))
+}
+
+/// `count` generations of `base` at image strengths evenly spaced from
+/// `from` to `to`, both included, with the seed of `base`
+///
+/// Pinning the seed keeps the frames coherent, so the sequence morphs from
+/// the prompt towards the init image, or back with `from > to`.
+#[cfg(feature = "image-to-image")]
+pub async fn strength_walk(
+    base: &ImageToImage,
+    engine: &str,
+    from: f32,
+    to: f32,
+    count: u32,
+) -> Result<BatchOutcome<ImageToImage, Frame>> {
+    let requests = (0..count)
+        .map(|i| {
+            let strength = match count {
+                1 => from,
+                _ => from + (to - from) * i as f32 / (count - 1) as f32,
+            };
+            let request = base
+                .to_builder()
+                .init_image_mode(ImageMode::ImageStrength)?
+                .image_strength(strength)?
+                .build()?;
+            Ok((strength, request))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let results = join_all(requests.into_iter().map(|(strength, request)| async move {
+        let response = request.generate(engine).await.map(|response| Frame {
+            // a random seed is only known once the API has picked it
+            seed: response
+                .artifacts
+                .first()
+                .map_or(request.seed.as_u32(), |image| image.seed),
+            image_strength: Some(strength),
+            response,
+        });
+        (request, response)
+    }))
+    .await;
+    Ok(BatchOutcome::from_results(results))
+}
+
+/// Write the first image of every frame to `path` as a looping GIF, showing
+/// `fps` frames a second
+///
+/// Passing `outcome.outputs()` of a walk skips the frames which failed.
+/// See [`Animation`](crate::animation::Animation) for APNG output.
+#[cfg(feature = "anim")]
+pub fn assemble_gif<'a>(
+    frames: impl IntoIterator<Item = &'a Frame>,
+    path: impl AsRef<std::path::Path>,
+    fps: u32,
+) -> Result<()> {
+    use crate::animation::Animation;
+    use crate::error::Error;
+
+    let mut artifacts = Vec::new();
+    for frame in frames {
+        let Some(image) = frame.response.artifacts.first() else {
+            return Err(Box::new(Error::NoArtifacts));
+        };
+        artifacts.push(image.to_artifact()?);
+    }
+    Animation::from_artifacts(&artifacts)?.save_gif(path, fps)
+}
+
+#[cfg(all(test, feature = "text-to-image"))]
+mod tests {
+    use super::*;
+    use crate::text_to_img::TextToImageBuilder;
+    use crate::StylePreset;
+
+    #[tokio::test]
+    async fn seed_walk_is_stepping_the_seed_in_order() {
+        let base = TextToImageBuilder::new()
+            .style_preset(StylePreset::Photographic)
+            .unwrap()
+            .text_prompt("a lighthouse", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let transport = crate::mock::transport();
+        let outcome = transport
+            .scope(seed_walk(&base, "stable-diffusion-xl-1024-v1-0", 41, 3))
+            .await
+            .unwrap();
+
+        assert!(outcome.is_complete());
+        assert_eq!(
+            outcome.outputs().map(|f| f.seed).collect::<Vec<_>>(),
+            vec![41, 42, 43]
+        );
+        let sent: Vec<u64> = transport
+            .requests()
+            .iter()
+            .map(|r| {
+                serde_json::from_slice::<serde_json::Value>(&r.body).unwrap()["seed"]
+                    .as_u64()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(sent, vec![41, 42, 43]);
+    }
+
+    #[cfg(feature = "anim")]
+    #[test]
+    fn assemble_gif_is_writing_one_frame_per_generation() {
+        use crate::testing::image_response;
+        use image::AnimationDecoder;
+
+        let frames: Vec<Frame> = (0..3)
+            .map(|seed| Frame {
+                seed,
+                image_strength: None,
+                response: image_response(&[seed]),
+            })
+            .collect();
+        let path =
+            std::env::temp_dir().join(format!("stability_rs_walk_{}.gif", std::process::id()));
+        assemble_gif(&frames, &path, 10).unwrap();
+
+        let file = std::io::BufReader::new(std::fs::File::open(&path).unwrap());
+        let decoded = image::codecs::gif::GifDecoder::new(file)
+            .unwrap()
+            .into_frames()
+            .count();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(decoded, 3);
+    }
+}
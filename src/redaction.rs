@@ -0,0 +1,124 @@
+//! Keeping prompt text out of logs.
+//!
+//! Once redaction is [`enable`]d, the `Debug` output of requests shows a
+//! short SHA-256 of each prompt instead of its text, and [`to_log_json`]
+//! does the same to serialized values, so requests can be logged without
+//! leaking what users asked for. Weights, seeds and every other field are
+//! kept, and the same prompt always maps to the same hash, so log lines can
+//! still be correlated.
+//!
+//! Requests sent to the API are never redacted.
+
+use crate::prelude::*;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Object keys whose string values [`to_log_json`] redacts
+const PROMPT_KEYS: [&str; 6] = [
+    "text",
+    "prompt",
+    "negative_prompt",
+    "search_prompt",
+    "background_prompt",
+    "foreground_prompt",
+];
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// `sha256:` followed by the first 16 hex digits of the SHA-256 of `text`
+pub fn prompt_hash(text: &str) -> String {
+    let digest: String = Sha256::digest(text.as_bytes())
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256:{}", digest)
+}
+
+/// Serialize `value` for logging, hashing prompt text when redaction is
+/// enabled
+///
+/// String values under a prompt key, such as `text`, `prompt` or
+/// `negative_prompt`, are replaced wherever they are nested.
+pub fn to_log_json<T: Serialize>(value: &T) -> Result<Value> {
+    let mut json = serde_json::to_value(value)?;
+    if is_enabled() {
+        redact(&mut json);
+    }
+    Ok(json)
+}
+
+pub(crate) fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                match value {
+                    Value::String(text) if PROMPT_KEYS.contains(&key.as_str()) => {
+                        *text = prompt_hash(text);
+                    }
+                    _ => redact(value),
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// Prompt text as `Debug` shows it: hashed when redaction is enabled
+pub(crate) struct PromptText<'a>(pub(crate) &'a str);
+
+impl fmt::Debug for PromptText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if is_enabled() {
+            f.write_str(&prompt_hash(self.0))
+        } else {
+            fmt::Debug::fmt(self.0, f)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::PromptGroups;
+
+    // a single test, as the switch is process-wide
+    #[test]
+    fn prompts_are_hashed_only_while_enabled() {
+        let groups = PromptGroups::from_text_prompts(&[crate::model::TextPrompt {
+            text: "a secret lighthouse".to_string(),
+            weight: -0.5,
+        }]);
+        assert!(format!("{:?}", groups).contains("a secret lighthouse"));
+
+        enable();
+        let debug = format!("{:?}", groups);
+        let json = to_log_json(&groups).unwrap();
+        disable();
+
+        assert!(!debug.contains("a secret lighthouse"));
+        assert!(debug.contains(&prompt_hash("a secret lighthouse")));
+        assert!(debug.contains("-0.5"));
+        assert_eq!(
+            json["negative"][0]["text"],
+            prompt_hash("a secret lighthouse")
+        );
+        assert_eq!(json["negative"][0]["weight"], -0.5);
+    }
+}
@@ -0,0 +1,153 @@
+//! How the API host is resolved to addresses.
+//!
+//! Connections normally resolve `api.stability.ai` with the system resolver
+//! and dial the addresses found as described in
+//! [`connect`](crate::api::rest::connect). Corporate networks may need an internal DNS server or a pinned address
+//! instead. Once a [`Resolver`] is [`install`]ed, hosts it pins an address
+//! for are never looked up, and any other host is resolved with its
+//! [`Resolve`] fallback, if it has one. TLS still verifies the certificate
+//! against the host name, so a pinned address can't be impersonated.
+//!
+//! ```
+//! use stability_rs::resolver::{self, Resolver};
+//!
+//! # fn main() -> stability_rs::Result<()> {
+//! resolver::install(Resolver::new().pin("api.stability.ai", "10.0.0.8".parse()?)?);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::Error;
+use crate::prelude::*;
+use futures_util::future::BoxFuture;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, RwLock};
+
+static GLOBAL: RwLock<Option<Resolver>> = RwLock::new(None);
+
+/// A source of addresses for a host, such as a client of an internal DNS
+/// server
+pub trait Resolve: Send + Sync {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> BoxFuture<'a, Result<Vec<SocketAddr>>>;
+}
+
+/// The operating system's resolver, as used when nothing is installed
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl Resolve for SystemResolver {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> BoxFuture<'a, Result<Vec<SocketAddr>>> {
+        Box::pin(async move { Ok(tokio::net::lookup_host((host, port)).await?.collect()) })
+    }
+}
+
+/// Addresses pinned per host, and the resolver used for the other hosts
+#[derive(Clone, Default)]
+pub struct Resolver {
+    pinned: HashMap<String, Vec<IpAddr>>,
+    fallback: Option<Arc<dyn Resolve>>,
+}
+
+impl fmt::Debug for Resolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Resolver")
+            .field("pinned", &self.pinned)
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connect to `ip` for `host` instead of looking it up; pinning several
+    /// addresses for a host keeps them in order
+    pub fn pin(mut self, host: &str, ip: IpAddr) -> Result<Self> {
+        self.pinned
+            .entry(host.to_ascii_lowercase())
+            .or_default()
+            .push(ip);
+        Ok(self)
+    }
+
+    /// Resolve hosts without pinned addresses with `resolver` rather than
+    /// the system's
+    pub fn fallback(mut self, resolver: impl Resolve + 'static) -> Result<Self> {
+        self.fallback = Some(Arc::new(resolver));
+        Ok(self)
+    }
+
+    /// The addresses to try for `host`, in order
+    pub async fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        let addrs = match self.pinned.get(&host.to_ascii_lowercase()) {
+            Some(ips) => ips.iter().map(|ip| SocketAddr::new(*ip, port)).collect(),
+            None => match &self.fallback {
+                Some(resolver) => resolver.resolve(host, port).await?,
+                None => SystemResolver.resolve(host, port).await?,
+            },
+        };
+        if addrs.is_empty() {
+            return Err(Box::new(Error::HostNotResolved(host.to_string())));
+        }
+        Ok(addrs)
+    }
+}
+
+pub fn install(resolver: Resolver) {
+    *GLOBAL.write().unwrap() = Some(resolver);
+}
+
+pub fn uninstall() {
+    *GLOBAL.write().unwrap() = None;
+}
+
+pub(crate) fn installed() -> Option<Resolver> {
+    GLOBAL.read().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed(Vec<SocketAddr>);
+
+    impl Resolve for Fixed {
+        fn resolve<'a>(&'a self, _: &'a str, _: u16) -> BoxFuture<'a, Result<Vec<SocketAddr>>> {
+            Box::pin(async move { Ok(self.0.clone()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve_is_preferring_pinned_addresses() {
+        let resolver = Resolver::new()
+            .pin("API.stability.ai", "10.0.0.8".parse().unwrap())
+            .unwrap()
+            .pin("api.stability.ai", "fd00::8".parse().unwrap())
+            .unwrap()
+            .fallback(Fixed(vec!["10.9.9.9:443".parse().unwrap()]))
+            .unwrap();
+
+        assert_eq!(
+            resolver.resolve("api.stability.ai", 443).await.unwrap(),
+            vec![
+                "10.0.0.8:443".parse::<SocketAddr>().unwrap(),
+                "[fd00::8]:443".parse().unwrap()
+            ]
+        );
+        assert_eq!(
+            resolver.resolve("example.com", 443).await.unwrap(),
+            vec!["10.9.9.9:443".parse::<SocketAddr>().unwrap()]
+        );
+    }
+
+    #[tokio::test]
+    async fn resolve_is_erring_when_no_address_is_found() {
+        let resolver = Resolver::new().fallback(Fixed(Vec::new())).unwrap();
+        let err = resolver.resolve("api.stability.ai", 443).await.unwrap_err();
+        assert_eq!(err.to_string(), "no address was found for api.stability.ai");
+    }
+}
@@ -0,0 +1,93 @@
+//! Detection of API schema drift.
+//!
+//! Response types ignore fields they don't know, so a new field added by the
+//! API never breaks a working application. Such fields are logged as a
+//! `tracing` warning instead, naming the response type and the path of each
+//! field. Maintainers and test suites can opt into strict mode, in which they
+//! fail with [`Error::UnknownFields`] as `deny_unknown_fields` would, and
+//! on [deprecated engines](crate::deprecation) too.
+//!
+//! ```
+//! use stability_rs::schema;
+//!
+//! // in a test suite run against the live API
+//! schema::set_strict(true);
+//! ```
+
+// responses are only parsed by the endpoint modules
+#![cfg_attr(
+    not(any(
+        feature = "text-to-image",
+        feature = "image-to-image",
+        feature = "user",
+        feature = "engines"
+    )),
+    allow(dead_code)
+)]
+
+use crate::error::Error;
+use crate::prelude::*;
+use serde::de::DeserializeOwned;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// Fail on unknown response fields rather than logging them
+pub fn set_strict(strict: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+pub fn is_strict() -> bool {
+    STRICT.load(Ordering::Relaxed)
+}
+
+/// Deserialize a response body, reporting the fields `T` does not know
+pub(crate) fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    from_slice_with(bytes, is_strict())
+}
+
+fn from_slice_with<T: DeserializeOwned>(bytes: &[u8], strict: bool) -> Result<T> {
+    let mut fields = Vec::new();
+    let de = &mut serde_json::Deserializer::from_slice(bytes);
+    let value: T = serde_ignored::deserialize(de, |path| fields.push(path.to_string()))?;
+    if fields.is_empty() {
+        return Ok(value);
+    }
+
+    let type_name = std::any::type_name::<T>();
+    if strict {
+        return Err(Box::new(Error::UnknownFields { type_name, fields }));
+    }
+    tracing::warn!(
+        response = type_name,
+        fields = %fields.join(", "),
+        "ignoring unknown response fields"
+    );
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ImageResponse;
+
+    const RESPONSE: &[u8] =
+        br#"{"artifacts":[{"base64":"","finishReason":"SUCCESS","seed":1,"nsfw":false}],"id":"x"}"#;
+
+    #[test]
+    fn unknown_fields_are_ignored_by_default() {
+        let resp = from_slice_with::<ImageResponse>(RESPONSE, false).unwrap();
+        assert_eq!(resp.artifacts[0].seed, 1);
+    }
+
+    #[test]
+    fn strict_mode_is_naming_every_unknown_field() {
+        let err = from_slice_with::<ImageResponse>(RESPONSE, true).unwrap_err();
+        match err.downcast_ref::<Error>() {
+            Some(Error::UnknownFields { fields, .. }) => {
+                assert_eq!(fields, &["artifacts.0.nsfw", "id"]);
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Picking the best of several samples automatically.
+//!
+//! An [`ArtifactScorer`] rates each artifact of a response, and
+//! [`keep_best`] narrows the response to the successful artifact rated
+//! highest. [`Pipeline::scorer`](crate::pipeline::Pipeline::scorer) does so
+//! after every stage, so later stages work on the best sample, and
+//! `BatchRunner::keep_best` saves only the best sample of each item.
+//!
+//! [`NoopScorer`] rates every artifact the same, which keeps the first
+//! successful one. With the `image` feature, [`SharpnessScorer`] prefers
+//! the sharpest, going by the variance of the Laplacian.
+//!
+//! ```
+//! use stability_rs::api::rest::generation::{GenerationMetadata, Image, ImageResponse};
+//! use stability_rs::scoring::{self, ArtifactScorer};
+//!
+//! /// Prefers the highest seed, standing in for a real quality model
+//! struct HighestSeed;
+//!
+//! impl ArtifactScorer for HighestSeed {
+//!     fn score(&self, image: &Image) -> f64 {
+//!         image.seed as f64
+//!     }
+//! }
+//!
+//! let mut resp = ImageResponse {
+//!     artifacts: [3, 9, 4]
+//!         .into_iter()
+//!         .map(|seed| Image {
+//!             base64: String::new(),
+//!             finish_reason: "SUCCESS".to_string(),
+//!             seed,
+//!         })
+//!         .collect(),
+//!     metadata: GenerationMetadata::default(),
+//! };
+//! scoring::keep_best(&mut resp, &HighestSeed);
+//! assert_eq!(resp.artifacts[0].seed, 9);
+//! ```
+
+use crate::api::rest::generation::{Image, ImageResponse};
+use std::fmt;
+
+/// Rates artifacts, higher being better
+pub trait ArtifactScorer: Send + Sync {
+    /// The score of `image`; an image which can't be rated should get
+    /// `f64::NAN`, which is never selected
+    fn score(&self, image: &Image) -> f64;
+}
+
+impl fmt::Debug for dyn ArtifactScorer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ArtifactScorer")
+    }
+}
+
+/// Rates every artifact the same, so the first successful one is kept
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopScorer;
+
+impl ArtifactScorer for NoopScorer {
+    fn score(&self, _: &Image) -> f64 {
+        0.0
+    }
+}
+
+/// Rates artifacts by their sharpness, the variance of the Laplacian of
+/// their luma
+///
+/// Blurry or washed out samples score low. Decoding every artifact makes
+/// this take a few milliseconds per megapixel.
+#[cfg(feature = "image")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SharpnessScorer;
+
+#[cfg(feature = "image")]
+impl ArtifactScorer for SharpnessScorer {
+    fn score(&self, image: &Image) -> f64 {
+        match image.to_artifact().and_then(|artifact| artifact.decode()) {
+            Ok(decoded) => laplacian_variance(&decoded.to_luma8()),
+            Err(_) => f64::NAN,
+        }
+    }
+}
+
+/// The variance of the 4-neighbour Laplacian over the interior of `gray`,
+/// 0 for images too small to have one
+#[cfg(feature = "image")]
+pub fn laplacian_variance(gray: &image::GrayImage) -> f64 {
+    let (width, height) = gray.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+
+    let luma = |x: u32, y: u32| gray.get_pixel(x, y)[0] as f64;
+    let mut sum = 0.0;
+    let mut sum_of_squares = 0.0;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let laplacian = 4.0 * luma(x, y)
+                - luma(x - 1, y)
+                - luma(x + 1, y)
+                - luma(x, y - 1)
+                - luma(x, y + 1);
+            sum += laplacian;
+            sum_of_squares += laplacian * laplacian;
+        }
+    }
+    let n = ((width - 2) * (height - 2)) as f64;
+    let mean = sum / n;
+    sum_of_squares / n - mean * mean
+}
+
+/// Narrow `resp` to its successful artifact scored highest by `scorer`
+///
+/// A response without a successful artifact, or whose successful artifacts
+/// can't be rated, is left as it is.
+pub fn keep_best(resp: &mut ImageResponse, scorer: &dyn ArtifactScorer) {
+    let best = resp
+        .best_by(|image| scorer.score(image))
+        .and_then(|best| resp.artifacts.iter().position(|image| std::ptr::eq(image, best)));
+    if let Some(index) = best {
+        let best = resp.artifacts.swap_remove(index);
+        resp.artifacts = vec![best];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{image, image_response, FINISH_CONTENT_FILTERED};
+
+    struct BySeed;
+
+    impl ArtifactScorer for BySeed {
+        fn score(&self, image: &Image) -> f64 {
+            image.seed as f64
+        }
+    }
+
+    #[test]
+    fn keep_best_is_keeping_the_highest_successful_artifact() {
+        let mut resp = image_response(&[3, 5]);
+        resp.artifacts.push(image(8, FINISH_CONTENT_FILTERED));
+
+        keep_best(&mut resp, &BySeed);
+
+        assert_eq!(resp.artifacts.len(), 1);
+        assert_eq!(resp.artifacts[0].seed, 5);
+    }
+
+    #[test]
+    fn keep_best_is_leaving_responses_without_successes_alone() {
+        let mut resp = image_response(&[]);
+        resp.artifacts.push(image(1, FINISH_CONTENT_FILTERED));
+        resp.artifacts.push(image(2, FINISH_CONTENT_FILTERED));
+
+        keep_best(&mut resp, &NoopScorer);
+
+        assert_eq!(resp.artifacts.len(), 2);
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn laplacian_variance_is_higher_for_sharper_images() {
+        let flat = image::GrayImage::from_pixel(8, 8, image::Luma([128]));
+        let checkered = image::GrayImage::from_fn(8, 8, |x, y| {
+            image::Luma([if (x + y) % 2 == 0 { 0 } else { 255 }])
+        });
+
+        assert_eq!(laplacian_variance(&flat), 0.0);
+        assert!(laplacian_variance(&checkered) > 0.0);
+    }
+}
@@ -0,0 +1,113 @@
+//! Signing requests for gateways in front of the API.
+//!
+//! Some deployments reach the Stability API through a gateway which only
+//! accepts requests carrying a signature, such as an HMAC over the method,
+//! path and body. Implement [`RequestSigner`] and [`install`] it, and every
+//! request sent by this crate is passed to it as a [`CanonicalRequest`] just
+//! before it is sent; the headers it returns are added to the request.
+
+use crate::prelude::*;
+use hyper::http::request::Parts;
+use hyper::HeaderMap;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+static GLOBAL: RwLock<Option<Arc<dyn RequestSigner>>> = RwLock::new(None);
+
+/// What a signature covers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CanonicalRequest {
+    /// Upper case, e.g. `POST`
+    pub method: String,
+    /// Path and query, e.g. `/v1/generation/stable-diffusion-xl-1024-v1-0/text-to-image`
+    pub path: String,
+    /// Lower case hex SHA-256 of the body, which is that of no bytes for a
+    /// request without one
+    pub body_sha256: String,
+}
+
+/// The method, path and body hash on separate lines, a common string to sign
+impl fmt::Display for CanonicalRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n{}\n{}", self.method, self.path, self.body_sha256)
+    }
+}
+
+/// Computes the headers which authenticate a request to a gateway
+pub trait RequestSigner: Send + Sync {
+    fn sign(&self, request: &CanonicalRequest) -> Result<HeaderMap>;
+}
+
+/// Sign every request sent by this crate with `signer`
+pub fn install(signer: Arc<dyn RequestSigner>) {
+    *GLOBAL.write().unwrap() = Some(signer);
+}
+
+pub fn uninstall() {
+    *GLOBAL.write().unwrap() = None;
+}
+
+/// Add the installed signer's headers to a request about to be sent
+pub(crate) fn sign(parts: &mut Parts, body: &[u8]) -> Result<()> {
+    let Some(signer) = GLOBAL.read().unwrap().clone() else {
+        return Ok(());
+    };
+
+    let request = CanonicalRequest {
+        method: parts.method.as_str().to_string(),
+        path: parts
+            .uri
+            .path_and_query()
+            .map(|p| p.as_str().to_string())
+            .unwrap_or_else(|| "/".to_string()),
+        body_sha256: Sha256::digest(body)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect(),
+    };
+    for (name, value) in signer.sign(&request)? {
+        if let Some(name) = name {
+            parts.headers.insert(name, value);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "engines"))]
+mod tests {
+    use super::*;
+    use crate::api::rest::engine::get_engines;
+    use crate::testing::{json_response, FakeTransport};
+    use hyper::body::Bytes;
+    use hyper::header::HeaderValue;
+    use hyper::StatusCode;
+
+    struct Echo;
+
+    impl RequestSigner for Echo {
+        fn sign(&self, request: &CanonicalRequest) -> Result<HeaderMap> {
+            let mut headers = HeaderMap::new();
+            let signature = request.to_string().replace('\n', " ");
+            headers.insert("x-signature", HeaderValue::from_str(&signature)?);
+            Ok(headers)
+        }
+    }
+
+    #[tokio::test]
+    async fn installed_signer_is_adding_its_headers() {
+        let transport = FakeTransport::new(|_| json_response(StatusCode::OK, Bytes::from("[]")));
+
+        install(Arc::new(Echo));
+        let result = transport.scope(get_engines()).await;
+        uninstall();
+        result.unwrap();
+
+        let request = &transport.requests()[0];
+        assert_eq!(
+            request.headers["x-signature"],
+            "GET /v1/engines/list \
+             e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
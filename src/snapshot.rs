@@ -0,0 +1,220 @@
+//! Deterministic text renderings of requests for snapshot tests.
+//!
+//! [`canonicalize`] turns a request recorded by a
+//! [`FakeTransport`](crate::testing::FakeTransport) into a string that only
+//! changes when what is sent changes: headers and JSON object keys are
+//! sorted, multipart parts are sorted by name with the random boundary
+//! replaced by [`BOUNDARY`], and file contents are summarized by their size
+//! and hash. Credentials, the User-Agent and the content length are left
+//! out. The result can be compared against a stored snapshot with `insta`
+//! or a plain `assert_eq!`.
+//!
+#![cfg_attr(feature = "text-to-image", doc = "```")]
+#![cfg_attr(not(feature = "text-to-image"), doc = "```ignore")]
+//! use stability_rs::{snapshot, testing::*, text_to_img::*, Result, StylePreset};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     let transport = FakeTransport::with_response(&image_response(&[1]));
+//!     let image = TextToImageBuilder::new()
+//!         .style_preset(StylePreset::Photographic)?
+//!         .text_prompt("A lighthouse at dusk", 1.0)?
+//!         .build()?;
+//!     transport
+//!         .scope(image.generate("stable-diffusion-xl-1024-v1-0"))
+//!         .await?;
+//!
+//!     let snapshot = snapshot::canonicalize(&transport.requests()[0]);
+//!     assert!(snapshot.starts_with("POST /v1/generation/stable-diffusion-xl-1024-v1-0/text-to-image\n"));
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::testing::{parse_multipart, RecordedRequest};
+use hyper::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, USER_AGENT};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+
+/// Stands in for the random multipart boundary
+pub const BOUNDARY: &str = "BOUNDARY";
+
+/// Render `request` as a stable, human readable string
+///
+/// The method and path come first, then the headers one per line and, after
+/// a blank line, the body: pretty-printed JSON, the multipart parts, or the
+/// body as text if it is neither.
+pub fn canonicalize(request: &RecordedRequest) -> String {
+    let path = request.uri.path_and_query().map_or("/", |p| p.as_str());
+    let mut out = format!("{} {}\n", request.method, path);
+
+    let content_type = request
+        .headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let boundary = content_type.strip_prefix("multipart/form-data; boundary=");
+
+    let mut headers: Vec<(&str, String)> = request
+        .headers
+        .iter()
+        .filter(|(name, _)| ![AUTHORIZATION, CONTENT_LENGTH, USER_AGENT].contains(name))
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            match boundary {
+                Some(boundary) if *name == CONTENT_TYPE => {
+                    (name.as_str(), value.replace(boundary, BOUNDARY))
+                }
+                _ => (name.as_str(), value),
+            }
+        })
+        .collect();
+    headers.sort();
+    for (name, value) in headers {
+        let _ = writeln!(out, "{}: {}", name, value);
+    }
+
+    if request.body.is_empty() {
+        return out;
+    }
+    out.push('\n');
+    let json = serde_json::from_slice::<Value>(&request.body).ok();
+    match (boundary, json) {
+        (Some(boundary), _) => match parse_multipart(&request.body, boundary) {
+            Ok(mut parts) => {
+                // sort_by is stable, so repeated names keep their order
+                parts.sort_by(|a, b| a.name.cmp(&b.name));
+                for part in parts {
+                    let _ = write!(
+                        out,
+                        "--{}\nContent-Disposition: form-data; name=\"{}\"",
+                        BOUNDARY, part.name
+                    );
+                    if let Some(filename) = &part.filename {
+                        let _ = write!(out, "; filename=\"{}\"", filename);
+                    }
+                    if let Some(content_type) = &part.content_type {
+                        let _ = write!(out, "\nContent-Type: {}", content_type);
+                    }
+                    let body = match (&part.filename, part.text()) {
+                        (None, Ok(text)) => text,
+                        _ => summary(&part.data),
+                    };
+                    let _ = writeln!(out, "\n\n{}", body);
+                }
+                let _ = writeln!(out, "--{}--", BOUNDARY);
+            }
+            Err(_) => out.push_str(&String::from_utf8_lossy(&request.body)),
+        },
+        (None, Some(json)) => {
+            // unwrap warranted because a Value always serializes
+            out.push_str(&serde_json::to_string_pretty(&sorted(json)).unwrap());
+            out.push('\n');
+        }
+        (None, None) => out.push_str(&String::from_utf8_lossy(&request.body)),
+    }
+    out
+}
+
+/// `bytes` as their length and the first 16 hex digits of their SHA-256,
+/// so snapshots stay small and readable
+fn summary(bytes: &[u8]) -> String {
+    let digest: String = Sha256::digest(bytes)
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("<{} bytes, sha256:{}>", bytes.len(), digest)
+}
+
+/// `value` with the keys of every object in sorted order, whichever order
+/// `serde_json` keeps them in
+fn sorted(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(String, Value)> = map.into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sorted(value)))
+                    .collect::<Map<_, _>>(),
+            )
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(sorted).collect()),
+        value => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::rest::client::{HeaderMap, Method};
+    use hyper::body::Bytes;
+
+    fn request(content_type: &str, body: &[u8]) -> RecordedRequest {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "sk-secret".parse().unwrap());
+        headers.insert("accept", "application/json".parse().unwrap());
+        headers.insert(CONTENT_TYPE, content_type.parse().unwrap());
+        RecordedRequest {
+            method: Method::POST,
+            uri: "https://api.stability.ai/v1/generation/e/text-to-image"
+                .parse()
+                .unwrap(),
+            headers,
+            body: Bytes::copy_from_slice(body),
+        }
+    }
+
+    #[test]
+    fn canonicalize_is_sorting_json_keys_and_dropping_credentials() {
+        let snapshot = canonicalize(&request(
+            "application/json",
+            br#"{"steps":30,"cfg_scale":7,"text_prompts":[{"weight":1,"text":"a fox"}]}"#,
+        ));
+
+        assert_eq!(
+            snapshot,
+            "POST /v1/generation/e/text-to-image\n\
+             accept: application/json\n\
+             content-type: application/json\n\
+             \n\
+             {\n  \"cfg_scale\": 7,\n  \"steps\": 30,\n  \"text_prompts\": [\n    {\n      \
+             \"text\": \"a fox\",\n      \"weight\": 1\n    }\n  ]\n}\n"
+        );
+    }
+
+    #[test]
+    fn canonicalize_is_fixing_the_boundary_and_sorting_parts() {
+        let body = b"--xyz\r\n\
+            Content-Disposition: form-data; name=\"steps\"\r\n\r\n30\r\n\
+            --xyz\r\n\
+            Content-Disposition: form-data; name=\"init_image\"; filename=\"in.png\"\r\n\
+            Content-Type: image/png\r\n\r\n\x89PNG\r\n\
+            --xyz--\r\n";
+        let snapshot = canonicalize(&request("multipart/form-data; boundary=xyz", body));
+
+        assert_eq!(
+            snapshot,
+            format!(
+                "POST /v1/generation/e/text-to-image\n\
+                 accept: application/json\n\
+                 content-type: multipart/form-data; boundary=BOUNDARY\n\
+                 \n\
+                 --BOUNDARY\n\
+                 Content-Disposition: form-data; name=\"init_image\"; filename=\"in.png\"\n\
+                 Content-Type: image/png\n\
+                 \n\
+                 {}\n\
+                 --BOUNDARY\n\
+                 Content-Disposition: form-data; name=\"steps\"\n\
+                 \n\
+                 30\n\
+                 --BOUNDARY--\n",
+                summary(b"\x89PNG")
+            )
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Staging files for intermediate artifacts.
+//!
+//! Flows that hand one step's output to the next through the file system,
+//! such as an upscale of a generated image, stage the files in a
+//! [`TempStore`]: a directory of its own which is removed, with everything
+//! in it, once the store is dropped, whether the flow completed or a step
+//! failed halfway. Stores are created under the system temporary directory
+//! unless [`set_dir`] picks another, e.g. a volume with room for huge
+//! upscales.
+//!
+#![cfg_attr(all(feature = "text-to-image", feature = "upscale"), doc = "```no_run")]
+#![cfg_attr(not(all(feature = "text-to-image", feature = "upscale")), doc = "```ignore")]
+//! use stability_rs::staging::{self, TempStore};
+//! use stability_rs::{text_to_img::*, upscale::*, Result, StylePreset};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     staging::set_dir("/mnt/scratch");
+//!
+//!     let resp = TextToImageBuilder::new()
+//!         .style_preset(StylePreset::Photographic)?
+//!         .text_prompt("a lighthouse at dusk", 1.0)?
+//!         .build()?
+//!         .generate("stable-diffusion-xl-1024-v1-0")
+//!         .await?;
+//!
+//!     let store = TempStore::new()?;
+//!     let generated = store.save("generated.png", &resp.artifacts[0].to_artifact()?).await?;
+//!     let upscaled = UpscalerBuilder::new()
+//!         .image(&generated)?
+//!         .build()?
+//!         .generate(UpscaleEngine::EsrganV1X2Plus)
+//!         .await?;
+//!     upscaled.artifacts[0].save("upscaled.png").await?;
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::api::rest::artifact::Artifact;
+use crate::atomic_write::OverwritePolicy;
+use crate::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
+
+static DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Create stores under `dir` from now on
+pub fn set_dir(dir: impl AsRef<Path>) {
+    *DIR.write().unwrap() = Some(dir.as_ref().to_path_buf());
+}
+
+/// Create stores under the system temporary directory again
+pub fn reset_dir() {
+    *DIR.write().unwrap() = None;
+}
+
+/// The directory stores are created under
+pub fn dir() -> PathBuf {
+    DIR.read()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// A directory of staged files, removed when dropped
+#[derive(Debug)]
+pub struct TempStore {
+    dir: PathBuf,
+    keep: bool,
+}
+
+impl TempStore {
+    /// Create a store under [`dir`]
+    pub fn new() -> Result<Self> {
+        Self::in_dir(dir())
+    }
+
+    /// Create a store under `parent`
+    pub fn in_dir(parent: impl AsRef<Path>) -> Result<Self> {
+        let dir = parent.as_ref().join(format!(
+            "stability_rs_staging_{}_{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir, keep: false })
+    }
+
+    /// The store's own directory
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Where a file named `name` is staged
+    pub fn file(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// Stage `artifact` as `name`, returning its path
+    ///
+    /// Staged files are never [encrypted](crate::encryption), as they are
+    /// read back to be uploaded.
+    pub async fn save(&self, name: &str, artifact: &Artifact) -> Result<PathBuf> {
+        artifact
+            .save_unencrypted(self.file(name), OverwritePolicy::Overwrite)
+            .await
+    }
+
+    /// Leave the staged files in place, e.g. to look into a failed step,
+    /// returning the directory they are in
+    pub fn keep(mut self) -> PathBuf {
+        self.keep = true;
+        self.dir.clone()
+    }
+}
+
+impl Drop for TempStore {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::png_1x1;
+
+    fn artifact() -> Artifact {
+        Artifact::new(IMAGE_PNG, png_1x1().into()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn drop_is_removing_staged_files_after_a_failed_step() {
+        let parent =
+            std::env::temp_dir().join(format!("stability_rs_staging_test_{}", std::process::id()));
+        let staged = async {
+            let store = TempStore::in_dir(&parent)?;
+            store.save("generated.png", &artifact()).await?;
+            assert!(store.file("generated.png").exists());
+            Err::<(), _>(Box::<dyn std::error::Error + Send + Sync>::from("upscale failed"))
+        }
+        .await;
+
+        assert!(staged.is_err());
+        assert_eq!(std::fs::read_dir(&parent).unwrap().count(), 0);
+        std::fs::remove_dir_all(&parent).unwrap();
+    }
+
+    #[tokio::test]
+    async fn keep_is_leaving_staged_files_in_place() {
+        let parent =
+            std::env::temp_dir().join(format!("stability_rs_staging_keep_{}", std::process::id()));
+        let store = TempStore::in_dir(&parent).unwrap();
+        store.save("generated.png", &artifact()).await.unwrap();
+
+        let dir = store.keep();
+
+        assert!(dir.join("generated.png").exists());
+        std::fs::remove_dir_all(&parent).unwrap();
+    }
+}
@@ -1,2 +1,76 @@
+//! The building blocks of the HTTP transport, for assembling custom ones.
+//!
+//! Requests are normally sent by resolving the API host, dialing it with
+//! [`happy_eyeballs`](crate::api::rest::connect::happy_eyeballs),
+//! establishing TLS with `connect_tls` and running one HTTP/1 `exchange` over
+//! the connection, adapted to hyper by [`TokioIo`].
+//! Applications with unusual networks can put the same pieces together
+//! around a stream of their own; a local mock or proxy speaking plain HTTP
+//! over TCP or a Unix socket only needs
+//! [`set_base_url`](crate::api::rest::client::set_base_url).
+//!
+//! `exchange`, `connect_tls`, `TlsStream` and the re-export of
+//! `happy_eyeballs` are only available with the `unstable-transport` feature,
+//! and aren't covered by semver: their signatures follow the transport's
+//! needs and may change in any minor release. `TlsStream` is a different type
+//! under each TLS backend.
+//!
+//! ```no_run
+//! use stability_rs::api::rest::client::{Bytes, Full, Request};
+//! # #[cfg(feature = "unstable-transport")]
+//! use stability_rs::support::{exchange, TokioIo};
+//! use stability_rs::Result;
+//!
+//! # #[cfg(not(feature = "unstable-transport"))]
+//! # fn main() {}
+//! # #[cfg(feature = "unstable-transport")]
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     let stream = tokio::net::UnixStream::connect("/tmp/proxy.sock").await?;
+//!     let req = Request::get("http://localhost/v1/engines/list")
+//!         .header("host", "localhost")
+//!         .body(Full::new(Bytes::new()))?;
+//!
+//!     let res = exchange(TokioIo::new(stream), req, 1024 * 1024).await?;
+//!     println!("{}", res.status());
+//!
+//!     Ok(())
+//! }
+//! ```
+
 mod tokiort;
+#[cfg(feature = "unstable-transport")]
+pub use crate::api::rest::connect::happy_eyeballs;
+#[cfg(feature = "unstable-transport")]
+pub use crate::api::rest::exchange::{connect_tls, exchange, TlsStream};
 pub use tokiort::{TokioExecutor, TokioIo, TokioTimer};
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::api::rest::exchange::exchange;
+    use crate::api::rest::client::{Full, Request, Response};
+    use hyper::body::Bytes;
+    use hyper::service::service_fn;
+
+    #[tokio::test]
+    async fn exchange_is_running_over_a_unix_socket() {
+        let (client, server) = tokio::net::UnixStream::pair().unwrap();
+        tokio::spawn(
+            hyper::server::conn::http1::Builder::new().serve_connection(
+                TokioIo::new(server),
+                service_fn(|_| async {
+                    Ok::<_, std::convert::Infallible>(Response::new(Full::new(Bytes::from("ok"))))
+                }),
+            ),
+        );
+        let req = Request::get("http://localhost/")
+            .header("host", "localhost")
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+
+        let res = exchange(TokioIo::new(client), req, 1024).await.unwrap();
+
+        assert_eq!(res.body().as_ref(), b"ok");
+    }
+}
@@ -44,7 +44,7 @@ impl Timer for TokioTimer {
 
     fn reset(&self, sleep: &mut Pin<Box<dyn Sleep>>, new_deadline: Instant) {
         if let Some(sleep) = sleep.as_mut().downcast_mut_pin::<TokioSleep>() {
-            sleep.reset(new_deadline.into())
+            sleep.reset(new_deadline)
         }
     }
 }
@@ -90,6 +90,8 @@ impl TokioSleep {
 }
 
 pin_project! {
+    /// Adapts a tokio stream, such as a `TcpStream`, `UnixStream` or TLS
+    /// stream, to hyper's IO traits, and a hyper connection back to tokio's
     #[derive(Debug)]
     pub struct TokioIo<T> {
         #[pin]
@@ -102,6 +104,7 @@ impl<T> TokioIo<T> {
         Self { inner }
     }
 
+    /// The adapted stream
     pub fn inner(self) -> T {
         self.inner
     }
@@ -0,0 +1,335 @@
+//! Per-tenant usage tracking and quotas, for backends serving many callers.
+//!
+//! A service proxying this crate for its own customers runs each caller's
+//! work in [`with_tenant`]. Once a [`QuotaTracker`] is [`install`]ed, every
+//! request sent in that scope is first checked against the tenant's
+//! [`Quota`], failing with [`Error::QuotaExceeded`] once it is used up, and
+//! then counted in the tracker's [`QuotaStore`], requests and estimated
+//! credits alike. Requests sent outside a tenant scope are neither checked
+//! nor counted.
+//!
+#![cfg_attr(feature = "text-to-image", doc = "```no_run")]
+#![cfg_attr(not(feature = "text-to-image"), doc = "```ignore")]
+//! use stability_rs::tenancy::{self, MemoryStore, Quota, QuotaTracker};
+//! use stability_rs::{text_to_img::TextToImageBuilder, Result, StylePreset};
+//! use std::sync::Arc;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     let store = Arc::new(MemoryStore::new());
+//!     let tracker = QuotaTracker::new(store, Quota::new().max_credits(100.0)?)
+//!         .on_rejected(|tenant, usage| eprintln!("{} is over quota: {:?}", tenant, usage));
+//!     tracker.set_quota("acme", Quota::new().max_credits(1000.0)?);
+//!     tenancy::install(Arc::new(tracker));
+//!
+//!     let request = TextToImageBuilder::new()
+//!         .style_preset(StylePreset::Enhance)?
+//!         .text_prompt("A lighthouse in a storm", 1.0)?
+//!         .build()?;
+//!     let resp = tenancy::with_tenant("acme", request.generate("stable-diffusion-xl-1024-v1-0"))
+//!         .await?;
+//!     # let _ = resp;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! A quota is checked before a request is sent and counted once its response
+//! is in, so concurrent requests of one tenant can go over it by the requests
+//! in flight.
+
+use crate::audit::AuditRecord;
+use crate::error::Error;
+use crate::prelude::*;
+use crate::usage::Usage;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex, RwLock};
+
+static GLOBAL: RwLock<Option<Arc<QuotaTracker>>> = RwLock::new(None);
+
+tokio::task_local! {
+    static TENANT: String;
+}
+
+/// Keeps the usage of each tenant, e.g. in a database shared by several
+/// instances of a service
+pub trait QuotaStore: Send + Sync {
+    /// The usage counted for `tenant` so far, zero for a tenant never seen
+    fn usage(&self, tenant: &str) -> Result<Usage>;
+
+    /// Count a request of `tenant`
+    fn record(&self, tenant: &str, record: &AuditRecord) -> Result<()>;
+
+    /// Start the count of `tenant` over, e.g. at a new billing period
+    fn reset(&self, tenant: &str) -> Result<()>;
+}
+
+impl fmt::Debug for dyn QuotaStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("QuotaStore")
+    }
+}
+
+/// Keeps usage in memory, for a single process
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    usage: Mutex<HashMap<String, Usage>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl QuotaStore for MemoryStore {
+    fn usage(&self, tenant: &str) -> Result<Usage> {
+        Ok(self.usage.lock().unwrap().get(tenant).cloned().unwrap_or_default())
+    }
+
+    fn record(&self, tenant: &str, record: &AuditRecord) -> Result<()> {
+        let mut usage = self.usage.lock().unwrap();
+        usage.entry(tenant.to_string()).or_default().add(record);
+        Ok(())
+    }
+
+    fn reset(&self, tenant: &str) -> Result<()> {
+        self.usage.lock().unwrap().remove(tenant);
+        Ok(())
+    }
+}
+
+/// How much a tenant may use, without limits by default
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Quota {
+    max_requests: Option<usize>,
+    max_credits: Option<f64>,
+}
+
+impl Quota {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow `requests` requests, failed ones included
+    pub fn max_requests(mut self, requests: usize) -> Result<Self> {
+        self.max_requests = Some(requests);
+        Ok(self)
+    }
+
+    /// Allow requests until the estimated credits reach `credits`
+    pub fn max_credits(mut self, credits: f64) -> Result<Self> {
+        self.max_credits = Some(credits);
+        Ok(self)
+    }
+
+    /// Whether a tenant which used `usage` may send another request
+    pub fn allows(&self, usage: &Usage) -> bool {
+        self.max_requests.is_none_or(|max| usage.requests < max)
+            && self.max_credits.is_none_or(|max| usage.estimated_credits < max)
+    }
+}
+
+type RejectedFn = Box<dyn Fn(&str, &Usage) + Send + Sync>;
+
+/// Checks and counts the requests of each tenant against its quota
+pub struct QuotaTracker {
+    store: Arc<dyn QuotaStore>,
+    default_quota: Quota,
+    quotas: RwLock<HashMap<String, Quota>>,
+    rejected: Option<RejectedFn>,
+}
+
+impl fmt::Debug for QuotaTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QuotaTracker")
+            .field("default_quota", &self.default_quota)
+            .field("quotas", &self.quotas)
+            .finish()
+    }
+}
+
+impl QuotaTracker {
+    /// Count usage in `store`, holding tenants without a quota of their own
+    /// to `default_quota`
+    pub fn new(store: Arc<dyn QuotaStore>, default_quota: Quota) -> Self {
+        Self {
+            store,
+            default_quota,
+            quotas: RwLock::new(HashMap::new()),
+            rejected: None,
+        }
+    }
+
+    /// Call `f` with the tenant and its usage each time a request is
+    /// rejected, e.g. to alert the tenant or upsell a larger plan
+    pub fn on_rejected<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&str, &Usage) + Send + Sync + 'static,
+    {
+        self.rejected = Some(Box::new(f));
+        self
+    }
+
+    /// Hold `tenant` to `quota` from now on, instead of the default quota
+    pub fn set_quota(&self, tenant: &str, quota: Quota) {
+        self.quotas.write().unwrap().insert(tenant.to_string(), quota);
+    }
+
+    pub fn quota(&self, tenant: &str) -> Quota {
+        self.quotas
+            .read()
+            .unwrap()
+            .get(tenant)
+            .copied()
+            .unwrap_or(self.default_quota)
+    }
+
+    pub fn usage(&self, tenant: &str) -> Result<Usage> {
+        self.store.usage(tenant)
+    }
+
+    pub fn reset(&self, tenant: &str) -> Result<()> {
+        self.store.reset(tenant)
+    }
+
+    /// Err with [`Error::QuotaExceeded`] when `tenant` may not send another
+    /// request
+    pub fn check(&self, tenant: &str) -> Result<()> {
+        let usage = self.store.usage(tenant)?;
+        if self.quota(tenant).allows(&usage) {
+            return Ok(());
+        }
+        if let Some(rejected) = &self.rejected {
+            rejected(tenant, &usage);
+        }
+        Err(Box::new(Error::QuotaExceeded {
+            tenant: tenant.to_string(),
+        }))
+    }
+
+    pub(crate) fn record(&self, tenant: &str, record: &AuditRecord) -> Result<()> {
+        self.store.record(tenant, record)
+    }
+}
+
+/// Check and count every request sent in a tenant scope with `tracker`
+pub fn install(tracker: Arc<QuotaTracker>) {
+    *GLOBAL.write().unwrap() = Some(tracker);
+}
+
+pub fn uninstall() {
+    *GLOBAL.write().unwrap() = None;
+}
+
+pub(crate) fn installed() -> Option<Arc<QuotaTracker>> {
+    GLOBAL.read().unwrap().clone()
+}
+
+/// Run `f` with every request it sends made on behalf of `tenant`
+pub async fn with_tenant<F: Future>(tenant: impl Into<String>, f: F) -> F::Output {
+    TENANT.scope(tenant.into(), f).await
+}
+
+/// The tenant of the current task, if it runs in [`with_tenant`]
+pub fn current_tenant() -> Option<String> {
+    TENANT.try_with(|tenant| tenant.clone()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(estimated_credits: f64) -> AuditRecord {
+        AuditRecord {
+            timestamp: "2024-03-01T12:00:00Z".to_string(),
+            method: "POST".to_string(),
+            path: "/v1/generation/stable-diffusion-xl-1024-v1-0/text-to-image".to_string(),
+            engine: Some("stable-diffusion-xl-1024-v1-0".to_string()),
+            request: serde_json::Value::Null,
+            status: Some(200),
+            error: None,
+            artifacts: Some(1),
+            seeds: vec![1],
+            estimated_credits: Some(estimated_credits),
+            latency_ms: 100,
+        }
+    }
+
+    #[test]
+    fn check_is_rejecting_tenants_over_their_own_quota() {
+        let rejected = Arc::new(Mutex::new(Vec::new()));
+        let tracker = {
+            let rejected = rejected.clone();
+            QuotaTracker::new(Arc::new(MemoryStore::new()), Quota::new())
+                .on_rejected(move |tenant, _| rejected.lock().unwrap().push(tenant.to_string()))
+        };
+        tracker.set_quota("acme", Quota::new().max_credits(1.0).unwrap());
+
+        tracker.check("acme").unwrap();
+        tracker.record("acme", &record(0.6)).unwrap();
+        tracker.record("acme", &record(0.6)).unwrap();
+        tracker.record("globex", &record(0.6)).unwrap();
+        tracker.record("globex", &record(0.6)).unwrap();
+
+        let err = tracker.check("acme").unwrap_err();
+        assert_eq!(err.to_string(), "tenant acme has used up its quota");
+        tracker.check("globex").unwrap();
+        assert_eq!(*rejected.lock().unwrap(), ["acme"]);
+
+        tracker.reset("acme").unwrap();
+        tracker.check("acme").unwrap();
+    }
+
+    #[test]
+    fn quota_is_allowing_usage_below_every_limit() {
+        let quota = Quota::new().max_requests(2).unwrap().max_credits(5.0).unwrap();
+        let usage = |requests, estimated_credits| Usage {
+            requests,
+            estimated_credits,
+            ..Usage::default()
+        };
+
+        assert!(quota.allows(&usage(1, 4.9)));
+        assert!(!quota.allows(&usage(2, 0.0)));
+        assert!(!quota.allows(&usage(1, 5.0)));
+        assert!(Quota::new().allows(&usage(1_000, 1e6)));
+    }
+
+    #[cfg(feature = "text-to-image")]
+    #[tokio::test]
+    async fn requests_in_a_tenant_scope_are_counted() {
+        use crate::testing::{image_response, FakeTransport};
+        use crate::text_to_img::TextToImageBuilder;
+        use crate::StylePreset;
+
+        let tracker = Arc::new(QuotaTracker::new(
+            Arc::new(MemoryStore::new()),
+            Quota::new().max_requests(1).unwrap(),
+        ));
+        install(tracker.clone());
+        let request = TextToImageBuilder::new()
+            .style_preset(StylePreset::Enhance)
+            .unwrap()
+            .text_prompt("a lighthouse", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+        let transport = FakeTransport::with_response(&image_response(&[1]));
+        let engine = "stable-diffusion-xl-1024-v1-0";
+
+        let first = transport.scope(with_tenant("acme", request.generate(engine))).await;
+        let second = transport.scope(with_tenant("acme", request.generate(engine))).await;
+        let untracked = transport.scope(request.generate(engine)).await;
+        uninstall();
+
+        first.unwrap();
+        assert!(second.unwrap_err().to_string().contains("quota"));
+        untracked.unwrap();
+        assert_eq!(transport.requests().len(), 2);
+        let usage = tracker.usage("acme").unwrap();
+        assert_eq!(usage.requests, 1);
+        assert!(usage.estimated_credits > 0.0);
+    }
+}
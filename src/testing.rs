@@ -0,0 +1,251 @@
+//! Fixtures for testing code built on this crate without network access.
+//!
+//! Enable the `testing` feature, then run code under test inside
+//! [`FakeTransport::scope`] to answer its requests with canned responses.
+//! With the `wiremock` feature, the `stub_*` functions mount matching stubs of
+//! each generation endpoint on a `wiremock` server instead, and
+//! [`snapshot::canonicalize`](crate::snapshot::canonicalize) renders what was
+//! sent for snapshot tests.
+//!
+#![cfg_attr(feature = "text-to-image", doc = "```")]
+#![cfg_attr(not(feature = "text-to-image"), doc = "```ignore")]
+//! use stability_rs::{testing::*, text_to_img::*, Result, StylePreset};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     let transport = FakeTransport::with_response(&image_response(&[1, 2]));
+//!
+//!     let image = TextToImageBuilder::new()
+//!         .style_preset(StylePreset::Photographic)?
+//!         .text_prompt("A lighthouse at dusk", 1.0)?
+//!         .build()?;
+//!     let resp = transport
+//!         .scope(image.generate("stable-diffusion-xl-1024-v1-0"))
+//!         .await?;
+//!
+//!     assert_eq!(resp.artifacts.len(), 2);
+//!     assert!(transport.requests()[0].uri.path().ends_with("/text-to-image"));
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::api::rest::client::{with_transport, BoxFuture, HeaderMap, Method, Transport, Uri};
+use crate::api::rest::generation::{GenerationMetadata, Image, ImageResponse};
+use crate::prelude::*;
+use base64::{engine::general_purpose, Engine as _};
+use hyper::body::Bytes;
+use hyper::{Request, Response, StatusCode};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+/// A valid 1x1 transparent PNG, base64 encoded
+pub const PNG_1X1_BASE64: &str =
+    "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAAC0lEQVR4nGNgAAIAAAUAAXpeqz8AAAAASUVORK5CYII=";
+
+#[cfg(feature = "wiremock")]
+mod stubs;
+#[cfg(feature = "wiremock")]
+pub use stubs::*;
+
+pub const FINISH_SUCCESS: &str = "SUCCESS";
+pub const FINISH_CONTENT_FILTERED: &str = "CONTENT_FILTERED";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_fixture_is_decoding_to_a_png() {
+        assert!(png_1x1().starts_with(b"\x89PNG\r\n\x1a\n"));
+    }
+
+    #[cfg(feature = "engines")]
+    #[tokio::test]
+    async fn fake_transport_is_answering_api_errors() {
+        let transport = FakeTransport::with_error(404, "not_found", "engine not found");
+        let err = transport
+            .scope(crate::api::rest::engine::get_engines())
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("engine not found"));
+        assert_eq!(transport.requests()[0].uri.path(), "/v1/engines/list");
+    }
+}
+
+/// The decoded bytes of [`PNG_1X1_BASE64`]
+pub fn png_1x1() -> Vec<u8> {
+    // unwrap warranted because the fixture is valid base64
+    general_purpose::STANDARD.decode(PNG_1X1_BASE64).unwrap()
+}
+
+/// An artifact holding [`PNG_1X1_BASE64`]
+pub fn image(seed: u32, finish_reason: &str) -> Image {
+    Image {
+        base64: PNG_1X1_BASE64.to_string(),
+        finish_reason: finish_reason.to_string(),
+        seed,
+    }
+}
+
+/// A response with one successful artifact per seed
+pub fn image_response(seeds: &[u32]) -> ImageResponse {
+    ImageResponse {
+        artifacts: seeds.iter().map(|seed| image(*seed, FINISH_SUCCESS)).collect(),
+        metadata: GenerationMetadata::default(),
+    }
+}
+
+/// A request as seen by a [`FakeTransport`]
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub uri: Uri,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+type Handler = dyn Fn(&RecordedRequest) -> Response<Bytes> + Send + Sync;
+
+/// A transport answering every request from a handler and recording what was sent
+pub struct FakeTransport {
+    handler: Box<Handler>,
+    requests: Mutex<Vec<RecordedRequest>>,
+}
+
+impl FakeTransport {
+    pub fn new<F>(handler: F) -> Arc<Self>
+    where
+        F: Fn(&RecordedRequest) -> Response<Bytes> + Send + Sync + 'static,
+    {
+        Arc::new(Self {
+            handler: Box::new(handler),
+            requests: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Answer every request with `resp` as JSON
+    pub fn with_response(resp: &ImageResponse) -> Arc<Self> {
+        // unwrap warranted because ImageResponse always serializes
+        let body = Bytes::from(serde_json::to_vec(resp).unwrap());
+        Self::new(move |_| json_response(StatusCode::OK, body.clone()))
+    }
+
+    /// Answer every request with an API error
+    pub fn with_error(status: u16, name: &str, message: &str) -> Arc<Self> {
+        let body = Bytes::from(
+            serde_json::json!({ "id": "fake", "name": name, "message": message }).to_string(),
+        );
+        let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        Self::new(move |_| json_response(status, body.clone()))
+    }
+
+    /// Every request sent so far, oldest first
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Run `f` with every request it sends answered by this transport
+    pub async fn scope<F: Future>(self: &Arc<Self>, f: F) -> F::Output {
+        with_transport(self.clone(), f).await
+    }
+}
+
+impl Transport for FakeTransport {
+    fn send(&self, req: Request<Bytes>) -> BoxFuture<'_, Result<Response<Bytes>>> {
+        let (parts, body) = req.into_parts();
+        let recorded = RecordedRequest {
+            method: parts.method,
+            uri: parts.uri,
+            headers: parts.headers,
+            body,
+        };
+        let resp = (self.handler)(&recorded);
+        self.requests.lock().unwrap().push(recorded);
+        Box::pin(async move { Ok(resp) })
+    }
+}
+
+pub fn json_response(status: StatusCode, body: Bytes) -> Response<Bytes> {
+    let mut resp = Response::new(body);
+    *resp.status_mut() = status;
+    resp.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static("application/json"),
+    );
+    resp
+}
+
+/// One part of a `multipart/form-data` body
+#[derive(Debug)]
+pub(crate) struct Part {
+    pub name: String,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+impl Part {
+    pub fn text(&self) -> std::result::Result<String, String> {
+        String::from_utf8(self.data.clone())
+            .map_err(|_| format!("{}: must be UTF-8 text", self.name))
+    }
+}
+
+/// The parts of a multipart `body`, in the order they were sent
+pub(crate) fn parse_multipart(body: &[u8], boundary: &str) -> std::result::Result<Vec<Part>, String> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut rest = body
+        .strip_prefix(delimiter.as_slice())
+        .ok_or("multipart body must start with the boundary")?;
+    let mut parts = Vec::new();
+
+    loop {
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+        rest = rest
+            .strip_prefix(b"\r\n")
+            .ok_or("boundary must be followed by CRLF")?;
+
+        let header_end =
+            find(rest, b"\r\n\r\n").ok_or("part headers must end with a blank line")?;
+        let headers =
+            std::str::from_utf8(&rest[..header_end]).map_err(|_| "part headers must be UTF-8")?;
+        rest = &rest[header_end + 4..];
+
+        let mut next = b"\r\n".to_vec();
+        next.extend_from_slice(&delimiter);
+        let data_end = find(rest, &next).ok_or("multipart body is missing its closing boundary")?;
+        let data = rest[..data_end].to_vec();
+        rest = &rest[data_end + next.len()..];
+
+        let disposition = headers
+            .lines()
+            .find_map(|l| l.strip_prefix("Content-Disposition: form-data; "))
+            .ok_or("part is missing its Content-Disposition")?;
+        let param = |key: &str| {
+            disposition.split("; ").find_map(|p| {
+                p.strip_prefix(key)
+                    .and_then(|v| v.strip_prefix("=\""))
+                    .and_then(|v| v.strip_suffix('"'))
+                    .map(str::to_string)
+            })
+        };
+
+        parts.push(Part {
+            name: param("name").ok_or("part is missing its name")?,
+            filename: param("filename"),
+            content_type: headers
+                .lines()
+                .find_map(|l| l.strip_prefix("Content-Type: "))
+                .map(str::to_string),
+            data,
+        });
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
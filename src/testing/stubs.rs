@@ -0,0 +1,204 @@
+//! Stubs of the generation endpoints for a [`wiremock`] server.
+//!
+//! Each `stub_*` function mounts a mock on the server which matches only
+//! requests shaped the way this crate sends them to that endpoint (method,
+//! path, headers and body fields) and answers them with a fixture. Run the
+//! code under test inside [`with_wiremock`] to send its requests to the
+//! server instead of the API.
+
+use crate::api::rest::client::{max_response_size, with_transport, BoxFuture, Transport};
+use crate::api::rest::exchange::exchange;
+use crate::api::rest::generation::ImageResponse;
+use crate::prelude::*;
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::header::{HeaderValue, HOST};
+use hyper::{Request, Response, Uri};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use wiremock::matchers::{header, method, path_regex};
+use wiremock::{Match, Mock, MockServer, ResponseTemplate};
+
+const ENGINE_SEGMENT: &str = "^/v1/generation/[a-z0-9.-]+";
+
+/// Stub `POST /v1/generation/{engine}/text-to-image`, which takes a JSON
+/// body with at least one text prompt
+pub async fn stub_text_to_image(server: &MockServer, fixture: &ImageResponse) {
+    Mock::given(method("POST"))
+        .and(path_regex(format!("{}/text-to-image$", ENGINE_SEGMENT)))
+        .and(header("accept", "application/json"))
+        .and(header("content-type", "application/json"))
+        .and(JsonPrompts)
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture))
+        .mount(server)
+        .await;
+}
+
+/// Stub `POST /v1/generation/{engine}/image-to-image`, a multipart upload of
+/// an init image with text prompts
+pub async fn stub_image_to_image(server: &MockServer, fixture: &ImageResponse) {
+    stub_multipart(
+        server,
+        "/image-to-image",
+        &["text_prompts[0][text]", "init_image_mode", "init_image"],
+        fixture,
+    )
+    .await;
+}
+
+/// Stub `POST /v1/generation/{engine}/image-to-image/upscale`, a multipart
+/// upload of the image to upscale
+pub async fn stub_upscale(server: &MockServer, fixture: &ImageResponse) {
+    stub_multipart(server, "/image-to-image/upscale", &["image"], fixture).await;
+}
+
+/// Stub `POST /v1/generation/{engine}/image-to-image/masking`, a multipart
+/// upload of an init image, a mask source and text prompts
+pub async fn stub_masking(server: &MockServer, fixture: &ImageResponse) {
+    stub_multipart(
+        server,
+        "/image-to-image/masking",
+        &["text_prompts[0][text]", "mask_source", "init_image"],
+        fixture,
+    )
+    .await;
+}
+
+async fn stub_multipart(
+    server: &MockServer,
+    suffix: &str,
+    fields: &'static [&'static str],
+    fixture: &ImageResponse,
+) {
+    Mock::given(method("POST"))
+        .and(path_regex(format!("{}{}$", ENGINE_SEGMENT, suffix)))
+        .and(header("accept", "application/json"))
+        .and(MultipartFields(fields))
+        .respond_with(ResponseTemplate::new(200).set_body_json(fixture))
+        .mount(server)
+        .await;
+}
+
+/// A JSON body with a non-empty `text_prompts` array
+struct JsonPrompts;
+
+impl Match for JsonPrompts {
+    fn matches(&self, request: &wiremock::Request) -> bool {
+        serde_json::from_slice::<serde_json::Value>(&request.body)
+            .ok()
+            .and_then(|body| body["text_prompts"].as_array().map(|p| !p.is_empty()))
+            .unwrap_or(false)
+    }
+}
+
+/// A multipart body, with the declared boundary, holding every named field
+struct MultipartFields(&'static [&'static str]);
+
+impl Match for MultipartFields {
+    fn matches(&self, request: &wiremock::Request) -> bool {
+        let Some(boundary) = request
+            .headers
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("multipart/form-data; boundary="))
+        else {
+            return false;
+        };
+        let body = String::from_utf8_lossy(&request.body);
+        body.starts_with(&format!("--{}\r\n", boundary))
+            && body.ends_with(&format!("--{}--\r\n", boundary))
+            && self
+                .0
+                .iter()
+                .all(|field| body.contains(&format!("name=\"{}\"", field)))
+    }
+}
+
+/// A transport sending every request to `server` over plain HTTP
+pub fn wiremock_transport(server: &MockServer) -> Arc<dyn Transport> {
+    Arc::new(Forward(*server.address()))
+}
+
+/// Run `f` with every request it sends answered by `server`
+pub async fn with_wiremock<F: Future>(server: &MockServer, f: F) -> F::Output {
+    with_transport(wiremock_transport(server), f).await
+}
+
+struct Forward(SocketAddr);
+
+impl Transport for Forward {
+    fn send(&self, req: Request<Bytes>) -> BoxFuture<'_, Result<Response<Bytes>>> {
+        Box::pin(async move {
+            let (mut parts, body) = req.into_parts();
+            let path = parts
+                .uri
+                .path_and_query()
+                .map(|p| p.as_str())
+                .unwrap_or("/");
+            parts.uri = format!("http://{}{}", self.0, path).parse::<Uri>()?;
+            parts
+                .headers
+                .insert(HOST, HeaderValue::from_str(&self.0.to_string())?);
+
+            let stream = TcpStream::connect(self.0).await?;
+            let req = Request::from_parts(parts, Full::new(body));
+            exchange(
+                crate::support::TokioIo::new(stream),
+                req,
+                max_response_size(),
+            )
+            .await
+        })
+    }
+}
+
+#[cfg(all(test, feature = "text-to-image", feature = "upscale"))]
+mod tests {
+    use super::*;
+    use crate::testing::{image_response, png_1x1};
+    use crate::text_to_img::TextToImageBuilder;
+    use crate::upscale::{UpscaleEngine, UpscalerBuilder};
+    use crate::StylePreset;
+
+    #[tokio::test]
+    async fn text_to_image_stub_is_answering_requests_sent_by_the_client() {
+        let server = MockServer::start().await;
+        stub_text_to_image(&server, &image_response(&[7])).await;
+
+        let image = TextToImageBuilder::new()
+            .style_preset(StylePreset::Photographic)
+            .unwrap()
+            .text_prompt("a lighthouse", 1.0)
+            .unwrap()
+            .build()
+            .unwrap();
+        let resp = with_wiremock(&server, image.generate("stable-diffusion-xl-1024-v1-0"))
+            .await
+            .unwrap();
+
+        assert_eq!(resp.artifacts[0].seed, 7);
+    }
+
+    #[tokio::test]
+    async fn upscale_stub_is_matching_the_multipart_upload() {
+        let server = MockServer::start().await;
+        stub_upscale(&server, &image_response(&[3])).await;
+
+        let path =
+            std::env::temp_dir().join(format!("stability_rs_stub_{}.png", std::process::id()));
+        std::fs::write(&path, png_1x1()).unwrap();
+        let upscaler = UpscalerBuilder::new()
+            .image(&path)
+            .unwrap()
+            .build()
+            .unwrap();
+        let resp = with_wiremock(&server, upscaler.generate(UpscaleEngine::EsrganV1X2Plus))
+            .await
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(resp.artifacts[0].seed, 3);
+    }
+}
@@ -0,0 +1,153 @@
+//! Progress of request uploads.
+//!
+//! Multipart requests carrying init images or mask images can take a while
+//! to upload. Requests sent within [`with_upload_progress`] stream their body
+//! in chunks and call back with an [`UploadProgress`] after each one, so GUI
+//! applications can draw a progress bar instead of appearing frozen.
+//!
+#![cfg_attr(feature = "image-to-image", doc = "```no_run")]
+#![cfg_attr(not(feature = "image-to-image"), doc = "```ignore")]
+//! use stability_rs::upload_progress::with_upload_progress;
+//! use stability_rs::{img_to_img::*, Result, StylePreset};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     let request = ImageToImageBuilder::new()
+//!         .init_image_path("large_photo.png")?
+//!         .style_preset(StylePreset::Photographic)?
+//!         .text_prompt("a watercolour harbour", 1.0)?
+//!         .build()?;
+//!
+//!     let resp = with_upload_progress(
+//!         |progress| println!("{:.0}%", progress.fraction() * 100.0),
+//!         request.generate("stable-diffusion-xl-1024-v1-0"),
+//!     )
+//!     .await?;
+//!     # let _ = resp;
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Bodies are handed to the connection in chunks of this many bytes
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+type Callback = Arc<dyn Fn(UploadProgress) + Send + Sync>;
+
+tokio::task_local! {
+    static CALLBACK: Callback;
+}
+
+/// How much of a request body has been handed to the connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadProgress {
+    pub sent: u64,
+    pub total: u64,
+}
+
+impl UploadProgress {
+    /// From 0 to 1; an empty body counts as fully sent
+    pub fn fraction(&self) -> f64 {
+        match self.total {
+            0 => 1.0,
+            total => self.sent as f64 / total as f64,
+        }
+    }
+}
+
+/// Run `f`, calling `callback` as the body of every request it sends is
+/// uploaded
+pub async fn with_upload_progress<C, F>(callback: C, f: F) -> F::Output
+where
+    C: Fn(UploadProgress) + Send + Sync + 'static,
+    F: Future,
+{
+    CALLBACK.scope(Arc::new(callback), f).await
+}
+
+pub(crate) fn current() -> Option<Callback> {
+    CALLBACK.try_with(|callback| callback.clone()).ok()
+}
+
+/// A buffered body yielded in [`CHUNK_SIZE`] frames, reporting each one to
+/// a callback
+pub(crate) struct ProgressBody {
+    bytes: Bytes,
+    total: u64,
+    callback: Option<Callback>,
+}
+
+impl ProgressBody {
+    pub(crate) fn new(bytes: Bytes, callback: Option<Callback>) -> Self {
+        Self {
+            total: bytes.len() as u64,
+            bytes,
+            callback,
+        }
+    }
+}
+
+impl Body for ProgressBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        if self.bytes.is_empty() {
+            return Poll::Ready(None);
+        }
+
+        let len = self.bytes.len().min(CHUNK_SIZE);
+        let chunk = self.bytes.split_to(len);
+        if let Some(callback) = &self.callback {
+            callback(UploadProgress {
+                sent: self.total - self.bytes.len() as u64,
+                total: self.total,
+            });
+        }
+        Poll::Ready(Some(Ok(Frame::data(chunk))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.bytes.len() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn progress_body_is_reporting_every_chunk() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let seen = reports.clone();
+        let callback: Callback = Arc::new(move |progress| seen.lock().unwrap().push(progress));
+        let bytes = Bytes::from(vec![7u8; CHUNK_SIZE * 2 + 10]);
+
+        let body = ProgressBody::new(bytes.clone(), Some(callback));
+        assert_eq!(body.size_hint().exact(), Some(bytes.len() as u64));
+        let collected = body.collect().await.unwrap().to_bytes();
+
+        assert_eq!(collected, bytes);
+        let sent: Vec<u64> = reports.lock().unwrap().iter().map(|p| p.sent).collect();
+        assert_eq!(
+            sent,
+            vec![CHUNK_SIZE as u64, CHUNK_SIZE as u64 * 2, bytes.len() as u64]
+        );
+    }
+}
@@ -0,0 +1,298 @@
+//! Usage totals and daily budget alerts, built on the [`crate::audit`] log.
+//!
+//! [`read`] loads the records a [`JsonlAudit`](crate::audit::JsonlAudit)
+//! wrote, and [`summarize`] totals them per day and per engine. Dates are the
+//! `YYYY-MM-DD` prefix of each record's UTC timestamp, so ranges are written
+//! as string ranges:
+//!
+//! ```no_run
+//! use stability_rs::{usage, Result};
+//!
+//! fn main() -> Result<()> {
+//!     let records = usage::read("audit.jsonl")?;
+//!     let summary = usage::summarize(&records, "2024-03-01"..="2024-03-31");
+//!
+//!     for day in &summary.days {
+//!         println!("{}: {:.1} credits", day.date, day.total.estimated_credits);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! A [`DailyBudget`] sits in front of another sink and calls back once a
+//! day's estimated credits go over a threshold.
+
+use crate::audit::{AuditRecord, AuditSink};
+use crate::prelude::*;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::BufRead;
+use std::ops::RangeBounds;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Counts over a set of requests
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Usage {
+    pub requests: usize,
+    /// Requests without a response or with a non-2xx status
+    pub failed: usize,
+    pub artifacts: usize,
+    pub estimated_credits: f64,
+    /// Requests without a known price, left out of `estimated_credits`
+    pub unpriced: usize,
+}
+
+impl Usage {
+    pub(crate) fn add(&mut self, record: &AuditRecord) {
+        self.requests += 1;
+        if !matches!(record.status, Some(200..=299)) {
+            self.failed += 1;
+        }
+        self.artifacts += record.artifacts.unwrap_or_default();
+        match record.estimated_credits {
+            Some(credits) => self.estimated_credits += credits,
+            None => self.unpriced += 1,
+        }
+    }
+}
+
+/// A day's usage, in total and by engine
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DailyUsage {
+    /// `YYYY-MM-DD`, in UTC
+    pub date: String,
+    pub total: Usage,
+    /// Requests without an engine, such as account calls, are only counted
+    /// in the total
+    pub engines: BTreeMap<String, Usage>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageSummary {
+    /// Days with at least one request, oldest first
+    pub days: Vec<DailyUsage>,
+    pub total: Usage,
+}
+
+/// Read the records of a JSON lines audit log
+pub fn read(path: impl AsRef<Path>) -> Result<Vec<AuditRecord>> {
+    let file = std::io::BufReader::new(std::fs::File::open(path)?);
+    let mut records = Vec::new();
+    for line in file.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        records.push(serde_json::from_str(&line)?);
+    }
+    Ok(records)
+}
+
+/// Total the records dated within `range`, per day and per engine
+pub fn summarize<'a>(records: &[AuditRecord], range: impl RangeBounds<&'a str>) -> UsageSummary {
+    let mut days: BTreeMap<&str, DailyUsage> = BTreeMap::new();
+    let mut total = Usage::default();
+
+    for record in records {
+        let date = date_of(&record.timestamp);
+        if !range.contains(&date) {
+            continue;
+        }
+
+        let day = days.entry(date).or_insert_with(|| DailyUsage {
+            date: date.to_string(),
+            ..Default::default()
+        });
+        day.total.add(record);
+        if let Some(engine) = &record.engine {
+            day.engines.entry(engine.clone()).or_default().add(record);
+        }
+        total.add(record);
+    }
+
+    UsageSummary {
+        days: days.into_values().collect(),
+        total,
+    }
+}
+
+/// The date of an RFC 3339 timestamp
+fn date_of(timestamp: &str) -> &str {
+    timestamp.get(..10).unwrap_or(timestamp)
+}
+
+/// A day's estimated credits went over a [`DailyBudget`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetAlert {
+    pub date: String,
+    pub estimated_credits: f64,
+    pub threshold: f64,
+}
+
+type AlertFn = Box<dyn Fn(&BudgetAlert) + Send + Sync>;
+
+/// An audit sink which keeps a running total of the day's estimated credits
+/// and calls back the first time it goes over a threshold, then passes every
+/// record on to the sink it wraps
+pub struct DailyBudget {
+    inner: Arc<dyn AuditSink>,
+    threshold: f64,
+    alert: AlertFn,
+    today: Mutex<(String, f64)>,
+}
+
+impl fmt::Debug for DailyBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DailyBudget")
+            .field("threshold", &self.threshold)
+            .field("today", &self.today)
+            .finish()
+    }
+}
+
+impl DailyBudget {
+    pub fn new<F>(inner: Arc<dyn AuditSink>, threshold: f64, alert: F) -> Self
+    where
+        F: Fn(&BudgetAlert) + Send + Sync + 'static,
+    {
+        Self {
+            inner,
+            threshold,
+            alert: Box::new(alert),
+            today: Mutex::new((String::new(), 0.0)),
+        }
+    }
+
+    /// Start from the credits already spent today according to `records`,
+    /// such as those [`read`] back from the log after a restart
+    pub fn resume(self, records: &[AuditRecord]) -> Self {
+        if let Some(last) = records.last() {
+            let date = date_of(&last.timestamp);
+            let spent = summarize(records, date..=date).total.estimated_credits;
+            *self.today.lock().unwrap() = (date.to_string(), spent);
+        }
+        self
+    }
+}
+
+impl AuditSink for DailyBudget {
+    fn record(&self, record: &AuditRecord) -> Result<()> {
+        let alert = {
+            let mut today = self.today.lock().unwrap();
+            let date = date_of(&record.timestamp);
+            if today.0 != date {
+                *today = (date.to_string(), 0.0);
+            }
+            let before = today.1;
+            today.1 += record.estimated_credits.unwrap_or_default();
+            (before <= self.threshold && today.1 > self.threshold).then(|| BudgetAlert {
+                date: today.0.clone(),
+                estimated_credits: today.1,
+                threshold: self.threshold,
+            })
+        };
+        if let Some(alert) = alert {
+            (self.alert)(&alert);
+        }
+
+        self.inner.record(record)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn record(timestamp: &str, engine: Option<&str>, status: u16, credits: f64) -> AuditRecord {
+        AuditRecord {
+            timestamp: timestamp.to_string(),
+            method: "POST".to_string(),
+            path: String::new(),
+            engine: engine.map(str::to_string),
+            request: Value::Null,
+            status: Some(status),
+            error: None,
+            artifacts: Some(usize::from(status == 200)),
+            seeds: Vec::new(),
+            estimated_credits: Some(credits),
+            latency_ms: 0,
+        }
+    }
+
+    struct Discard;
+
+    impl AuditSink for Discard {
+        fn record(&self, _: &AuditRecord) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn summarize_is_totalling_per_day_and_engine_within_range() {
+        let records = vec![
+            record("2024-03-01T09:00:00Z", Some("sdxl"), 200, 0.2),
+            record("2024-03-02T09:00:00Z", Some("sdxl"), 200, 0.2),
+            record("2024-03-02T10:00:00Z", Some("esrgan"), 200, 0.2),
+            record("2024-03-02T11:00:00Z", Some("sdxl"), 400, 0.0),
+            record("2024-03-02T12:00:00Z", None, 200, 0.0),
+            record("2024-03-03T09:00:00Z", Some("sdxl"), 200, 0.2),
+        ];
+
+        let summary = summarize(&records, "2024-03-02"..="2024-03-03");
+
+        assert_eq!(summary.days.len(), 2);
+        let day = &summary.days[0];
+        assert_eq!(day.date, "2024-03-02");
+        assert_eq!(day.total.requests, 4);
+        assert_eq!(day.total.failed, 1);
+        assert_eq!(day.engines["sdxl"].requests, 2);
+        assert_eq!(day.engines["sdxl"].artifacts, 1);
+        assert_eq!(day.engines["esrgan"].estimated_credits, 0.2);
+        assert_eq!(summary.total.requests, 5);
+    }
+
+    #[test]
+    fn unpriced_requests_are_counted_apart_from_the_credits() {
+        let mut unpriced = record("2024-03-01T09:00:00Z", None, 200, 0.0);
+        unpriced.estimated_credits = None;
+        let records = vec![record("2024-03-01T08:00:00Z", Some("sdxl"), 200, 0.2), unpriced];
+
+        let summary = summarize(&records, ..);
+
+        assert_eq!(summary.total.estimated_credits, 0.2);
+        assert_eq!(summary.total.unpriced, 1);
+    }
+
+    #[test]
+    fn daily_budget_is_alerting_once_a_day_over_threshold() {
+        let alerts = Arc::new(Mutex::new(Vec::new()));
+        let seen = alerts.clone();
+        let budget = DailyBudget::new(Arc::new(Discard), 0.5, move |alert| {
+            seen.lock().unwrap().push(alert.clone())
+        })
+        .resume(&[record("2024-03-01T08:00:00Z", Some("sdxl"), 200, 0.2)]);
+
+        for timestamp in [
+            "2024-03-01T09:00:00Z",
+            "2024-03-01T10:00:00Z",
+            "2024-03-01T11:00:00Z",
+            "2024-03-02T09:00:00Z",
+        ] {
+            budget
+                .record(&record(timestamp, Some("sdxl"), 200, 0.2))
+                .unwrap();
+        }
+
+        let alerts = alerts.lock().unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].date, "2024-03-01");
+        assert!((alerts[0].estimated_credits - 0.6).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,240 @@
+//! The parameter rules enforced by the request builders, as standalone
+//! functions so front-ends can validate input before building a request.
+//! The limits themselves are described by [`crate::capabilities`].
+
+use crate::capabilities::{
+    self, Dimensions, CFG_SCALE, FIT_MAX_SIDE, MAX_GROW_MASK, MAX_SAMPLES, MIN_SIDE, SIDE_MULTIPLE,
+    STEPS, UPSCALE_MIN_SIDE,
+};
+use crate::error::ImageBuilderError;
+use crate::model::edit::MAX_OUTPAINT;
+use crate::model::Seed;
+
+type Validation = std::result::Result<(), ImageBuilderError>;
+
+pub use crate::capabilities::{SDXL_DIMENSIONS, SD_V1_6_SIDE_RANGE};
+
+pub fn validate_height(height: u32) -> Validation {
+    if !height.is_multiple_of(SIDE_MULTIPLE) {
+        return Err(ImageBuilderError::HeightNotMultipleOf64(height));
+    }
+
+    if height < MIN_SIDE {
+        return Err(ImageBuilderError::HeightLessThan128(height));
+    }
+
+    Ok(())
+}
+
+pub fn validate_width(width: u32) -> Validation {
+    if !width.is_multiple_of(SIDE_MULTIPLE) {
+        return Err(ImageBuilderError::WidthNotMultipleOf64(width));
+    }
+
+    if width < MIN_SIDE {
+        return Err(ImageBuilderError::WidthLessThan128(width));
+    }
+
+    Ok(())
+}
+
+/// Check a width and height against the sizes `engine` accepts
+pub fn validate_dimensions(engine: &str, width: u32, height: u32) -> Validation {
+    validate_width(width)?;
+    validate_height(height)?;
+
+    if !capabilities::for_engine(engine).dimensions.contains(width, height) {
+        return Err(ImageBuilderError::DimensionsNotSupportedByEngine {
+            engine: engine.to_lowercase(),
+            width,
+            height,
+        });
+    }
+
+    Ok(())
+}
+
+/// The size closest to `width` x `height` in aspect ratio that `engine`
+/// accepts, at roughly the pixel count the engine was trained on
+pub fn fit_dimensions(engine: &str, width: u32, height: u32) -> (u32, u32) {
+    let capabilities = capabilities::for_engine(engine);
+    let aspect = width.max(1) as f64 / height.max(1) as f64;
+
+    match capabilities.dimensions {
+        Dimensions::Fixed(sizes) => {
+            // unwrap warranted because engines list at least one size
+            *sizes
+                .iter()
+                .min_by(|a, b| {
+                    let da = (a.0 as f64 / a.1 as f64).ln() - aspect.ln();
+                    let db = (b.0 as f64 / b.1 as f64).ln() - aspect.ln();
+                    da.abs().total_cmp(&db.abs())
+                })
+                .unwrap()
+        }
+        Dimensions::Sides {
+            min,
+            max,
+            multiple_of,
+        } => {
+            let side = capabilities.native_side as f64;
+            let max = max.unwrap_or(FIT_MAX_SIDE);
+            let fit = |length: f64| {
+                (((length / multiple_of as f64).round() as u32) * multiple_of).clamp(min, max)
+            };
+            (fit(side * aspect.sqrt()), fit(side / aspect.sqrt()))
+        }
+    }
+}
+
+pub fn validate_cfg_scale(cfg_scale: u32) -> Validation {
+    if cfg_scale > *CFG_SCALE.end() {
+        return Err(ImageBuilderError::CfgScaleGreaterThan35(cfg_scale));
+    }
+
+    Ok(())
+}
+
+pub fn validate_samples(samples: u32) -> Validation {
+    if samples > MAX_SAMPLES {
+        return Err(ImageBuilderError::SamplesGreaterThan10(samples));
+    }
+
+    Ok(())
+}
+
+pub fn validate_steps(steps: u32) -> Validation {
+    if steps > *STEPS.end() {
+        return Err(ImageBuilderError::StepsGreaterThan150(steps));
+    }
+
+    if steps < *STEPS.start() {
+        return Err(ImageBuilderError::StepsLessThan10(steps));
+    }
+
+    Ok(())
+}
+
+/// Check a seed can be sent as asked, as the API reads 0 as a random seed
+pub fn validate_seed(seed: Seed) -> Validation {
+    if seed == Seed::Fixed(0) {
+        return Err(ImageBuilderError::FixedSeedZero);
+    }
+
+    Ok(())
+}
+
+pub fn validate_grow_mask(grow_mask: u32) -> Validation {
+    if grow_mask > MAX_GROW_MASK {
+        return Err(ImageBuilderError::GrowMaskGreaterThan100(grow_mask));
+    }
+
+    Ok(())
+}
+
+/// Check how far an outpaint extends an image on `side`, named in the error
+pub fn validate_outpaint(side: &'static str, pixels: u32) -> Validation {
+    if pixels > MAX_OUTPAINT {
+        return Err(ImageBuilderError::OutpaintGreaterThan2000 { side, pixels });
+    }
+
+    Ok(())
+}
+
+/// Check a setting which ranges from 0 to 1, named `name` in the error
+pub fn validate_fraction(name: &'static str, value: f32) -> Validation {
+    if !(0.0..=1.0).contains(&value) {
+        return Err(ImageBuilderError::FractionOutOfRange { name, value });
+    }
+
+    Ok(())
+}
+
+pub fn validate_upscale_height(height: u32) -> Validation {
+    if height < UPSCALE_MIN_SIDE {
+        return Err(ImageBuilderError::UpscaleHeightLessThan512(height));
+    }
+
+    Ok(())
+}
+
+pub fn validate_upscale_width(width: u32) -> Validation {
+    if width < UPSCALE_MIN_SIDE {
+        return Err(ImageBuilderError::UpscaleWidthLessThan512(width));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn height_and_width_are_valid_exactly_for_multiples_of_64_from_128() {
+        for v in 0..4096 {
+            let expected = v >= 128 && v % 64 == 0;
+            assert_eq!(validate_height(v).is_ok(), expected, "height {}", v);
+            assert_eq!(validate_width(v).is_ok(), expected, "width {}", v);
+        }
+    }
+
+    #[test]
+    fn steps_are_valid_exactly_from_10_to_150() {
+        for v in 0..1000 {
+            assert_eq!(
+                validate_steps(v).is_ok(),
+                (10..=150).contains(&v),
+                "steps {}",
+                v
+            );
+        }
+    }
+
+    #[test]
+    fn cfg_scale_and_samples_are_bounded() {
+        for v in 0..1000 {
+            assert_eq!(validate_cfg_scale(v).is_ok(), v <= 35, "cfg_scale {}", v);
+            assert_eq!(validate_samples(v).is_ok(), v <= 10, "samples {}", v);
+        }
+    }
+
+    #[test]
+    fn sdxl_dimensions_are_restricted_to_the_supported_list() {
+        let engine = "stable-diffusion-xl-1024-v1-0";
+        for w in (128..=2048).step_by(64) {
+            for h in (128..=2048).step_by(64) {
+                assert_eq!(
+                    validate_dimensions(engine, w, h).is_ok(),
+                    SDXL_DIMENSIONS.contains(&(w, h)),
+                    "{}x{}",
+                    w,
+                    h
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fitted_dimensions_are_valid_for_the_engine() {
+        for engine in [
+            "stable-diffusion-xl-1024-v1-0",
+            "stable-diffusion-v1-6",
+            "stable-diffusion-512-v2-1",
+        ] {
+            for (w, h) in [(1500, 900), (333, 4000), (64, 64), (1, 1), (4000, 10)] {
+                let (fw, fh) = fit_dimensions(engine, w, h);
+                assert!(validate_dimensions(engine, fw, fh).is_ok(), "{engine} {w}x{h}");
+            }
+        }
+        assert_eq!(fit_dimensions("stable-diffusion-xl-1024-v1-0", 1500, 900), (1344, 768));
+    }
+
+    #[test]
+    fn sd_v1_6_dimensions_are_bounded_per_side() {
+        let engine = "stable-diffusion-v1-6";
+        assert!(validate_dimensions(engine, 320, 1536).is_ok());
+        assert!(validate_dimensions(engine, 256, 512).is_err());
+        assert!(validate_dimensions(engine, 512, 1600).is_err());
+    }
+}
@@ -0,0 +1,319 @@
+//! A small local gallery for reviewing generated artifacts.
+//!
+//! [`serve`] lists the images in a directory alongside the batch records
+//! describing them (prompt, seed, engine, finish reason), read from the batch
+//! progress file and any JSONL reports in that directory.
+
+use crate::batch::{progress::PROGRESS_FILE, BatchRecord};
+use crate::prelude::*;
+use crate::support::TokioIo;
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+const IMAGE_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "webp"];
+const FILES_PREFIX: &str = "/files/";
+
+/// Serve a gallery of the images in `dir` on `127.0.0.1:port` until the
+/// returned future is dropped
+///
+/// # Example
+///
+/// ```no_run
+/// use stability_rs::{viewer, Result};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<()> {
+///     // open http://127.0.0.1:8080 to review the outputs of a batch run
+///     viewer::serve("out", 8080).await
+/// }
+/// ```
+pub async fn serve(dir: impl AsRef<Path>, port: u16) -> Result<()> {
+    let dir = Arc::new(dir.as_ref().to_path_buf());
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], port))).await?;
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let dir = dir.clone();
+        tokio::task::spawn(async move {
+            let service = service_fn(move |req| handle(dir.clone(), req));
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::warn!("viewer connection error: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle(
+    dir: Arc<PathBuf>,
+    req: Request<Incoming>,
+) -> std::result::Result<Response<Full<Bytes>>, Infallible> {
+    Ok(route(&dir, req.uri().path()).await)
+}
+
+async fn route(dir: &Path, path: &str) -> Response<Full<Bytes>> {
+    if path == "/" {
+        match index(dir).await {
+            Ok(html) => respond(StatusCode::OK, "text/html; charset=utf-8", html.into()),
+            Err(e) => respond(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "text/plain",
+                e.to_string().into(),
+            ),
+        }
+    } else if let Some(name) = path
+        .strip_prefix(FILES_PREFIX)
+        .and_then(percent_decode)
+        .filter(|n| is_plain_file_name(n) && is_image(n))
+    {
+        match tokio::fs::read(dir.join(&name)).await {
+            Ok(bytes) => respond(StatusCode::OK, content_type(&name), bytes.into()),
+            Err(_) => respond(StatusCode::NOT_FOUND, "text/plain", "not found".into()),
+        }
+    } else {
+        respond(StatusCode::NOT_FOUND, "text/plain", "not found".into())
+    }
+}
+
+fn respond(status: StatusCode, content_type: &str, body: Bytes) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(body));
+    *response.status_mut() = status;
+    if let Ok(value) = content_type.parse() {
+        response
+            .headers_mut()
+            .insert(hyper::header::CONTENT_TYPE, value);
+    }
+    response
+}
+
+async fn index(dir: &Path) -> Result<String> {
+    let mut images = Vec::new();
+    let mut records = HashMap::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let extension = name
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some(ext) if IMAGE_EXTENSIONS.contains(&ext) => images.push(name),
+            Some("jsonl") => {
+                read_records(&entry.path(), name == PROGRESS_FILE, &mut records).await?
+            }
+            _ => {}
+        }
+    }
+
+    images.sort();
+    Ok(render_index(&images, &records))
+}
+
+/// Collect the records of a JSONL report, or of the batch progress file whose
+/// lines each hold the records of one item
+async fn read_records(
+    path: &Path,
+    progress: bool,
+    records: &mut HashMap<String, BatchRecord>,
+) -> Result<()> {
+    #[derive(serde::Deserialize)]
+    struct Entry {
+        records: Vec<BatchRecord>,
+    }
+
+    let contents = tokio::fs::read_to_string(path).await?;
+    for line in contents.lines() {
+        let parsed = if progress {
+            serde_json::from_str::<Entry>(line).map(|e| e.records)
+        } else {
+            serde_json::from_str::<BatchRecord>(line).map(|r| vec![r])
+        };
+
+        for record in parsed.unwrap_or_default() {
+            if let Some(name) = Path::new(&record.output_path).file_name() {
+                records.insert(name.to_string_lossy().into_owned(), record);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn render_index(images: &[String], records: &HashMap<String, BatchRecord>) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>stability_rs viewer</title>\
+         <style>body{font-family:sans-serif;display:flex;flex-wrap:wrap;gap:16px}\
+         figure{width:320px;margin:0}img{width:100%}figcaption{font-size:12px}</style>\
+         </head><body>",
+    );
+
+    for name in images {
+        let _ = write!(
+            html,
+            "<figure><a href=\"{0}{1}\"><img src=\"{0}{1}\" loading=\"lazy\"></a><figcaption><b>{2}</b>",
+            FILES_PREFIX,
+            percent_encode(name),
+            escape(name)
+        );
+        if let Some(r) = records.get(name) {
+            let _ = write!(
+                html,
+                "<br>{}<br>seed {} &middot; {} &middot; {} &middot; {} ms",
+                escape(&r.prompt),
+                r.seed,
+                escape(&r.engine),
+                escape(&r.finish_reason),
+                r.latency_ms
+            );
+        }
+        html.push_str("</figcaption></figure>");
+    }
+
+    html.push_str("</body></html>");
+    html
+}
+
+fn is_plain_file_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['/', '\\']) && name != ".." && name != "."
+}
+
+/// Whether `name` has one of the image extensions the gallery lists, so the
+/// progress file, reports and anything else in the directory stay private
+fn is_image(name: &str) -> bool {
+    name.rsplit_once('.')
+        .is_some_and(|(_, ext)| IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+fn content_type(name: &str) -> &'static str {
+    match name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+/// `name` as a URL path segment, every byte but the unreserved ones
+/// percent-encoded
+fn percent_encode(name: &str) -> String {
+    name.bytes().fold(String::new(), |mut encoded, b| {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(b as char)
+            }
+            _ => {
+                let _ = write!(encoded, "%{:02X}", b);
+            }
+        }
+        encoded
+    })
+}
+
+/// The path segment `segment` with its percent-encoded bytes decoded, or
+/// `None` when it isn't valid UTF-8 once decoded
+fn percent_decode(segment: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(segment.len());
+    let mut bytes = segment.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'%' {
+            decoded.push(b);
+            continue;
+        }
+        let hex = [bytes.next()?, bytes.next()?];
+        decoded.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_is_escaping_record_metadata() {
+        let record = BatchRecord {
+            item_id: "item0".to_string(),
+            prompt: "<script>alert(1)</script>".to_string(),
+            prompts: Default::default(),
+            seed: 7,
+            engine: "stable-diffusion-xl-1024-v1-0".to_string(),
+            sampler: None,
+            clip_guidance_preset: None,
+            params: serde_json::Value::Null,
+            output_path: "out/item0_0.png".to_string(),
+            finish_reason: "SUCCESS".to_string(),
+            latency_ms: 10,
+            estimated_credits: 0.2,
+        };
+        let html = render_index(
+            &["item0_0.png".to_string()],
+            &HashMap::from([("item0_0.png".to_string(), record)]),
+        );
+
+        assert!(html.contains("src=\"/files/item0_0.png\""));
+        assert!(html.contains("&lt;script&gt;"));
+        assert!(!html.contains("<script>"));
+    }
+
+    #[tokio::test]
+    async fn files_are_found_when_their_name_has_a_space() {
+        let dir = std::env::temp_dir().join(format!("stability_rs_viewer_{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        tokio::fs::write(dir.join("sunset #2.png"), b"png").await.unwrap();
+
+        let html = index(&dir).await.unwrap();
+        let found = route(&dir, "/files/sunset%20%232.png").await;
+        let missing = route(&dir, "/files/sunset%202.png").await;
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+        assert!(html.contains("src=\"/files/sunset%20%232.png\""));
+        assert_eq!(found.status(), StatusCode::OK);
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn only_images_are_served() {
+        let dir = std::env::temp_dir().join(format!(
+            "stability_rs_viewer_only_images_{}",
+            std::process::id()
+        ));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        tokio::fs::write(dir.join("item0_0.PNG"), b"png").await.unwrap();
+        tokio::fs::write(dir.join(PROGRESS_FILE), b"{}").await.unwrap();
+        tokio::fs::write(dir.join("report.jsonl"), b"{}").await.unwrap();
+
+        let image = route(&dir, "/files/item0_0.PNG").await;
+        let progress = route(&dir, &format!("/files/{}", PROGRESS_FILE)).await;
+        let report = route(&dir, "/files/report.jsonl").await;
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+        assert_eq!(image.status(), StatusCode::OK);
+        assert_eq!(progress.status(), StatusCode::NOT_FOUND);
+        assert_eq!(report.status(), StatusCode::NOT_FOUND);
+    }
+}
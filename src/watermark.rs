@@ -0,0 +1,316 @@
+//! Stamping a text or logo watermark onto generated images.
+//!
+//! Text is drawn with a built-in 5x7 pixel font covering ASCII letters,
+//! digits and common punctuation, upper-cased; other characters are drawn
+//! as `?`. Use a logo for anything fancier.
+//!
+//! ```no_run
+//! use stability_rs::watermark::{Corner, Watermark};
+//! use stability_rs::Result;
+//!
+//! fn main() -> Result<()> {
+//!     let watermark = Watermark::text("AI generated")
+//!         .corner(Corner::BottomLeft)?
+//!         .opacity(0.6)?;
+//!
+//!     let mut image = image::open("image_0.png")?.to_rgba8();
+//!     watermark.apply(&mut image);
+//!     image.save("image_0_labelled.png")?;
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::api::rest::artifact::Artifact;
+use crate::error::Error;
+use crate::prelude::*;
+use image::{ImageFormat, Rgba, RgbaImage};
+use std::io::Cursor;
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+/// Text pixels this large already make a glyph 448 image pixels tall
+const MAX_SCALE: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Debug, Clone)]
+enum Mark {
+    Text { text: String, color: [u8; 3] },
+    Logo(RgbaImage),
+}
+
+#[derive(Debug, Clone)]
+pub struct Watermark {
+    mark: Mark,
+    corner: Corner,
+    opacity: f32,
+    scale: Option<u32>,
+}
+
+impl Watermark {
+    /// A white text watermark in the bottom right corner
+    pub fn text(text: &str) -> Self {
+        Self::new(Mark::Text {
+            text: text.to_string(),
+            color: [255, 255, 255],
+        })
+    }
+
+    /// A logo watermark in the bottom right corner, drawn with its own alpha
+    pub fn logo(logo: RgbaImage) -> Self {
+        Self::new(Mark::Logo(logo))
+    }
+
+    fn new(mark: Mark) -> Self {
+        Self {
+            mark,
+            corner: Corner::BottomRight,
+            opacity: 0.8,
+            scale: None,
+        }
+    }
+
+    pub fn corner(mut self, corner: Corner) -> Result<Self> {
+        self.corner = corner;
+        Ok(self)
+    }
+
+    /// How opaque the mark is, from 0 (invisible) to 1
+    pub fn opacity(mut self, opacity: f32) -> Result<Self> {
+        if !(0.0..=1.0).contains(&opacity) {
+            return Err(Box::new(Error::WatermarkOpacityOutOfRange(opacity)));
+        }
+
+        self.opacity = opacity;
+
+        Ok(self)
+    }
+
+    /// Color of a text mark
+    pub fn color(mut self, rgb: [u8; 3]) -> Result<Self> {
+        if let Mark::Text { color, .. } = &mut self.mark {
+            *color = rgb;
+        }
+        Ok(self)
+    }
+
+    /// Size of a text pixel in image pixels, from 1 to 64; by default text
+    /// is scaled to the image width
+    pub fn scale(mut self, scale: u32) -> Result<Self> {
+        if !(1..=MAX_SCALE).contains(&scale) {
+            return Err(Box::new(Error::WatermarkScaleOutOfRange {
+                scale,
+                max: MAX_SCALE,
+            }));
+        }
+
+        self.scale = Some(scale);
+
+        Ok(self)
+    }
+
+    /// Stamp the mark onto `image`
+    pub fn apply(&self, image: &mut RgbaImage) {
+        let scale = self
+            .scale
+            .unwrap_or((image.width() / 320).clamp(1, MAX_SCALE));
+        let margin = 4 * scale as i64;
+
+        match &self.mark {
+            Mark::Text { text, color } => {
+                let glyphs: Vec<[u8; 7]> = text.chars().map(glyph).collect();
+                let advance = (GLYPH_WIDTH + 1) as i64 * scale as i64;
+                let width = glyphs.len() as i64 * advance;
+                let height = (GLYPH_HEIGHT * scale) as i64;
+                let (x0, y0) = self.origin(image, width, height, margin);
+
+                // a dark shadow one text pixel down and right keeps the text
+                // legible on light backgrounds
+                for (offset, rgb) in [(scale as i64, [0, 0, 0]), (0, *color)] {
+                    for (i, rows) in glyphs.iter().enumerate() {
+                        let gx = x0 + offset + i as i64 * advance;
+                        if gx >= image.width() as i64 {
+                            break;
+                        }
+                        for (row, bits) in rows.iter().enumerate() {
+                            for col in 0..GLYPH_WIDTH {
+                                if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                                    continue;
+                                }
+                                let px = gx + (col * scale) as i64;
+                                let py = y0 + offset + (row as u32 * scale) as i64;
+                                fill(
+                                    image,
+                                    px,
+                                    py,
+                                    scale,
+                                    Rgba([rgb[0], rgb[1], rgb[2], 255]),
+                                    self.opacity,
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+            Mark::Logo(logo) => {
+                let (width, height) = (logo.width().into(), logo.height().into());
+                let (x0, y0) = self.origin(image, width, height, margin);
+                for (x, y, pixel) in logo.enumerate_pixels() {
+                    fill(image, x0 + x as i64, y0 + y as i64, 1, *pixel, self.opacity);
+                }
+            }
+        }
+    }
+
+    fn origin(&self, image: &RgbaImage, width: i64, height: i64, margin: i64) -> (i64, i64) {
+        let right = image.width() as i64 - width - margin;
+        let bottom = image.height() as i64 - height - margin;
+        match self.corner {
+            Corner::TopLeft => (margin, margin),
+            Corner::TopRight => (right, margin),
+            Corner::BottomLeft => (margin, bottom),
+            Corner::BottomRight => (right, bottom),
+        }
+    }
+}
+
+impl Artifact {
+    /// A PNG copy of this image artifact with `watermark` stamped on it
+    pub fn watermarked(&self, watermark: &Watermark) -> Result<Artifact> {
+        let mut image = self.decode()?.to_rgba8();
+        watermark.apply(&mut image);
+
+        let mut png = Vec::new();
+        image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+        Artifact::new(IMAGE_PNG, png.into())
+    }
+}
+
+/// Blend a `size` x `size` square of `color` at (`x`, `y`), clipped to the image
+fn fill(image: &mut RgbaImage, x: i64, y: i64, size: u32, color: Rgba<u8>, opacity: f32) {
+    let alpha = opacity * color.0[3] as f32 / 255.0;
+    let (x1, y1) = (x + size as i64, y + size as i64);
+    for py in y.max(0)..y1.min(image.height() as i64) {
+        for px in x.max(0)..x1.min(image.width() as i64) {
+            let pixel = image.get_pixel_mut(px as u32, py as u32);
+            for c in 0..3 {
+                let blended = pixel.0[c] as f32 * (1.0 - alpha) + color.0[c] as f32 * alpha;
+                pixel.0[c] = blended.round() as u8;
+            }
+        }
+    }
+}
+
+/// Rows of a 5x7 glyph, most significant of the low five bits leftmost
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        'A' => [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'B' => [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e],
+        'C' => [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e],
+        'D' => [0x1e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1e],
+        'E' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f],
+        'F' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10],
+        'G' => [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f],
+        'H' => [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'I' => [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f],
+        'M' => [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'P' => [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10],
+        'Q' => [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d],
+        'R' => [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11],
+        'S' => [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e],
+        'T' => [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a],
+        'X' => [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x0a, 0x04, 0x04, 0x04, 0x04],
+        'Z' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f],
+        '0' => [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+        '1' => [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        '2' => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+        '3' => [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+        '4' => [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+        '5' => [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+        '6' => [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+        '7' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+        '9' => [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+        ' ' => [0x00; 7],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08],
+        '-' => [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f],
+        '+' => [0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00],
+        ':' => [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '!' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04],
+        '\'' => [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00],
+        '#' => [0x0a, 0x0a, 0x1f, 0x0a, 0x1f, 0x0a, 0x0a],
+        '&' => [0x0c, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0d],
+        '@' => [0x0e, 0x11, 0x01, 0x0d, 0x15, 0x15, 0x0e],
+        _ => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_stamped_in_the_chosen_corner_only() {
+        let mut image = RgbaImage::from_pixel(200, 100, Rgba([0, 0, 0, 255]));
+        Watermark::text("AI")
+            .corner(Corner::TopLeft)
+            .unwrap()
+            .opacity(1.0)
+            .unwrap()
+            .scale(2)
+            .unwrap()
+            .apply(&mut image);
+
+        let lit = |x0: u32, x1: u32, y0: u32, y1: u32| {
+            (x0..x1).any(|x| (y0..y1).any(|y| image.get_pixel(x, y).0[0] == 255))
+        };
+        assert!(lit(0, 40, 0, 30));
+        assert!(!lit(100, 200, 50, 100));
+    }
+
+    #[test]
+    fn scale_is_erring_outside_its_bounds() {
+        assert!(Watermark::text("AI").scale(0).is_err());
+        let err = Watermark::text("AI").scale(u32::MAX).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "watermark scale must be between 1 and 64, but was 4294967295"
+        );
+
+        let mut image = RgbaImage::new(8, 8);
+        Watermark::text("a mark far wider than the image")
+            .scale(64)
+            .unwrap()
+            .apply(&mut image);
+    }
+
+    #[test]
+    fn opacity_is_erring_outside_zero_to_one() {
+        let err = Watermark::text("AI").opacity(1.5).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "watermark opacity must be between 0 and 1, but was 1.5"
+        );
+    }
+}